@@ -11,6 +11,7 @@ use glfw::{self, Context};
 use image;
 
 use crate::shader::Shader;
+use crate::resources;
 
 const MESSAGE : &str = "Chapter 1 : Part 4 : Ex 3 : Display the pixels in the center (zoom in)";
 const WINDOW_WIDTH: u32 = 800;
@@ -124,7 +125,7 @@ pub fn main_1_4_3() {
         //gl::TexParameterfv(gl::TEXTURE_2D,gl::TEXTURE_BORDER_COLOR,[1.0, 1.0, 1.0, 1.0].as_ptr());
         
         // Loading image from file
-        let img = image::open(&Path::new("./resources/textures/wall.jpg")).expect("Failed to load texture").flipv().into_rgb8();
+        let img = image::open(&Path::new(&resources::require("textures/wall.jpg"))).expect("Failed to load texture").flipv().into_rgb8();
 
         // Store data into texture
         gl::TexImage2D(gl::TEXTURE_2D,0,gl::RGB as i32,img.width() as i32,img.height() as i32,0,gl::RGB as u32,gl::UNSIGNED_BYTE,img.as_ptr() as *const u8 as *const c_void);
@@ -159,7 +160,7 @@ pub fn main_1_4_3() {
         //gl::TexParameterfv(gl::TEXTURE_2D,gl::TEXTURE_BORDER_COLOR,[1.0, 1.0, 1.0, 1.0].as_ptr());
         
         // Loading image from file
-        let img = image::open(&Path::new("./resources/textures/ferris.png")).expect("Failed to load texture").flipv().into_rgba8();
+        let img = image::open(&Path::new(&resources::require("textures/ferris.png"))).expect("Failed to load texture").flipv().into_rgba8();
 
         // Store data into texture
         gl::TexImage2D(gl::TEXTURE_2D,0,gl::RGBA as i32,img.width() as i32,img.height() as i32,0,gl::RGBA as u32,gl::UNSIGNED_BYTE,img.as_ptr() as *const u8 as *const c_void);
@@ -1,11 +1,11 @@
 /// Similar to free cam, but can only move in the xz plane and cannot roll
 /// For 7-Ex1, doesnt actually check for terrain or anything
 
-use glfw;
 use nalgebra_glm as glm;
 use std::ffi::CStr;
 use crate::shader::Shader;
 use crate::camera::Camera;
+use crate::input::{InputState, Key, MouseButton};
 pub struct FPSCamera {
     // Current Screen Info
     width : u32,
@@ -59,31 +59,6 @@ impl Camera for FPSCamera {
         cam
     }
     
-    /// Function to handle all window events
-    /// Resize window, scroll etc. This can be used for direction as well
-    /// Note : framebuffer and scroll polling has to be enabled
-    fn handle_window_event(&mut self, event : &glfw::WindowEvent, time_delta : &f64) {
-        match event {
-            // Update width/height when frame buffer changes
-            glfw::WindowEvent::FramebufferSize(width, height) => {
-                self.width = *width as u32;
-                self.height = *height as u32;
-                self.calculate_cam_matrix = true;
-            },
-
-            // Zoom in/out when user scrolls
-            glfw::WindowEvent::Scroll(_x_offset, y_offset) => {
-                self.fov -= *y_offset as f32 * *time_delta as f32 * self.zoom_sensitivity;
-                if self.fov <= 1.0 { self.fov = 1.0; }
-                if self.fov >= 90.0 { self.fov = 90.0; }
-                self.calculate_cam_matrix = true;
-            },
-            
-            // Could implement direction here
-            _ => {}
-        }
-    }
-
     /// Function to set the camera's cam_matrix to the shader's camMatrix
     /// ! This activates the shader
     fn set_cam_matrix(&mut self, shader : &Shader) {
@@ -104,43 +79,45 @@ impl Camera for FPSCamera {
     }
     
     /// Function to handle all updates to the camera
-    fn update(&mut self, window : &mut glfw::PWindow, time_delta : f64) {
+    fn update(&mut self, input : &InputState, time_delta : f64) {
         let mut update_speed = self.speed * time_delta as f32;
         let update_sensitivty = self.sensitivity * time_delta as f32;
 
+        // Update width/height when frame buffer changes
+        if let Some((width, height)) = input.resized && width > 0 && height > 0 {
+            self.width = width;
+            self.height = height;
+            self.calculate_cam_matrix = true;
+        }
+
+        // Zoom in/out when user scrolls
+        if input.scroll.y != 0. {
+            self.fov -= input.scroll.y * time_delta as f32 * self.zoom_sensitivity;
+            if self.fov <= 1.0 { self.fov = 1.0; }
+            if self.fov >= 90.0 { self.fov = 90.0; }
+            self.calculate_cam_matrix = true;
+        }
+
         // Shift - speed multiplier for position/roll
-        if window.get_key(glfw::Key::LeftShift) == glfw::Action::Press {
+        if input.key_down(Key::LeftShift) {
             update_speed *= self.shift_multiplier;
         }
 
         // Direction - pitch and yaw from mouse
 
-        // This can be done using setMouseCallback instead as it is in learnopengl
-        // but this done with global variables in the example and im not sure how to do that
-        // in rust, since the callback may outlive the main function
-
-        // This can be done using events, but i'm leaving it here to refer lated
-        // it would look exactly like the scroll part of the handler
-        if window.get_mouse_button(glfw::MouseButtonRight) == glfw::Action::Press {
-
+        // The input adapter hides the cursor while the right button is held, see captures_cursor
+        if input.button_down(MouseButton::Right) {
             // Do nothing on the first click
             if self.first_click {
-                // Hide cursor
-                window.set_cursor_mode(glfw::CursorMode::Hidden);
-                // Reset cursor position
-                window.set_cursor_pos(self.width as f64/2., self.height as f64/2.);
                 self.first_click = false;
                 return;
             }
 
-            // Get mouse cursor position
-            let (mouse_x, mouse_y) = window.get_cursor_pos();
-
-            // Rotate pitch (up and dow) using cursor's y pos
-            self.pitch -= update_sensitivty * (mouse_y as f32 - (self.height as f32 / 2.))/self.height as f32;
+            // Rotate pitch (up and dow) using cursor's y movement
+            self.pitch -= update_sensitivty * input.cursor_delta.y/self.height as f32;
 
-            // Rotate yaw (left and right) using cursor's x pos
-            self.yaw += update_sensitivty * (mouse_x as f32 - (self.width as f32 / 2.))/self.width as f32;
+            // Rotate yaw (left and right) using cursor's x movement
+            self.yaw += update_sensitivty * input.cursor_delta.x/self.width as f32;
 
             // Restricting pitch, since going beyond 90 inverts the mouse
             if self.pitch > 85. { self.pitch = 85. };
@@ -148,13 +125,8 @@ impl Camera for FPSCamera {
 
             // Recalculate view matrix and update camera's direction vectors
             self.calculate_cam_matrix = true;
-
-            // Reset cursor back to center of screen
-            window.set_cursor_pos(self.width as f64/2., self.height as f64/2.);
         }
-        if window.get_mouse_button(glfw::MouseButtonRight) == glfw::Action::Release {
-            // Show cursor
-            window.set_cursor_mode(glfw::CursorMode::Normal);
+        else {
             self.first_click = true;
         }
 
@@ -170,22 +142,22 @@ impl Camera for FPSCamera {
         let plane_left = glm::rotate_vec2(&plane_dir, f32::to_radians(90.));
         // getting x and z components as x and y (normalized)
 
-        if window.get_key(glfw::Key::W) == glfw::Action::Press {
+        if input.key_down(Key::W) {
             self.calculate_cam_matrix = true;
             self.position.x += update_speed * plane_dir.x;
             self.position.z += update_speed * plane_dir.y;
         }
-        if window.get_key(glfw::Key::S) == glfw::Action::Press {
+        if input.key_down(Key::S) {
             self.calculate_cam_matrix = true;
             self.position.x -= update_speed * plane_dir.x;
             self.position.z -= update_speed * plane_dir.y;
         }
-        if window.get_key(glfw::Key::A) == glfw::Action::Press {
+        if input.key_down(Key::A) {
             self.calculate_cam_matrix = true;
             self.position.x -= update_speed * plane_left.x;
             self.position.z -= update_speed * plane_left.y;
         }
-        if window.get_key(glfw::Key::D) == glfw::Action::Press {
+        if input.key_down(Key::D) {
             self.calculate_cam_matrix = true;
             self.position.x += update_speed * plane_left.x;
             self.position.z += update_speed * plane_left.y;
@@ -197,6 +169,10 @@ impl Camera for FPSCamera {
         }
     }
 
+    fn captures_cursor(&self) -> bool {
+        !self.first_click
+    }
+
     /// Function to update the camera's direction
     fn update_cam_direction(&mut self) {
         // Calculating direction from euler angles
@@ -233,6 +209,14 @@ impl Camera for FPSCamera {
         self.set_cam_matrix(shader);
         self.calculate_cam_matrix = false;
     }
+
+    fn view_matrix(&self) -> glm::Mat4 {
+        glm::look_at_rh(&self.position, &(self.position + self.direction), &self.up)
+    }
+
+    fn projection_matrix(&self) -> glm::Mat4 {
+        glm::perspective(self.width as f32/self.height as f32, f32::to_radians(self.fov), self.near_plane, self.far_plane)
+    }
 }
 
 impl Default for FPSCamera {
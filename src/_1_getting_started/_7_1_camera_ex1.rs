@@ -13,7 +13,9 @@ use nalgebra_glm as glm;
 
 use crate::shader::Shader;
 use crate::camera::Camera;
+use crate::input::GlfwInput;
 use crate::_1_getting_started::_7_1_camera_class::FPSCamera;
+use crate::resources;
 
 const MESSAGE : &str = "Chapter 1 : Part 7 : Ex1 : FPS style camera, WASD - move, mouse(hold right click) - view , scroll - zoom";
 const WINDOW_WIDTH: u32 = 800;
@@ -124,7 +126,7 @@ pub fn main_1_7_1() {
         //gl::TexParameterfv(gl::TEXTURE_2D,gl::TEXTURE_BORDER_COLOR,[1.0, 1.0, 1.0, 1.0].as_ptr());
         
         // Loading image from file
-        let img = image::open(&Path::new("./resources/textures/wall.jpg")).expect("Failed to load texture").flipv().into_rgb8();
+        let img = image::open(&Path::new(&resources::require("textures/wall.jpg"))).expect("Failed to load texture").flipv().into_rgb8();
 
         // Store data into texture
         gl::TexImage2D(gl::TEXTURE_2D,0,gl::RGB as i32,img.width() as i32,img.height() as i32,0,gl::RGB as u32,gl::UNSIGNED_BYTE,img.as_ptr() as *const u8 as *const c_void);
@@ -159,7 +161,7 @@ pub fn main_1_7_1() {
         //gl::TexParameterfv(gl::TEXTURE_2D,gl::TEXTURE_BORDER_COLOR,[1.0, 1.0, 1.0, 1.0].as_ptr());
         
         // Loading image from file
-        let img = image::open(&Path::new("./resources/textures/ferris.png")).expect("Failed to load texture").flipv().into_rgba8();
+        let img = image::open(&Path::new(&resources::require("textures/ferris.png"))).expect("Failed to load texture").flipv().into_rgba8();
 
         // Store data into texture
         gl::TexImage2D(gl::TEXTURE_2D,0,gl::RGBA as i32,img.width() as i32,img.height() as i32,0,gl::RGBA as u32,gl::UNSIGNED_BYTE,img.as_ptr() as *const u8 as *const c_void);
@@ -177,6 +179,7 @@ pub fn main_1_7_1() {
 
     // Camera
     let mut camera : FPSCamera = FPSCamera::new(glm::vec3(0.,2.,0.), 0., 0., 0., WINDOW_WIDTH, WINDOW_HEIGHT);
+    let mut input = GlfwInput::new();   // input snapshots for the camera, filled from the window's events
 
     // Viewport
     unsafe {
@@ -217,7 +220,8 @@ pub fn main_1_7_1() {
         // Update -- restricting to 60 ups
         if time_delta >= 1./60. { 
             process_input(&mut window);
-            camera.update(&mut window, time_delta);
+            let input_state = input.snapshot(&mut window, camera.captures_cursor());
+            camera.update(&input_state, time_delta);
             prev_time = curr_time;
         }
 
@@ -226,8 +230,8 @@ pub fn main_1_7_1() {
         // Handle window events
         for (_, event) in glfw::flush_messages(&events) {
             //println!("{:?}", event);
-            // passing all events to the camera - maybe not required? but will have to move the match block outside and create separate handlers
-            camera.handle_window_event(&event, &time_delta);
+            // collecting events for the camera's next input snapshot
+            input.handle_event(&event);
             match event {
                 glfw::WindowEvent::Key(glfw::Key::Escape, _, glfw::Action::Press, _) => {
                     window.set_should_close(true)
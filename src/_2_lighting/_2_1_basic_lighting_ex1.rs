@@ -11,6 +11,7 @@ use nalgebra_glm as glm;
 
 use crate::shader::Shader;
 use crate::camera::{FreeCamera, Camera};
+use crate::input::GlfwInput;
 
 const MESSAGE : &str = "Chapter 2 : Part 2 : Ex 1 : Moving the light source around , Space - pause/unpause light";
 const WINDOW_WIDTH: u32 = 800;
@@ -155,6 +156,7 @@ pub fn main_2_2_1() {
 
     // Camera
     let mut camera : FreeCamera = FreeCamera::new(glm::vec3(0.,0.,4.), 0., 0., -90., WINDOW_WIDTH, WINDOW_HEIGHT);
+    let mut input = GlfwInput::new();   // input snapshots for the camera, filled from the window's events
 
     // Viewport
     unsafe {
@@ -193,7 +195,8 @@ pub fn main_2_2_1() {
         // Update -- restricting to 60 ups
         if time_delta >= 1./60. { 
             process_input(&mut window);
-            camera.update(&mut window, time_delta);
+            let input_state = input.snapshot(&mut window, camera.captures_cursor());
+            camera.update(&input_state, time_delta);
             prev_time = curr_time;
 
             // pause/unpause
@@ -207,8 +210,8 @@ pub fn main_2_2_1() {
         // Handle window events
         for (_, event) in glfw::flush_messages(&events) {
             //println!("{:?}", event);
-            // passing all events to the camera - maybe not required? but will have to move the match block outside and create separate handlers
-            camera.handle_window_event(&event, &time_delta);
+            // collecting events for the camera's next input snapshot
+            input.handle_event(&event);
             match event {
                 glfw::WindowEvent::Key(glfw::Key::Escape, _, glfw::Action::Press, _) => {
                     window.set_should_close(true)
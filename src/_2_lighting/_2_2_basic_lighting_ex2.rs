@@ -17,6 +17,7 @@ use nalgebra_glm as glm;
 
 use crate::shader::Shader;
 use crate::camera::{FreeCamera, Camera};
+use crate::input::GlfwInput;
 
 const MESSAGE : &str = "Chapter 2 : Part 2 : Ex 2 : Experimenting with various lighting strengths, shininiess";
 const WINDOW_WIDTH: u32 = 800;
@@ -169,6 +170,7 @@ pub fn main_2_2_2() {
 
     // Camera
     let mut camera : FreeCamera = FreeCamera::new(glm::vec3(-1.,0.,6.), 0., 0., -85., WINDOW_WIDTH, WINDOW_HEIGHT);
+    let mut input = GlfwInput::new();   // input snapshots for the camera, filled from the window's events
 
     // Viewport
     unsafe {
@@ -202,7 +204,8 @@ pub fn main_2_2_2() {
         // Update -- restricting to 60 ups
         if time_delta >= 1./60. { 
             process_input(&mut window);
-            camera.update(&mut window, time_delta);
+            let input_state = input.snapshot(&mut window, camera.captures_cursor());
+            camera.update(&input_state, time_delta);
             prev_time = curr_time;
         }
 
@@ -211,8 +214,8 @@ pub fn main_2_2_2() {
         // Handle window events
         for (_, event) in glfw::flush_messages(&events) {
             //println!("{:?}", event);
-            // passing all events to the camera - maybe not required? but will have to move the match block outside and create separate handlers
-            camera.handle_window_event(&event, &time_delta);
+            // collecting events for the camera's next input snapshot
+            input.handle_event(&event);
             match event {
                 glfw::WindowEvent::Key(glfw::Key::Escape, _, glfw::Action::Press, _) => {
                     window.set_should_close(true)
@@ -12,6 +12,8 @@ use nalgebra_glm as glm;
 
 use crate::shader::Shader;
 use crate::camera::{FreeCamera, Camera};
+use crate::input::GlfwInput;
+use crate::resources;
 
 const MESSAGE : &str = "Chapter 2 : Part 5 : Light casters, N - cycle light source (Directional, Point, Spotlight, Spotlight (Soft Edges))";
 const WINDOW_WIDTH: u32 = 800;
@@ -174,14 +176,15 @@ pub fn main_2_5() {
 
     // Lighting maps
     let (diffuse_map,specular_map) = unsafe {(
-        load_texture("./resources/textures/container_diffuse.png"),
-        load_texture("./resources/textures/container_specular.png")
+        load_texture(&resources::require("textures/container_diffuse.png")),
+        load_texture(&resources::require("textures/container_specular.png"))
     )};
     
     // --Initial Config - Viewport------------------------------------------------------------------------------------------------- //
 
     // Camera
     let mut camera : FreeCamera = FreeCamera::new(glm::vec3(-2.,0.,2.), 0., 0., -40., WINDOW_WIDTH, WINDOW_HEIGHT);
+    let mut input = GlfwInput::new();   // input snapshots for the camera, filled from the window's events
 
     // Viewport
     unsafe {
@@ -218,7 +221,8 @@ pub fn main_2_5() {
         // Update -- restricting to 60 ups
         if time_delta >= 1./60. { 
             process_input(&mut window);
-            camera.update(&mut window, time_delta);
+            let input_state = input.snapshot(&mut window, camera.captures_cursor());
+            camera.update(&input_state, time_delta);
             prev_time = curr_time;
         }
 
@@ -227,8 +231,8 @@ pub fn main_2_5() {
         // Handle window events
         for (_, event) in glfw::flush_messages(&events) {
             //println!("{:?}", event);
-            // passing all events to the camera - maybe not required? but will have to move the match block outside and create separate handlers
-            camera.handle_window_event(&event, &time_delta);
+            // collecting events for the camera's next input snapshot
+            input.handle_event(&event);
             match event {
                 glfw::WindowEvent::Key(glfw::Key::Escape, _, glfw::Action::Press, _) => {
                     window.set_should_close(true)
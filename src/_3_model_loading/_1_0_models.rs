@@ -6,9 +6,11 @@ use gl::{self, types::*};
 use glfw::{self, Context};
 use nalgebra_glm as glm;
 
-use crate::model::Model;
+use crate::model::{DrawParams, Model};
 use crate::shader::Shader;
 use crate::camera::{FreeCamera, Camera};
+use crate::input::GlfwInput;
+use crate::resources;
 
 const MESSAGE : &str = "Chapter 3 : Part 1 : Model Loading using tobj";
 const WINDOW_WIDTH: u32 = 800;
@@ -51,16 +53,17 @@ pub fn main_3_1() {
     
     // Loading models
     let mut model_ferris = Model::new();
-    model_ferris.load_model("./resources/models/ferris3d_v1.0.obj");
+    model_ferris.load_model(&resources::require("models/ferris3d_v1.0.obj")).expect("Failed to load model");
     let mut model_container = Model::new();
-    model_container.load_model("./resources/models/container.obj");
+    model_container.load_model(&resources::require("models/container.obj")).expect("Failed to load model");
     let mut model_container_emissive = Model::new();
-    model_container_emissive.load_model("./resources/models/container_emissive.obj");
+    model_container_emissive.load_model(&resources::require("models/container_emissive.obj")).expect("Failed to load model");
 
     // --Initial Config - Viewport------------------------------------------------------------------------------------------------- //
 
     // Camera
     let mut camera : FreeCamera = FreeCamera::new(glm::vec3(0.,0.5,4.), 0., 0., -90., WINDOW_WIDTH, WINDOW_HEIGHT);
+    let mut input = GlfwInput::new();   // input snapshots for the camera, filled from the window's events
 
     // Viewport
     unsafe {
@@ -99,7 +102,8 @@ pub fn main_3_1() {
         // Update -- restricting to 60 ups
         if time_delta >= 1./60. { 
             process_input(&mut window);
-            camera.update(&mut window, time_delta);
+            let input_state = input.snapshot(&mut window, camera.captures_cursor());
+            camera.update(&input_state, time_delta);
             prev_time = curr_time;
         }
 
@@ -108,8 +112,8 @@ pub fn main_3_1() {
         // Handle window events
         for (_, event) in glfw::flush_messages(&events) {
             //println!("{:?}", event);
-            // passing all events to the camera - maybe not required? but will have to move the match block outside and create separate handlers
-            camera.handle_window_event(&event, &time_delta);
+            // collecting events for the camera's next input snapshot
+            input.handle_event(&event);
             match event {
                 glfw::WindowEvent::Key(glfw::Key::Escape, _, glfw::Action::Press, _) => {
                     window.set_should_close(true)
@@ -143,16 +147,13 @@ pub fn main_3_1() {
             default_shader.set_vec3(c_str!("dirLight.direction"), light_dir);
 
             // Drawing ferris at the center
-            default_shader.set_mat4(c_str!("model"), model_matrix_f);
-            model_ferris.draw(&default_shader);
+            model_ferris.draw_with(&default_shader, &DrawParams { transform : model_matrix_f, ..Default::default() });
 
             // Drawing the first container to the right
-            default_shader.set_mat4(c_str!("model"), model_matrix_c);
-            model_container.draw(&default_shader);
+            model_container.draw_with(&default_shader, &DrawParams { transform : model_matrix_c, ..Default::default() });
             
             // Drawing the second container to the left
-            default_shader.set_mat4(c_str!("model"), model_matrix_c_e);
-            model_container_emissive.draw(&default_shader);   
+            model_container_emissive.draw_with(&default_shader, &DrawParams { transform : model_matrix_c_e, ..Default::default() });
         }
 
         // Swap front and back buffers
@@ -0,0 +1,331 @@
+// Animation - skeletal animation and morph targets on procedural meshes
+// Left - a tube skinned to a chain of 3 joints, bent by an animation clip and turned by its parent node in a Scene
+// Right - a sphere with 2 morph targets (squash and bumps), their weights animated with a MorphAnimation
+
+const TUBE_LENGTH : f32 = 2.;
+const TUBE_RADIUS : f32 = 0.15;
+const TUBE_RINGS : usize = 24;      // rings along the length of the tube
+const TUBE_SEGMENTS : usize = 16;   // vertices around each ring
+const TUBE_JOINTS : usize = 3;      // joints in the chain, each one moves an equal part of the tube
+
+const SPHERE_RADIUS : f32 = 0.6;
+const SPHERE_STACKS : usize = 24;
+const SPHERE_SLICES : usize = 32;
+
+use std::ffi::CStr;
+use std::f32::consts::PI;
+use std::rc::Rc;
+
+use gl::{self, types::*};
+use glfw::{self, Context};
+use nalgebra_glm as glm;
+
+use crate::animation::{self, AnimationClip, Channel, ChannelValues, Interpolation, Joint, Skeleton};
+use crate::material::Material;
+use crate::mesh::{BufferUsage, Mesh, Vertex, VertexSkin};
+use crate::model::{DrawParams, Model};
+use crate::morph::{MorphAnimation, MorphMode, MorphTarget};
+use crate::scene::{Scene, Transform};
+use crate::shader::Shader;
+use crate::camera::{FreeCamera, Camera};
+use crate::input::GlfwInput;
+
+const MESSAGE : &str = "Chapter 3 : Part 2 : Animation, Space - Pause the animations, C - Toggle cpu / gpu morph target blending";
+const WINDOW_WIDTH: u32 = 800;
+const WINDOW_HEIGHT: u32 = 800;
+const WINDOW_TITLE: &str = "Animation";
+
+const TUBE_POSITION: glm::Vec3 = glm::Vec3::new(-1.,-1.,0.);
+const SPHERE_POSITION: glm::Vec3 = glm::Vec3::new(1.,0.,0.);
+
+pub fn main_3_2() {
+
+    println!("{}\n{}", WINDOW_TITLE, MESSAGE);
+
+    // --Initialize GLFW, Create window and load OpenGL functions------------------------------------------------------------------ //
+
+    // Initialize GLFW
+    use glfw::fail_on_errors;
+    let mut glfw = glfw::init(glfw::fail_on_errors!()).unwrap();
+
+    // Set hints for open gl version
+    glfw.window_hint(glfw::WindowHint::ContextVersionMajor(3));
+    glfw.window_hint(glfw::WindowHint::ContextVersionMinor(3));
+    glfw.window_hint(glfw::WindowHint::OpenGlProfile(glfw::OpenGlProfileHint::Core));
+
+    // Create window
+    let (mut window, events) = glfw
+        .create_window(WINDOW_WIDTH, WINDOW_HEIGHT, WINDOW_TITLE, glfw::WindowMode::Windowed)
+        .expect("Failed to create GLFW window!");
+
+    // Set current context , enable polling
+    window.make_current();
+    window.set_key_polling(true);
+    window.set_framebuffer_size_polling(true);
+    window.set_scroll_polling(true);
+
+    // Load open gl functions
+    gl::load_with(|symbol| window.get_proc_address(symbol) as *const _);
+
+    // --Creating OpenGL Objects--------------------------------------------------------------------------------------------------- //
+
+    // Shader Programs - same lighting, different ways of moving the vertices
+    let skinned_shader = Shader::new("./src/_3_model_loading/shaders/2_skinned.vert","./src/_3_model_loading/shaders/1_default.frag");
+    let morph_shader = Shader::new("./src/_3_model_loading/shaders/3_morph.vert","./src/_3_model_loading/shaders/1_default.frag");
+
+    // Skinned tube, attached to an arm node under a turntable node so the whole arm turns with it
+    let skeleton = tube_skeleton();
+    let clip = bend_clip();
+    let mut tube = Model::new();
+    let (vertices, indices, skin) = tube_mesh();
+    let mut tube_mesh = Mesh::new(vertices, indices, Rc::new(Material { diffuse_color : glm::vec4(0.9, 0.5, 0.2, 1.), shininess : 32., ..Default::default() }));
+    tube_mesh.set_skin(skin);
+    tube.meshes.push(tube_mesh);
+
+    let mut scene = Scene::new();
+    let turntable = scene.add_node("turntable", None, Transform::default());
+    let arm = scene.add_node("arm", Some(turntable), Transform::from_translation(TUBE_POSITION));
+    scene.attach(arm, Rc::new(tube));
+
+    // Morphing sphere, Dynamic since cpu blending uploads new vertices whenever the weights change
+    let (vertices, indices) = sphere_mesh();
+    let targets = sphere_targets(&vertices);
+    let mut sphere = Model::new();
+    sphere.meshes.push(Mesh::with_usage(vertices, indices, Rc::new(Material { diffuse_color : glm::vec4(0.3, 0.7, 0.4, 1.), shininess : 32., ..Default::default() }), BufferUsage::Dynamic));
+    let mut morph_mode = MorphMode::Gpu;
+    sphere.meshes[0].set_morph_targets(targets.clone(), morph_mode);
+    let morph_animation = MorphAnimation {
+        times : vec![0., 1., 2., 3.],
+        weights : vec![vec![0., 0.], vec![1., 0.], vec![0., 1.], vec![0., 0.]],
+    };
+
+    // --Initial Config - Viewport------------------------------------------------------------------------------------------------- //
+
+    // Camera
+    let mut camera : FreeCamera = FreeCamera::new(glm::vec3(0.,0.,4.), 0., 0., -90., WINDOW_WIDTH, WINDOW_HEIGHT);
+    let mut input = GlfwInput::new();   // input snapshots for the camera, filled from the window's events
+
+    // Viewport
+    unsafe {
+        gl::Viewport(0, 0, WINDOW_WIDTH as GLint, WINDOW_HEIGHT as GLint);
+    }
+
+    // Enable depth testing to put display top most primitives
+    unsafe {
+        gl::Enable(gl::DEPTH_TEST);
+    }
+
+    // Time
+    let mut prev_time = glfw.get_time();
+
+    // Model matrices
+    let sphere_matrix = glm::translation(&SPHERE_POSITION);
+
+    // Variables changed by user for demo
+    let mut paused = false;
+    let mut animation_time = 0.;
+
+    // --Render loop--------------------------------------------------------------------------------------------------------------- //
+
+    while !window.should_close() {
+
+        // Time
+        let curr_time = glfw.get_time();
+        let time_delta = curr_time - prev_time;
+
+        // Update -- restricting to 60 ups
+        if time_delta >= 1./60. {
+            let input_state = input.snapshot(&mut window, camera.captures_cursor());
+            camera.update(&input_state, time_delta);
+            if !paused {
+                animation_time += time_delta as f32;
+            }
+            prev_time = curr_time;
+        }
+
+        // Handle window events
+        for (_, event) in glfw::flush_messages(&events) {
+            // collecting events for the camera's next input snapshot
+            input.handle_event(&event);
+            match event {
+                glfw::WindowEvent::Key(glfw::Key::Escape, _, glfw::Action::Press, _) => {
+                    window.set_should_close(true)
+                }
+                glfw::WindowEvent::Key(glfw::Key::Space, _, glfw::Action::Press, _) => {
+                    paused = !paused;
+                    println!("Paused : {}", paused);
+                }
+                glfw::WindowEvent::Key(glfw::Key::C, _, glfw::Action::Press, _) => {
+                    morph_mode = if morph_mode == MorphMode::Gpu { MorphMode::Cpu } else { MorphMode::Gpu };
+                    println!("Morph target blending : {:?}", morph_mode);
+                    sphere.meshes[0].set_morph_targets(targets.clone(), morph_mode);
+                }
+                glfw::WindowEvent::FramebufferSize(w, h) => unsafe {
+                    gl::Viewport(0, 0, w, h);
+                }
+                _ => {}
+            }
+        }
+
+        // Animate - turn the turntable node, pose the skeleton and set the morph weights
+        scene.set_rotation(turntable, glm::quat_angle_axis(animation_time * 0.5, &glm::vec3(0., 1., 0.)));
+        let pose = clip.sample(&skeleton, animation_time, true);
+        let joint_matrices = skeleton.skinning_matrices(&pose);
+        sphere.meshes[0].set_morph_weights(&morph_animation.sample(animation_time, true));
+
+        // Rendering
+        unsafe {
+            // Clearing the screen
+            gl::ClearColor(0.1, 0.1, 0.1, 1.0);
+            gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+
+            // Drawing the skinned tube, the scene sets the model matrix of the arm node
+            skinned_shader.use_program();
+            set_frame_uniforms(&skinned_shader, &mut camera);
+            animation::set_joint_matrices(&skinned_shader, &joint_matrices);
+            scene.draw(&skinned_shader);
+
+            // Drawing the morphing sphere
+            morph_shader.use_program();
+            set_frame_uniforms(&morph_shader, &mut camera);
+            sphere.draw_with(&morph_shader, &DrawParams { transform : sphere_matrix, ..Default::default() });
+        }
+
+        // Swap front and back buffers
+        window.swap_buffers();
+
+        // Poll for window events
+        glfw.poll_events();
+    }
+}
+
+/// Function to set the camera and light uniforms that both shaders share
+///
+/// **Assumes that the shader program is activated**
+unsafe fn set_frame_uniforms(shader : &Shader, camera : &mut FreeCamera) {
+    unsafe {
+        camera.set_cam_matrix(shader);
+        shader.set_vec3(c_str!("viewPos"), camera.position);    // View position for specular highlights
+
+        // Set light uniforms - directional light
+        shader.set_vec3_values(c_str!("dirLight.ambient"),  0.3, 0.3, 0.3);
+        shader.set_vec3_values(c_str!("dirLight.diffuse"),  1.0, 1.0, 1.0);
+        shader.set_vec3_values(c_str!("dirLight.specular"),  1.0,1.0,1.0);
+        shader.set_vec3(c_str!("dirLight.direction"), glm::vec3(-1.,-1.,-1.));
+    }
+}
+
+/// Function to get the chain of joints along the tube, each joint is a child of the previous one
+///
+/// The tube is modelled along +y, so the inverse bind matrix of a joint just moves it back down to the origin
+fn tube_skeleton() -> Skeleton {
+    let spacing = TUBE_LENGTH / TUBE_JOINTS as f32;
+    Skeleton::new((0..TUBE_JOINTS).map(|i| Joint {
+        name : format!("joint_{}", i),
+        parent : i.checked_sub(1),
+        inverse_bind : glm::translation(&glm::vec3(0., -spacing * i as f32, 0.)),
+        rest : Transform::from_translation(glm::vec3(0., if i == 0 { 0. } else { spacing }, 0.)),
+    }).collect())
+}
+
+/// Function to get a looping clip that bends every joint but the root back and forth around z
+fn bend_clip() -> AnimationClip {
+    let times = vec![0., 1., 2., 3., 4.];
+    let bend = |angle : f32| glm::quat_angle_axis(angle, &glm::vec3(0., 0., 1.));
+    let rotations = vec![bend(0.), bend(0.6), bend(0.), bend(-0.6), bend(0.)];
+    AnimationClip {
+        name : "bend".into(),
+        duration : 4.,
+        channels : (1..TUBE_JOINTS).map(|joint| Channel {
+            joint,
+            times : times.clone(),
+            values : ChannelValues::Rotation(rotations.clone()),
+            interpolation : Interpolation::Linear,
+        }).collect(),
+    }
+}
+
+/// Function to get the vertices, indices and skin of an open tube from y = 0 to TUBE_LENGTH
+///
+/// Each ring is weighted to the two joints whose centres are closest, so the tube bends smoothly at the joints
+fn tube_mesh() -> (Vec<Vertex>, Vec<GLuint>, Vec<VertexSkin>) {
+    let spacing = TUBE_LENGTH / TUBE_JOINTS as f32;
+    let mut vertices = Vec::new();
+    let mut skin = Vec::new();
+    for ring in 0..=TUBE_RINGS {
+        let v = ring as f32 / TUBE_RINGS as f32;
+        let y = v * TUBE_LENGTH;
+
+        // Weight falls off linearly with the distance to the middle of each joint's part of the tube
+        let mut weights = [0.; 4];
+        for (joint, weight) in weights.iter_mut().take(TUBE_JOINTS).enumerate() {
+            let centre = spacing * (joint as f32 + 0.5);
+            *weight = (1. - (y - centre).abs() / spacing).max(0.);
+        }
+        let total: f32 = weights.iter().sum();
+        weights.iter_mut().for_each(|weight| *weight /= total);
+
+        for segment in 0..=TUBE_SEGMENTS {
+            let u = segment as f32 / TUBE_SEGMENTS as f32;
+            let (sin, cos) = (u * 2. * PI).sin_cos();
+            vertices.push(Vertex {
+                position : glm::vec3(cos * TUBE_RADIUS, y, sin * TUBE_RADIUS),
+                normal : glm::vec3(cos, 0., sin),
+                tex_coords : glm::vec2(u, v),
+            });
+            skin.push(VertexSkin { joints : [0, 1, 2, 0], weights });
+        }
+    }
+    (vertices, grid_indices(TUBE_RINGS, TUBE_SEGMENTS), skin)
+}
+
+/// Function to get the vertices and indices of a uv sphere centred at the origin
+fn sphere_mesh() -> (Vec<Vertex>, Vec<GLuint>) {
+    let mut vertices = Vec::new();
+    for stack in 0..=SPHERE_STACKS {
+        let v = stack as f32 / SPHERE_STACKS as f32;
+        let (sin_polar, cos_polar) = (v * PI).sin_cos();
+        for slice in 0..=SPHERE_SLICES {
+            let u = slice as f32 / SPHERE_SLICES as f32;
+            let (sin_azimuth, cos_azimuth) = (u * 2. * PI).sin_cos();
+            let normal = glm::vec3(sin_polar * cos_azimuth, cos_polar, sin_polar * sin_azimuth);
+            vertices.push(Vertex { position : normal * SPHERE_RADIUS, normal, tex_coords : glm::vec2(u, v) });
+        }
+    }
+    (vertices, grid_indices(SPHERE_STACKS, SPHERE_SLICES))
+}
+
+/// Function to get the morph targets of the sphere - squashed flat, and covered in bumps
+///
+/// Only the squash target has normal deltas, the bumps are small enough to keep the sphere's normals
+fn sphere_targets(vertices : &[Vertex]) -> Vec<MorphTarget> {
+    let squash = |vertex : &Vertex| glm::vec3(vertex.position.x * 0.4, vertex.position.y * -0.5, vertex.position.z * 0.4);
+    // Normal of the squashed ellipsoid is the normal scaled by the inverse of the squash, minus the current normal
+    let squash_normal = |vertex : &Vertex| glm::normalize(&glm::vec3(vertex.normal.x / 1.4, vertex.normal.y / 0.5, vertex.normal.z / 1.4)) - vertex.normal;
+    let bump = |vertex : &Vertex| vertex.normal * 0.1 * ((vertex.tex_coords.x * 12. * PI).sin() * (vertex.tex_coords.y * 8. * PI).sin()).max(0.);
+    vec![
+        MorphTarget {
+            name : "squash".into(),
+            position_deltas : vertices.iter().map(squash).collect(),
+            normal_deltas : vertices.iter().map(squash_normal).collect(),
+        },
+        MorphTarget {
+            name : "bumps".into(),
+            position_deltas : vertices.iter().map(bump).collect(),
+            normal_deltas : Vec::new(),
+        },
+    ]
+}
+
+/// Function to get the indices of a grid of (rows + 1) x (columns + 1) vertices, two triangles for each quad
+fn grid_indices(rows : usize, columns : usize) -> Vec<GLuint> {
+    let stride = columns as GLuint + 1;
+    let mut indices = Vec::with_capacity(rows * columns * 6);
+    for row in 0..rows as GLuint {
+        for column in 0..columns as GLuint {
+            let corner = row * stride + column;
+            indices.extend_from_slice(&[corner, corner + 1, corner + stride, corner + 1, corner + stride + 1, corner + stride]);
+        }
+    }
+    indices
+}
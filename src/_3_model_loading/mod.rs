@@ -1,2 +1,4 @@
 mod _1_0_models;
-pub use _1_0_models::*;
\ No newline at end of file
+pub use _1_0_models::*;
+mod _2_0_animation;
+pub use _2_0_animation::*;
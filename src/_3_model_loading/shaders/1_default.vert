@@ -2,14 +2,18 @@
 layout (location = 0) in vec3 aPos;     // the position variable has attribute position 0
 layout (location = 1) in vec3 aNormal;     // the texcoord variable has attribute position 1
 layout (location = 2) in vec2 aTex;     // the texcoord variable has attribute position 2
+layout (location = 5) in vec4 aColor;   // vertex colour, set using Mesh::set_colors
 
 out vec3 vertexColor;
 out vec3 Normal;
 out vec3 FragPos;
 out vec2 TexCoord;
+out vec4 VertexColor;
 
 uniform mat4 model;
 uniform mat4 camMatrix;
+uniform mat3 normalMatrix;      // set by Model::draw_with
+uniform bool hasNormalMatrix;
 
 void main()
 {
@@ -18,7 +22,9 @@ void main()
     //vertexColor = aNormal;
     vertexColor = vec3(aTex,0.0);
     FragPos = vec3(model * vec4(aPos, 1.0));            // pass actual position to fragment in world coordinates
-    Normal = mat3(transpose(inverse(model))) * aNormal; // using normal matrix in case we are applying a non-uniform scale
+    // using normal matrix in case we are applying a non-uniform scale, Model::draw_with computes it once on the cpu
+    Normal = (hasNormalMatrix ? normalMatrix : mat3(transpose(inverse(model)))) * aNormal;
     TexCoord = aTex;
+    VertexColor = aColor;
 
 }
\ No newline at end of file
@@ -0,0 +1,35 @@
+#version 330 core
+layout (location = 0) in vec3 aPos;     // the position variable has attribute position 0
+layout (location = 1) in vec3 aNormal;  // the normal variable has attribute position 1
+layout (location = 2) in vec2 aTex;     // the texcoord variable has attribute position 2
+layout (location = 3) in uvec4 aJoints; // joints influencing the vertex, set using Mesh::set_skin
+layout (location = 4) in vec4 aWeights; // weight of each joint, adds up to 1
+layout (location = 5) in vec4 aColor;   // vertex colour, set using Mesh::set_colors
+
+// Keep in sync with MAX_JOINTS in animation.rs
+const int MAX_JOINTS = 64;
+
+out vec3 Normal;
+out vec3 FragPos;
+out vec2 TexCoord;
+out vec4 VertexColor;
+
+uniform mat4 model;
+uniform mat4 camMatrix;
+uniform mat4 jointMatrices[MAX_JOINTS];  // joint world matrix * inverse bind matrix, for the current pose
+
+void main()
+{
+    // Matrix palette skinning - blend the matrices of up to 4 joints
+    mat4 skin = aWeights.x * jointMatrices[aJoints.x]
+              + aWeights.y * jointMatrices[aJoints.y]
+              + aWeights.z * jointMatrices[aJoints.z]
+              + aWeights.w * jointMatrices[aJoints.w];
+    mat4 skinnedModel = model * skin;
+
+    gl_Position = camMatrix * skinnedModel * vec4(aPos, 1.0);
+    FragPos = vec3(skinnedModel * vec4(aPos, 1.0));                 // pass actual position to fragment in world coordinates
+    Normal = mat3(transpose(inverse(skinnedModel))) * aNormal;      // using normal matrix in case we are applying a non-uniform scale
+    TexCoord = aTex;
+    VertexColor = aColor;
+}
@@ -0,0 +1,43 @@
+#version 330 core
+layout (location = 0) in vec3 aPos;     // the position variable has attribute position 0
+layout (location = 1) in vec3 aNormal;  // the normal variable has attribute position 1
+layout (location = 2) in vec2 aTex;     // the texcoord variable has attribute position 2
+layout (location = 5) in vec4 aColor;   // vertex colour, set using Mesh::set_colors
+
+// Keep in sync with MAX_MORPH_TARGETS in morph.rs
+const int MAX_MORPH_TARGETS = 8;
+
+out vec3 Normal;
+out vec3 FragPos;
+out vec2 TexCoord;
+out vec4 VertexColor;
+
+uniform mat4 model;
+uniform mat4 camMatrix;
+uniform mat3 normalMatrix;      // set by Model::draw_with
+uniform bool hasNormalMatrix;
+
+// Morph target deltas, for each target all position deltas followed by all normal deltas
+uniform samplerBuffer morphTargets;
+uniform int morphTargetCount;
+uniform int morphVertexCount;
+uniform float morphWeights[MAX_MORPH_TARGETS];
+
+void main()
+{
+    // Blend the deltas of every target, gl_VertexID is the index of the vertex in the mesh
+    vec3 position = aPos;
+    vec3 normal = aNormal;
+    for (int i = 0; i < morphTargetCount; i++) {
+        int offset = i * 2 * morphVertexCount + gl_VertexID;
+        position += morphWeights[i] * texelFetch(morphTargets, offset).xyz;
+        normal += morphWeights[i] * texelFetch(morphTargets, offset + morphVertexCount).xyz;
+    }
+
+    gl_Position = camMatrix * model * vec4(position, 1.0);
+    FragPos = vec3(model * vec4(position, 1.0));                // pass actual position to fragment in world coordinates
+    // using normal matrix in case we are applying a non-uniform scale, Model::draw_with computes it once on the cpu
+    Normal = (hasNormalMatrix ? normalMatrix : mat3(transpose(inverse(model)))) * normal;
+    TexCoord = aTex;
+    VertexColor = aColor;
+}
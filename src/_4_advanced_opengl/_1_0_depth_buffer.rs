@@ -11,6 +11,8 @@ use nalgebra_glm as glm;
 use crate::model::Model;
 use crate::shader::Shader;
 use crate::camera::{FreeCamera, Camera};
+use crate::input::GlfwInput;
+use crate::resources;
 
 const MESSAGE : &str = "Chapter 4 : Part 1 : Depth bfufer visualization \nM - change depth testing mode(LESS, ALWAYS)\nN - change shader (Normal, Visualize Depth Buffer, Visualize Depth Buffer Linear)";
 const WINDOW_WIDTH: u32 = 800;
@@ -79,14 +81,15 @@ pub fn main_4_1() {
 
     // Loading models
     let mut model_ferris = Model::new();
-    model_ferris.load_model("./resources/models/ferris3d_v1.0.obj");
+    model_ferris.load_model(&resources::require("models/ferris3d_v1.0.obj")).expect("Failed to load model");
     let mut model_container = Model::new();
-    model_container.load_model("./resources/models/container.obj");
+    model_container.load_model(&resources::require("models/container.obj")).expect("Failed to load model");
 
     // --Initial Config - Viewport------------------------------------------------------------------------------------------------- //
 
     // Camera
     let mut camera : FreeCamera = FreeCamera::new(glm::vec3(0.,0.5,4.), 0., 0., -90., WINDOW_WIDTH, WINDOW_HEIGHT);
+    let mut input = GlfwInput::new();   // input snapshots for the camera, filled from the window's events
 
     // Viewport
     unsafe {
@@ -121,7 +124,8 @@ pub fn main_4_1() {
         // Update -- restricting to 60 ups
         if time_delta >= 1./60. { 
             process_input(&mut window);
-            camera.update(&mut window, time_delta);
+            let input_state = input.snapshot(&mut window, camera.captures_cursor());
+            camera.update(&input_state, time_delta);
             prev_time = curr_time;
         }
 
@@ -130,8 +134,8 @@ pub fn main_4_1() {
         // Handle window events
         for (_, event) in glfw::flush_messages(&events) {
             //println!("{:?}", event);
-            // passing all events to the camera - maybe not required? but will have to move the match block outside and create separate handlers
-            camera.handle_window_event(&event, &time_delta);
+            // collecting events for the camera's next input snapshot
+            input.handle_event(&event);
             match event {
                 glfw::WindowEvent::Key(glfw::Key::Escape, _, glfw::Action::Press, _) => {
                     window.set_should_close(true)
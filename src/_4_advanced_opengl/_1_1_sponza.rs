@@ -2,16 +2,24 @@
 // Press N to change shader
 
 use std::ffi::CStr;
+use std::time::Duration;
 
 use gl::{self, types::*};
 use glfw::{self, Context};
 use nalgebra_glm as glm;
 
-use crate::model::Model;
+use crate::asset_loader::ModelLoader;
+use crate::mesh::InvalidMeshPolicy;
+use crate::model::{DrawParams, Model};
 use crate::shader::Shader;
 use crate::camera::{FreeCamera, Camera};
+use crate::input::GlfwInput;
+use crate::resources;
 
-const MESSAGE : &str = "Chapter 4 : Part 1 : Sponza model, N - change shader (Normal, Visualize Depth Buffer Linear, Fog)\nPlease download the model (link in README) and update the path in this fileFil";
+// Logical name of the model, found in the asset search paths (--assets <dir>, LEARN_OPENGL_ASSETS, assets.cfg or ./resources)
+const SPONZA_PATH : &str = "models/sponza/sponza.obj";
+
+const MESSAGE : &str = "Chapter 4 : Part 1 : Sponza model, N - change shader (Normal, Visualize Depth Buffer Linear, Fog)\nShift+P - record a camera path, P - play it back (L - loop, scroll - speed)\nPlease download the model (link in README) into an asset directory as models/sponza/sponza.obj, see resources.rs";
 const WINDOW_WIDTH: u32 = 800;
 const WINDOW_HEIGHT: u32 = 800;
 const WINDOW_TITLE: &str = "Depth Buffer";
@@ -60,14 +68,15 @@ pub fn main_4_1_1() {
 
     println!("Current shader : {}", shader_names[current_shader]);
 
-    // Loading models
-    let mut model_sponza = Model::new();
-    model_sponza.load_model("C:/Users/harik/Downloads/Sponza-master/Sponza-master/sponza.obj");
+    // Loading models - in the background, a progress bar is drawn until the model is ready
+    let mut loader = ModelLoader::new(&resources::require(SPONZA_PATH), InvalidMeshPolicy::default());
+    let mut model_sponza : Option<Model> = None;
 
     // --Initial Config - Viewport------------------------------------------------------------------------------------------------- //
 
     // Camera
     let mut camera : FreeCamera = FreeCamera::new(glm::vec3(-1220.,100.,-45.), 0., 0., 0., WINDOW_WIDTH, WINDOW_HEIGHT);
+    let mut input = GlfwInput::new();   // input snapshots for the camera, filled from the window's events
     camera.near_plane = 1.; // to reduce z fighting
     camera.far_plane = 3000.; // needed since the model is very large without scaling down
     camera.speed = 100.; // needed since the model is very large without scaling down
@@ -117,7 +126,8 @@ pub fn main_4_1_1() {
         // Update -- restricting to 60 ups
         if time_delta >= 1./60. { 
             process_input(&mut window);
-            camera.update(&mut window, time_delta);
+            let input_state = input.snapshot(&mut window, camera.captures_cursor());
+            camera.update(&input_state, time_delta);
             fps = format!("FPS : {} / MS : {}", (1./time_delta as f32)*(frame_counter as f32) , (time_delta as f32/(frame_counter as f32)*1000.));
             window.set_title(format!("{} {}",WINDOW_TITLE,fps).as_str());
             prev_time = curr_time;
@@ -129,8 +139,8 @@ pub fn main_4_1_1() {
         // Handle window events
         for (_, event) in glfw::flush_messages(&events) {
             //println!("{:?}", event);
-            // passing all events to the camera - maybe not required? but will have to move the match block outside and create separate handlers
-            camera.handle_window_event(&event, &time_delta);
+            // collecting events for the camera's next input snapshot
+            input.handle_event(&event);
             match event {
                 glfw::WindowEvent::Key(glfw::Key::Escape, _, glfw::Action::Press, _) => {
                     window.set_should_close(true)
@@ -147,6 +157,25 @@ pub fn main_4_1_1() {
             }
         }
 
+        // Loading screen - upload for a few ms per frame, and draw the progress as a bar using the scissor test
+        if model_sponza.is_none() {
+            model_sponza = loader.update(Duration::from_millis(8)).expect("Failed to load model");
+            let (width, height) = window.get_framebuffer_size();
+            unsafe {
+                gl::ClearColor(0.1, 0.1, 0.1, 1.0);
+                gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+                gl::Enable(gl::SCISSOR_TEST);
+                gl::Scissor(width / 10, height / 2 - 10, (loader.progress() * (width as f32 * 0.8)) as GLint, 20);
+                gl::ClearColor(0.8, 0.8, 0.8, 1.0);
+                gl::Clear(gl::COLOR_BUFFER_BIT);
+                gl::Disable(gl::SCISSOR_TEST);
+            }
+            window.set_title(format!("{} - Loading {:.0}%", WINDOW_TITLE, loader.progress() * 100.).as_str());
+            window.swap_buffers();
+            glfw.poll_events();
+            continue;
+        }
+
         // Rendering
         unsafe {
             // Clearing the screen
@@ -175,9 +204,14 @@ pub fn main_4_1_1() {
             default_shader.set_float(c_str!("far"), camera.far_plane);
             default_shader.set_float(c_str!("near"), camera.near_plane);
 
-            // Drawing ferris at the center
-            default_shader.set_mat4(c_str!("model"), model_matrix_f);
-            model_sponza.draw(&default_shader);
+            // Drawing sponza at the center - foliage is alpha tested, blended materials are drawn last back to front
+            if let Some(model_sponza) = &model_sponza {
+                model_sponza.draw_with(default_shader, &DrawParams {
+                    transform : model_matrix_f,
+                    view_position : Some(camera.position),
+                    ..Default::default()
+                });
+            }
         }
 
         // Swap front and back buffers
@@ -9,6 +9,8 @@ use nalgebra_glm as glm;
 use crate::model::Model;
 use crate::shader::Shader;
 use crate::camera::{FreeCamera, Camera};
+use crate::input::GlfwInput;
+use crate::resources;
 
 const MESSAGE : &str = "Chapter 4 : Part 3 : Blending and Discarding fragments";
 const WINDOW_WIDTH: u32 = 800;
@@ -66,13 +68,13 @@ pub fn main_4_3() {
     
     // Loading models
     let mut model_ground = Model::new();
-    model_ground.load_plane("./resources/textures/ground.jpg",10.0,10.0);
+    model_ground.load_plane(&resources::require("textures/ground.jpg"),10.0,10.0);
     let mut model_grass = Model::new();
-    model_grass.load_plane("./resources/textures/grass.png",1.,1.);
+    model_grass.load_plane(&resources::require("textures/grass.png"),1.,1.);
     let mut model_window = Model::new();
-    model_window.load_plane("./resources/textures/window.png",0.5,1.);
+    model_window.load_plane(&resources::require("textures/window.png"),0.5,1.);
     let mut model_ferris = Model::new();
-    model_ferris.load_model("./resources/models/ferris3d_v1.0.obj");
+    model_ferris.load_model(&resources::require("models/ferris3d_v1.0.obj")).expect("Failed to load model");
 
     // Set texture unit 0 as a blank texture
     // Required since shader is expecting a emission texture but none are provided
@@ -89,6 +91,7 @@ pub fn main_4_3() {
 
     // Camera
     let mut camera : FreeCamera = FreeCamera::new(glm::vec3(0.,0.5,4.), 0., 0., -90., WINDOW_WIDTH, WINDOW_HEIGHT);
+    let mut input = GlfwInput::new();   // input snapshots for the camera, filled from the window's events
 
     // Viewport
     unsafe {
@@ -137,7 +140,8 @@ pub fn main_4_3() {
         // Update -- restricting to 60 ups
         if time_delta >= 1./60. { 
             process_input(&mut window);
-            camera.update(&mut window, time_delta);
+            let input_state = input.snapshot(&mut window, camera.captures_cursor());
+            camera.update(&input_state, time_delta);
             prev_time = curr_time;
         }
 
@@ -146,8 +150,8 @@ pub fn main_4_3() {
         // Handle window events
         for (_, event) in glfw::flush_messages(&events) {
             //println!("{:?}", event);
-            // passing all events to the camera - maybe not required? but will have to move the match block outside and create separate handlers
-            camera.handle_window_event(&event, &time_delta);
+            // collecting events for the camera's next input snapshot
+            input.handle_event(&event);
             match event {
                 glfw::WindowEvent::Key(glfw::Key::Escape, _, glfw::Action::Press, _) => {
                     window.set_should_close(true)
@@ -1,6 +1,6 @@
 // Face Culling - using subdivion from _4_e_subdivision
 
-const MODEL_PATH : &str = "./resources/models/ferris3d_v1.0.obj";
+const MODEL_PATH : &str = "models/ferris3d_v1.0.obj";
 // using existing model to not add large files to the repo, may help people with poor internet (me in the past) plus is a good excuse to implement mesh.subdivide()
 // feel free to replace the model, larger models give more noticable results 
 // you may have to change the FrontFace back to CCW if you are using a different model
@@ -16,6 +16,8 @@ use nalgebra_glm as glm;
 use crate::model::Model;
 use crate::shader::Shader;
 use crate::camera::{FreeCamera, Camera};
+use crate::input::GlfwInput;
+use crate::resources;
 
 const MESSAGE : &str = "Chapter 4 : Part 4 : Face Culling, Space - Toggle face culling, M - Toggle Wireframe mode";
 const WINDOW_WIDTH: u32 = 800;
@@ -62,7 +64,7 @@ pub fn main_4_4() {
     // Loading models
     let mut model = Model::new();
     //model.load_model("./resources/models/unecessarily_detailed_torus.obj"); // just a torus from blender with max vertices subdivided 
-    model.load_model(MODEL_PATH);   
+    model.load(&resources::require(MODEL_PATH)).expect("Failed to load model");   
     model.subdivide_meshes(SUBDIVIDE_MODEL);
 
     // Set texture unit 0 as a blank texture
@@ -80,6 +82,7 @@ pub fn main_4_4() {
 
     // Camera
     let mut camera : FreeCamera = FreeCamera::new(glm::vec3(0.,0.5,5.), 0., -5., -90., WINDOW_WIDTH, WINDOW_HEIGHT);
+    let mut input = GlfwInput::new();   // input snapshots for the camera, filled from the window's events
 
     // Viewport
     unsafe {
@@ -131,7 +134,8 @@ pub fn main_4_4() {
         // Update -- restricting to 60 ups
         if time_delta >= 1./60. { 
             process_input(&mut window);
-            camera.update(&mut window, time_delta);
+            let input_state = input.snapshot(&mut window, camera.captures_cursor());
+            camera.update(&input_state, time_delta);
 
             window.set_title(format!("{} Face Culling {} FPS : {} / MS : {}",WINDOW_TITLE,cull_face,(1./time_delta as f32)*(frame_counter as f32) , (time_delta as f32/(frame_counter as f32)*1000.)).as_str());
             
@@ -144,8 +148,8 @@ pub fn main_4_4() {
         // Handle window events
         for (_, event) in glfw::flush_messages(&events) {
             //println!("{:?}", event);
-            // passing all events to the camera - maybe not required? but will have to move the match block outside and create separate handlers
-            camera.handle_window_event(&event, &time_delta);
+            // collecting events for the camera's next input snapshot
+            input.handle_event(&event);
             match event {
                 glfw::WindowEvent::Key(glfw::Key::Escape, _, glfw::Action::Press, _) => {
                     window.set_should_close(true)
@@ -13,6 +13,7 @@ use nalgebra_glm as glm;
 use crate::model::Model;
 use crate::shader::Shader;
 use crate::camera::{FreeCamera, Camera};
+use crate::input::GlfwInput;
 
 const MESSAGE : &str = "Chapter 4 : Part 4 : Extra : Implementing subdivision, Space - Toggle face culling, M - Toggle Wireframe mode";
 const WINDOW_WIDTH: u32 = 800;
@@ -81,6 +82,7 @@ pub fn main_4_4_e() {
 
     // Camera
     let mut camera : FreeCamera = FreeCamera::new(glm::vec3(0.,0.0,2.), 0., 0., -90., WINDOW_WIDTH, WINDOW_HEIGHT);
+    let mut input = GlfwInput::new();   // input snapshots for the camera, filled from the window's events
     camera.update_cam_matrix(false); // recalculate matrices
 
     // Viewport
@@ -133,7 +135,8 @@ pub fn main_4_4_e() {
         // Update -- restricting to 60 ups
         if time_delta >= 1./60. { 
             process_input(&mut window);
-            camera.update(&mut window, time_delta);
+            let input_state = input.snapshot(&mut window, camera.captures_cursor());
+            camera.update(&input_state, time_delta);
 
             window.set_title(format!("{} Face Culling {} FPS : {} / MS : {}",WINDOW_TITLE,cull_face,(1./time_delta as f32)*(frame_counter as f32) , (time_delta as f32/(frame_counter as f32)*1000.)).as_str());
             
@@ -146,8 +149,8 @@ pub fn main_4_4_e() {
         // Handle window events
         for (_, event) in glfw::flush_messages(&events) {
             //println!("{:?}", event);
-            // passing all events to the camera - maybe not required? but will have to move the match block outside and create separate handlers
-            camera.handle_window_event(&event, &time_delta);
+            // collecting events for the camera's next input snapshot
+            input.handle_event(&event);
             match event {
                 glfw::WindowEvent::Key(glfw::Key::Escape, _, glfw::Action::Press, _) => {
                     window.set_should_close(true)
@@ -1,7 +1,7 @@
 // Frame Buffers
 // Note: frame buffer texture size is not changed with window size
 
-const MODEL_PATH : &str = "./resources/models/ferris3d_v1.0.obj";
+const MODEL_PATH : &str = "models/ferris3d_v1.0.obj";
 
 use std::ffi::CStr;
 use std::ptr;
@@ -13,6 +13,8 @@ use nalgebra_glm as glm;
 use crate::model::Model;
 use crate::shader::Shader;
 use crate::camera::{FreeCamera, Camera};
+use crate::input::GlfwInput;
+use crate::resources;
 
 const MESSAGE : &str = "Chapter 4 : Part 5 : Frame Buffers, N - Change frame buffer shader";
 const WINDOW_WIDTH: u32 = 800;
@@ -73,7 +75,7 @@ pub fn main_4_5() {
     // Loading models
     let mut model = Model::new();
     let mut frame_buffer_quad = Model::new();
-    model.load_model(MODEL_PATH);   
+    model.load(&resources::require(MODEL_PATH)).expect("Failed to load model");   
     frame_buffer_quad.load_plane_blank(1.0);
     
     // Set texture unit 0 as a blank texture
@@ -91,6 +93,7 @@ pub fn main_4_5() {
 
     // Camera
     let mut camera : FreeCamera = FreeCamera::new(glm::vec3(0.,0.5,5.), 0., -5., -90., WINDOW_WIDTH, WINDOW_HEIGHT);
+    let mut input = GlfwInput::new();   // input snapshots for the camera, filled from the window's events
 
     // Viewport
     unsafe {
@@ -180,7 +183,8 @@ pub fn main_4_5() {
         // Update -- restricting to 60 ups
         if time_delta >= 1./60. { 
             process_input(&mut window);
-            camera.update(&mut window, time_delta);
+            let input_state = input.snapshot(&mut window, camera.captures_cursor());
+            camera.update(&input_state, time_delta);
 
             window.set_title(format!("{} FPS : {} / MS : {}",WINDOW_TITLE,(1./time_delta as f32)*(frame_counter as f32) , (time_delta as f32/(frame_counter as f32)*1000.)).as_str());
             
@@ -193,8 +197,8 @@ pub fn main_4_5() {
         // Handle window events
         for (_, event) in glfw::flush_messages(&events) {
             //println!("{:?}", event);
-            // passing all events to the camera - maybe not required? but will have to move the match block outside and create separate handlers
-            camera.handle_window_event(&event, &time_delta);
+            // collecting events for the camera's next input snapshot
+            input.handle_event(&event);
             match event {
                 glfw::WindowEvent::Key(glfw::Key::Escape, _, glfw::Action::Press, _) => {
                     window.set_should_close(true)
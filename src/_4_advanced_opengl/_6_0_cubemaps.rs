@@ -0,0 +1,207 @@
+// Cubemaps - skybox and environment mapping
+// Note: skybox faces are expected in SKYBOX_DIRECTORY as right.jpg, left.jpg, top.jpg, bottom.jpg, front.jpg, back.jpg
+// (e.g. the skybox from learnopengl.com), an equirectangular .hdr image at HDR_PATH is optional
+
+const SKYBOX_DIRECTORY : &str = "textures/skybox";
+const HDR_PATH : &str = "textures/hdr/environment.hdr";
+const HDR_FACE_SIZE : u32 = 512;
+
+use std::ffi::CStr;
+
+use gl::{self, types::*};
+use glfw::{self, Context};
+use nalgebra_glm as glm;
+
+use crate::cubemap::{self, EnvironmentMapping};
+use crate::model::Model;
+use crate::shader::Shader;
+use crate::skybox::Skybox;
+use crate::texture::{TextureOptions, Wrap};
+use crate::camera::{FreeCamera, Camera};
+use crate::input::GlfwInput;
+use crate::resources;
+
+const MESSAGE : &str = "Chapter 4 : Part 6 : Cubemaps, N - Change environment mapping, H - Toggle HDR skybox";
+const WINDOW_WIDTH: u32 = 800;
+const WINDOW_HEIGHT: u32 = 800;
+const WINDOW_TITLE: &str = "Cubemaps";
+
+pub fn main_4_6() {
+
+    println!("{}\n{}", WINDOW_TITLE, MESSAGE);
+
+    // --Initialize GLFW, Create window and load OpenGL functions------------------------------------------------------------------ //
+
+    // Initialize GLFW
+    use glfw::fail_on_errors;
+    let mut glfw = glfw::init(glfw::fail_on_errors!()).unwrap();
+
+    // Set hints for open gl version
+    glfw.window_hint(glfw::WindowHint::ContextVersionMajor(3));
+    glfw.window_hint(glfw::WindowHint::ContextVersionMinor(3));
+    glfw.window_hint(glfw::WindowHint::OpenGlProfile(glfw::OpenGlProfileHint::Core));
+
+    // Create window
+    let (mut window, events) = glfw
+        .create_window(WINDOW_WIDTH, WINDOW_HEIGHT, WINDOW_TITLE, glfw::WindowMode::Windowed)
+        .expect("Failed to create GLFW window!");
+
+    // Set current context , enable polling
+    window.make_current();
+    window.set_key_polling(true);
+    window.set_framebuffer_size_polling(true);
+    window.set_scroll_polling(true);
+
+    // Load open gl functions
+    gl::load_with(|symbol| window.get_proc_address(symbol) as *const _);
+
+    // --Creating OpenGL Objects--------------------------------------------------------------------------------------------------- //
+
+    // Shader Program
+    let default_shader = Shader::new("./src/_3_model_loading/shaders/1_default.vert","./src/_3_model_loading/shaders/1_default.frag");
+
+    // Loading models
+    let mut model_ferris = Model::new();
+    model_ferris.load_model(&resources::require("models/ferris3d_v1.0.obj")).expect("Failed to load model");
+    let mut model_container = Model::new();
+    model_container.load_model(&resources::require("models/container.obj")).expect("Failed to load model");
+
+    // Cubemaps - clamped, or the edges of the faces show as seams. sRGB like the models' colour textures
+    let cubemap_options = TextureOptions { wrap_s : Wrap::ClampToEdge, wrap_t : Wrap::ClampToEdge, mip_filter : None, srgb : true, ..Default::default() };
+    let skybox_cubemap = cubemap::load_cubemap_directory(&resources::require(SKYBOX_DIRECTORY), "jpg", &cubemap_options).expect("Failed to load skybox");
+    let mut skybox = Skybox::new(skybox_cubemap.clone());
+
+    // HDR skybox, converted from an equirectangular image once it is first shown
+    let mut hdr_cubemap = None;
+    let mut use_hdr = false;
+
+    // Environment mapping modes, cycled with N
+    let environment_modes = [
+        EnvironmentMapping::None,
+        EnvironmentMapping::Reflection,
+        EnvironmentMapping::Refraction(1. / 1.52),
+    ];
+    let mode_names = ["None", "Reflection", "Refraction - glass"];
+    let mut current_mode = 1;
+    println!("Current environment mapping : {}", mode_names[current_mode]);
+
+    // --Initial Config - Viewport------------------------------------------------------------------------------------------------- //
+
+    // Camera
+    let mut camera : FreeCamera = FreeCamera::new(glm::vec3(0.,0.5,4.), 0., 0., -90., WINDOW_WIDTH, WINDOW_HEIGHT);
+    let mut input = GlfwInput::new();   // input snapshots for the camera, filled from the window's events
+
+    // Viewport
+    unsafe {
+        gl::Viewport(0, 0, WINDOW_WIDTH as GLint, WINDOW_HEIGHT as GLint);
+    }
+
+    // Enable depth testing to put display top most primitives
+    // Seamless cubemaps filter across face edges
+    unsafe {
+        gl::Enable(gl::DEPTH_TEST);
+        gl::Enable(gl::TEXTURE_CUBE_MAP_SEAMLESS);
+    }
+
+    // Time
+    let mut prev_time = glfw.get_time();
+
+    // Model matrices
+    let identity = glm::Mat4::identity();
+    let model_matrix_f = identity;
+    let model_matrix_c = glm::translation(&glm::vec3(1.5,0.,0.)) * glm::scale(&identity, &glm::vec3(0.25, 0.25, 0.25));
+
+    // --Render loop--------------------------------------------------------------------------------------------------------------- //
+
+    while !window.should_close() {
+
+        // Time
+        let curr_time = glfw.get_time();
+        let time_delta = curr_time - prev_time;
+
+        // Update -- restricting to 60 ups
+        if time_delta >= 1./60. {
+            process_input(&mut window);
+            let input_state = input.snapshot(&mut window, camera.captures_cursor());
+            camera.update(&input_state, time_delta);
+            prev_time = curr_time;
+        }
+
+        // Handle window events
+        for (_, event) in glfw::flush_messages(&events) {
+            input.handle_event(&event);
+            match event {
+                glfw::WindowEvent::Key(glfw::Key::Escape, _, glfw::Action::Press, _) => {
+                    window.set_should_close(true)
+                }
+                glfw::WindowEvent::FramebufferSize(w, h) => unsafe {
+                    gl::Viewport(0, 0, w, h);
+                }
+                glfw::WindowEvent::Key(glfw::Key::N, _, glfw::Action::Press, _) => {
+                    current_mode = (current_mode + 1) % environment_modes.len();
+                    println!("Current environment mapping : {}", mode_names[current_mode]);
+                }
+                glfw::WindowEvent::Key(glfw::Key::H, _, glfw::Action::Press, _) => {
+                    if hdr_cubemap.is_none() {
+                        let hdr_options = TextureOptions { half_float : true, ..cubemap_options };
+                        match resources::resolve(HDR_PATH) {
+                            Ok(path) => match cubemap::load_equirectangular(&path, "", HDR_FACE_SIZE, &hdr_options) {
+                                Ok(cubemap) => hdr_cubemap = Some(cubemap),
+                                Err(error) => println!("Warning: failed to load HDR skybox {} ({})", path, error),
+                            },
+                            Err(error) => println!("Warning: {}", error),
+                        }
+                    }
+                    if let Some(hdr) = &hdr_cubemap {
+                        use_hdr = !use_hdr;
+                        skybox.cubemap = if use_hdr { hdr.clone() } else { skybox_cubemap.clone() };
+                        skybox.tone_mapping = use_hdr;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        // Rendering
+        unsafe {
+            // Clearing the screen
+            gl::ClearColor(0.1, 0.1, 0.1, 1.0);
+            gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+
+            // Drawing the objects
+            default_shader.use_program();
+
+            // Set transformation matrices, forced since the skybox changes the active program
+            camera.force_set_cam_matrix(&default_shader);
+            default_shader.set_vec3(c_str!("viewPos"), camera.position);    // View position for specular highlights and reflections
+
+            // Set light uniforms - directional light
+            default_shader.set_vec3_values(c_str!("dirLight.ambient"),  0.3, 0.3, 0.3);
+            default_shader.set_vec3_values(c_str!("dirLight.diffuse"),  1.0, 1.0, 1.0);
+            default_shader.set_vec3_values(c_str!("dirLight.specular"),  1.0,1.0,1.0);
+            default_shader.set_vec3(c_str!("dirLight.direction"), glm::vec3(-1.,-1.,0.));
+
+            // Environment mapping using the visible skybox
+            environment_modes[current_mode].apply(&default_shader, &skybox.cubemap, 0.8);
+
+            default_shader.set_mat4(c_str!("model"), model_matrix_f);
+            model_ferris.draw(&default_shader);
+            default_shader.set_mat4(c_str!("model"), model_matrix_c);
+            model_container.draw(&default_shader);
+
+            // Skybox last, only covers pixels where nothing was drawn
+            skybox.draw(&camera);
+        }
+
+        // Swap front and back buffers
+        window.swap_buffers();
+
+        // Poll for window events
+        glfw.poll_events();
+    }
+}
+
+/// Function to process input
+fn process_input(_window: &mut glfw::PWindow) {
+    // Not used, but can process inputs like camera.update()
+}
@@ -0,0 +1,236 @@
+// Advanced Data - updating vertex buffers after they are created
+// Two copies of a rippling grid whose vertices are recalculated every frame on the cpu
+// Left - a Mesh created with BufferUsage::Stream, updated in place with update_vertices (glBufferSubData)
+// Right - a StreamMesh, written into the next region of a ring buffer with mapped ranges, see stream_mesh.rs
+
+const GRID_SIZE : usize = 64;      // quads along each side of the grid
+const GRID_SCALE : f32 = 2.;        // size of the grid in world units
+
+use std::ffi::CStr;
+use std::rc::Rc;
+
+use gl::{self, types::*};
+use glfw::{self, Context};
+use nalgebra_glm as glm;
+
+use crate::material::Material;
+use crate::mesh::{BufferUsage, Mesh, Vertex};
+use crate::shader::Shader;
+use crate::stream_mesh::StreamMesh;
+use crate::camera::{FreeCamera, Camera};
+use crate::input::GlfwInput;
+
+const MESSAGE : &str = "Chapter 4 : Part 7 : Advanced Data, Space - Pause the ripples, M - Toggle Wireframe mode";
+const WINDOW_WIDTH: u32 = 800;
+const WINDOW_HEIGHT: u32 = 800;
+const WINDOW_TITLE: &str = "Advanced Data";
+
+const BUFFER_POSITION: glm::Vec3 = glm::Vec3::new(-1.2,0.,0.);
+const STREAM_POSITION: glm::Vec3 = glm::Vec3::new(1.2,0.,0.);
+
+pub fn main_4_7() {
+
+    println!("{}\n{}", WINDOW_TITLE, MESSAGE);
+
+    // --Initialize GLFW, Create window and load OpenGL functions------------------------------------------------------------------ //
+
+    // Initialize GLFW
+    use glfw::fail_on_errors;
+    let mut glfw = glfw::init(glfw::fail_on_errors!()).unwrap();
+
+    // Set hints for open gl version
+    glfw.window_hint(glfw::WindowHint::ContextVersionMajor(3));
+    glfw.window_hint(glfw::WindowHint::ContextVersionMinor(3));
+    glfw.window_hint(glfw::WindowHint::OpenGlProfile(glfw::OpenGlProfileHint::Core));
+
+    // Create window
+    let (mut window, events) = glfw
+        .create_window(WINDOW_WIDTH, WINDOW_HEIGHT, WINDOW_TITLE, glfw::WindowMode::Windowed)
+        .expect("Failed to create GLFW window!");
+
+    // Set current context , enable polling
+    window.make_current();
+    window.set_key_polling(true);
+    window.set_framebuffer_size_polling(true);
+    window.set_scroll_polling(true);
+
+    // Load open gl functions
+    gl::load_with(|symbol| window.get_proc_address(symbol) as *const _);
+
+    // --Creating OpenGL Objects--------------------------------------------------------------------------------------------------- //
+
+    // Shader Program
+    let default_shader = Shader::new("./src/_3_model_loading/shaders/1_default.vert","./src/_4_advanced_opengl/shaders/4_default.frag");
+
+    // Both grids share the same material and indices, only the vertices change
+    let material = Rc::new(Material { diffuse_color : glm::vec4(0.2, 0.45, 0.8, 1.), shininess : 64., ..Default::default() });
+    let indices = grid_indices();
+    let mut vertices = ripple_vertices(0.);
+    let mut buffer_mesh = Mesh::with_usage(vertices.clone(), indices.clone(), material.clone(), BufferUsage::Stream);
+    let mut stream_mesh = StreamMesh::new(vertices.len(), indices.len(), material);
+
+    // --Initial Config - Viewport------------------------------------------------------------------------------------------------- //
+
+    // Camera
+    let mut camera : FreeCamera = FreeCamera::new(glm::vec3(0.,1.5,3.5), 0., -25., -90., WINDOW_WIDTH, WINDOW_HEIGHT);
+    let mut input = GlfwInput::new();   // input snapshots for the camera, filled from the window's events
+    camera.update_cam_matrix(false); // recalculate matrices
+
+    // Viewport
+    unsafe {
+        gl::Viewport(0, 0, WINDOW_WIDTH as GLint, WINDOW_HEIGHT as GLint);
+    }
+
+    // Enable depth testing to put display top most primitives
+    unsafe {
+        gl::Enable(gl::DEPTH_TEST);
+    }
+
+    // Time
+    let mut prev_time = glfw.get_time();
+    let mut frame_counter = 0;
+
+    // Get all model matrices
+    let identity = glm::Mat4::identity();
+    let buffer_matrix = glm::translate(&identity, &BUFFER_POSITION);
+    let stream_matrix = glm::translate(&identity, &STREAM_POSITION);
+
+    glfw.set_swap_interval(glfw::SwapInterval::Sync(0)); // disable vsync to uncap fps
+
+    // Variables changed by user for demo
+    let mut paused = false;
+    let mut ripple_time = 0.;
+    let mut wireframe_mode = false;
+
+    // --Render loop--------------------------------------------------------------------------------------------------------------- //
+
+    while !window.should_close() {
+
+        // Time
+        let curr_time = glfw.get_time();
+        let time_delta = curr_time - prev_time;
+
+        frame_counter += 1;
+
+        // Update -- restricting to 60 ups
+        if time_delta >= 1./60. {
+            let input_state = input.snapshot(&mut window, camera.captures_cursor());
+            camera.update(&input_state, time_delta);
+            if !paused {
+                ripple_time += time_delta as f32;
+            }
+
+            window.set_title(format!("{} FPS : {} / MS : {}",WINDOW_TITLE,(1./time_delta as f32)*(frame_counter as f32) , (time_delta as f32/(frame_counter as f32)*1000.)).as_str());
+
+            prev_time = curr_time;
+            frame_counter = 0;
+        }
+
+        // Handle window events
+        for (_, event) in glfw::flush_messages(&events) {
+            // collecting events for the camera's next input snapshot
+            input.handle_event(&event);
+            match event {
+                glfw::WindowEvent::Key(glfw::Key::Escape, _, glfw::Action::Press, _) => {
+                    window.set_should_close(true)
+                }
+                glfw::WindowEvent::Key(glfw::Key::Space, _, glfw::Action::Press, _) => {
+                    paused = !paused;
+                    println!("Paused : {}", paused);
+                }
+                glfw::WindowEvent::Key(glfw::Key::M, _, glfw::Action::Press, _) => {
+                    wireframe_mode = !wireframe_mode;
+                    println!("Wireframe Mode : {}", wireframe_mode);
+                    unsafe { gl::PolygonMode(gl::FRONT_AND_BACK, if wireframe_mode { gl::LINE } else { gl::FILL }); }
+                }
+                glfw::WindowEvent::FramebufferSize(w, h) => unsafe {
+                    gl::Viewport(0, 0, w, h);
+                }
+                _ => {}
+            }
+        }
+
+        // Recalculate the ripples on the cpu, then upload them both ways
+        if !paused {
+            vertices = ripple_vertices(ripple_time);
+        }
+        buffer_mesh.update_vertices(0, &vertices);     // overwrites the buffer in place
+        stream_mesh.write(&vertices, &indices);         // writes the next region, the gpu may still be drawing the others
+
+        // Rendering
+        unsafe {
+            // Clearing the screen
+            gl::ClearColor(0.1, 0.1, 0.1, 1.0);
+            gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+
+            default_shader.use_program();
+
+            // Set transformation matrices
+            camera.set_cam_matrix(&default_shader);
+            default_shader.set_vec3(c_str!("viewPos"), camera.position);    // View position for specular highlights
+            default_shader.set_int(c_str!("hasNormalMatrix"), 0);
+
+            // Set light uniforms - directional light
+            default_shader.set_vec3_values(c_str!("dirLight.ambient"),  0.2, 0.2, 0.2);
+            default_shader.set_vec3_values(c_str!("dirLight.diffuse"),  1.0, 1.0, 1.0);
+            default_shader.set_vec3_values(c_str!("dirLight.specular"),  1.0,1.0,1.0);
+            default_shader.set_vec3(c_str!("dirLight.direction"), glm::vec3(-1.,-1.,-0.5));
+
+            default_shader.set_mat4(c_str!("model"), buffer_matrix);
+            buffer_mesh.draw(&default_shader);
+
+            default_shader.set_mat4(c_str!("model"), stream_matrix);
+            stream_mesh.draw(&default_shader);
+        }
+
+        // Swap front and back buffers
+        window.swap_buffers();
+
+        // Poll for window events
+        glfw.poll_events();
+    }
+}
+
+/// Function to get the vertices of the grid with ripples spreading from its center at a time in seconds
+///
+/// The height is a sine of the distance to the center, the normal comes from its derivatives
+fn ripple_vertices(time : f32) -> Vec<Vertex> {
+    let mut vertices = Vec::with_capacity((GRID_SIZE + 1) * (GRID_SIZE + 1));
+    for row in 0..=GRID_SIZE {
+        for column in 0..=GRID_SIZE {
+            let u = column as f32 / GRID_SIZE as f32;
+            let v = row as f32 / GRID_SIZE as f32;
+            let x = (u - 0.5) * GRID_SCALE;
+            let z = (v - 0.5) * GRID_SCALE;
+
+            let distance = (x * x + z * z).sqrt();
+            let phase = distance * 12. - time * 4.;
+            let amplitude = 0.05 / (1. + distance * 4.);
+            let height = amplitude * phase.sin();
+
+            // d height / d distance, the amplitude falloff is ignored since it is small
+            let slope = amplitude * 12. * phase.cos();
+            let (dx, dz) = if distance > 1e-6 { (slope * x / distance, slope * z / distance) } else { (0., 0.) };
+
+            vertices.push(Vertex {
+                position : glm::vec3(x, height, z),
+                normal : glm::normalize(&glm::vec3(-dx, 1., -dz)),
+                tex_coords : glm::vec2(u, v),
+            });
+        }
+    }
+    vertices
+}
+
+/// Function to get the indices of the grid's quads, two counter-clockwise triangles each seen from above
+fn grid_indices() -> Vec<GLuint> {
+    let stride = GRID_SIZE as GLuint + 1;
+    let mut indices = Vec::with_capacity(GRID_SIZE * GRID_SIZE * 6);
+    for row in 0..GRID_SIZE as GLuint {
+        for column in 0..GRID_SIZE as GLuint {
+            let corner = row * stride + column;
+            indices.extend_from_slice(&[corner, corner + stride, corner + 1, corner + 1, corner + stride, corner + stride + 1]);
+        }
+    }
+    indices
+}
@@ -11,4 +11,7 @@ pub use _4_0_face_culling::*;
 mod _4_e_subdivision;
 pub use _4_e_subdivision::*;
 mod _5_0_framebuffers;
-pub use _5_0_framebuffers::*;
\ No newline at end of file
+pub use _5_0_framebuffers::*;mod _6_0_cubemaps;
+pub use _6_0_cubemaps::*;
+mod _7_0_advanced_data;
+pub use _7_0_advanced_data::*;
@@ -1,11 +1,32 @@
 #version 330 core
-// Struct to hold material data
-// These define the color of the material for each component
+// Struct to hold material data, set by Material::bind
+// Colours multiply their texture, and are used alone if the texture slot is empty
 struct Material {
     sampler2D texture_diffuse0;
     sampler2D texture_specular0;
     sampler2D texture_emissive0;
+    sampler2D texture_normal0;      // height map if bump_scale > 0
+    sampler2D texture_opacity0;
+    sampler2D texture_roughness0;
+    sampler2D texture_metallic0;
+
+    int use_texture_diff;
+    int use_texture_spec;
+    int use_texture_emis;
+    int use_texture_norm;
+    int use_texture_opac;
+    int use_texture_rough;
+    int use_texture_metal;
+
+    vec4 diffuse_color;
+    vec3 specular_color;
+    vec3 emissive_color;
     float shininess;
+    float roughness;
+    float metallic;
+    float bump_scale;
+    int alpha_mode;                 // 0 - opaque, 1 - mask, 2 - blend, see AlphaMode
+    float alpha_cutoff;
 };
 
 // Struct to hold light data
@@ -31,35 +52,91 @@ uniform Material material;
 uniform Light dirLight;
 uniform vec3 viewPos;
 
-vec3 calculateDirectionalLight(Light light, Material material, vec3 viewPos, vec3 Normal, vec3 FragPos, vec2 TexCoord);
+vec3 calculateDirectionalLight(Light light, Material material, vec3 albedo, vec3 viewPos, vec3 norm, vec3 FragPos, vec2 TexCoord);
+vec4 materialAlbedo(Material material, vec2 TexCoord);
+vec3 bumpNormal(Material material, vec3 norm, vec3 FragPos, vec2 TexCoord);
+float roughnessToShininess(float roughness);
 
 void main()
 {
-    if(texture(material.texture_diffuse0, TexCoord).a < 0.5) {
-        discard;
-    } // required for sponza model
+    // Alpha tested materials (sponza's foliage, MTL map_d) are discarded here
+    vec4 albedo = materialAlbedo(material, TexCoord);
+    vec3 norm = bumpNormal(material, normalize(Normal), FragPos, TexCoord);
 
-    vec3 result = calculateDirectionalLight(dirLight, material, viewPos, Normal, FragPos, TexCoord);
-    
-    FragColor = vec4(result, 1.0);
+    vec3 result = calculateDirectionalLight(dirLight, material, albedo.rgb, viewPos, norm, FragPos, TexCoord);
+
+    // Colour textures are sampled as linear (sRGB formats, see TextureOptions::color), gamma encode for the screen
+    FragColor = vec4(pow(result, vec3(1.0 / 2.2)), material.alpha_mode == 2 ? albedo.a : 1.0);
 }
 
-vec3 calculateDirectionalLight(Light light, Material material, vec3 viewPos, vec3 Normal, vec3 FragPos, vec2 TexCoord) {
-    // Diffuse lighting
-    vec3 norm = normalize(Normal);
+vec3 calculateDirectionalLight(Light light, Material material, vec3 albedo, vec3 viewPos, vec3 norm, vec3 FragPos, vec2 TexCoord) {
+    // Specular colour and shininess, metallic surfaces reflect their own colour
+    vec3 specularColor = material.specular_color;
+    if (material.use_texture_spec != 0) {
+        specularColor *= vec3(texture(material.texture_specular0, TexCoord));
+    }
+    if (material.use_texture_metal != 0) {
+        specularColor = mix(specularColor, albedo, material.metallic * texture(material.texture_metallic0, TexCoord).b);
+    }
+    float shininess = material.shininess;
+    if (material.use_texture_rough != 0) {
+        shininess = roughnessToShininess(material.roughness * texture(material.texture_roughness0, TexCoord).g);
+    }
+    vec3 emissive = material.emissive_color;
+    if (material.use_texture_emis != 0) {
+        emissive *= vec3(texture(material.texture_emissive0, TexCoord));
+    }
+
+    // Diffuse lighting, norm is expected to be normalized
     vec3 lightDir = normalize(-light.direction);  // direction is expected to point towards the light
     float diff = max(dot(norm, lightDir), 0.0);   // get angle between light and normal, we don't want values < 0
     // Specular highlights
     vec3 viewDir = normalize(viewPos - FragPos);  // calculate normal against view direction
     vec3 reflectDir = reflect(-lightDir, norm);   // reflect light direction along normal, -1 * since reflect expects vector to point from the source
-    float spec = pow(max(dot(viewDir, reflectDir), 0.0), material.shininess);
+    float spec = pow(max(dot(viewDir, reflectDir), 0.0), shininess);
     
     // Add everything together
-    vec3 ambient = light.ambient * vec3(texture(material.texture_diffuse0, TexCoord));
-    vec3 diffuse = light.diffuse * (diff * vec3(texture(material.texture_diffuse0, TexCoord)));
-    vec3 specular = light.specular * (spec * vec3(texture(material.texture_specular0, TexCoord)));
-    vec3 emissive = vec3(texture(material.texture_emissive0, TexCoord));
+    vec3 ambient = light.ambient * albedo;
+    vec3 diffuse = light.diffuse * (diff * albedo);
+    vec3 specular = light.specular * (spec * specularColor);
     vec3 result = ambient + diffuse + specular + emissive;   // Removing objectColor since color is a part of the material
     
     return result;
-}
\ No newline at end of file
+}
+
+// Diffuse colour and opacity of the fragment, discards it if it fails the alpha test
+vec4 materialAlbedo(Material material, vec2 TexCoord) {
+    vec4 albedo = material.diffuse_color;
+    if (material.use_texture_diff != 0) {
+        albedo *= texture(material.texture_diffuse0, TexCoord);
+    }
+    if (material.use_texture_opac != 0) {
+        albedo.a *= texture(material.texture_opacity0, TexCoord).r;   // MTL map_d, a greyscale mask
+    }
+    if (material.alpha_mode == 1 && albedo.a < material.alpha_cutoff) {
+        discard;    // alpha tested foliage, fences...
+    }
+    return albedo;
+}
+
+// Perturbs the normal with the slopes of a bump (height) map, using screen space derivatives so that no tangents
+// are needed - "Bump Mapping Unparametrized Surfaces on the GPU", Mikkelsen
+vec3 bumpNormal(Material material, vec3 norm, vec3 FragPos, vec2 TexCoord) {
+    if (material.use_texture_norm == 0 || material.bump_scale <= 0.0) {
+        return norm;
+    }
+    float height = material.bump_scale * texture(material.texture_normal0, TexCoord).r;
+    vec3 dpdx = dFdx(FragPos);
+    vec3 dpdy = dFdy(FragPos);
+    vec3 r1 = cross(dpdy, norm);
+    vec3 r2 = cross(norm, dpdx);
+    float det = dot(dpdx, r1);
+    vec3 gradient = sign(det) * (dFdx(height) * r1 + dFdy(height) * r2);
+    return normalize(abs(det) * norm - gradient);
+}
+
+// Same as Material::roughness_to_shininess
+float roughnessToShininess(float roughness) {
+    float alpha = max(roughness * roughness, 0.01);
+    return clamp(2.0 / (alpha * alpha) - 2.0, 1.0, 1024.0);
+}
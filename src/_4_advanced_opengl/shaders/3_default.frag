@@ -42,8 +42,9 @@ void main()
 {
     // !Note! texture unit 0 is expected to have a blank 1x1 texture with alpha 0
     vec4 result = calculateDirectionalLight(dirLight, material, viewPos, Normal, FragPos, TexCoord);
-    
-    FragColor = result;
+
+    // Colour textures are sampled as linear (sRGB formats, see TextureOptions::color), gamma encode for the screen
+    FragColor = vec4(pow(result.rgb, vec3(1.0 / 2.2)), result.a);
 }
 
 vec4 calculateDirectionalLight(Light light, Material material, vec3 viewPos, vec3 Normal, vec3 FragPos, vec2 TexCoord) {
@@ -0,0 +1,18 @@
+#version 330 core
+in vec3 TexCoords;
+
+out vec4 FragColor;
+
+uniform samplerCube skybox;
+uniform int toneMapping;    // 1 for HDR cubemaps - exposure tone mapping
+uniform float exposure;
+
+void main()
+{
+    // Cubemaps are linear (sRGB or float formats), like the models' colour textures
+    vec3 color = texture(skybox, TexCoords).rgb;
+    if (toneMapping == 1) {
+        color = vec3(1.0) - exp(-color * exposure);
+    }
+    FragColor = vec4(pow(color, vec3(1.0 / 2.2)), 1.0);
+}
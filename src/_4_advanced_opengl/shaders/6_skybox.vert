@@ -0,0 +1,13 @@
+#version 330 core
+layout (location = 0) in vec3 aPos;
+
+out vec3 TexCoords;
+
+uniform mat4 camMatrix;     // projection * view without the view's translation
+
+void main()
+{
+    TexCoords = aPos;
+    vec4 pos = camMatrix * vec4(aPos, 1.0);
+    gl_Position = pos.xyww;     // z = w, so the depth is always 1.0 after the perspective divide
+}
@@ -0,0 +1,370 @@
+// Skeletal animation - skeletons, animation clips and poses for matrix palette skinning
+
+// Notes:
+// A clip is sampled into a Pose (a local transform for every joint), poses can be blended, and the skeleton turns a
+// pose into the skinning matrices (joint world matrix * inverse bind matrix) that are uploaded to the vertex shader.
+// Everything here is cpu only, the shader side is jointMatrices in the skinned vertex shader.
+
+use std::ffi::CStr;
+
+use nalgebra_glm as glm;
+
+use crate::scene::Transform;
+use crate::shader::Shader;
+
+/// Maximum number of joints supported by the skinned vertex shader, keep in sync with MAX_JOINTS in the shader
+pub const MAX_JOINTS : usize = 64;
+
+pub struct Joint {
+    pub name : String,
+    pub parent : Option<usize>,     // must come before the joint in Skeleton::joints
+    pub inverse_bind : glm::Mat4,   // transforms a vertex from model space into the joint's space
+    pub rest : Transform,           // local transform used when a clip does not animate the joint
+}
+
+pub struct Skeleton {
+    pub joints : Vec<Joint>,
+}
+
+/// Local transform of every joint in a skeleton
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pose {
+    pub locals : Vec<Transform>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[allow(dead_code)]
+pub enum Interpolation {
+    Step,       // hold the previous keyframe
+    Linear,     // lerp for translation and scale, slerp for rotation
+}
+
+/// Keyframe values of a channel, one per keyframe time
+#[derive(Clone, Debug)]
+#[allow(dead_code)]
+pub enum ChannelValues {
+    Translation(Vec<glm::Vec3>),
+    Rotation(Vec<glm::Quat>),
+    Scale(Vec<glm::Vec3>),
+}
+
+impl ChannelValues {
+    pub fn len(&self) -> usize {
+        match self {
+            ChannelValues::Translation(values) | ChannelValues::Scale(values) => values.len(),
+            ChannelValues::Rotation(values) => values.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Animates one property of one joint
+#[derive(Clone, Debug)]
+pub struct Channel {
+    pub joint : usize,
+    pub times : Vec<f32>,   // in seconds, increasing
+    pub values : ChannelValues,
+    pub interpolation : Interpolation,
+}
+
+#[derive(Clone, Debug)]
+#[allow(dead_code)]
+pub struct AnimationClip {
+    pub name : String,
+    pub duration : f32,
+    pub channels : Vec<Channel>,
+}
+
+impl Skeleton {
+    pub fn new(joints : Vec<Joint>) -> Skeleton {
+        assert!(joints.len() <= MAX_JOINTS, "Skeleton has {} joints, at most {} are supported", joints.len(), MAX_JOINTS);
+        for (i, joint) in joints.iter().enumerate() {
+            if let Some(parent) = joint.parent {
+                assert!(parent < i, "Joint '{}' must come after its parent", joint.name);
+            }
+        }
+        Skeleton { joints }
+    }
+
+    /// Function to get the pose where every joint is at its rest transform
+    pub fn rest_pose(&self) -> Pose {
+        Pose { locals : self.joints.iter().map(|joint| joint.rest).collect() }
+    }
+
+    /// Function to calculate the model space matrix of every joint for a pose
+    pub fn world_matrices(&self, pose : &Pose) -> Vec<glm::Mat4> {
+        let mut world: Vec<glm::Mat4> = Vec::with_capacity(self.joints.len());
+        for (joint, local) in self.joints.iter().zip(&pose.locals) {
+            let parent = joint.parent.map(|parent| world[parent]).unwrap_or_else(glm::Mat4::identity);
+            world.push(parent * local.matrix());
+        }
+        world
+    }
+
+    /// Function to calculate the skinning matrices (matrix palette) for a pose
+    pub fn skinning_matrices(&self, pose : &Pose) -> Vec<glm::Mat4> {
+        self.world_matrices(pose).iter().zip(&self.joints).map(|(world, joint)| world * joint.inverse_bind).collect()
+    }
+}
+
+impl AnimationClip {
+    /// Function to sample the clip at a time, joints without channels keep their rest transform
+    ///
+    /// If looping, time wraps around the clip's duration, otherwise it is clamped to it
+    pub fn sample(&self, skeleton : &Skeleton, time : f32, looping : bool) -> Pose {
+        let time = if looping && self.duration > 0. { time.rem_euclid(self.duration) } else { time.clamp(0., self.duration) };
+
+        let mut pose = skeleton.rest_pose();
+        for channel in &self.channels {
+            // A channel without keyframes leaves the joint in its rest pose
+            if channel.times.is_empty() || channel.values.is_empty() {
+                continue;
+            }
+            let local = &mut pose.locals[channel.joint];
+            match &channel.values {
+                ChannelValues::Translation(values) => local.translation = channel.sample_vec3(values, time),
+                ChannelValues::Scale(values) => local.scale = channel.sample_vec3(values, time),
+                ChannelValues::Rotation(values) => {
+                    let (i, t) = channel.keyframe(time);
+                    let (i, next) = (i.min(values.len() - 1), (i + 1).min(values.len() - 1));
+                    local.rotation = match channel.interpolation {
+                        Interpolation::Step => values[i],
+                        Interpolation::Linear => slerp(&values[i], &values[next], t),
+                    };
+                },
+            }
+        }
+        pose
+    }
+}
+
+impl Channel {
+    /// Function to find the keyframe before time, and how far time is towards the next keyframe (0..1)
+    fn keyframe(&self, time : f32) -> (usize, f32) {
+        // Index of the first keyframe after time
+        let next = self.times.partition_point(|t| *t <= time);
+        if next == 0 {
+            return (0, 0.);
+        }
+        if next == self.times.len() {
+            return (self.times.len() - 1, 0.);
+        }
+        let (t0, t1) = (self.times[next - 1], self.times[next]);
+        (next - 1, (time - t0) / (t1 - t0))
+    }
+
+    /// Function to sample translation or scale values, there must be at least one keyframe
+    ///
+    /// If there are fewer values than times, the last value is held
+    fn sample_vec3(&self, values : &[glm::Vec3], time : f32) -> glm::Vec3 {
+        let (i, t) = self.keyframe(time);
+        let (i, next) = (i.min(values.len() - 1), (i + 1).min(values.len() - 1));
+        match self.interpolation {
+            Interpolation::Step => values[i],
+            Interpolation::Linear => glm::lerp(&values[i], &values[next], t),
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl Pose {
+    /// Function to blend two poses, weight 0 returns a and weight 1 returns b
+    pub fn blend(a : &Pose, b : &Pose, weight : f32) -> Pose {
+        Pose {
+            locals : a.locals.iter().zip(&b.locals).map(|(a, b)| Transform {
+                translation : glm::lerp(&a.translation, &b.translation, weight),
+                rotation : slerp(&a.rotation, &b.rotation, weight),
+                scale : glm::lerp(&a.scale, &b.scale, weight),
+            }).collect()
+        }
+    }
+}
+
+/// Function to spherically interpolate between two rotations, taking the shortest path
+///
+/// Falls back to a normalized lerp when the rotations are almost equal, where slerp is numerically unstable. t outside
+/// 0..1 extrapolates, camera paths rely on it for their spline
+pub fn slerp(a : &glm::Quat, b : &glm::Quat, t : f32) -> glm::Quat {
+    let mut b = *b;
+    let mut cos_theta = glm::quat_dot(a, &b);
+    if cos_theta < 0. {
+        b = -b;
+        cos_theta = -cos_theta;
+    }
+
+    if cos_theta > 0.9995 {
+        return glm::quat_normalize(&(a * (1. - t) + b * t));
+    }
+
+    let theta = cos_theta.acos();
+    let sin_theta = theta.sin();
+    let wa = ((1. - t) * theta).sin() / sin_theta;
+    let wb = (t * theta).sin() / sin_theta;
+    a * wa + b * wb
+}
+
+/// Function to upload skinning matrices to the shader's jointMatrices uniform
+///
+/// **Assumes that the shader program is activated**
+pub unsafe fn set_joint_matrices(shader : &Shader, matrices : &[glm::Mat4]) {
+    unsafe {
+        shader.set_mat4_array(c_str!("jointMatrices"), &matrices[..matrices.len().min(MAX_JOINTS)]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON : f32 = 1e-5;
+
+    fn assert_mat4_eq(a : &glm::Mat4, b : &glm::Mat4) {
+        assert!(a.iter().zip(b.iter()).all(|(x, y)| (x - y).abs() < EPSILON), "{} != {}", a, b);
+    }
+
+    /// Two joint arm, the child is 1 unit along x from its parent
+    fn arm() -> Skeleton {
+        Skeleton::new(vec![
+            Joint {
+                name : "root".into(),
+                parent : None,
+                inverse_bind : glm::Mat4::identity(),
+                rest : Transform::default(),
+            },
+            Joint {
+                name : "forearm".into(),
+                parent : Some(0),
+                inverse_bind : glm::translation(&glm::vec3(-1., 0., 0.)),
+                rest : Transform::from_translation(glm::vec3(1., 0., 0.)),
+            },
+        ])
+    }
+
+    fn clip(interpolation : Interpolation) -> AnimationClip {
+        AnimationClip {
+            name : "wave".into(),
+            duration : 2.,
+            channels : vec![
+                Channel {
+                    joint : 0,
+                    times : vec![0., 2.],
+                    values : ChannelValues::Translation(vec![glm::vec3(0., 0., 0.), glm::vec3(0., 4., 0.)]),
+                    interpolation,
+                },
+                Channel {
+                    joint : 1,
+                    times : vec![0., 1.],
+                    values : ChannelValues::Rotation(vec![
+                        glm::quat_identity(),
+                        glm::quat_angle_axis(f32::to_radians(90.), &glm::Vec3::z()),
+                    ]),
+                    interpolation,
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn rest_pose_skinning_matrices_are_identity() {
+        let skeleton = arm();
+        for matrix in skeleton.skinning_matrices(&skeleton.rest_pose()) {
+            assert_mat4_eq(&matrix, &glm::Mat4::identity());
+        }
+    }
+
+    #[test]
+    fn linear_sampling_interpolates_translation_and_slerps_rotation() {
+        let skeleton = arm();
+        let pose = clip(Interpolation::Linear).sample(&skeleton, 0.5, false);
+
+        assert!((pose.locals[0].translation - glm::vec3(0., 1., 0.)).norm() < EPSILON);
+        // Halfway between 0 and 90 degrees around z
+        let expected = glm::quat_angle_axis(f32::to_radians(45.), &glm::Vec3::z());
+        assert!((pose.locals[1].rotation.coords - expected.coords).norm() < EPSILON);
+        // Translation of the forearm is not animated, it keeps its rest transform
+        assert_eq!(pose.locals[1].translation, glm::vec3(1., 0., 0.));
+    }
+
+    #[test]
+    fn step_sampling_holds_previous_keyframe() {
+        let skeleton = arm();
+        let pose = clip(Interpolation::Step).sample(&skeleton, 0.9, false);
+        assert_eq!(pose.locals[0].translation, glm::vec3(0., 0., 0.));
+        assert_eq!(pose.locals[1].rotation, glm::quat_identity());
+    }
+
+    #[test]
+    fn sampling_loops_or_clamps() {
+        let skeleton = arm();
+        let clip = clip(Interpolation::Linear);
+        assert_eq!(clip.sample(&skeleton, 2.5, true), clip.sample(&skeleton, 0.5, false));
+        assert_eq!(clip.sample(&skeleton, 5., false), clip.sample(&skeleton, 2., false));
+    }
+
+    #[test]
+    fn skinning_matrices_follow_the_hierarchy() {
+        let skeleton = arm();
+        let pose = clip(Interpolation::Linear).sample(&skeleton, 1., false);
+        let matrices = skeleton.skinning_matrices(&pose);
+
+        // Root moved up by 2, forearm rotated 90 degrees around its own origin at (1,2,0) in model space
+        let expected_root = glm::translation(&glm::vec3(0., 2., 0.));
+        let expected_forearm = glm::translation(&glm::vec3(1., 2., 0.))
+            * glm::rotation(f32::to_radians(90.), &glm::Vec3::z())
+            * glm::translation(&glm::vec3(-1., 0., 0.));
+        assert_mat4_eq(&matrices[0], &expected_root);
+        assert_mat4_eq(&matrices[1], &expected_forearm);
+
+        // A vertex at the tip of the forearm (2,0,0) ends up above the elbow
+        let tip = matrices[1] * glm::vec4(2., 0., 0., 1.);
+        assert!((tip.xyz() - glm::vec3(1., 3., 0.)).norm() < EPSILON);
+    }
+
+    #[test]
+    fn blending_poses() {
+        let skeleton = arm();
+        let clip = clip(Interpolation::Linear);
+        let a = clip.sample(&skeleton, 0., false);
+        let b = clip.sample(&skeleton, 1., false);
+
+        assert_eq!(Pose::blend(&a, &b, 0.), a);
+        let halfway = Pose::blend(&a, &b, 0.5);
+        assert!((halfway.locals[0].translation - glm::vec3(0., 1., 0.)).norm() < EPSILON);
+        let expected = glm::quat_angle_axis(f32::to_radians(45.), &glm::Vec3::z());
+        assert!((halfway.locals[1].rotation.coords - expected.coords).norm() < EPSILON);
+    }
+
+    #[test]
+    fn slerp_takes_the_shortest_path() {
+        let a = glm::quat_identity();
+        let b = -glm::quat_angle_axis(f32::to_radians(90.), &glm::Vec3::y());  // same rotation, opposite sign
+        let result = slerp(&a, &b, 0.5);
+        let expected = glm::quat_angle_axis(f32::to_radians(45.), &glm::Vec3::y());
+        assert!((result.coords - expected.coords).norm() < EPSILON);
+    }
+
+    #[test]
+    fn empty_channels_keep_the_rest_pose() {
+        let skeleton = arm();
+        let mut clip = clip(Interpolation::Linear);
+        clip.channels.push(Channel {
+            joint : 1,
+            times : Vec::new(),
+            values : ChannelValues::Translation(Vec::new()),
+            interpolation : Interpolation::Linear,
+        });
+        // Keyframe times without values
+        clip.channels.push(Channel {
+            joint : 0,
+            times : vec![0., 1.],
+            values : ChannelValues::Scale(Vec::new()),
+            interpolation : Interpolation::Step,
+        });
+        let pose = clip.sample(&skeleton, 0.5, true);
+        assert_eq!(pose.locals[1].translation, glm::vec3(1., 0., 0.));
+        assert_eq!(pose.locals[0].scale, glm::vec3(1., 1., 1.));
+    }
+}
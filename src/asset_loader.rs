@@ -0,0 +1,278 @@
+// Background model loading - parsing and image decoding on worker threads, uploads spread over frames
+
+// Notes:
+// OpenGL calls can only be made on the thread owning the context, so the work is split in two. A worker thread parses
+// the OBJ/MTL (Model::parse_obj, or its mesh cache) and decodes every texture, using a few more threads for the images. Results are sent
+// back over a channel. ModelLoader::update is called once per frame on the GL thread, and uploads as many textures and
+// meshes as fit in the given time budget, so the window keeps responding while a large model like Sponza loads.
+
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::material::Material;
+use crate::mesh_cache;
+use crate::mesh::{InvalidMeshPolicy, Mesh};
+use crate::model::{Model, ModelError, ObjData};
+use crate::texture::{Texture, TextureCache, TextureData, TextureOptions};
+
+/// Messages sent from the worker threads to the GL thread
+enum WorkerMessage {
+    Parsed(ObjData, usize),                 // data and the number of textures that will be decoded
+    ParseFailed(ModelError),
+    Image(String, TextureOptions, Result<TextureData, String>),
+}
+
+/// What a ModelLoader is currently waiting for
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoadStage {
+    Parsing,
+    Textures,   // decoding and uploading textures
+    Meshes,     // uploading meshes
+    Done,
+}
+
+/// Loads an OBJ model in the background, see the notes at the top of the file
+pub struct ModelLoader {
+    path : String,
+    receiver : Receiver<WorkerMessage>,
+    model : Model,
+    data : Option<ObjData>,
+    textures_total : usize,
+    decoded : Vec<(String, TextureOptions, TextureData)>,       // waiting for upload
+    textures : HashMap<(String, TextureOptions), Texture>,      // uploaded
+    materials : Vec<Rc<Material>>,              // created once every texture is uploaded
+    meshes_total : usize,
+    stage : LoadStage,
+}
+
+impl ModelLoader {
+    /// Function to start loading an OBJ model, returns immediately
+    pub fn new(path : &str, policy : InvalidMeshPolicy) -> ModelLoader {
+        println!("Loading model in the background from {}", path);
+        let (sender, receiver) = mpsc::channel();
+        let worker_path = path.to_string();
+        thread::spawn(move || load_worker(worker_path, policy, sender));
+
+        let mut model = Model::new();
+        model.invalid_mesh_policy = policy;
+        ModelLoader {
+            path : path.into(),
+            receiver,
+            model,
+            data : None,
+            textures_total : 0,
+            decoded : Vec::new(),
+            textures : HashMap::new(),
+            materials : Vec::new(),
+            meshes_total : 0,
+            stage : LoadStage::Parsing,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn stage(&self) -> LoadStage {
+        self.stage
+    }
+
+    /// Function to get how much of the model is loaded, from 0 to 1
+    ///
+    /// Parsing counts as one step, then each texture decode, texture upload and mesh upload counts as one step
+    pub fn progress(&self) -> f32 {
+        match self.stage {
+            LoadStage::Parsing => return 0.,
+            LoadStage::Done => return 1.,
+            _ => {},
+        }
+        let total = 1 + self.textures_total * 2 + self.meshes_total;
+        let done = 1 + (self.textures.len() + self.decoded.len()) + self.textures.len() + self.model.meshes.len();
+        (done as f32 / total as f32).min(1.)
+    }
+
+    /// Function to receive results from the workers and upload them, call once per frame on the GL thread
+    ///
+    /// Uploads stop once budget is used up, but at least one item is uploaded per call.
+    /// Returns the model once everything is uploaded, None before that and after the model was returned
+    pub fn update(&mut self, budget : Duration) -> Result<Option<Model>, ModelError> {
+        if self.stage == LoadStage::Done {
+            return Ok(None);
+        }
+        let start = Instant::now();
+        self.receive()?;
+
+        // Textures first, materials need all of them
+        while self.stage == LoadStage::Textures {
+            if let Some((path, options, data)) = self.decoded.pop() {
+                // The texture may have been loaded by another model in the meantime
+                let directory = &self.data.as_ref().unwrap().directory;
+                let key = TextureCache::canonical_key(&path, directory);
+                let texture = TextureCache::with_global(|cache| {
+                    cache.get(&key, options).unwrap_or_else(|| cache.insert_data(&key, options, &data))
+                });
+                self.textures.insert((path, options), texture);
+            }
+            if self.textures.len() == self.textures_total {
+                self.create_materials();
+                self.stage = LoadStage::Meshes;
+            }
+            if start.elapsed() >= budget || self.decoded.is_empty() {
+                break;
+            }
+        }
+
+        while self.stage == LoadStage::Meshes {
+            let data = self.data.as_mut().unwrap();
+            let Some(mesh) = data.meshes.pop() else {
+                self.stage = LoadStage::Done;
+                break;
+            };
+            // Ids without a material (missing MTL) use the default material at the end
+            let material = mesh.material.and_then(|material_id| self.materials.get(material_id)).unwrap_or(self.materials.last().unwrap()).clone();
+            let mut gpu_mesh = Mesh::new(mesh.vertices, mesh.indices, material);
+            gpu_mesh.has_tex_coords = mesh.has_tex_coords;
+            self.model.meshes.push(gpu_mesh);
+            if start.elapsed() >= budget {
+                break;
+            }
+        }
+
+        if self.stage == LoadStage::Meshes && self.data.as_ref().unwrap().meshes.is_empty() {
+            self.stage = LoadStage::Done;
+        }
+        if self.stage == LoadStage::Done {
+            println!("Finished loading model from {}", self.path);
+            return Ok(Some(std::mem::replace(&mut self.model, Model::new())));
+        }
+        Ok(None)
+    }
+
+    fn receive(&mut self) -> Result<(), ModelError> {
+        loop {
+            match self.receiver.try_recv() {
+                Ok(WorkerMessage::Parsed(mut data, textures_total)) => {
+                    // Meshes are popped from the back while uploading, reverse to keep the file order
+                    data.meshes.reverse();
+                    self.meshes_total = data.meshes.len();
+                    self.textures_total = textures_total;
+                    self.data = Some(data);
+                    self.stage = LoadStage::Textures;
+                },
+                Ok(WorkerMessage::ParseFailed(error)) => {
+                    self.stage = LoadStage::Done;
+                    return Err(error);
+                },
+                Ok(WorkerMessage::Image(path, options, Ok(data))) => self.decoded.push((path, options, data)),
+                Ok(WorkerMessage::Image(path, options, Err(error))) => {
+                    println!("Warning: failed to load texture {} ({}), using a fallback texture", path, error);
+                    self.textures.insert((path, options), TextureCache::with_global(|cache| cache.fallback()));
+                },
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    // Workers exit after sending everything, so this is only an error if something is missing
+                    let missing = match self.stage {
+                        LoadStage::Parsing => true,
+                        LoadStage::Textures => self.textures.len() + self.decoded.len() < self.textures_total,
+                        LoadStage::Meshes | LoadStage::Done => false,
+                    };
+                    if missing {
+                        self.stage = LoadStage::Done;
+                        return Err(ModelError::WorkerStopped { path : self.path.clone() });
+                    }
+                    break;
+                },
+            }
+        }
+        Ok(())
+    }
+
+    /// Function to create the materials from the uploaded textures, plus a default material at the end
+    fn create_materials(&mut self) {
+        let data = self.data.as_ref().unwrap();
+        self.materials = data.materials.iter().map(|material| {
+            Rc::new(Model::create_material(material, |path, options| self.textures[&(path.to_string(), options)].clone()))
+        }).collect();
+        self.materials.push(Rc::new(Material::default()));
+    }
+}
+
+/// Function run on the worker thread - parses the model, then decodes its textures on several threads
+fn load_worker(path : String, policy : InvalidMeshPolicy, sender : Sender<WorkerMessage>) {
+    let data = match mesh_cache::load_obj_cached(&path, policy) {
+        Ok(data) => data,
+        Err(error) => {
+            let _ = sender.send(WorkerMessage::ParseFailed(error));
+            return;
+        }
+    };
+
+    // Each texture is decoded once, even if several materials use it
+    let mut paths: Vec<(String, TextureOptions)> = Vec::new();
+    for material in &data.materials {
+        for (slot, path) in Model::material_textures(material) {
+            let options = TextureOptions::for_slot(slot);
+            if !paths.iter().any(|(p, o)| *p == path && *o == options) {
+                paths.push((path, options));
+            }
+        }
+    }
+    let directory = data.directory.clone();
+    if sender.send(WorkerMessage::Parsed(data, paths.len())).is_err() {
+        return; // loader was dropped
+    }
+
+    // Threads take the next path until none are left
+    let next = AtomicUsize::new(0);
+    let threads = thread::available_parallelism().map_or(1, |n| n.get()).min(paths.len());
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            let sender = sender.clone();
+            let (next, paths, directory) = (&next, &paths, &directory);
+            scope.spawn(move || loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let Some((path, options)) = paths.get(i) else {
+                    break;
+                };
+                let data = TextureData::read(path, directory, options.flip).map_err(|error| error.to_string());
+                if sender.send(WorkerMessage::Image(path.clone(), *options, data)).is_err() {
+                    break;
+                }
+            });
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Loader whose worker has already exited without sending anything
+    fn stopped_loader(stage : LoadStage, textures_total : usize) -> ModelLoader {
+        let (sender, receiver) = mpsc::channel();
+        drop(sender);
+        ModelLoader {
+            path : "model.obj".into(),
+            receiver,
+            model : Model::new(),
+            data : None,
+            textures_total,
+            decoded : Vec::new(),
+            textures : HashMap::new(),
+            materials : Vec::new(),
+            meshes_total : 0,
+            stage,
+        }
+    }
+
+    #[test]
+    fn stopped_worker_is_an_error() {
+        for stage in [LoadStage::Parsing, LoadStage::Textures] {
+            let mut loader = stopped_loader(stage, 2);
+            assert!(matches!(loader.update(Duration::ZERO), Err(ModelError::WorkerStopped { .. })));
+            assert_eq!(loader.stage(), LoadStage::Done);
+            assert!(matches!(loader.update(Duration::ZERO), Ok(None)));
+        }
+    }
+}
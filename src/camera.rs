@@ -6,34 +6,66 @@
 
 // update_cam_matrix does calculcate the projection matrix every frame, but this can be changed by screen or user
 // input. Not sure if its worth it to spearate it and only update if necessary (aspect, fov change)
+// The shape of the view is a Projection (perspective, orthographic or off-axis), see projection.rs
+// Cameras never touch the window, they read an InputState snapshot of keys, mouse and resizes, see input.rs
+
+// QuatCamera stores its orientation as a quaternion instead of euler angles. Mouse and keyboard rotations are
+// applied around the camera's own axes, so pitch and yaw follow the current roll and there is no gimbal lock at
+// +-90 degrees pitch. The angles passed to new() use the same convention as FreeCamera (yaw -90 looks down -z).
+
+// OrbitCamera rotates around a target instead of around itself, for inspecting models. Its position is derived from
+// the target, the distance and the yaw/pitch angles, and the mouse moves the cursor freely instead of being
+// warped to the center of the window.
+
+// FreeCamera records and plays back camera paths (see camera_path.rs) for repeatable flythroughs : Shift+P starts
+// and stops recording to path_file, P plays it back. While playing, the mouse and keys only control the playback.
 
-use glfw;
 use nalgebra_glm as glm;
 use std::ffi::CStr;
+use crate::camera_path::{CameraPath, CameraPlayer, CameraRecorder};
+use crate::input::{InputState, Key, MouseButton};
+use crate::mesh::Bounds;
+use crate::model::Model;
+use crate::projection::Projection;
 use crate::shader::Shader;
 
 pub trait Camera {
     // Create and init a new camera
     fn new(position : glm::Vec3, roll : f32, pitch : f32, yaw : f32,  width: u32, height : u32) -> Self;
-    // Handle window events
-    fn handle_window_event(&mut self, event : &glfw::WindowEvent, time_delta : &f64);
     // Used to update the shader's camMatrix
     fn set_cam_matrix(&mut self, shader : &Shader);
-    // Main update function
-    fn update(&mut self, window : &mut glfw::PWindow, time_delta : f64);
+    // Main update function, input is everything since the previous update, see input.rs
+    fn update(&mut self, input : &InputState, time_delta : f64);
     // Update camera's cam_matrix
     fn update_cam_matrix(&mut self, calc_dir : bool);
     // Update camera's direction
     fn update_cam_direction(&mut self);
     // Force update to shader once
     fn force_set_cam_matrix(&mut self, shader : &Shader);
+    // Get the current view matrix
+    fn view_matrix(&self) -> glm::Mat4;
+    // Get the current projection matrix
+    fn projection_matrix(&self) -> glm::Mat4;
+
+    /// Function to get projection * view without the view's translation, for skyboxes that should never get closer
+    fn rotation_cam_matrix(&self) -> glm::Mat4 {
+        self.projection_matrix() * glm::mat3_to_mat4(&glm::mat4_to_mat3(&self.view_matrix()))
+    }
+
+    /// Function to check if the cursor should be hidden and kept in the window, e.g. while the mouse rotates the camera
+    fn captures_cursor(&self) -> bool {
+        false
+    }
 }
 
 /// Free Camera
 /// updates are handled internally using camera.update
 /// WASD to move, QE to rotate, Mouse to pitch/yaw while holding right click
 /// Shift to speed up, scroll to zoom in/out
+/// Shift+P to start/stop recording a camera path, P to play it back or stop, while playing L toggles looping and
+/// scroll changes the speed
 /// Limitation: Cannot pitch/yaw when roll != 0. since the mouse input is added to pitch/yaw and does not account for the current roll
+/// see QuatCamera for a camera without this limitation
 pub struct FreeCamera {
     // Current Screen Info
     pub width : u32,
@@ -49,6 +81,8 @@ pub struct FreeCamera {
     pub direction : glm::Vec3,
     pub up : glm::Vec3,
     cam_matrix : glm::Mat4, // projection * view
+    view : glm::Mat4,
+    projection : glm::Mat4,
 
     // Movement speed
     pub speed : f32,
@@ -57,11 +91,17 @@ pub struct FreeCamera {
     sensitivity : f32,
     zoom_sensitivity : f32,
 
-    // Perspective
-    fov : f32,
+    // Projection, perspective by default
+    projection_mode : Projection,
     pub near_plane : f32,
     pub far_plane : f32,
 
+    // Camera paths
+    pub path_file : String,             // file Shift+P records to and P plays back
+    pub keyframe_interval : f32,        // seconds between recorded keyframes
+    recorder : Option<CameraRecorder>,
+    player : Option<CameraPlayer>,
+
     // Internal flags
     first_click : bool,             // is this the first click?
     calculate_cam_matrix : bool,    // should matrix be recalculated?
@@ -72,6 +112,86 @@ pub struct FreeCamera {
     // or with a sepratae event handler
 }
 
+impl FreeCamera {
+    #[allow(dead_code)]
+    pub fn projection(&self) -> Projection {
+        self.projection_mode
+    }
+
+    /// Function to change the projection, e.g. to an orthographic view, the matrices are updated on the next use
+    #[allow(dead_code)]
+    pub fn set_projection(&mut self, projection : Projection) {
+        self.projection_mode = projection;
+        self.calculate_cam_matrix = true;
+    }
+
+    /// Function to get the camera's orientation as it is rendered
+    pub fn orientation(&self) -> glm::Quat {
+        orientation_from_axes(&self.direction, &self.up).unwrap_or_else(|| orientation_from_euler(self.roll, self.pitch, self.yaw))
+    }
+
+    /// Function to move the camera to a position and orientation, the angles are updated to match
+    pub fn set_pose(&mut self, position : glm::Vec3, orientation : glm::Quat) {
+        (self.roll, self.pitch, self.yaw) = euler_from_orientation(&orientation);
+        self.position = position;
+        self.direction = glm::quat_rotate_vec3(&orientation, &-glm::Vec3::z());
+        self.up = glm::quat_rotate_vec3(&orientation, &glm::Vec3::y());
+        self.calculate_cam_matrix = true;
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recorder.is_some()
+    }
+
+    #[allow(dead_code)]
+    pub fn is_playing(&self) -> bool {
+        self.player.is_some()
+    }
+
+    /// Function to start recording a camera path, or to stop and save it to path_file
+    pub fn toggle_recording(&mut self) {
+        match self.recorder.take() {
+            None => {
+                self.player = None;
+                self.recorder = Some(CameraRecorder::new(self.keyframe_interval));
+                println!("Recording camera path, Shift+P to stop");
+            },
+            Some(recorder) => {
+                let path = recorder.finish(self.position, self.orientation());
+                match path.save(&self.path_file) {
+                    Ok(()) => println!("Saved {} keyframes ({:.1}s) to {}", path.keyframes.len(), path.duration(), self.path_file),
+                    Err(error) => println!("Warning: could not save camera path to {} : {}", self.path_file, error),
+                }
+            },
+        }
+    }
+
+    /// Function to play back path_file, or to stop playing
+    pub fn toggle_playback(&mut self) {
+        if self.player.take().is_some() {
+            println!("Stopped camera path playback");
+            return;
+        }
+        if self.is_recording() {
+            self.toggle_recording();
+        }
+        match CameraPath::load(&self.path_file) {
+            Ok(path) if !path.keyframes.is_empty() => {
+                println!("Playing {} ({:.1}s), L to toggle looping, scroll to change the speed", self.path_file, path.duration());
+                self.play(path);
+            },
+            Ok(_) => println!("Warning: camera path {} has no keyframes", self.path_file),
+            Err(error) => println!("Warning: could not load camera path {} : {}", self.path_file, error),
+        }
+    }
+
+    /// Function to play a path back, returns the player to set its speed and looping
+    pub fn play(&mut self, path : CameraPath) -> &mut CameraPlayer {
+        self.recorder = None;
+        self.player.insert(CameraPlayer::new(path))
+    }
+}
+
 impl Camera for FreeCamera {
 
     // Returns a fly camera with all fields initialized
@@ -90,31 +210,6 @@ impl Camera for FreeCamera {
         cam
     }
     
-    /// Function to handle all window events
-    /// Resize window, scroll etc. This can be used for direction as well
-    /// Note : framebuffer and scroll polling has to be enabled
-    fn handle_window_event(&mut self, event : &glfw::WindowEvent, time_delta : &f64) {
-        match event {
-            // Update width/height when frame buffer changes
-            glfw::WindowEvent::FramebufferSize(width, height) => {
-                self.width = *width as u32;
-                self.height = *height as u32;
-                self.calculate_cam_matrix = true;
-            },
-
-            // Zoom in/out when user scrolls
-            glfw::WindowEvent::Scroll(_x_offset, y_offset) => {
-                self.fov -= *y_offset as f32 * *time_delta as f32 * self.zoom_sensitivity;
-                if self.fov <= 1.0 { self.fov = 1.0; }
-                if self.fov >= 90.0 { self.fov = 90.0; }
-                self.calculate_cam_matrix = true;
-            },
-            
-            // Could implement direction here
-            _ => {}
-        }
-    }
-
     /// Function to set the camera's cam_matrix to the shader's camMatrix
     /// ! This activates the shader
     fn set_cam_matrix(&mut self, shader : &Shader) {
@@ -134,7 +229,7 @@ impl Camera for FreeCamera {
     }
     
     /// Function to handle all updates to the camera
-    fn update(&mut self, window : &mut glfw::PWindow, time_delta : f64) {
+    fn update(&mut self, input : &InputState, time_delta : f64) {
         let mut update_speed = self.speed * time_delta as f32;
         let mut update_roll_speed = self.roll_speed * time_delta as f32;
         let update_sensitivty = self.sensitivity * time_delta as f32;
@@ -143,70 +238,90 @@ impl Camera for FreeCamera {
         self.is_matrix_updated = false;
         self.calculate_cam_matrix = false;
 
-        // Shift - speed multiplier for position/roll
-        if window.get_key(glfw::Key::LeftShift) == glfw::Action::Press {
-            update_speed *= self.shift_multiplier;
-            update_roll_speed *= self.shift_multiplier;
+        // Update width/height when frame buffer changes
+        // A minimized window has no size, keep the last one so the aspect ratio stays valid
+        if let Some((width, height)) = input.resized && width > 0 && height > 0 {
+            self.width = width;
+            self.height = height;
+            self.calculate_cam_matrix = true;
         }
 
-        // Debug - Print current position
-        if window.get_key(glfw::Key::P) == glfw::Action::Press && window.get_key(glfw::Key::LeftShift) == glfw::Action::Press {
-            println!("Position: {:?}, Pitch: {}, Roll: {}, Yaw: {}", self.position, self.pitch, self.roll, self.yaw);
+        // Camera paths - Shift+P records, P plays back
+        if input.key_pressed(Key::P) {
+            if input.key_down(Key::LeftShift) {
+                self.toggle_recording();
+            }
+            else {
+                self.toggle_playback();
+            }
+        }
+
+        // Playback replaces the user's input, L toggles looping and scroll changes the speed
+        if let Some(player) = &mut self.player {
+            if input.key_pressed(Key::L) {
+                player.looping = !player.looping;
+                println!("Looping: {}", player.looping);
+            }
+            if input.scroll.y != 0. {
+                player.speed = (player.speed * 1.25_f32.powf(input.scroll.y)).clamp(0.05, 20.);
+                println!("Playback speed: {:.2}", player.speed);
+            }
+            match player.advance(time_delta as f32) {
+                Some((position, orientation)) => self.set_pose(position, orientation),
+                None => {
+                    self.player = None;
+                    println!("Camera path playback finished");
+                },
+            }
+            if self.calculate_cam_matrix {
+                self.update_cam_matrix(false);
+            }
+            return;
         }
 
-        // Direction - pitch and yaw from mouse
+        // Zoom in/out when user scrolls
+        if input.scroll.y != 0. {
+            self.projection_mode.zoom(input.scroll.y * time_delta as f32 * self.zoom_sensitivity);
+            self.calculate_cam_matrix = true;
+        }
 
-        // This can be done using setMouseCallback instead as it is in learnopengl
-        // but this done with global variables in the example and im not sure how to do that
-        // in rust, since the callback may outlive the main function
+        // Shift - speed multiplier for position/roll
+        if input.key_down(Key::LeftShift) {
+            update_speed *= self.shift_multiplier;
+            update_roll_speed *= self.shift_multiplier;
+        }
 
-        // This can be done using events, but i'm leaving it here to refer lated
-        // it would look exactly like the scroll part of the handler
-        if window.get_mouse_button(glfw::MouseButtonRight) == glfw::Action::Press {
+        // Direction - pitch and yaw from mouse, the cursor is captured while the right button is held
+        if input.button_down(MouseButton::Right) {
             // Reset roll, due to limitation - would have to account for current roll when changing pitch/yaw
             self.roll = 0.;
 
-            // Do nothing on the first click
+            // Do nothing on the first click, the cursor is only captured from the next update
             if self.first_click {
-                // Hide cursor
-                window.set_cursor_mode(glfw::CursorMode::Hidden);
-                // Reset cursor position
-                window.set_cursor_pos(self.width as f64/2., self.height as f64/2.);
                 self.first_click = false;
-                return;
             }
+            else {
+                // Rotate pitch (up and down) and yaw (left and right) using the cursor's movement
+                self.pitch -= update_sensitivty * input.cursor_delta.y / self.height as f32;
+                self.yaw += update_sensitivty * input.cursor_delta.x / self.width as f32;
 
-            // Get mouse cursor position
-            let (mouse_x, mouse_y) = window.get_cursor_pos();
-
-            // Rotate pitch (up and dow) using cursor's y pos
-            self.pitch -= update_sensitivty * (mouse_y as f32 - (self.height as f32 / 2.))/self.height as f32;
-
-            // Rotate yaw (left and right) using cursor's x pos
-            self.yaw += update_sensitivty * (mouse_x as f32 - (self.width as f32 / 2.))/self.width as f32;
-
-            // Restricting pitch, since going beyond 90 inverts the mouse
-            if self.pitch > 85. { self.pitch = 85. };
-            if self.pitch < -85. { self.pitch = -85. };
+                // Restricting pitch, since going beyond 90 inverts the mouse
+                self.pitch = self.pitch.clamp(-85., 85.);
+            }
 
             // Recalculate view matrix and update camera's direction vectors
             self.calculate_cam_matrix = true;
-
-            // Reset cursor back to center of screen
-            window.set_cursor_pos(self.width as f64/2., self.height as f64/2.);
         }
-        if window.get_mouse_button(glfw::MouseButtonRight) == glfw::Action::Release {
-            // Show cursor
-            window.set_cursor_mode(glfw::CursorMode::Normal);
+        else {
             self.first_click = true;
         }
 
         // Direction - roll from keyboard inputs
-        if window.get_key(glfw::Key::Q) == glfw::Action::Press {
+        if input.key_down(Key::Q) {
             self.calculate_cam_matrix = true;
             self.roll -= update_roll_speed;
         }
-        if window.get_key(glfw::Key::E) == glfw::Action::Press {
+        if input.key_down(Key::E) {
             self.calculate_cam_matrix = true;
             self.roll += update_roll_speed;
         }
@@ -217,19 +332,19 @@ impl Camera for FreeCamera {
         }
 
         // Position - from keyboard inputs
-        if window.get_key(glfw::Key::W) == glfw::Action::Press {
+        if input.key_down(Key::W) {
             self.calculate_cam_matrix = true;
             self.position += update_speed * self.direction;
         }
-        if window.get_key(glfw::Key::S) == glfw::Action::Press {
+        if input.key_down(Key::S) {
             self.calculate_cam_matrix = true;
             self.position -= update_speed * self.direction;
         }
-        if window.get_key(glfw::Key::A) == glfw::Action::Press {
+        if input.key_down(Key::A) {
             self.calculate_cam_matrix = true;
             self.position -= update_speed * glm::normalize(&glm::cross(&self.direction, &self.up));
         }
-        if window.get_key(glfw::Key::D) == glfw::Action::Press {
+        if input.key_down(Key::D) {
             self.calculate_cam_matrix = true;
             self.position += update_speed * glm::normalize(&glm::cross(&self.direction, &self.up));
         }
@@ -238,6 +353,18 @@ impl Camera for FreeCamera {
         if self.calculate_cam_matrix {
             self.update_cam_matrix(false); // not calculating dir again
         }
+
+        // Record the pose as it is rendered
+        if self.recorder.is_some() {
+            let orientation = self.orientation();
+            if let Some(recorder) = &mut self.recorder {
+                recorder.record(time_delta as f32, self.position, orientation);
+            }
+        }
+    }
+
+    fn captures_cursor(&self) -> bool {
+        !self.first_click
     }
 
     /// Function to update the camera's direction
@@ -264,9 +391,11 @@ impl Camera for FreeCamera {
 
         // Calculating view and projection matrices
         let view = glm::look_at_rh(&self.position, &(self.position + self.direction), &self.up); 
-        let projection = glm::perspective(self.width as f32/self.height as f32, f32::to_radians(self.fov), self.near_plane, self.far_plane);
+        let projection = self.projection_mode.matrix(self.width as f32/self.height as f32, self.near_plane, self.far_plane);
 
         self.cam_matrix = projection * view;
+        self.view = view;
+        self.projection = projection;
         self.is_matrix_updated = true;
         self.calculate_cam_matrix = false;
     }
@@ -276,6 +405,14 @@ impl Camera for FreeCamera {
         self.set_cam_matrix(shader);
         self.calculate_cam_matrix = false;
     }
+
+    fn view_matrix(&self) -> glm::Mat4 {
+        self.view
+    }
+
+    fn projection_matrix(&self) -> glm::Mat4 {
+        self.projection
+    }
 }
 
 impl Default for FreeCamera {
@@ -296,15 +433,917 @@ impl Default for FreeCamera {
             height : 100,
             near_plane : 0.1,
             far_plane : 100.,
-            fov : 45.,
+            projection_mode : Projection::default(),
+
+            path_file : String::from("camera_path.txt"),
+            keyframe_interval : 0.25,
+            recorder : None,
+            player : None,
             
             first_click: true,
             calculate_cam_matrix : true,
             is_matrix_updated : true,
             
             cam_matrix : glm::Mat4::identity(),
+            view : glm::Mat4::identity(),
+            projection : glm::Mat4::identity(),
             direction : glm::vec3(0.,0.,0.),
             up : glm::vec3(0., 0., 0.),
         }
     }
-}
\ No newline at end of file
+}
+/// How QuatCamera moves
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FlightMode {
+    #[default]
+    Free,       // WASD moves in the view direction and sideways, R levels the horizon
+    SixDof,     // also Space/Left Control to move along the camera's up, for flying through scenes
+}
+
+/// Free Camera with a quaternion orientation
+/// updates are handled internally using camera.update
+/// WASD to move, QE to roll, Mouse to pitch/yaw while holding right click, relative to the current roll
+/// Space/Left Control to move up/down in FlightMode::SixDof, R to level the horizon
+/// Shift to speed up, scroll to zoom in/out
+pub struct QuatCamera {
+    // Current Screen Info
+    pub width : u32,
+    pub height : u32,
+
+    // Current Position and Orientation
+    pub position : glm::Vec3,
+    orientation : glm::Quat,    // rotation from camera space (looking down -z, y up) to world space
+    pub mode : FlightMode,
+
+    // Derived from the orientation, updated with the matrices
+    pub direction : glm::Vec3,
+    pub up : glm::Vec3,
+    pub right : glm::Vec3,
+    cam_matrix : glm::Mat4, // projection * view
+    view : glm::Mat4,
+    projection : glm::Mat4,
+
+    // Movement speed
+    pub speed : f32,
+    shift_multiplier : f32,
+    roll_speed : f32,
+    sensitivity : f32,
+    zoom_sensitivity : f32,
+
+    // Projection, perspective by default
+    projection_mode : Projection,
+    pub near_plane : f32,
+    pub far_plane : f32,
+
+    // Internal flags
+    first_click : bool,             // is this the first click?
+    calculate_cam_matrix : bool,    // should matrix be recalculated?
+    pub is_matrix_updated : bool    // should matrix be updated in the shader?
+}
+
+#[allow(dead_code)]
+impl QuatCamera {
+    pub fn projection(&self) -> Projection {
+        self.projection_mode
+    }
+
+    /// Function to change the projection, e.g. to an orthographic view, the matrices are updated on the next use
+    pub fn set_projection(&mut self, projection : Projection) {
+        self.projection_mode = projection;
+        self.calculate_cam_matrix = true;
+    }
+
+    pub fn orientation(&self) -> glm::Quat {
+        self.orientation
+    }
+
+    /// Function to set the orientation, it is normalized
+    pub fn set_orientation(&mut self, orientation : glm::Quat) {
+        self.orientation = glm::quat_normalize(&orientation);
+        self.calculate_cam_matrix = true;
+    }
+
+    /// Function to rotate around the camera's own axes, angles in degrees
+    /// yaw > 0 turns right, pitch > 0 looks up, roll > 0 rolls like FreeCamera's roll
+    pub fn rotate(&mut self, yaw : f32, pitch : f32, roll : f32) {
+        self.set_orientation(rotate_local(&self.orientation, yaw.to_radians(), pitch.to_radians(), roll.to_radians()));
+    }
+
+    /// Function to remove the roll, keeping the view direction
+    pub fn level(&mut self) {
+        self.set_orientation(level_orientation(&self.orientation));
+    }
+}
+
+impl Camera for QuatCamera {
+
+    // Returns a quaternion camera with all fields initialized, angles as in FreeCamera
+    fn new(position : glm::Vec3, roll : f32, pitch : f32, yaw : f32, width : u32, height : u32) -> QuatCamera {
+        let mut cam = QuatCamera {
+            position,
+            orientation : orientation_from_euler(roll, pitch, yaw),
+            width,
+            height,
+            ..Default::default()
+        };
+        cam.update_cam_matrix(true);
+        cam.calculate_cam_matrix = false;
+        cam
+    }
+
+    /// Function to set the camera's cam_matrix to the shader's camMatrix
+    /// ! This activates the shader
+    fn set_cam_matrix(&mut self, shader : &Shader) {
+        if self.calculate_cam_matrix {
+            self.update_cam_matrix(true);
+        }
+        if self.is_matrix_updated {
+            unsafe {
+                shader.use_program();
+                shader.set_mat4(c_str!("camMatrix"), self.cam_matrix);
+            }
+        }
+    }
+
+    /// Function to handle all updates to the camera
+    fn update(&mut self, input : &InputState, time_delta : f64) {
+        let mut update_speed = self.speed * time_delta as f32;
+        let mut update_roll_speed = self.roll_speed * time_delta as f32;
+        let update_sensitivty = self.sensitivity * time_delta as f32;
+
+        // Resetting flag here to allow callling set_cam_matrix for multiple shaders
+        self.is_matrix_updated = false;
+        self.calculate_cam_matrix = false;
+
+        // Update width/height when frame buffer changes, ignoring a minimized window
+        if let Some((width, height)) = input.resized && width > 0 && height > 0 {
+            self.width = width;
+            self.height = height;
+            self.calculate_cam_matrix = true;
+        }
+
+        // Zoom in/out when user scrolls
+        if input.scroll.y != 0. {
+            self.projection_mode.zoom(input.scroll.y * time_delta as f32 * self.zoom_sensitivity);
+            self.calculate_cam_matrix = true;
+        }
+
+        // Shift - speed multiplier for position/roll
+        if input.key_down(Key::LeftShift) {
+            update_speed *= self.shift_multiplier;
+            update_roll_speed *= self.shift_multiplier;
+        }
+
+        // Direction - pitch and yaw from mouse, around the camera's axes so the current roll is kept
+        if input.button_down(MouseButton::Right) {
+            // Do nothing on the first click, the cursor is only captured from the next update
+            if self.first_click {
+                self.first_click = false;
+            }
+            else {
+                let yaw = update_sensitivty * input.cursor_delta.x / self.width as f32;
+                let pitch = -update_sensitivty * input.cursor_delta.y / self.height as f32;
+                if yaw != 0. || pitch != 0. {
+                    self.rotate(yaw, pitch, 0.);
+                }
+            }
+        }
+        else {
+            self.first_click = true;
+        }
+
+        // Direction - roll from keyboard inputs, R to level
+        if input.key_down(Key::Q) {
+            self.rotate(0., 0., -update_roll_speed);
+        }
+        if input.key_down(Key::E) {
+            self.rotate(0., 0., update_roll_speed);
+        }
+        if input.key_down(Key::R) {
+            self.level();
+        }
+
+        // Direction - calculate, used by movement input
+        if self.calculate_cam_matrix {
+            self.update_cam_direction();
+        }
+
+        // Position - from keyboard inputs
+        let mut movement = glm::Vec3::zeros();
+        if input.key_down(Key::W) { movement += self.direction; }
+        if input.key_down(Key::S) { movement -= self.direction; }
+        if input.key_down(Key::A) { movement -= self.right; }
+        if input.key_down(Key::D) { movement += self.right; }
+        if self.mode == FlightMode::SixDof {
+            if input.key_down(Key::Space) { movement += self.up; }
+            if input.key_down(Key::LeftControl) { movement -= self.up; }
+        }
+        if movement != glm::Vec3::zeros() {
+            self.position += update_speed * movement;
+            self.calculate_cam_matrix = true;
+        }
+
+        // Calculate view and projection matrices
+        if self.calculate_cam_matrix {
+            self.update_cam_matrix(false); // not calculating dir again
+        }
+    }
+
+    fn captures_cursor(&self) -> bool {
+        !self.first_click
+    }
+
+    /// Function to update the camera's direction, up and right vectors from the orientation
+    fn update_cam_direction(&mut self) {
+        self.direction = glm::quat_rotate_vec3(&self.orientation, &-glm::Vec3::z());
+        self.up = glm::quat_rotate_vec3(&self.orientation, &glm::Vec3::y());
+        self.right = glm::quat_rotate_vec3(&self.orientation, &glm::Vec3::x());
+    }
+
+    /// Function to update the camera's cam_matrix using the current state
+    fn update_cam_matrix(&mut self, calc_dir : bool) {
+        if calc_dir {
+            self.update_cam_direction();
+        }
+
+        let view = glm::look_at_rh(&self.position, &(self.position + self.direction), &self.up);
+        let projection = self.projection_mode.matrix(self.width as f32/self.height as f32, self.near_plane, self.far_plane);
+
+        self.cam_matrix = projection * view;
+        self.view = view;
+        self.projection = projection;
+        self.is_matrix_updated = true;
+        self.calculate_cam_matrix = false;
+    }
+
+    fn force_set_cam_matrix(&mut self, shader : &Shader) {
+        self.calculate_cam_matrix = true;
+        self.set_cam_matrix(shader);
+        self.calculate_cam_matrix = false;
+    }
+
+    fn view_matrix(&self) -> glm::Mat4 {
+        self.view
+    }
+
+    fn projection_matrix(&self) -> glm::Mat4 {
+        self.projection
+    }
+}
+
+impl Default for QuatCamera {
+    fn default() -> QuatCamera {
+        QuatCamera {
+            position : glm::vec3(0., 0., 0.),
+            orientation : glm::Quat::identity(),
+            mode : FlightMode::default(),
+
+            speed : 1.,
+            shift_multiplier : 5.,
+            roll_speed : 25.,
+            sensitivity : 2000.,
+            zoom_sensitivity : 100.,
+
+            width : 100,
+            height : 100,
+            near_plane : 0.1,
+            far_plane : 100.,
+            projection_mode : Projection::default(),
+
+            first_click : true,
+            calculate_cam_matrix : true,
+            is_matrix_updated : true,
+
+            cam_matrix : glm::Mat4::identity(),
+            view : glm::Mat4::identity(),
+            projection : glm::Mat4::identity(),
+            direction : -glm::Vec3::z(),
+            up : glm::Vec3::y(),
+            right : glm::Vec3::x(),
+        }
+    }
+}
+
+/// Orbit Camera
+/// updates are handled internally using camera.update
+/// Left drag to rotate around the target, Middle drag to pan, scroll to zoom in/out
+/// F to frame focus_bounds (e.g. a model's bounds, see frame_model), O to switch between perspective and orthographic
+pub struct OrbitCamera {
+    // Current Screen Info
+    pub width : u32,
+    pub height : u32,
+
+    // Orbit, the position is derived from these
+    pub target : glm::Vec3,
+    pub distance : f32,
+    pub min_distance : f32,
+    pub max_distance : f32,
+    yaw : f32,      // FreeCamera convention, degrees
+    pitch : f32,    // limited to +-89 degrees, the view would flip over the poles
+
+    // Derived, updated with the matrices
+    pub position : glm::Vec3,
+    pub direction : glm::Vec3,
+    pub up : glm::Vec3,
+    cam_matrix : glm::Mat4, // projection * view
+    view : glm::Mat4,
+    projection : glm::Mat4,
+
+    // Bounds framed by the F key
+    pub focus_bounds : Option<Bounds>,
+
+    // Mouse
+    sensitivity : f32,          // degrees per pixel
+    zoom_sensitivity : f32,     // distance factor per scroll step
+
+    // Projection, perspective by default
+    projection_mode : Projection,
+    perspective_fov : f32,  // restored when switching back from orthographic
+    pub near_plane : f32,
+    pub far_plane : f32,
+
+    // Internal flags
+    dragging : bool,                    // was a mouse button held in the previous update?
+    calculate_cam_matrix : bool,        // should matrix be recalculated?
+    pub is_matrix_updated : bool        // should matrix be updated in the shader?
+}
+
+#[allow(dead_code)]
+impl OrbitCamera {
+    pub fn projection(&self) -> Projection {
+        self.projection_mode
+    }
+
+    /// Function to change the projection, e.g. to an orthographic view, the matrices are updated on the next use
+    pub fn set_projection(&mut self, projection : Projection) {
+        self.projection_mode = projection;
+        self.calculate_cam_matrix = true;
+    }
+
+    /// Function to switch between perspective and orthographic, keeping the size of the target on screen
+    ///
+    /// The perspective fov used before switching to orthographic is restored
+    pub fn toggle_orthographic(&mut self) {
+        if self.projection_mode.is_orthographic() {
+            let (projection, distance) = self.projection_mode.to_perspective(self.perspective_fov, self.distance);
+            self.projection_mode = projection;
+            self.set_distance(distance);
+        }
+        else {
+            if let Projection::Perspective { fov } = self.projection_mode {
+                self.perspective_fov = fov;
+            }
+            self.projection_mode = self.projection_mode.to_orthographic(self.distance);
+        }
+        self.calculate_cam_matrix = true;
+    }
+    /// Function to set the orbit angles in degrees, pitch is limited to +-89
+    pub fn set_angles(&mut self, pitch : f32, yaw : f32) {
+        self.pitch = pitch.clamp(-89., 89.);
+        self.yaw = yaw;
+        self.calculate_cam_matrix = true;
+    }
+
+    /// Function to set the distance to the target, limited to min_distance and max_distance
+    pub fn set_distance(&mut self, distance : f32) {
+        self.distance = distance.clamp(self.min_distance, self.max_distance);
+        self.calculate_cam_matrix = true;
+    }
+
+    /// Function to move the target and the camera parallel to the view plane, in pixels
+    ///
+    /// Points at the target's depth follow the cursor
+    pub fn pan(&mut self, dx : f32, dy : f32) {
+        let world_per_pixel = self.projection_mode.view_height(self.distance) / self.height.max(1) as f32;
+        let right = glm::normalize(&glm::cross(&self.direction, &self.up));
+        self.target += (-dx * right + dy * self.up) * world_per_pixel;
+        self.calculate_cam_matrix = true;
+    }
+
+    /// Function to look at the center of the bounds from the current direction, close enough to see all of it
+    ///
+    /// The distance limits and the near/far planes are adjusted to the size of the bounds, a narrow field of view can
+    /// need a distance further than the usual limit, which is then widened to it
+    pub fn frame(&mut self, bounds : &Bounds) {
+        let radius = (glm::length(&bounds.size()) / 2.).max(1e-3);
+        self.target = bounds.center();
+        self.distance = self.projection_mode.fit(radius, self.width as f32 / self.height.max(1) as f32);
+        self.min_distance = radius * 0.01;
+        self.max_distance = (radius * 20.).max(self.distance);
+        self.near_plane = radius * 0.01;
+        self.far_plane = self.max_distance + radius;
+        self.calculate_cam_matrix = true;
+    }
+
+    /// Function to frame a model drawn with a transform, and remember its bounds for the F key
+    ///
+    /// Returns false if the model has no vertices, the camera is not changed
+    pub fn frame_model(&mut self, model : &Model, transform : &glm::Mat4) -> bool {
+        match model.bounds() {
+            Some(bounds) => {
+                let bounds = bounds.transformed(transform);
+                self.frame(&bounds);
+                self.focus_bounds = Some(bounds);
+                true
+            },
+            None => false,
+        }
+    }
+}
+
+impl Camera for OrbitCamera {
+
+    // Returns an orbit camera looking in the direction given by the angles, roll is ignored
+    // The target is as far in front of the camera as the camera is from the origin, so tutorials that look at
+    // the origin orbit around it
+    fn new(position : glm::Vec3, _roll : f32, pitch : f32, yaw : f32, width : u32, height : u32) -> OrbitCamera {
+        let mut cam = OrbitCamera {
+            width,
+            height,
+            ..Default::default()
+        };
+        cam.set_angles(pitch, yaw);
+        cam.update_cam_direction();
+        cam.distance = glm::length(&position).max(1.);
+        cam.target = position + cam.direction * cam.distance;
+        cam.update_cam_matrix(true);
+        cam.calculate_cam_matrix = false;
+        cam
+    }
+
+    /// Function to set the camera's cam_matrix to the shader's camMatrix
+    /// ! This activates the shader
+    fn set_cam_matrix(&mut self, shader : &Shader) {
+        if self.calculate_cam_matrix {
+            self.update_cam_matrix(true);
+        }
+        if self.is_matrix_updated {
+            unsafe {
+                shader.use_program();
+                shader.set_mat4(c_str!("camMatrix"), self.cam_matrix);
+            }
+        }
+    }
+
+    /// Function to handle all updates to the camera
+    fn update(&mut self, input : &InputState, _time_delta : f64) {
+        // Resetting flag here to allow callling set_cam_matrix for multiple shaders
+        self.is_matrix_updated = false;
+
+        // Update width/height when frame buffer changes, ignoring a minimized window
+        if let Some((width, height)) = input.resized && width > 0 && height > 0 {
+            self.width = width;
+            self.height = height;
+            self.calculate_cam_matrix = true;
+        }
+
+        // Zoom in/out when user scrolls, by a factor so it feels the same at every distance
+        // Moving does not change the size of things in an orthographic view, the projection is scaled instead
+        if input.scroll.y != 0. {
+            let factor = self.zoom_sensitivity.powf(input.scroll.y);
+            if self.projection_mode.is_orthographic() {
+                self.projection_mode.scale(1. / factor);
+                self.calculate_cam_matrix = true;
+            }
+            else {
+                self.set_distance(self.distance * factor);
+            }
+        }
+
+        if input.key_pressed(Key::O) {
+            self.toggle_orthographic();
+        }
+        if input.key_pressed(Key::F) && let Some(bounds) = self.focus_bounds {
+            self.frame(&bounds);
+        }
+
+        let rotating = input.button_down(MouseButton::Left);
+        let panning = input.button_down(MouseButton::Middle);
+        if rotating || panning {
+            // Nothing to do on the first update of a drag, the cursor may have moved before the button was pressed
+            let (dx, dy) = (input.cursor_delta.x, input.cursor_delta.y);
+            if self.dragging && (dx != 0. || dy != 0.) {
+                if rotating {
+                    // The camera moves against the cursor around the target, so the model turns with the cursor
+                    self.set_angles(self.pitch - dy * self.sensitivity, self.yaw + dx * self.sensitivity);
+                }
+                else {
+                    self.pan(dx, dy);
+                }
+            }
+        }
+        self.dragging = rotating || panning;
+
+        if self.calculate_cam_matrix {
+            self.update_cam_matrix(true);
+        }
+    }
+
+    /// Function to update the camera's direction and position from the orbit
+    fn update_cam_direction(&mut self) {
+        self.direction = glm::normalize(
+            &glm::vec3(
+                f32::cos(f32::to_radians(self.yaw)) * f32::cos(f32::to_radians(self.pitch)),
+                f32::sin(f32::to_radians(self.pitch)),
+                f32::sin(f32::to_radians(self.yaw)) * f32::cos(f32::to_radians(self.pitch))));
+        self.up = glm::Vec3::y();
+        self.position = self.target - self.direction * self.distance;
+    }
+
+    /// Function to update the camera's cam_matrix using the current state
+    fn update_cam_matrix(&mut self, calc_dir : bool) {
+        if calc_dir {
+            self.update_cam_direction();
+        }
+
+        let view = glm::look_at_rh(&self.position, &self.target, &self.up);
+        let projection = self.projection_mode.matrix(self.width as f32/self.height as f32, self.near_plane, self.far_plane);
+
+        self.cam_matrix = projection * view;
+        self.view = view;
+        self.projection = projection;
+        self.is_matrix_updated = true;
+        self.calculate_cam_matrix = false;
+    }
+
+    fn force_set_cam_matrix(&mut self, shader : &Shader) {
+        self.calculate_cam_matrix = true;
+        self.set_cam_matrix(shader);
+        self.calculate_cam_matrix = false;
+    }
+
+    fn view_matrix(&self) -> glm::Mat4 {
+        self.view
+    }
+
+    fn projection_matrix(&self) -> glm::Mat4 {
+        self.projection
+    }
+}
+
+impl Default for OrbitCamera {
+    fn default() -> OrbitCamera {
+        OrbitCamera {
+            target : glm::vec3(0., 0., 0.),
+            distance : 3.,
+            min_distance : 0.1,
+            max_distance : 100.,
+            yaw : -90.,
+            pitch : 0.,
+
+            focus_bounds : None,
+            sensitivity : 0.3,
+            zoom_sensitivity : 0.9,
+
+            width : 100,
+            height : 100,
+            near_plane : 0.1,
+            far_plane : 100.,
+            projection_mode : Projection::default(),
+            perspective_fov : 45.,
+
+            dragging : false,
+            calculate_cam_matrix : true,
+            is_matrix_updated : true,
+
+            cam_matrix : glm::Mat4::identity(),
+            view : glm::Mat4::identity(),
+            projection : glm::Mat4::identity(),
+            position : glm::vec3(0., 0., 3.),
+            direction : -glm::Vec3::z(),
+            up : glm::Vec3::y(),
+        }
+    }
+}
+
+/// Function to get the orientation FreeCamera has for the same angles, in degrees
+///
+/// Yaw turns around the world's y axis (-90 looks down -z), then pitch around the camera's x axis, then roll around
+/// the view direction
+pub fn orientation_from_euler(roll : f32, pitch : f32, yaw : f32) -> glm::Quat {
+    let yaw = glm::quat_angle_axis(-(yaw + 90.).to_radians(), &glm::Vec3::y());
+    let pitch = glm::quat_angle_axis(pitch.to_radians(), &glm::Vec3::x());
+    let roll = glm::quat_angle_axis(roll.to_radians(), &-glm::Vec3::z());
+    glm::quat_normalize(&(yaw * pitch * roll))
+}
+
+/// Function to rotate an orientation around its own axes, angles in radians
+///
+/// yaw > 0 turns right around the camera's up, pitch > 0 looks up around its right, roll > 0 turns around the view
+/// direction. The result is normalized, so repeated small rotations do not drift
+pub fn rotate_local(orientation : &glm::Quat, yaw : f32, pitch : f32, roll : f32) -> glm::Quat {
+    let yaw = glm::quat_angle_axis(-yaw, &glm::Vec3::y());
+    let pitch = glm::quat_angle_axis(pitch, &glm::Vec3::x());
+    let roll = glm::quat_angle_axis(roll, &-glm::Vec3::z());
+    glm::quat_normalize(&(orientation * yaw * pitch * roll))
+}
+
+/// Function to remove the roll of an orientation, so its right axis is horizontal and its up points upwards
+///
+/// The view direction is kept, looking straight up or down returns the orientation unchanged
+pub fn level_orientation(orientation : &glm::Quat) -> glm::Quat {
+    let direction = glm::quat_rotate_vec3(orientation, &-glm::Vec3::z());
+    orientation_from_axes(&direction, &glm::Vec3::y()).unwrap_or(*orientation)
+}
+
+/// Function to get the orientation looking along direction, with up as close to up as possible like glm::look_at
+///
+/// None if direction and up are parallel
+pub fn orientation_from_axes(direction : &glm::Vec3, up : &glm::Vec3) -> Option<glm::Quat> {
+    let right = glm::cross(direction, up);
+    if glm::length2(&right) < 1e-8 {
+        return None;
+    }
+    let direction = glm::normalize(direction);
+    let right = glm::normalize(&right);
+    let up = glm::cross(&right, &direction);
+    // Columns are the camera's axes in world space
+    let rotation = glm::mat3(
+        right.x, up.x, -direction.x,
+        right.y, up.y, -direction.y,
+        right.z, up.z, -direction.z,
+    );
+    Some(glm::quat_normalize(&glm::mat3_to_quat(&rotation)))
+}
+
+/// Function to get FreeCamera's angles for an orientation in degrees, the inverse of orientation_from_euler
+///
+/// Returns (roll, pitch, yaw), the roll is 0 when looking straight up or down
+pub fn euler_from_orientation(orientation : &glm::Quat) -> (f32, f32, f32) {
+    let direction = glm::quat_rotate_vec3(orientation, &-glm::Vec3::z());
+    let up = glm::quat_rotate_vec3(orientation, &glm::Vec3::y());
+    let pitch = direction.y.clamp(-1., 1.).asin();
+    let yaw = direction.z.atan2(direction.x);
+    // Roll is the angle around the direction from the level up to the actual up
+    let roll = orientation_from_axes(&direction, &glm::Vec3::y()).map_or(0., |level| {
+        let level_up = glm::quat_rotate_vec3(&level, &glm::Vec3::y());
+        glm::dot(&glm::cross(&level_up, &up), &direction).atan2(glm::dot(&level_up, &up))
+    });
+    (roll.to_degrees(), pitch.to_degrees(), yaw.to_degrees())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera_path::CameraKeyframe;
+
+    const EPSILON : f32 = 1e-4;
+
+    fn assert_vec3_eq(a : &glm::Vec3, b : &glm::Vec3) {
+        assert!((a - b).norm() < EPSILON, "{} != {}", a, b);
+    }
+
+    fn axes(orientation : &glm::Quat) -> (glm::Vec3, glm::Vec3) {
+        (glm::quat_rotate_vec3(orientation, &-glm::Vec3::z()), glm::quat_rotate_vec3(orientation, &glm::Vec3::y()))
+    }
+
+    #[test]
+    fn euler_angles_match_free_camera() {
+        for (roll, pitch, yaw) in [(0., 0., -90.), (0., 30., 45.), (20., -60., 170.), (-45., 10., 0.)] {
+            let (direction, up) = axes(&orientation_from_euler(roll, pitch, yaw));
+
+            // Same formulas as FreeCamera::update_cam_direction
+            let (pitch, yaw) = (f32::to_radians(pitch), f32::to_radians(yaw));
+            let expected = glm::vec3(yaw.cos() * pitch.cos(), pitch.sin(), yaw.sin() * pitch.cos());
+            assert_vec3_eq(&direction, &expected);
+            // FreeCamera rotates world up around the direction, which is only the same up when there is no pitch
+            if pitch == 0. {
+                assert_vec3_eq(&up, &glm::rotate_vec3(&glm::Vec3::y(), f32::to_radians(roll), &expected));
+            }
+        }
+    }
+
+    #[test]
+    fn yaw_and_pitch_follow_the_roll() {
+        // Rolled 90 degrees, the camera's up is along world x
+        let rolled = orientation_from_euler(90., 0., -90.);
+        let (direction, up) = axes(&rolled);
+        assert_vec3_eq(&direction, &-glm::Vec3::z());
+        assert_eq!(up.x.abs().round(), 1.);
+
+        // Yawing turns around the camera's up, not the world's
+        let (yawed_direction, yawed_up) = axes(&rotate_local(&rolled, 0.3, 0., 0.));
+        assert_vec3_eq(&yawed_up, &up);
+        assert_vec3_eq(&yawed_direction, &glm::rotate_vec3(&direction, -0.3, &up));
+
+        // Pitching up moves the direction towards the camera's up
+        let (pitched_direction, _) = axes(&rotate_local(&rolled, 0., 0.3, 0.));
+        assert!(glm::dot(&pitched_direction, &up) > 0.2);
+    }
+
+    #[test]
+    fn pitch_goes_over_the_top() {
+        // No clamping or gimbal lock, a full loop comes back to the start
+        let start = orientation_from_euler(0., 0., -90.);
+        let mut orientation = start;
+        for step in 0..360 {
+            orientation = rotate_local(&orientation, 0., 1_f32.to_radians(), 0.);
+            let (direction, up) = axes(&orientation);
+            assert!(direction.iter().chain(up.iter()).all(|value| value.is_finite()));
+            if step == 89 {
+                assert_vec3_eq(&direction, &glm::Vec3::y());
+            }
+        }
+        let (direction, up) = axes(&orientation);
+        assert_vec3_eq(&direction, &-glm::Vec3::z());
+        assert_vec3_eq(&up, &glm::Vec3::y());
+    }
+
+    #[test]
+    fn rotations_stay_normalized() {
+        let mut orientation = glm::Quat::identity();
+        for _ in 0..10000 {
+            orientation = rotate_local(&orientation, 0.013, -0.007, 0.011);
+        }
+        assert!((glm::quat_length(&orientation) - 1.).abs() < EPSILON);
+    }
+
+    #[test]
+    fn framing_fits_the_bounds() {
+        let mut camera = OrbitCamera::new(glm::vec3(0., 0., 3.), 0., 20., -60., 400, 800);
+        let bounds = Bounds { min : glm::vec3(8., -2., 0.), max : glm::vec3(12., 2., 1.) };
+        camera.frame(&bounds);
+        camera.update_cam_matrix(true);
+
+        assert_vec3_eq(&camera.target, &bounds.center());
+        assert_vec3_eq(&(camera.position + camera.direction * camera.distance), &camera.target);
+        // Every corner ends up inside the view, the window is narrow so the horizontal fov is the limit
+        let cam_matrix = camera.projection_matrix() * camera.view_matrix();
+        for corner in 0..8 {
+            let point = glm::vec3(
+                if corner & 1 == 0 { bounds.min.x } else { bounds.max.x },
+                if corner & 2 == 0 { bounds.min.y } else { bounds.max.y },
+                if corner & 4 == 0 { bounds.min.z } else { bounds.max.z });
+            let clip = cam_matrix * glm::vec4(point.x, point.y, point.z, 1.);
+            let ndc = clip.xyz() / clip.w;
+            assert!(ndc.iter().all(|value| value.abs() <= 1.), "{} is outside the view", ndc);
+        }
+    }
+
+    fn dragging(button : MouseButton, cursor_delta : glm::Vec2) -> InputState {
+        InputState { buttons_down : [button].into(), cursor_delta, ..Default::default() }
+    }
+
+    #[test]
+    fn free_camera_moves_with_keys() {
+        let mut camera = FreeCamera::new(glm::vec3(0., 0., 3.), 0., 0., -90., 800, 600);
+        camera.update(&InputState::with_keys(&[Key::W]), 0.5);
+        assert_vec3_eq(&camera.position, &glm::vec3(0., 0., 2.5));
+
+        // Shift multiplies the speed, D strafes right
+        camera.update(&InputState::with_keys(&[Key::D, Key::LeftShift]), 0.1);
+        assert_vec3_eq(&camera.position, &glm::vec3(0.5, 0., 2.5));
+
+        // Nothing held, nothing changes
+        camera.update(&InputState::default(), 1.);
+        assert_vec3_eq(&camera.position, &glm::vec3(0.5, 0., 2.5));
+        assert!(!camera.is_matrix_updated);
+    }
+
+    #[test]
+    fn free_camera_plays_a_path() {
+        let start = orientation_from_euler(0., 0., -90.);
+        let end = orientation_from_euler(20., 30., 0.);
+        let path = CameraPath { keyframes : vec![
+            CameraKeyframe { time : 0., position : glm::vec3(0., 0., 0.), orientation : start },
+            CameraKeyframe { time : 1., position : glm::vec3(2., 0., 0.), orientation : end },
+        ]};
+        let mut camera = FreeCamera::new(glm::vec3(0., 0., 3.), 0., 0., -90., 800, 600);
+        camera.play(path);
+
+        // Keys are ignored while playing
+        camera.update(&InputState::with_keys(&[Key::W]), 0.5);
+        assert!(camera.is_playing());
+        assert_vec3_eq(&camera.position, &glm::vec3(1., 0., 0.));
+
+        // At the end the camera stays at the last keyframe, with matching angles
+        camera.update(&InputState::default(), 0.5);
+        camera.update(&InputState::default(), 0.5);
+        assert!(!camera.is_playing());
+        assert_vec3_eq(&camera.position, &glm::vec3(2., 0., 0.));
+        let (direction, up) = axes(&end);
+        camera.update_cam_matrix(true);
+        assert_vec3_eq(&camera.direction, &direction);
+        assert_vec3_eq(&glm::normalize(&glm::cross(&glm::cross(&camera.direction, &camera.up), &camera.direction)), &up);
+    }
+
+    #[test]
+    fn euler_angles_round_trip() {
+        for (roll, pitch, yaw) in [(0., 0., -90.), (0., 30., 45.), (20., -60., 170.), (-45., 10., 0.)] {
+            let (r, p, y) = euler_from_orientation(&orientation_from_euler(roll, pitch, yaw));
+            assert!((r - roll).abs() < 1e-2 && (p - pitch).abs() < 1e-2 && (y - yaw).abs() < 1e-2, "{} {} {}", r, p, y);
+        }
+    }
+
+    #[test]
+    fn free_camera_pitch_and_zoom_are_clamped() {
+        let mut camera = FreeCamera::new(glm::vec3(0., 0., 0.), 0., 0., -90., 800, 600);
+
+        // The first update with the button held only captures the cursor
+        camera.update(&dragging(MouseButton::Right, glm::vec2(0., -1e6)), 0.1);
+        assert!(camera.captures_cursor());
+        assert_vec3_eq(&camera.direction, &-glm::Vec3::z());
+
+        camera.update(&dragging(MouseButton::Right, glm::vec2(0., -1e6)), 0.1);
+        assert_eq!(camera.pitch, 85.);
+        camera.update(&dragging(MouseButton::Right, glm::vec2(0., 1e6)), 0.1);
+        assert_eq!(camera.pitch, -85.);
+        camera.update(&InputState::default(), 0.1);
+        assert!(!camera.captures_cursor());
+
+        camera.update(&InputState { scroll : glm::vec2(0., 1e6), ..Default::default() }, 0.1);
+        assert_eq!(camera.projection(), Projection::Perspective { fov : 1. });
+        camera.update(&InputState { scroll : glm::vec2(0., -1e6), ..Default::default() }, 0.1);
+        assert_eq!(camera.projection(), Projection::Perspective { fov : 90. });
+    }
+
+    #[test]
+    fn resizing_keeps_the_aspect() {
+        let mut camera = FreeCamera::new(glm::vec3(0., 0., 0.), 0., 0., -90., 800, 600);
+        camera.set_projection(Projection::orthographic(2.));
+        camera.update(&InputState { resized : Some((400, 200)), ..Default::default() }, 0.1);
+        let projection = camera.projection_matrix();
+        assert!((projection[(1, 1)] / projection[(0, 0)] - 2.).abs() < EPSILON);
+
+        // A minimized window reports a zero size, which is ignored
+        camera.update(&InputState { resized : Some((0, 0)), ..Default::default() }, 0.1);
+        assert_eq!((camera.width, camera.height), (400, 200));
+        assert_eq!(camera.projection_matrix(), projection);
+    }
+
+    #[test]
+    fn quat_camera_moves_along_its_axes() {
+        let mut camera = QuatCamera::new(glm::vec3(0., 0., 0.), 90., 0., -90., 800, 600);
+        // Rolled 90 degrees, strafing right moves along world -y
+        camera.update(&InputState::with_keys(&[Key::D]), 1.);
+        assert_vec3_eq(&camera.position, &(-glm::Vec3::y()));
+
+        // Up and down only in six-DOF mode, along the camera's up
+        camera.update(&InputState::with_keys(&[Key::Space]), 1.);
+        assert_vec3_eq(&camera.position, &(-glm::Vec3::y()));
+        camera.mode = FlightMode::SixDof;
+        camera.update(&InputState::with_keys(&[Key::Space]), 1.);
+        assert_vec3_eq(&camera.position, &glm::vec3(1., -1., 0.));
+    }
+
+    #[test]
+    fn orbit_camera_is_clamped() {
+        let mut camera = OrbitCamera::new(glm::vec3(0., 0., 3.), 0., 0., -90., 800, 600);
+
+        // Dragging down far enough stops just before the pole, the first update of a drag is skipped
+        camera.update(&dragging(MouseButton::Left, glm::vec2(0., 1e6)), 0.1);
+        assert_eq!(camera.pitch, 0.);
+        camera.update(&dragging(MouseButton::Left, glm::vec2(0., 1e6)), 0.1);
+        assert_eq!(camera.pitch, -89.);
+        assert!(!camera.captures_cursor());
+
+        camera.update(&InputState { scroll : glm::vec2(0., 1e3), ..Default::default() }, 0.1);
+        assert_eq!(camera.distance, camera.min_distance);
+        camera.update(&InputState { scroll : glm::vec2(0., -1e3), ..Default::default() }, 0.1);
+        assert_eq!(camera.distance, camera.max_distance);
+        assert_vec3_eq(&(camera.position + camera.direction * camera.distance), &camera.target);
+
+        // F frames the focus bounds
+        let bounds = Bounds { min : glm::vec3(1., 1., 1.), max : glm::vec3(3., 3., 3.) };
+        camera.focus_bounds = Some(bounds);
+        camera.update(&InputState { keys_pressed : [Key::F].into(), ..Default::default() }, 0.1);
+        assert_vec3_eq(&camera.target, &bounds.center());
+    }
+
+    #[test]
+    fn framing_with_a_narrow_fov_widens_the_distance_limit() {
+        let mut camera = OrbitCamera::new(glm::vec3(0., 0., 3.), 0., 0., -90., 800, 600);
+        camera.projection_mode = Projection::Perspective { fov : 2. };
+        let bounds = Bounds { min : glm::vec3(-1., -1., -1.), max : glm::vec3(1., 1., 1.) };
+        camera.frame(&bounds);
+
+        let radius = glm::length(&bounds.size()) / 2.;
+        assert!(camera.distance > radius * 20.);
+        assert_eq!(camera.max_distance, camera.distance);
+        assert!(camera.far_plane > camera.distance + radius - EPSILON);
+
+        // Zooming out does not jump back in to the usual limit
+        camera.update(&InputState { scroll : glm::vec2(0., -1e3), ..Default::default() }, 0.1);
+        assert_eq!(camera.distance, camera.max_distance);
+    }
+
+    #[test]
+    fn level_removes_the_roll() {
+        let rolled = orientation_from_euler(35., 20., 10.);
+        let (direction, _) = axes(&rolled);
+        let level = level_orientation(&rolled);
+        let (level_direction, level_up) = axes(&level);
+        assert_vec3_eq(&level_direction, &direction);
+        assert!((glm::quat_length(&level) - 1.).abs() < EPSILON);
+        let right = glm::quat_rotate_vec3(&level, &glm::Vec3::x());
+        assert!(right.y.abs() < EPSILON);
+        assert!(level_up.y > 0.);
+        // Same as the unrolled euler angles
+        let (_, expected_up) = axes(&orientation_from_euler(0., 20., 10.));
+        assert_vec3_eq(&level_up, &expected_up);
+
+        // Looking straight up there is no horizon to level to
+        let up = orientation_from_euler(10., 90., 0.);
+        assert_eq!(level_orientation(&up), up);
+    }
+}
@@ -0,0 +1,367 @@
+// Camera paths - recording timed keyframes to a file and playing them back along a spline
+
+// Notes:
+// A path is a list of keyframes, each with a time, a position and an orientation. Playback interpolates positions
+// with a Catmull-Rom spline, which goes through every keyframe with a continuous velocity. The spline is evaluated
+// with the Barry-Goldman pyramid : three levels of linear interpolations weighted by the keyframe times, so unevenly
+// spaced keyframes do not overshoot. Orientations use the same pyramid with slerp instead of lerp, which turns
+// smoothly through the keyframes instead of changing direction at each one like plain slerp does.
+// Used for repeatable benchmark flythroughs (Sponza) and demo videos, see FreeCamera : Shift+P starts and stops
+// recording, P plays the recorded file back.
+// File format, one keyframe per line, # starts a comment :
+//   time position.x position.y position.z orientation.x orientation.y orientation.z orientation.w
+
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+
+use nalgebra_glm as glm;
+
+use crate::animation;
+
+/// Errors that stop a camera path from loading
+#[derive(Debug)]
+pub enum CameraPathError {
+    Io(io::Error),
+    Parse { line : usize, message : String },   // line numbers start at 1
+}
+
+impl fmt::Display for CameraPathError {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CameraPathError::Io(error) => write!(f, "{}", error),
+            CameraPathError::Parse { line, message } => write!(f, "line {} : {}", line, message),
+        }
+    }
+}
+
+impl Error for CameraPathError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CameraPathError::Io(error) => Some(error),
+            CameraPathError::Parse { .. } => None,
+        }
+    }
+}
+
+/// Camera position and orientation at a time
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CameraKeyframe {
+    pub time : f32,                 // seconds from the start of the path
+    pub position : glm::Vec3,
+    pub orientation : glm::Quat,    // rotation from camera space (looking down -z, y up) to world space
+}
+
+/// Keyframes sorted by time
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CameraPath {
+    pub keyframes : Vec<CameraKeyframe>,
+}
+
+impl CameraPath {
+    /// Function to get the time of the last keyframe
+    pub fn duration(&self) -> f32 {
+        self.keyframes.last().map_or(0., |keyframe| keyframe.time)
+    }
+
+    /// Function to get the position and orientation at a time, clamped to the start and end of the path
+    ///
+    /// None if the path has no keyframes, or if time is not finite
+    pub fn sample(&self, time : f32) -> Option<(glm::Vec3, glm::Quat)> {
+        // NaN fails every comparison below and would look up the keyframe before the first one
+        if !time.is_finite() {
+            return None;
+        }
+        let keys = &self.keyframes;
+        let first = keys.first()?;
+        let last = keys.last()?;
+        if keys.len() == 1 || time <= first.time {
+            return Some((first.position, first.orientation));
+        }
+        if time >= last.time {
+            return Some((last.position, last.orientation));
+        }
+
+        // Segment from keys[i] to keys[i + 1], with one neighbour on each side. The ends are extended by
+        // repeating the end keyframe one segment further, which keeps the spline straight into the end
+        let i = keys.partition_point(|keyframe| keyframe.time <= time) - 1;
+        let previous = if i > 0 { keys[i - 1] } else { CameraKeyframe { time : 2. * keys[0].time - keys[1].time, ..keys[0] } };
+        let next = if i + 2 < keys.len() { keys[i + 2] } else { CameraKeyframe { time : 2. * last.time - keys[i].time, ..*last } };
+        let segment = [previous, keys[i], keys[i + 1], next];
+        let times = segment.map(|keyframe| keyframe.time);
+
+        let position = catmull_rom(segment.map(|keyframe| keyframe.position), times, time, |a, b, t| a + (b - a) * t);
+
+        // q and -q are the same rotation, keep neighbours on the same side so slerp takes the short way
+        let mut orientations = segment.map(|keyframe| keyframe.orientation);
+        for j in 1..4 {
+            if glm::quat_dot(&orientations[j - 1], &orientations[j]) < 0. {
+                orientations[j] = -orientations[j];
+            }
+        }
+        let orientation = catmull_rom(orientations, times, time, animation::slerp);
+
+        Some((position, glm::quat_normalize(&orientation)))
+    }
+
+    /// Function to parse a camera path file, keyframes are sorted by time
+    pub fn parse(text : &str) -> Result<CameraPath, CameraPathError> {
+        let mut keyframes = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = |message : String| CameraPathError::Parse { line : index + 1, message };
+            let values = line.split_whitespace()
+                .map(|word| word.parse::<f32>().map_err(|_| error(format!("'{}' is not a number", word))))
+                .collect::<Result<Vec<f32>, _>>()?;
+            if values.len() != 8 {
+                return Err(error(format!("expected 8 values (time, position, orientation), found {}", values.len())));
+            }
+            if values.iter().any(|value| !value.is_finite()) {
+                return Err(error("values must be finite".into()));
+            }
+            let orientation = glm::quat(values[4], values[5], values[6], values[7]);
+            if glm::quat_length(&orientation) < 1e-6 {
+                return Err(error("orientation is not a rotation".into()));
+            }
+            keyframes.push(CameraKeyframe {
+                time : values[0],
+                position : glm::vec3(values[1], values[2], values[3]),
+                orientation : glm::quat_normalize(&orientation),
+            });
+        }
+        keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+        Ok(CameraPath { keyframes })
+    }
+
+    /// Function to get the path in the file format, see parse
+    pub fn to_text(&self) -> String {
+        let mut text = String::from("# camera path : time, position xyz, orientation quaternion xyzw\n");
+        for keyframe in &self.keyframes {
+            let (p, q) = (keyframe.position, keyframe.orientation.coords);
+            text.push_str(&format!("{} {} {} {} {} {} {} {}\n", keyframe.time, p.x, p.y, p.z, q.x, q.y, q.z, q.w));
+        }
+        text
+    }
+
+    pub fn load(path : &str) -> Result<CameraPath, CameraPathError> {
+        CameraPath::parse(&fs::read_to_string(path).map_err(CameraPathError::Io)?)
+    }
+
+    pub fn save(&self, path : &str) -> io::Result<()> {
+        fs::write(path, self.to_text())
+    }
+}
+
+/// Records keyframes while the camera moves
+pub struct CameraRecorder {
+    pub path : CameraPath,
+    pub interval : f32,     // minimum seconds between keyframes, the spline fills in between
+    time : f32,
+}
+
+impl CameraRecorder {
+    pub fn new(interval : f32) -> CameraRecorder {
+        CameraRecorder { path : CameraPath::default(), interval, time : 0. }
+    }
+
+    /// Function to advance the recording by time_delta seconds, adds a keyframe if interval seconds have passed
+    pub fn record(&mut self, time_delta : f32, position : glm::Vec3, orientation : glm::Quat) {
+        self.time += time_delta;
+        let due = self.path.keyframes.last().is_none_or(|last| self.time - last.time >= self.interval);
+        if due {
+            self.path.keyframes.push(CameraKeyframe { time : self.time, position, orientation });
+        }
+    }
+
+    /// Function to stop recording, adds the current pose as the last keyframe so the path ends where the camera is
+    pub fn finish(mut self, position : glm::Vec3, orientation : glm::Quat) -> CameraPath {
+        if self.path.keyframes.last().is_none_or(|last| self.time > last.time) {
+            self.path.keyframes.push(CameraKeyframe { time : self.time, position, orientation });
+        }
+        self.path
+    }
+}
+
+/// Plays a path back, with a speed multiplier and optional looping
+pub struct CameraPlayer {
+    pub path : CameraPath,
+    pub speed : f32,        // 1 plays the path in the time it was recorded in
+    pub looping : bool,     // restart at the end, record the path back to its start for a seamless loop
+    time : f32,
+}
+
+impl CameraPlayer {
+    pub fn new(path : CameraPath) -> CameraPlayer {
+        CameraPlayer { path, speed : 1., looping : false, time : 0. }
+    }
+
+    /// Function to get the current time along the path
+    #[allow(dead_code)]
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    /// Function to advance the playback by time_delta seconds, scaled by the speed
+    ///
+    /// Returns the pose at the new time, None once a path that is not looping has ended, or if it is empty
+    pub fn advance(&mut self, time_delta : f32) -> Option<(glm::Vec3, glm::Quat)> {
+        let start = self.path.keyframes.first()?.time;
+        let duration = self.path.duration() - start;
+        self.time += time_delta * self.speed;
+        if self.time > duration {
+            if !self.looping {
+                return None;
+            }
+            self.time = if duration > 0. { self.time % duration } else { 0. };
+        }
+        self.path.sample(start + self.time)
+    }
+}
+
+/// Function to evaluate a Catmull-Rom spline between points[1] and points[2] at time, with any interpolation
+///
+/// Barry-Goldman pyramid, times are the times of the points and must increase
+fn catmull_rom<T>(points : [T; 4], times : [f32; 4], time : f32, lerp : impl Fn(&T, &T, f32) -> T) -> T {
+    let [p0, p1, p2, p3] = points;
+    let [t0, t1, t2, t3] = times;
+    // Weight of b when interpolating from a at time ta to b at time tb, keyframes at the same time give b
+    let weight = |ta : f32, tb : f32| if tb - ta > f32::EPSILON { (time - ta) / (tb - ta) } else { 1. };
+
+    let a1 = lerp(&p0, &p1, weight(t0, t1));
+    let a2 = lerp(&p1, &p2, weight(t1, t2));
+    let a3 = lerp(&p2, &p3, weight(t2, t3));
+    let b1 = lerp(&a1, &a2, weight(t0, t2));
+    let b2 = lerp(&a2, &a3, weight(t1, t3));
+    lerp(&b1, &b2, weight(t1, t2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON : f32 = 1e-4;
+
+    fn assert_vec3_eq(a : &glm::Vec3, b : &glm::Vec3) {
+        assert!((a - b).norm() < EPSILON, "{} != {}", a, b);
+    }
+
+    /// Same rotation, either sign
+    fn assert_quat_eq(a : &glm::Quat, b : &glm::Quat) {
+        assert!(glm::quat_dot(a, b).abs() > 1. - EPSILON, "{:?} != {:?}", a, b);
+    }
+
+    fn yaw(degrees : f32) -> glm::Quat {
+        glm::quat_angle_axis(degrees.to_radians(), &glm::Vec3::y())
+    }
+
+    fn path() -> CameraPath {
+        let keyframe = |time : f32, x : f32, y : f32, angle : f32| CameraKeyframe { time, position : glm::vec3(x, y, 0.), orientation : yaw(angle) };
+        CameraPath { keyframes : vec![
+            keyframe(0., 0., 0., 0.),
+            keyframe(1., 1., 2., 40.),
+            keyframe(3., 4., 0., 100.),
+            keyframe(3.5, 5., 1., 170.),
+        ]}
+    }
+
+    #[test]
+    fn spline_goes_through_the_keyframes() {
+        let path = path();
+        for keyframe in &path.keyframes {
+            let (position, orientation) = path.sample(keyframe.time).unwrap();
+            assert_vec3_eq(&position, &keyframe.position);
+            assert_quat_eq(&orientation, &keyframe.orientation);
+        }
+        // Clamped outside the path
+        assert_eq!(path.sample(-1.).unwrap().0, path.keyframes[0].position);
+        assert_eq!(path.sample(10.).unwrap().0, path.keyframes[3].position);
+        assert!(CameraPath::default().sample(0.).is_none());
+        assert!(path.sample(f32::NAN).is_none());
+        assert!(path.sample(f32::INFINITY).is_none());
+    }
+
+    #[test]
+    fn velocity_is_continuous_at_keyframes() {
+        let path = path();
+        let h = 1e-2;
+        for keyframe in &path.keyframes[1..3] {
+            let t = keyframe.time;
+            let before = (path.sample(t).unwrap().0 - path.sample(t - h).unwrap().0) / h;
+            let after = (path.sample(t + h).unwrap().0 - path.sample(t).unwrap().0) / h;
+            assert!((before - after).norm() < 0.1 * before.norm(), "{} != {} at {}", before, after, t);
+
+            // Same for the angular velocity, as the angle turned around y
+            let angle = |t : f32| 2. * path.sample(t).unwrap().1.coords.y.atan2(path.sample(t).unwrap().1.coords.w);
+            let before = (angle(t) - angle(t - h)) / h;
+            let after = (angle(t + h) - angle(t)) / h;
+            assert!((before - after).abs() < 0.1 * before.abs(), "{} != {} at {}", before, after, t);
+        }
+    }
+
+    #[test]
+    fn orientations_take_the_short_way() {
+        // The same rotations with flipped signs give the same result
+        let mut flipped = path();
+        for keyframe in flipped.keyframes.iter_mut().skip(1).step_by(2) {
+            keyframe.orientation = -keyframe.orientation;
+        }
+        for time in [0.3, 1.7, 3.2] {
+            let (_, a) = path().sample(time).unwrap();
+            let (_, b) = flipped.sample(time).unwrap();
+            assert_quat_eq(&a, &b);
+            assert!((glm::quat_length(&a) - 1.).abs() < EPSILON);
+        }
+
+        // Slerp halfway between two rotations
+        assert_quat_eq(&animation::slerp(&yaw(0.), &-yaw(90.), 0.5), &yaw(45.));
+    }
+
+    #[test]
+    fn file_round_trip() {
+        let path = path();
+        let loaded = CameraPath::parse(&path.to_text()).unwrap();
+        assert_eq!(loaded.keyframes.len(), path.keyframes.len());
+        for (a, b) in loaded.keyframes.iter().zip(&path.keyframes) {
+            assert_eq!(a.time, b.time);
+            assert_vec3_eq(&a.position, &b.position);
+            assert_quat_eq(&a.orientation, &b.orientation);
+        }
+
+        assert!(matches!(CameraPath::parse("# header\n0 0 0 0 0 0 0 1\n1 2 3"), Err(CameraPathError::Parse { line : 3, .. })));
+        assert!(matches!(CameraPath::parse("0 0 0 0 0 0 0 x"), Err(CameraPathError::Parse { line : 1, .. })));
+        assert!(matches!(CameraPath::parse("0 0 0 0 0 0 0 0"), Err(CameraPathError::Parse { line : 1, .. })));
+    }
+
+    #[test]
+    fn recording_adds_keyframes_at_the_interval() {
+        let mut recorder = CameraRecorder::new(0.25);
+        for i in 0..8 {
+            recorder.record(0.125, glm::vec3(i as f32, 0., 0.), yaw(0.));
+        }
+        let path = recorder.finish(glm::vec3(8., 0., 0.), yaw(0.));
+        // First update, then every other update, and the end
+        let times = path.keyframes.iter().map(|keyframe| keyframe.time).collect::<Vec<f32>>();
+        assert_eq!(times, [0.125, 0.375, 0.625, 0.875, 1.]);
+        assert_vec3_eq(&path.keyframes[4].position, &glm::vec3(8., 0., 0.));
+    }
+
+    #[test]
+    fn playback_speed_and_looping() {
+        let mut player = CameraPlayer::new(path());
+        player.speed = 2.;
+        let (position, _) = player.advance(0.5).unwrap();
+        assert_vec3_eq(&position, &path().keyframes[1].position);
+        assert!(player.advance(1.2).is_some());
+        assert!(player.advance(0.1).is_none());
+
+        let mut player = CameraPlayer::new(path());
+        player.looping = true;
+        let (position, _) = player.advance(3.5 + 1.).unwrap();
+        assert!((player.time() - 1.).abs() < EPSILON);
+        assert_vec3_eq(&position, &path().keyframes[1].position);
+    }
+}
@@ -0,0 +1,849 @@
+// Block compressed textures (BC1-5, BC7) from DDS and KTX2 containers
+
+// Notes:
+// BCn formats store 4x4 pixel blocks in 8 or 16 bytes, so they use 4-8 times less memory than RGBA8 and are uploaded
+// as is with glCompressedTexImage2D, including the mip chain stored in the file.
+// BC4/BC5 (RGTC) are core since OpenGL 3.0, BC1-3 (S3TC) are an extension and BC7 (BPTC) is core since 4.2. If the
+// driver is missing one, the blocks are decoded to RGBA8 on the cpu instead.
+// Files are usually stored top row first, flipping swaps block rows and the rows inside each block. BC7 blocks cannot
+// be flipped that way, so flipped BC7 textures are decoded on the cpu (see TextureData::read).
+
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::os::raw::c_void;
+use std::path::Path;
+
+use ddsfile::{D3DFormat, Dds, DxgiFormat};
+use gl::{self, types::*};
+
+use crate::texture::{self, TextureOptions};
+
+// S3TC enums are from an extension, the gl crate does not include them
+const COMPRESSED_RGBA_S3TC_DXT1 : GLenum = 0x83F1;
+const COMPRESSED_RGBA_S3TC_DXT3 : GLenum = 0x83F2;
+const COMPRESSED_RGBA_S3TC_DXT5 : GLenum = 0x83F3;
+const COMPRESSED_SRGB_ALPHA_S3TC_DXT1 : GLenum = 0x8C4D;
+const COMPRESSED_SRGB_ALPHA_S3TC_DXT3 : GLenum = 0x8C4E;
+const COMPRESSED_SRGB_ALPHA_S3TC_DXT5 : GLenum = 0x8C4F;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlockFormat {
+    Bc1,    // DXT1 - RGB with 1 bit alpha
+    Bc2,    // DXT3 - RGB with explicit 4 bit alpha
+    Bc3,    // DXT5 - RGB with interpolated alpha
+    Bc4,    // one channel, read as red
+    Bc5,    // two channels, read as red and green - normal maps
+    Bc7,    // high quality RGBA
+}
+
+impl BlockFormat {
+    /// Function to get the size of a 4x4 block in bytes
+    pub fn block_bytes(self) -> usize {
+        match self {
+            BlockFormat::Bc1 | BlockFormat::Bc4 => 8,
+            _ => 16,
+        }
+    }
+
+    /// Function to get the size of an image in bytes, partial blocks at the edges are stored as full blocks
+    pub fn level_size(self, width : u32, height : u32) -> usize {
+        width.div_ceil(4) as usize * height.div_ceil(4) as usize * self.block_bytes()
+    }
+
+    /// Function to get the OpenGL internal format, BC4 and BC5 have no sRGB variant
+    pub fn gl_format(self, srgb : bool) -> GLenum {
+        match (self, srgb) {
+            (BlockFormat::Bc1, false) => COMPRESSED_RGBA_S3TC_DXT1,
+            (BlockFormat::Bc1, true) => COMPRESSED_SRGB_ALPHA_S3TC_DXT1,
+            (BlockFormat::Bc2, false) => COMPRESSED_RGBA_S3TC_DXT3,
+            (BlockFormat::Bc2, true) => COMPRESSED_SRGB_ALPHA_S3TC_DXT3,
+            (BlockFormat::Bc3, false) => COMPRESSED_RGBA_S3TC_DXT5,
+            (BlockFormat::Bc3, true) => COMPRESSED_SRGB_ALPHA_S3TC_DXT5,
+            (BlockFormat::Bc4, _) => gl::COMPRESSED_RED_RGTC1,
+            (BlockFormat::Bc5, _) => gl::COMPRESSED_RG_RGTC2,
+            (BlockFormat::Bc7, false) => gl::COMPRESSED_RGBA_BPTC_UNORM,
+            (BlockFormat::Bc7, true) => gl::COMPRESSED_SRGB_ALPHA_BPTC_UNORM,
+        }
+    }
+
+    /// Function to check if the driver can sample this format, needs a current OpenGL context
+    pub fn is_supported(self, srgb : bool) -> bool {
+        match self {
+            BlockFormat::Bc1 | BlockFormat::Bc2 | BlockFormat::Bc3 => {
+                texture::has_extension("GL_EXT_texture_compression_s3tc")
+                    && (!srgb || texture::has_extension("GL_EXT_texture_sRGB") || texture::has_extension("GL_EXT_texture_compression_s3tc_srgb"))
+            },
+            BlockFormat::Bc4 | BlockFormat::Bc5 => true,
+            BlockFormat::Bc7 => gl_version_at_least(4, 2) || texture::has_extension("GL_ARB_texture_compression_bptc"),
+        }
+    }
+}
+
+/// Compressed image with its mip chain, level 0 is the full size image
+#[derive(Clone, Debug)]
+pub struct CompressedImage {
+    pub format : BlockFormat,
+    pub width : u32,
+    pub height : u32,
+    pub levels : Vec<Vec<u8>>,
+}
+
+#[derive(Debug)]
+pub enum CompressedTextureError {
+    Io(io::Error),
+    Dds(ddsfile::Error),
+    Ktx2(ktx2::ParseError),
+    UnsupportedFormat(String),
+    Supercompressed,    // KTX2 files using BasisLZ, zstd or zlib
+    MissingData,        // the file is shorter than its first level
+}
+
+impl fmt::Display for CompressedTextureError {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CompressedTextureError::Io(error) => write!(f, "{}", error),
+            CompressedTextureError::Dds(error) => write!(f, "invalid DDS file : {}", error),
+            CompressedTextureError::Ktx2(error) => write!(f, "invalid KTX2 file : {}", error),
+            CompressedTextureError::UnsupportedFormat(format) => write!(f, "unsupported format {}", format),
+            CompressedTextureError::Supercompressed => write!(f, "supercompressed KTX2 files are not supported"),
+            CompressedTextureError::MissingData => write!(f, "file is missing image data"),
+        }
+    }
+}
+
+impl Error for CompressedTextureError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CompressedTextureError::Io(error) => Some(error),
+            CompressedTextureError::Dds(error) => Some(error),
+            CompressedTextureError::Ktx2(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// Function to check if a path is a DDS or KTX2 file, by extension
+pub fn is_compressed_path(path : &str) -> bool {
+    let extension = Path::new(path).extension().and_then(|e| e.to_str()).unwrap_or("").to_ascii_lowercase();
+    extension == "dds" || extension == "ktx2"
+}
+
+/// Function to read a DDS or KTX2 file
+pub fn read_compressed(path : &Path) -> Result<CompressedImage, CompressedTextureError> {
+    let bytes = fs::read(path).map_err(CompressedTextureError::Io)?;
+    let is_ktx2 = path.extension().and_then(|e| e.to_str()).is_some_and(|e| e.eq_ignore_ascii_case("ktx2"));
+    if is_ktx2 { parse_ktx2(&bytes) } else { parse_dds(&bytes) }
+}
+
+/// Function to parse a DDS file, only the first array layer or cubemap face is used
+pub fn parse_dds(bytes : &[u8]) -> Result<CompressedImage, CompressedTextureError> {
+    let dds = Dds::read(bytes).map_err(CompressedTextureError::Dds)?;
+
+    // Files with a DX10 header have a dxgi format, older ones a FourCC
+    let format = match (dds.get_dxgi_format(), dds.get_d3d_format()) {
+        (_, Some(D3DFormat::DXT1)) => BlockFormat::Bc1,
+        (_, Some(D3DFormat::DXT2 | D3DFormat::DXT3)) => BlockFormat::Bc2,
+        (_, Some(D3DFormat::DXT4 | D3DFormat::DXT5)) => BlockFormat::Bc3,
+        (Some(DxgiFormat::BC1_Typeless | DxgiFormat::BC1_UNorm | DxgiFormat::BC1_UNorm_sRGB), _) => BlockFormat::Bc1,
+        (Some(DxgiFormat::BC2_Typeless | DxgiFormat::BC2_UNorm | DxgiFormat::BC2_UNorm_sRGB), _) => BlockFormat::Bc2,
+        (Some(DxgiFormat::BC3_Typeless | DxgiFormat::BC3_UNorm | DxgiFormat::BC3_UNorm_sRGB), _) => BlockFormat::Bc3,
+        (Some(DxgiFormat::BC4_Typeless | DxgiFormat::BC4_UNorm), _) => BlockFormat::Bc4,
+        (Some(DxgiFormat::BC5_Typeless | DxgiFormat::BC5_UNorm), _) => BlockFormat::Bc5,
+        (Some(DxgiFormat::BC7_Typeless | DxgiFormat::BC7_UNorm | DxgiFormat::BC7_UNorm_sRGB), _) => BlockFormat::Bc7,
+        (Some(dxgi), _) => return Err(CompressedTextureError::UnsupportedFormat(format!("{:?}", dxgi))),
+        (None, d3d) => return Err(CompressedTextureError::UnsupportedFormat(format!("{:?}", d3d))),
+    };
+
+    // The first layer comes first, the data is not sliced with get_data so truncated mip chains can still be used
+    let levels = split_levels(format, dds.get_width(), dds.get_height(), dds.get_num_mipmap_levels().max(1), &dds.data)?;
+    Ok(CompressedImage { format, width : dds.get_width(), height : dds.get_height(), levels })
+}
+
+/// Function to parse a KTX2 file, only the first array layer or cubemap face is used
+pub fn parse_ktx2(bytes : &[u8]) -> Result<CompressedImage, CompressedTextureError> {
+    use ktx2::Format;
+
+    let reader = ktx2::Reader::new(bytes).map_err(CompressedTextureError::Ktx2)?;
+    let header = reader.header();
+    if header.supercompression_scheme.is_some() {
+        return Err(CompressedTextureError::Supercompressed);
+    }
+
+    let format = match header.format {
+        Some(Format::BC1_RGB_UNORM_BLOCK | Format::BC1_RGB_SRGB_BLOCK | Format::BC1_RGBA_UNORM_BLOCK | Format::BC1_RGBA_SRGB_BLOCK) => BlockFormat::Bc1,
+        Some(Format::BC2_UNORM_BLOCK | Format::BC2_SRGB_BLOCK) => BlockFormat::Bc2,
+        Some(Format::BC3_UNORM_BLOCK | Format::BC3_SRGB_BLOCK) => BlockFormat::Bc3,
+        Some(Format::BC4_UNORM_BLOCK) => BlockFormat::Bc4,
+        Some(Format::BC5_UNORM_BLOCK) => BlockFormat::Bc5,
+        Some(Format::BC7_UNORM_BLOCK | Format::BC7_SRGB_BLOCK) => BlockFormat::Bc7,
+        other => return Err(CompressedTextureError::UnsupportedFormat(format!("{:?}", other))),
+    };
+
+    let (width, height) = (header.pixel_width, header.pixel_height.max(1));
+    let mut levels = Vec::new();
+    for (i, level) in reader.levels().enumerate() {
+        let (level_width, level_height) = level_dimensions(width, height, i);
+        let size = format.level_size(level_width, level_height);
+        // Each level holds every layer and face one after another, the first one comes first
+        match level.data.get(..size) {
+            Some(data) => levels.push(data.to_vec()),
+            None if i == 0 => return Err(CompressedTextureError::MissingData),
+            None => break,
+        }
+    }
+    Ok(CompressedImage { format, width, height, levels })
+}
+
+/// Function to split consecutive mip levels, a truncated chain keeps the complete levels
+fn split_levels(format : BlockFormat, width : u32, height : u32, level_count : u32, mut data : &[u8]) -> Result<Vec<Vec<u8>>, CompressedTextureError> {
+    let mut levels = Vec::new();
+    for i in 0..level_count as usize {
+        let (level_width, level_height) = level_dimensions(width, height, i);
+        let size = format.level_size(level_width, level_height);
+        if data.len() < size {
+            if i == 0 {
+                return Err(CompressedTextureError::MissingData);
+            }
+            println!("Warning: compressed texture has {} of {} mip levels", i, level_count);
+            break;
+        }
+        levels.push(data[..size].to_vec());
+        data = &data[size..];
+    }
+    Ok(levels)
+}
+
+fn level_dimensions(width : u32, height : u32, level : usize) -> (u32, u32) {
+    ((width >> level).max(1), (height >> level).max(1))
+}
+
+fn gl_version_at_least(major : GLint, minor : GLint) -> bool {
+    let (mut gl_major, mut gl_minor) = (0, 0);
+    unsafe {
+        gl::GetIntegerv(gl::MAJOR_VERSION, &mut gl_major);
+        gl::GetIntegerv(gl::MINOR_VERSION, &mut gl_minor);
+    }
+    (gl_major, gl_minor) >= (major, minor)
+}
+
+impl CompressedImage {
+    pub fn level_dimensions(&self, level : usize) -> (u32, u32) {
+        level_dimensions(self.width, self.height, level)
+    }
+
+    /// Function to flip every level vertically without decoding, so the first row is the bottom of the image
+    ///
+    /// Returns false and leaves the image unchanged if that is not possible - for BC7, or for levels whose height
+    /// is above 4 and not a multiple of 4, since the padding rows would move to the top
+    pub fn flip_vertical(&mut self) -> bool {
+        let can_flip = self.format != BlockFormat::Bc7 && (0..self.levels.len()).all(|i| {
+            let height = self.level_dimensions(i).1;
+            height <= 4 || height.is_multiple_of(4)
+        });
+        if !can_flip {
+            return false;
+        }
+
+        let block_bytes = self.format.block_bytes();
+        for i in 0..self.levels.len() {
+            let (width, height) = self.level_dimensions(i);
+            let row_bytes = width.div_ceil(4) as usize * block_bytes;
+            let level = &mut self.levels[i];
+
+            // Reverse the order of block rows
+            let block_rows = level.len() / row_bytes;
+            for row in 0..block_rows / 2 {
+                let (top, bottom) = level.split_at_mut((block_rows - 1 - row) * row_bytes);
+                top[row * row_bytes..(row + 1) * row_bytes].swap_with_slice(&mut bottom[..row_bytes]);
+            }
+
+            // Then the rows inside each block, only the used rows if the image is less than a block tall
+            let rows = height.min(4) as usize;
+            for block in level.chunks_exact_mut(block_bytes) {
+                flip_block(self.format, block, rows);
+            }
+        }
+        true
+    }
+
+    /// Function to decode a level to RGBA8 on the cpu, BC4 is stored as (r,0,0,1) and BC5 as (r,g,0,1) like OpenGL
+    pub fn decode_level(&self, level : usize) -> Vec<u8> {
+        let (width, height) = self.level_dimensions(level);
+        let (width, height) = (width as usize, height as usize);
+        let blocks_x = width.div_ceil(4);
+        let mut pixels = vec![0u8; width * height * 4];
+
+        for (i, block) in self.levels[level].chunks_exact(self.format.block_bytes()).enumerate() {
+            let decoded = decode_block(self.format, block);
+            let (block_x, block_y) = (i % blocks_x * 4, i / blocks_x * 4);
+            for (j, pixel) in decoded.iter().enumerate() {
+                let (x, y) = (block_x + j % 4, block_y + j / 4);
+                if x < width && y < height {
+                    let offset = (y * width + x) * 4;
+                    pixels[offset..offset + 4].copy_from_slice(pixel);
+                }
+            }
+        }
+        pixels
+    }
+
+    /// Function to create a gl texture with every level of the image
+    ///
+    /// Falls back to uploading RGBA8 decoded on the cpu if the driver does not support the format
+    pub unsafe fn upload(&self, options : &TextureOptions) -> GLuint {
+        let supported = self.format.is_supported(options.srgb);
+        if !supported {
+            println!("Warning: {:?} textures are not supported by the driver, decoding on the cpu", self.format);
+        }
+
+        let mut texture: GLuint = 0;
+        unsafe {
+            gl::GenTextures(1, &mut texture);
+            gl::BindTexture(gl::TEXTURE_2D, texture);
+            options.apply(gl::TEXTURE_2D);
+
+            // Mip maps are not generated for compressed textures, limit sampling to the levels in the file
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAX_LEVEL, self.levels.len() as GLint - 1);
+
+            for (i, data) in self.levels.iter().enumerate() {
+                let (width, height) = self.level_dimensions(i);
+                if supported {
+                    gl::CompressedTexImage2D(gl::TEXTURE_2D, i as GLint, self.format.gl_format(options.srgb), width as GLsizei, height as GLsizei, 0, data.len() as GLsizei, data.as_ptr() as *const c_void);
+                }
+                else {
+                    let pixels = self.decode_level(i);
+                    gl::TexImage2D(gl::TEXTURE_2D, i as GLint, options.internal_format() as GLint, width as GLsizei, height as GLsizei, 0, gl::RGBA, gl::UNSIGNED_BYTE, pixels.as_ptr() as *const c_void);
+                }
+            }
+
+            gl::BindTexture(gl::TEXTURE_2D, 0);
+        }
+        texture
+    }
+}
+
+// --Flipping----------------------------------------------------------------------------------------------------------------- //
+
+/// Function to reverse the first rows of pixel rows in a block
+fn flip_block(format : BlockFormat, block : &mut [u8], rows : usize) {
+    match format {
+        BlockFormat::Bc1 => flip_color_block(block, rows),
+        BlockFormat::Bc2 => {
+            // 4 bit alpha, 2 bytes per row
+            for row in 0..rows / 2 {
+                let other = rows - 1 - row;
+                block.swap(row * 2, other * 2);
+                block.swap(row * 2 + 1, other * 2 + 1);
+            }
+            flip_color_block(&mut block[8..], rows);
+        },
+        BlockFormat::Bc3 => {
+            flip_alpha_block(&mut block[..8], rows);
+            flip_color_block(&mut block[8..], rows);
+        },
+        BlockFormat::Bc4 => flip_alpha_block(block, rows),
+        BlockFormat::Bc5 => {
+            flip_alpha_block(&mut block[..8], rows);
+            flip_alpha_block(&mut block[8..], rows);
+        },
+        BlockFormat::Bc7 => unreachable!("BC7 blocks cannot be flipped"),
+    }
+}
+
+/// BC1 colour block - two colours, then one byte of 2 bit indices per row
+fn flip_color_block(block : &mut [u8], rows : usize) {
+    block[4..4 + rows].reverse();
+}
+
+/// BC4 block - two values, then 48 bits of 3 bit indices, 12 bits per row
+fn flip_alpha_block(block : &mut [u8], rows : usize) {
+    let mut bits = [0u8; 8];
+    bits[..6].copy_from_slice(&block[2..8]);
+    let indices = u64::from_le_bytes(bits);
+
+    let mut flipped = indices;
+    for row in 0..rows {
+        let other = rows - 1 - row;
+        let value = (indices >> (row * 12)) & 0xFFF;
+        flipped = (flipped & !(0xFFF << (other * 12))) | (value << (other * 12));
+    }
+    block[2..8].copy_from_slice(&flipped.to_le_bytes()[..6]);
+}
+
+// --Cpu decoding------------------------------------------------------------------------------------------------------------- //
+
+/// Function to decode a block to 16 RGBA8 pixels, row by row
+fn decode_block(format : BlockFormat, block : &[u8]) -> [[u8; 4]; 16] {
+    match format {
+        BlockFormat::Bc1 => decode_color_block(block, true),
+        BlockFormat::Bc2 => {
+            let mut pixels = decode_color_block(&block[8..], false);
+            let alpha = u64::from_le_bytes(block[..8].try_into().unwrap());
+            for (i, pixel) in pixels.iter_mut().enumerate() {
+                pixel[3] = ((alpha >> (i * 4)) & 0xF) as u8 * 17;
+            }
+            pixels
+        },
+        BlockFormat::Bc3 => {
+            let mut pixels = decode_color_block(&block[8..], false);
+            for (pixel, alpha) in pixels.iter_mut().zip(decode_alpha_block(&block[..8])) {
+                pixel[3] = alpha;
+            }
+            pixels
+        },
+        BlockFormat::Bc4 => decode_alpha_block(block).map(|r| [r, 0, 0, 255]),
+        BlockFormat::Bc5 => {
+            let (red, green) = (decode_alpha_block(&block[..8]), decode_alpha_block(&block[8..]));
+            std::array::from_fn(|i| [red[i], green[i], 0, 255])
+        },
+        BlockFormat::Bc7 => bc7::decode_block(block.try_into().unwrap()),
+    }
+}
+
+fn rgb565(color : u16) -> [u8; 3] {
+    let (r, g, b) = ((color >> 11) & 31, (color >> 5) & 63, color & 31);
+    [((r << 3) | (r >> 2)) as u8, ((g << 2) | (g >> 4)) as u8, ((b << 3) | (b >> 2)) as u8]
+}
+
+/// BC1 colour block, BC2 and BC3 always use the 4 colour mode
+fn decode_color_block(block : &[u8], allow_alpha : bool) -> [[u8; 4]; 16] {
+    let (c0, c1) = (u16::from_le_bytes([block[0], block[1]]), u16::from_le_bytes([block[2], block[3]]));
+    let (a, b) = (rgb565(c0), rgb565(c1));
+    let mix = |wa : u32, wb : u32| -> [u8; 4] {
+        let channel = |i : usize| ((a[i] as u32 * wa + b[i] as u32 * wb) / (wa + wb)) as u8;
+        [channel(0), channel(1), channel(2), 255]
+    };
+
+    let palette = if c0 > c1 || !allow_alpha {
+        [mix(1, 0), mix(0, 1), mix(2, 1), mix(1, 2)]
+    } else {
+        // Third colour is the average, fourth is transparent black
+        [mix(1, 0), mix(0, 1), mix(1, 1), [0, 0, 0, 0]]
+    };
+
+    let indices = u32::from_le_bytes(block[4..8].try_into().unwrap());
+    std::array::from_fn(|i| palette[((indices >> (i * 2)) & 3) as usize])
+}
+
+/// BC4 block, also used for the alpha of BC3 and both channels of BC5
+fn decode_alpha_block(block : &[u8]) -> [u8; 16] {
+    let (a0, a1) = (block[0] as u32, block[1] as u32);
+    let mut palette = [0u8; 8];
+    palette[0] = a0 as u8;
+    palette[1] = a1 as u8;
+    if a0 > a1 {
+        for i in 1..7 {
+            palette[i + 1] = (((7 - i as u32) * a0 + i as u32 * a1) / 7) as u8;
+        }
+    }
+    else {
+        for i in 1..5 {
+            palette[i + 1] = (((5 - i as u32) * a0 + i as u32 * a1) / 5) as u8;
+        }
+        palette[6] = 0;
+        palette[7] = 255;
+    }
+
+    let mut bits = [0u8; 8];
+    bits[..6].copy_from_slice(&block[2..8]);
+    let indices = u64::from_le_bytes(bits);
+    std::array::from_fn(|i| palette[((indices >> (i * 3)) & 7) as usize])
+}
+
+mod bc7 {
+    // BC7 decoding following the BPTC specification (ARB_texture_compression_bptc)
+
+    struct Mode {
+        subsets : usize,
+        partition_bits : u32,
+        rotation_bits : u32,
+        index_selection_bits : u32,
+        color_bits : u32,
+        alpha_bits : u32,
+        endpoint_pbits : bool,  // one p-bit per endpoint
+        shared_pbits : bool,    // one p-bit per subset
+        index_bits : u32,
+        index2_bits : u32,      // separate alpha (or colour, with index selection) indices
+    }
+
+    const MODES : [Mode; 8] = [
+        Mode { subsets : 3, partition_bits : 4, rotation_bits : 0, index_selection_bits : 0, color_bits : 4, alpha_bits : 0, endpoint_pbits : true, shared_pbits : false, index_bits : 3, index2_bits : 0 },
+        Mode { subsets : 2, partition_bits : 6, rotation_bits : 0, index_selection_bits : 0, color_bits : 6, alpha_bits : 0, endpoint_pbits : false, shared_pbits : true, index_bits : 3, index2_bits : 0 },
+        Mode { subsets : 3, partition_bits : 6, rotation_bits : 0, index_selection_bits : 0, color_bits : 5, alpha_bits : 0, endpoint_pbits : false, shared_pbits : false, index_bits : 2, index2_bits : 0 },
+        Mode { subsets : 2, partition_bits : 6, rotation_bits : 0, index_selection_bits : 0, color_bits : 7, alpha_bits : 0, endpoint_pbits : true, shared_pbits : false, index_bits : 2, index2_bits : 0 },
+        Mode { subsets : 1, partition_bits : 0, rotation_bits : 2, index_selection_bits : 1, color_bits : 5, alpha_bits : 6, endpoint_pbits : false, shared_pbits : false, index_bits : 2, index2_bits : 3 },
+        Mode { subsets : 1, partition_bits : 0, rotation_bits : 2, index_selection_bits : 0, color_bits : 7, alpha_bits : 8, endpoint_pbits : false, shared_pbits : false, index_bits : 2, index2_bits : 2 },
+        Mode { subsets : 1, partition_bits : 0, rotation_bits : 0, index_selection_bits : 0, color_bits : 7, alpha_bits : 7, endpoint_pbits : true, shared_pbits : false, index_bits : 4, index2_bits : 0 },
+        Mode { subsets : 2, partition_bits : 6, rotation_bits : 0, index_selection_bits : 0, color_bits : 5, alpha_bits : 5, endpoint_pbits : true, shared_pbits : false, index_bits : 2, index2_bits : 0 },
+    ];
+
+    // Subset of each pixel for 2 subset partitions, bit i is pixel i
+    pub(super) const PARTITIONS_2 : [u16; 64] = [
+        0xCCCC, 0x8888, 0xEEEE, 0xECC8, 0xC880, 0xFEEC, 0xFEC8, 0xEC80, 0xC800, 0xFFEC, 0xFE80, 0xE800, 0xFFE8, 0xFF00, 0xFFF0, 0xF000,
+        0xF710, 0x008E, 0x7100, 0x08CE, 0x008C, 0x7310, 0x3100, 0x8CCE, 0x088C, 0x3110, 0x6666, 0x366C, 0x17E8, 0x0FF0, 0x718E, 0x399C,
+        0xAAAA, 0xF0F0, 0x5A5A, 0x33CC, 0x3C3C, 0x55AA, 0x9696, 0xA55A, 0x73CE, 0x13C8, 0x324C, 0x3BDC, 0x6996, 0xC33C, 0x9966, 0x0660,
+        0x0272, 0x04E4, 0x4E40, 0x2720, 0xC936, 0x936C, 0x39C6, 0x639C, 0x9336, 0x9CC6, 0x817E, 0xE718, 0xCCF0, 0x0FCC, 0x7744, 0xEE22,
+    ];
+
+    // Subset of each pixel for 3 subset partitions
+    pub(super) const PARTITIONS_3 : [[u8; 16]; 64] = [
+        [0,0,1,1,0,0,1,1,0,2,2,1,2,2,2,2], [0,0,0,1,0,0,1,1,2,2,1,1,2,2,2,1], [0,0,0,0,2,0,0,1,2,2,1,1,2,2,1,1], [0,2,2,2,0,0,2,2,0,0,1,1,0,1,1,1],
+        [0,0,0,0,0,0,0,0,1,1,2,2,1,1,2,2], [0,0,1,1,0,0,1,1,0,0,2,2,0,0,2,2], [0,0,2,2,0,0,2,2,1,1,1,1,1,1,1,1], [0,0,1,1,0,0,1,1,2,2,1,1,2,2,1,1],
+        [0,0,0,0,0,0,0,0,1,1,1,1,2,2,2,2], [0,0,0,0,1,1,1,1,1,1,1,1,2,2,2,2], [0,0,0,0,1,1,1,1,2,2,2,2,2,2,2,2], [0,0,1,2,0,0,1,2,0,0,1,2,0,0,1,2],
+        [0,1,1,2,0,1,1,2,0,1,1,2,0,1,1,2], [0,1,2,2,0,1,2,2,0,1,2,2,0,1,2,2], [0,0,1,1,0,1,1,2,1,1,2,2,1,2,2,2], [0,0,1,1,2,0,0,1,2,2,0,0,2,2,2,0],
+        [0,0,0,1,0,0,1,1,0,1,1,2,1,1,2,2], [0,1,1,1,0,0,1,1,2,0,0,1,2,2,0,0], [0,0,0,0,1,1,2,2,1,1,2,2,1,1,2,2], [0,0,2,2,0,0,2,2,0,0,2,2,1,1,1,1],
+        [0,1,1,1,0,1,1,1,0,2,2,2,0,2,2,2], [0,0,0,1,0,0,0,1,2,2,2,1,2,2,2,1], [0,0,0,0,0,0,1,1,0,1,2,2,0,1,2,2], [0,0,0,0,1,1,0,0,2,2,1,0,2,2,1,0],
+        [0,1,2,2,0,1,2,2,0,0,1,1,0,0,0,0], [0,0,1,2,0,0,1,2,1,1,2,2,2,2,2,2], [0,1,1,0,1,2,2,1,1,2,2,1,0,1,1,0], [0,0,0,0,0,1,1,0,1,2,2,1,1,2,2,1],
+        [0,0,2,2,1,1,0,2,1,1,0,2,0,0,2,2], [0,1,1,0,0,1,1,0,2,0,0,2,2,2,2,2], [0,0,1,1,0,1,2,2,0,1,2,2,0,0,1,1], [0,0,0,0,2,0,0,0,2,2,1,1,2,2,2,1],
+        [0,0,0,0,0,0,0,2,1,1,2,2,1,2,2,2], [0,2,2,2,0,0,2,2,0,0,1,2,0,0,1,1], [0,0,1,1,0,0,1,2,0,0,2,2,0,2,2,2], [0,1,2,0,0,1,2,0,0,1,2,0,0,1,2,0],
+        [0,0,0,0,1,1,1,1,2,2,2,2,0,0,0,0], [0,1,2,0,1,2,0,1,2,0,1,2,0,1,2,0], [0,1,2,0,2,0,1,2,1,2,0,1,0,1,2,0], [0,0,1,1,2,2,0,0,1,1,2,2,0,0,1,1],
+        [0,0,1,1,1,1,2,2,2,2,0,0,0,0,1,1], [0,1,0,1,0,1,0,1,2,2,2,2,2,2,2,2], [0,0,0,0,0,0,0,0,2,1,2,1,2,1,2,1], [0,0,2,2,1,1,2,2,0,0,2,2,1,1,2,2],
+        [0,0,2,2,0,0,1,1,0,0,2,2,0,0,1,1], [0,2,2,0,1,2,2,1,0,2,2,0,1,2,2,1], [0,1,0,1,2,2,2,2,2,2,2,2,0,1,0,1], [0,0,0,0,2,1,2,1,2,1,2,1,2,1,2,1],
+        [0,1,0,1,0,1,0,1,0,1,0,1,2,2,2,2], [0,2,2,2,0,1,1,1,0,2,2,2,0,1,1,1], [0,0,0,2,1,1,1,2,0,0,0,2,1,1,1,2], [0,0,0,0,2,1,1,2,2,1,1,2,2,1,1,2],
+        [0,2,2,2,0,1,1,1,0,1,1,1,0,2,2,2], [0,0,0,2,1,1,1,2,1,1,1,2,0,0,0,2], [0,1,1,0,0,1,1,0,0,1,1,0,2,2,2,2], [0,0,0,0,0,0,0,0,2,1,1,2,2,1,1,2],
+        [0,1,1,0,0,1,1,0,2,2,2,2,2,2,2,2], [0,0,2,2,0,0,1,1,0,0,1,1,0,0,2,2], [0,0,2,2,1,1,2,2,1,1,2,2,0,0,2,2], [0,0,0,0,0,0,0,0,0,0,0,0,2,1,1,2],
+        [0,0,0,2,0,0,0,1,0,0,0,2,0,0,0,1], [0,2,2,2,1,2,2,2,0,2,2,2,1,2,2,2], [0,1,0,1,2,2,2,2,2,2,2,2,2,2,2,2], [0,1,1,1,2,0,1,1,2,2,0,1,2,2,2,0],
+    ];
+
+    // Anchor pixel of the second subset in 2 subset partitions, the first subset's anchor is always pixel 0
+    pub(super) const ANCHORS_2 : [u8; 64] = [
+        15,15,15,15,15,15,15,15, 15,15,15,15,15,15,15,15, 15, 2, 8, 2, 2, 8, 8,15, 2, 8, 2, 2, 8, 8, 2, 2,
+        15,15, 6, 8, 2, 8,15,15, 2, 8, 2, 2, 2,15,15, 6, 6, 2, 6, 8,15,15, 2, 2, 15,15,15,15,15, 2, 2,15,
+    ];
+
+    // Anchor pixels of the second and third subsets in 3 subset partitions
+    pub(super) const ANCHORS_3 : [[u8; 2]; 64] = [
+        [3,15], [3,8], [15,8], [15,3], [8,15], [3,15], [15,3], [15,8], [8,15], [8,15], [6,15], [6,15], [6,15], [5,15], [3,15], [3,8],
+        [3,15], [3,8], [8,15], [15,3], [3,15], [3,8], [6,15], [10,8], [5,3], [8,15], [8,6], [6,10], [8,15], [5,15], [15,10], [15,8],
+        [8,15], [15,3], [3,15], [5,10], [6,10], [10,8], [8,9], [15,10], [15,6], [3,15], [15,8], [5,15], [15,3], [15,6], [15,6], [15,8],
+        [3,15], [15,3], [5,15], [5,15], [5,15], [8,15], [5,15], [10,15], [5,15], [10,15], [8,15], [13,15], [15,3], [12,15], [3,15], [3,8],
+    ];
+
+    const WEIGHTS_2 : [u32; 4] = [0, 21, 43, 64];
+    const WEIGHTS_3 : [u32; 8] = [0, 9, 18, 27, 37, 46, 55, 64];
+    const WEIGHTS_4 : [u32; 16] = [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+
+    struct BitReader {
+        bits : u128,
+        position : u32,
+    }
+
+    impl BitReader {
+        fn read(&mut self, count : u32) -> u32 {
+            if count == 0 {
+                return 0;
+            }
+            let value = (self.bits >> self.position) & ((1u128 << count) - 1);
+            self.position += count;
+            value as u32
+        }
+    }
+
+    fn weights(bits : u32) -> &'static [u32] {
+        match bits {
+            2 => &WEIGHTS_2,
+            3 => &WEIGHTS_3,
+            _ => &WEIGHTS_4,
+        }
+    }
+
+    fn interpolate(e0 : u32, e1 : u32, weight : u32) -> u8 {
+        (((64 - weight) * e0 + weight * e1 + 32) >> 6) as u8
+    }
+
+    /// Function to expand a value with bits bits to 8 bits, repeating the high bits in the low bits
+    fn unquantize(value : u32, bits : u32) -> u32 {
+        let value = value << (8 - bits);
+        value | (value >> bits)
+    }
+
+    pub(super) fn decode_block(block : &[u8; 16]) -> [[u8; 4]; 16] {
+        let mut reader = BitReader { bits : u128::from_le_bytes(*block), position : 0 };
+
+        // The mode is the number of zero bits before the first one
+        let Some(mode_index) = (0..8).find(|i| block[0] & (1 << i) != 0) else {
+            return [[0; 4]; 16];    // reserved mode, decoded as transparent black
+        };
+        let mode = &MODES[mode_index];
+        reader.read(mode_index as u32 + 1);
+
+        let partition = reader.read(mode.partition_bits) as usize;
+        let rotation = reader.read(mode.rotation_bits);
+        let index_selection = reader.read(mode.index_selection_bits);
+
+        // Endpoints - all reds, then all greens, blues and alphas
+        let endpoint_count = mode.subsets * 2;
+        let mut endpoints = [[0u32; 4]; 6];
+        for channel in 0..3 {
+            for endpoint in endpoints.iter_mut().take(endpoint_count) {
+                endpoint[channel] = reader.read(mode.color_bits);
+            }
+        }
+        for endpoint in endpoints.iter_mut().take(endpoint_count) {
+            endpoint[3] = if mode.alpha_bits > 0 { reader.read(mode.alpha_bits) } else { 255 };
+        }
+
+        // P-bits add a low bit to every channel of an endpoint
+        let (mut color_bits, mut alpha_bits) = (mode.color_bits, mode.alpha_bits);
+        if mode.endpoint_pbits || mode.shared_pbits {
+            let pbits: Vec<u32> = if mode.endpoint_pbits {
+                (0..endpoint_count).map(|_| reader.read(1)).collect()
+            } else {
+                let shared: Vec<u32> = (0..mode.subsets).map(|_| reader.read(1)).collect();
+                (0..endpoint_count).map(|i| shared[i / 2]).collect()
+            };
+            for (endpoint, pbit) in endpoints.iter_mut().zip(pbits) {
+                for value in &mut endpoint[..3] {
+                    *value = (*value << 1) | pbit;
+                }
+                if mode.alpha_bits > 0 {
+                    endpoint[3] = (endpoint[3] << 1) | pbit;
+                }
+            }
+            color_bits += 1;
+            if alpha_bits > 0 {
+                alpha_bits += 1;
+            }
+        }
+        for endpoint in endpoints.iter_mut().take(endpoint_count) {
+            for value in &mut endpoint[..3] {
+                *value = unquantize(*value, color_bits);
+            }
+            if alpha_bits > 0 {
+                endpoint[3] = unquantize(endpoint[3], alpha_bits);
+            }
+        }
+
+        let subset_of = |pixel : usize| -> usize {
+            match mode.subsets {
+                1 => 0,
+                2 => ((PARTITIONS_2[partition] >> pixel) & 1) as usize,
+                _ => PARTITIONS_3[partition][pixel] as usize,
+            }
+        };
+        // Anchor pixels store their index with one bit less, the high bit is implied to be 0
+        let is_anchor = |pixel : usize| -> bool {
+            pixel == 0 || match mode.subsets {
+                2 => pixel == ANCHORS_2[partition] as usize,
+                3 => ANCHORS_3[partition].contains(&(pixel as u8)),
+                _ => false,
+            }
+        };
+
+        let indices: Vec<u32> = (0..16).map(|pixel| reader.read(mode.index_bits - is_anchor(pixel) as u32)).collect();
+        let indices2: Vec<u32> = if mode.index2_bits > 0 {
+            (0..16).map(|pixel| reader.read(mode.index2_bits - (pixel == 0) as u32)).collect()
+        } else {
+            Vec::new()
+        };
+
+        std::array::from_fn(|pixel| {
+            let subset = subset_of(pixel);
+            let (e0, e1) = (endpoints[subset * 2], endpoints[subset * 2 + 1]);
+
+            // With a second index set colour and alpha use different indices, index selection swaps them
+            let (color_weight, alpha_weight) = if mode.index2_bits == 0 {
+                let weight = weights(mode.index_bits)[indices[pixel] as usize];
+                (weight, weight)
+            } else if index_selection == 0 {
+                (weights(mode.index_bits)[indices[pixel] as usize], weights(mode.index2_bits)[indices2[pixel] as usize])
+            } else {
+                (weights(mode.index2_bits)[indices2[pixel] as usize], weights(mode.index_bits)[indices[pixel] as usize])
+            };
+
+            let mut color = [
+                interpolate(e0[0], e1[0], color_weight),
+                interpolate(e0[1], e1[1], color_weight),
+                interpolate(e0[2], e1[2], color_weight),
+                interpolate(e0[3], e1[3], alpha_weight),
+            ];
+            // Rotation swaps alpha with one of the colour channels
+            if rotation > 0 {
+                color.swap(3, rotation as usize - 1);
+            }
+            color
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Function to build a minimal uncompressed KTX2 file with the given levels, level 0 first
+    fn ktx2_file(vk_format : u32, width : u32, height : u32, levels : &[Vec<u8>]) -> Vec<u8> {
+        let index_end = 80 + 24 * levels.len();
+        let dfd = [0u8; 8];     // not read, only needs to be in bounds
+        let mut file = Vec::new();
+        file.extend_from_slice(&[0xAB, b'K', b'T', b'X', b' ', b'2', b'0', 0xBB, b'\r', b'\n', 0x1A, b'\n']);
+        for value in [vk_format, 1, width, height, 0, 0, 1, levels.len() as u32, 0] {
+            file.extend_from_slice(&value.to_le_bytes());
+        }
+        for value in [index_end as u32, dfd.len() as u32, 0, 0] {
+            file.extend_from_slice(&value.to_le_bytes());
+        }
+        file.extend_from_slice(&0u64.to_le_bytes());
+        file.extend_from_slice(&0u64.to_le_bytes());
+
+        // Level data is stored smallest level first after the dfd
+        let mut offset = index_end + dfd.len();
+        let mut offsets = vec![0; levels.len()];
+        for (i, level) in levels.iter().enumerate().rev() {
+            offsets[i] = offset;
+            offset += level.len();
+        }
+        for (level, offset) in levels.iter().zip(&offsets) {
+            for value in [*offset as u64, level.len() as u64, level.len() as u64] {
+                file.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        file.extend_from_slice(&dfd);
+        for level in levels.iter().rev() {
+            file.extend_from_slice(level);
+        }
+        file
+    }
+
+    fn dds_file(format : DxgiFormat, width : u32, height : u32, mipmap_levels : u32, data : Vec<u8>) -> Vec<u8> {
+        let mut dds = Dds::new_dxgi(ddsfile::NewDxgiParams {
+            height,
+            width,
+            depth : None,
+            format,
+            mipmap_levels : Some(mipmap_levels),
+            array_layers : None,
+            caps2 : None,
+            is_cubemap : false,
+            resource_dimension : ddsfile::D3D10ResourceDimension::Texture2D,
+            alpha_mode : ddsfile::AlphaMode::Unknown,
+        }).unwrap();
+        dds.data = data;
+        let mut file = Vec::new();
+        dds.write(&mut file).unwrap();
+        file
+    }
+
+    #[test]
+    fn level_sizes_round_up_to_blocks() {
+        assert_eq!(BlockFormat::Bc1.level_size(8, 8), 4 * 8);
+        assert_eq!(BlockFormat::Bc3.level_size(8, 8), 4 * 16);
+        assert_eq!(BlockFormat::Bc1.level_size(1, 1), 8);
+        assert_eq!(BlockFormat::Bc7.level_size(6, 2), 2 * 16);
+    }
+
+    #[test]
+    fn parses_dds_with_mip_chain() {
+        // 8x8 BC3 - levels of 4, 1, 1 and 1 blocks
+        let data: Vec<u8> = (0..7 * 16).map(|i| i as u8).collect();
+        let image = parse_dds(&dds_file(DxgiFormat::BC3_UNorm, 8, 8, 4, data.clone())).unwrap();
+
+        assert_eq!(image.format, BlockFormat::Bc3);
+        assert_eq!((image.width, image.height), (8, 8));
+        assert_eq!(image.levels.len(), 4);
+        assert_eq!(image.levels[0], data[..64]);
+        assert_eq!(image.levels[1], data[64..80]);
+        assert_eq!(image.levels[3], data[96..112]);
+        assert_eq!(image.level_dimensions(3), (1, 1));
+    }
+
+    #[test]
+    fn parses_ktx2_with_mip_chain() {
+        // VK_FORMAT_BC7_SRGB_BLOCK, 8x4 - levels of 2 and 1 blocks
+        let levels = vec![vec![1u8; 32], vec![2u8; 16]];
+        let image = parse_ktx2(&ktx2_file(146, 8, 4, &levels)).unwrap();
+
+        assert_eq!(image.format, BlockFormat::Bc7);
+        assert_eq!((image.width, image.height), (8, 4));
+        assert_eq!(image.levels, levels);
+    }
+
+    #[test]
+    fn rejects_unsupported_and_broken_files() {
+        // VK_FORMAT_R8G8B8A8_UNORM
+        let error = parse_ktx2(&ktx2_file(37, 4, 4, &[vec![0u8; 64]])).unwrap_err();
+        assert!(matches!(error, CompressedTextureError::UnsupportedFormat(_)));
+
+        assert!(matches!(parse_dds(b"not a dds file").unwrap_err(), CompressedTextureError::Dds(_)));
+        assert!(matches!(parse_ktx2(b"not a ktx2 file").unwrap_err(), CompressedTextureError::Ktx2(_)));
+
+        // Shorter than the first level
+        let error = parse_dds(&dds_file(DxgiFormat::BC1_UNorm, 8, 8, 1, vec![0u8; 8])).unwrap_err();
+        assert!(matches!(error, CompressedTextureError::MissingData));
+    }
+
+    #[test]
+    fn decodes_bc1_block() {
+        // Red and blue endpoints, rows use indices 0, 1, 2 and 3
+        let block = [0x00, 0xF8, 0x1F, 0x00, 0x00, 0x55, 0xAA, 0xFF];
+        let image = CompressedImage { format : BlockFormat::Bc1, width : 4, height : 4, levels : vec![block.to_vec()] };
+        let pixels = image.decode_level(0);
+
+        assert_eq!(pixels[0..4], [255, 0, 0, 255]);
+        assert_eq!(pixels[16..20], [0, 0, 255, 255]);
+        assert_eq!(pixels[32..36], [170, 0, 85, 255]);
+        assert_eq!(pixels[48..52], [85, 0, 170, 255]);
+    }
+
+    #[test]
+    fn decodes_bc4_block() {
+        // 8 value mode, first row index 0, the rest index 1
+        let block = [200, 100, 0b0000_0000, 0b1001_0000, 0b0010_0100, 0b0100_1001, 0b1001_0010, 0b0010_0100];
+        let pixels = decode_block(BlockFormat::Bc4, &block);
+        assert_eq!(pixels[0], [200, 0, 0, 255]);
+        assert_eq!(pixels[3], [200, 0, 0, 255]);
+        assert_eq!(pixels[4], [100, 0, 0, 255]);
+        assert_eq!(pixels[15], [100, 0, 0, 255]);
+    }
+
+    #[test]
+    fn decodes_bc7_mode_6_block() {
+        // Mode 6, endpoints (254,0,0,254) and (0,0,254,254) with p-bits of 0, index 0 everywhere
+        let mut bits : u128 = 1 << 6;
+        let mut position = 7;
+        let mut write = |value : u128, count : u32| {
+            bits |= value << position;
+            position += count;
+        };
+        for value in [127, 0, 0, 0, 0, 127, 127, 127] {
+            write(value, 7);
+        }
+        write(0, 2);    // p-bits, indices are all zero
+        let pixels = bc7::decode_block(&bits.to_le_bytes());
+        assert_eq!(pixels[0], [254, 0, 0, 254]);
+        assert_eq!(pixels[15], [254, 0, 0, 254]);
+
+        // Same block with index 15 for the last pixel
+        let pixels = bc7::decode_block(&(bits | (15u128 << 124)).to_le_bytes());
+        assert_eq!(pixels[15], [0, 0, 254, 254]);
+    }
+
+    #[test]
+    fn bc7_anchors_belong_to_their_subsets() {
+        for partition in 0..64 {
+            assert_eq!(bc7::PARTITIONS_2[partition] & 1, 0);
+            assert_eq!((bc7::PARTITIONS_2[partition] >> bc7::ANCHORS_2[partition]) & 1, 1, "partition {}", partition);
+            assert_eq!(bc7::PARTITIONS_3[partition][0], 0);
+            assert_eq!(bc7::PARTITIONS_3[partition][bc7::ANCHORS_3[partition][0] as usize], 1, "partition {}", partition);
+            assert_eq!(bc7::PARTITIONS_3[partition][bc7::ANCHORS_3[partition][1] as usize], 2, "partition {}", partition);
+        }
+    }
+
+    #[test]
+    fn flipping_matches_flipped_decode() {
+        // 4x8 BC3 with different data in every block
+        let data: Vec<u8> = (0..32).map(|i| (i * 37 % 251) as u8).collect();
+        let mut image = CompressedImage { format : BlockFormat::Bc3, width : 4, height : 8, levels : vec![data] };
+        let decoded = image.decode_level(0);
+
+        assert!(image.flip_vertical());
+        let flipped = image.decode_level(0);
+        for y in 0..8 {
+            assert_eq!(flipped[y * 16..(y + 1) * 16], decoded[(7 - y) * 16..(8 - y) * 16]);
+        }
+
+        let mut bc7 = CompressedImage { format : BlockFormat::Bc7, width : 4, height : 4, levels : vec![vec![0u8; 16]] };
+        assert!(!bc7.flip_vertical());
+    }
+}
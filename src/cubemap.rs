@@ -0,0 +1,246 @@
+// Cubemaps - loading from six face images or an equirectangular HDR image, and environment mapping
+
+// Notes:
+// A cubemap is sampled with a direction instead of uv coordinates, which makes it a good fit for skyboxes and for
+// reflections. Faces are stored in the order +X, -X, +Y, -Y, +Z, -Z (right, left, top, bottom, front, back), and unlike
+// 2D textures their first row is the top of the image, so face images are not flipped.
+// Equirectangular images (the usual format of HDR environments) are converted on the cpu when loading, each face pixel
+// is turned into a direction and the matching point of the panorama is sampled.
+
+use std::ffi::CStr;
+use std::path::Path;
+
+use gl::{self, types::*};
+use nalgebra_glm as glm;
+
+use crate::float_texture::FloatImage;
+use crate::material::TextureSlot;
+use crate::shader::Shader;
+use crate::texture::{Texture, TextureData, TextureError, TextureOptions};
+
+/// Face names in upload order, e.g. for files named right.jpg, left.jpg...
+pub const FACE_NAMES : [&str; 6] = ["right", "left", "top", "bottom", "front", "back"];
+
+/// Texture unit the environment map is bound to, after the material slots and the morph targets
+pub const ENVIRONMENT_TEXTURE_UNIT : u32 = TextureSlot::ALL.len() as u32 + 2;
+
+/// Function to load a cubemap from six images, in the order of FACE_NAMES
+///
+/// Faces can be any format TextureData::read supports, HDR faces are stored as float textures.
+/// options should clamp to edge, or seams show between faces
+pub fn load_cubemap(faces : [&str; 6], directory : &str, options : &TextureOptions) -> Result<Texture, TextureError> {
+    let mut data = Vec::with_capacity(6);
+    for face in faces {
+        let face_data = TextureData::read(face, directory, false)?;
+        let size = match &face_data {
+            TextureData::Rgba8(image) => (image.width(), image.height()),
+            TextureData::Compressed(image) => (image.width, image.height),
+            TextureData::Float(image) => (image.width, image.height),
+        };
+        if size.0 != size.1 {
+            return Err(TextureError::Cubemap(format!("cubemap face {} is {}x{}, faces have to be square", face, size.0, size.1)));
+        }
+        data.push((size.0, face_data));
+    }
+    if data.iter().any(|(size, _)| *size != data[0].0) {
+        return Err(TextureError::Cubemap("cubemap faces have to be the same size".into()));
+    }
+
+    let id = unsafe {
+        create_cubemap(options, |target| {
+            for (face, (size, face_data)) in data.iter().enumerate() {
+                let target = target + face as GLenum;
+                match face_data {
+                    TextureData::Rgba8(image) => {
+                        gl::TexImage2D(target, 0, options.internal_format() as GLint, *size as GLsizei, *size as GLsizei, 0, gl::RGBA, gl::UNSIGNED_BYTE, image.as_ptr() as *const _);
+                    },
+                    // Compressed faces are decoded, only their first level is used
+                    TextureData::Compressed(image) => {
+                        let pixels = image.decode_level(0);
+                        gl::TexImage2D(target, 0, options.internal_format() as GLint, *size as GLsizei, *size as GLsizei, 0, gl::RGBA, gl::UNSIGNED_BYTE, pixels.as_ptr() as *const _);
+                    },
+                    TextureData::Float(image) => image.tex_image(target, options.half_float),
+                }
+            }
+        })
+    };
+    Ok(Texture::new(id, &Path::new(directory).join(faces[0]).to_string_lossy()))
+}
+
+/// Function to load a cubemap from images named after FACE_NAMES in directory, like skybox/right.jpg
+pub fn load_cubemap_directory(directory : &str, extension : &str, options : &TextureOptions) -> Result<Texture, TextureError> {
+    let faces = FACE_NAMES.map(|name| format!("{}.{}", name, extension));
+    load_cubemap(faces.each_ref().map(|face| face.as_str()), directory, options)
+}
+
+/// Function to load an equirectangular (latitude-longitude) image and convert it into a cubemap with faces of face_size
+pub fn load_equirectangular(path : &str, directory : &str, face_size : u32, options : &TextureOptions) -> Result<Texture, TextureError> {
+    let panorama = match TextureData::read(path, directory, false)? {
+        TextureData::Float(image) => image,
+        TextureData::Rgba8(image) => {
+            let (width, height) = image.dimensions();
+            let pixels = image.into_raw().iter().map(|value| *value as f32 / 255.).collect();
+            FloatImage::from_pixels(width, height, 4, pixels)
+        },
+        TextureData::Compressed(image) => {
+            let pixels = image.decode_level(0).iter().map(|value| *value as f32 / 255.).collect();
+            FloatImage::from_pixels(image.width, image.height, 4, pixels)
+        },
+    };
+
+    let faces: Vec<FloatImage> = (0..6).map(|face| equirectangular_face(&panorama, face, face_size)).collect();
+    let id = unsafe {
+        create_cubemap(options, |target| {
+            for (face, image) in faces.iter().enumerate() {
+                image.tex_image(target + face as GLenum, options.half_float);
+            }
+        })
+    };
+    Ok(Texture::new(id, &Path::new(directory).join(path).to_string_lossy()))
+}
+
+/// Function to create a cubemap texture, upload is called with TEXTURE_CUBE_MAP_POSITIVE_X to store the faces
+unsafe fn create_cubemap(options : &TextureOptions, upload : impl FnOnce(GLenum)) -> GLuint {
+    let mut texture: GLuint = 0;
+    unsafe {
+        gl::GenTextures(1, &mut texture);
+        gl::BindTexture(gl::TEXTURE_CUBE_MAP, texture);
+        options.apply(gl::TEXTURE_CUBE_MAP);
+        gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_WRAP_R, options.wrap_t.gl_wrap() as GLint);
+
+        upload(gl::TEXTURE_CUBE_MAP_POSITIVE_X);
+
+        if options.mip_filter.is_some() {
+            gl::GenerateMipmap(gl::TEXTURE_CUBE_MAP);
+        }
+        gl::BindTexture(gl::TEXTURE_CUBE_MAP, 0);
+    }
+    texture
+}
+
+/// Function to get the direction a cubemap texel points to, s and t go from -1 to 1 across the face
+///
+/// Follows the face orientation table of the OpenGL specification
+pub fn face_direction(face : usize, s : f32, t : f32) -> glm::Vec3 {
+    let direction = match face {
+        0 => glm::vec3(1., -t, -s),
+        1 => glm::vec3(-1., -t, s),
+        2 => glm::vec3(s, 1., t),
+        3 => glm::vec3(s, -1., -t),
+        4 => glm::vec3(s, -t, 1.),
+        _ => glm::vec3(-s, -t, -1.),
+    };
+    glm::normalize(&direction)
+}
+
+/// Function to get the point of an equirectangular image a direction points to, (0, 0) is the top left corner
+pub fn equirectangular_uv(direction : &glm::Vec3) -> (f32, f32) {
+    let u = direction.z.atan2(direction.x) / std::f32::consts::TAU + 0.5;
+    let v = 0.5 - direction.y.clamp(-1., 1.).asin() / std::f32::consts::PI;
+    (u, v)
+}
+
+/// Function to build one face of a cubemap from an equirectangular image, always RGB
+fn equirectangular_face(panorama : &FloatImage, face : usize, size : u32) -> FloatImage {
+    let mut pixels = Vec::with_capacity((size * size * 3) as usize);
+    for y in 0..size {
+        for x in 0..size {
+            let s = 2. * (x as f32 + 0.5) / size as f32 - 1.;
+            let t = 2. * (y as f32 + 0.5) / size as f32 - 1.;
+            let (u, v) = equirectangular_uv(&face_direction(face, s, t));
+            pixels.extend_from_slice(&panorama.sample_bilinear(u, v)[..3]);
+        }
+    }
+    FloatImage::from_pixels(size, size, 3, pixels)
+}
+
+/// How the default model shader samples the environment map
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum EnvironmentMapping {
+    #[default]
+    None,
+    Reflection,
+    Refraction(f32),    // ratio of refractive indices, e.g. 1/1.52 from air into glass
+}
+
+impl EnvironmentMapping {
+    /// Function to bind the environment cubemap and set the environment uniforms
+    ///
+    /// strength blends between the lit colour (0) and the environment (1)
+    ///
+    /// **Assumes that the shader program is activated**
+    pub unsafe fn apply(&self, shader : &Shader, environment : &Texture, strength : f32) {
+        let (mode, ratio) = match self {
+            EnvironmentMapping::None => (0, 1.),
+            EnvironmentMapping::Reflection => (1, 1.),
+            EnvironmentMapping::Refraction(ratio) => (2, *ratio),
+        };
+        unsafe {
+            gl::ActiveTexture(gl::TEXTURE0 + ENVIRONMENT_TEXTURE_UNIT);
+            gl::BindTexture(gl::TEXTURE_CUBE_MAP, environment.id());
+            shader.set_int(c_str!("environmentMap"), ENVIRONMENT_TEXTURE_UNIT as GLint);
+            shader.set_int(c_str!("environmentMode"), mode);
+            shader.set_float(c_str!("refractionRatio"), ratio);
+            shader.set_float(c_str!("environmentStrength"), strength);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON : f32 = 1e-5;
+
+    fn assert_vec3_eq(a : &glm::Vec3, b : &glm::Vec3) {
+        assert!((a - b).norm() < EPSILON, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn face_centres_point_along_the_axes() {
+        let axes = [glm::Vec3::x(), -glm::Vec3::x(), glm::Vec3::y(), -glm::Vec3::y(), glm::Vec3::z(), -glm::Vec3::z()];
+        for (face, axis) in axes.iter().enumerate() {
+            assert_vec3_eq(&face_direction(face, 0., 0.), axis);
+        }
+    }
+
+    #[test]
+    fn cardinal_directions_in_the_panorama() {
+        let uv = |direction : glm::Vec3| {
+            let (u, v) = equirectangular_uv(&direction);
+            assert!((0. ..=1.).contains(&u) && (0. ..=1.).contains(&v), "{} {}", u, v);
+            (u, v)
+        };
+        // The horizon is the middle row, +x the middle column, turning towards +z moves right
+        assert_eq!(uv(glm::Vec3::x()), (0.5, 0.5));
+        assert_eq!(uv(glm::Vec3::z()), (0.75, 0.5));
+        assert_eq!(uv(-glm::Vec3::z()), (0.25, 0.5));
+        assert_eq!(uv(-glm::Vec3::x()).1, 0.5);
+        assert!(uv(-glm::Vec3::x()).0 == 0. || uv(-glm::Vec3::x()).0 == 1.);
+        assert_eq!(uv(glm::Vec3::y()).1, 0.);
+        assert_eq!(uv(-glm::Vec3::y()).1, 1.);
+    }
+
+    #[test]
+    fn adjacent_faces_meet_at_their_edges() {
+        // Every point on the edge of a face is also on the edge of another face
+        let steps = [-1., -0.5, 0., 0.5, 1.];
+        let edge = |face : usize| -> Vec<glm::Vec3> {
+            steps.iter().flat_map(|a| [(-1., *a), (1., *a), (*a, -1.), (*a, 1.)])
+                .map(|(s, t)| face_direction(face, s, t))
+                .collect()
+        };
+        for face in 0..6 {
+            for direction in edge(face) {
+                let shared = (0..6).filter(|other| *other != face)
+                    .any(|other| edge(other).iter().any(|d| (d - direction).norm() < EPSILON));
+                assert!(shared, "face {} edge {} is not on another face", face, direction);
+            }
+        }
+
+        // Going across the edge between +x and -z the panorama does not jump
+        let (u0, v0) = equirectangular_uv(&face_direction(0, 0.999, 0.2));
+        let (u1, v1) = equirectangular_uv(&face_direction(5, -0.999, 0.2));
+        assert!((u0 - u1).abs() < 1e-3 && (v0 - v1).abs() < 1e-3);
+    }
+}
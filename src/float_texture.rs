@@ -0,0 +1,218 @@
+// Float textures - HDR (.hdr) and OpenEXR (.exr) images, lookup tables and data textures
+
+// Notes:
+// 8 bit textures clamp values to [0, 1], which is not enough for HDR environment maps where the sun can be thousands of
+// times brighter than the sky. Float images are stored as RGB32F/RGBA32F, or with half the memory as RGB16F/RGBA16F if
+// TextureOptions::half_float is set. Half floats go up to 65504 with about 3 significant digits, enough for lighting.
+// Float data is always linear, TextureOptions::srgb is ignored.
+
+use std::os::raw::c_void;
+use std::path::Path;
+
+use gl::{self, types::*};
+use half::f16;
+
+use crate::texture::TextureOptions;
+
+/// Function to check if a path is a HDR or EXR file, by extension
+pub fn is_float_path(path : &str) -> bool {
+    let extension = Path::new(path).extension().and_then(|e| e.to_str()).unwrap_or("").to_ascii_lowercase();
+    extension == "hdr" || extension == "exr"
+}
+
+/// Image with 1 to 4 float channels per pixel, rows in the order they are uploaded
+#[derive(Clone, Debug)]
+pub struct FloatImage {
+    pub width : u32,
+    pub height : u32,
+    pub channels : u32,
+    pub pixels : Vec<f32>,
+}
+
+impl FloatImage {
+    /// Function to create an image from float data, for lookup tables and data generated on the cpu
+    pub fn from_pixels(width : u32, height : u32, channels : u32, pixels : Vec<f32>) -> FloatImage {
+        assert!((1..=4).contains(&channels), "float images have 1 to 4 channels, not {}", channels);
+        assert_eq!(pixels.len(), (width * height * channels) as usize, "float image data does not match its size");
+        FloatImage { width, height, channels, pixels }
+    }
+
+    /// Function to read an image file as floats, if flip is set the first row is the bottom of the image
+    ///
+    /// Images with alpha keep 4 channels, others are read as RGB. No OpenGL calls, so this can run on a worker thread
+    pub fn read(path : &Path, flip : bool) -> image::ImageResult<FloatImage> {
+        let image = image::open(path)?;
+        let image = if flip { image.flipv() } else { image };
+        let (width, height) = (image.width(), image.height());
+        Ok(if image.color().has_alpha() {
+            FloatImage { width, height, channels : 4, pixels : image.into_rgba32f().into_raw() }
+        } else {
+            FloatImage { width, height, channels : 3, pixels : image.into_rgb32f().into_raw() }
+        })
+    }
+
+    pub fn internal_format(&self, half_float : bool) -> GLenum {
+        match (self.channels, half_float) {
+            (1, false) => gl::R32F,
+            (1, true) => gl::R16F,
+            (2, false) => gl::RG32F,
+            (2, true) => gl::RG16F,
+            (3, false) => gl::RGB32F,
+            (3, true) => gl::RGB16F,
+            (_, false) => gl::RGBA32F,
+            (_, true) => gl::RGBA16F,
+        }
+    }
+
+    pub fn pixel_format(&self) -> GLenum {
+        match self.channels {
+            1 => gl::RED,
+            2 => gl::RG,
+            3 => gl::RGB,
+            _ => gl::RGBA,
+        }
+    }
+
+    /// Function to convert the pixels to half floats, values above 65504 become infinity
+    pub fn to_half(&self) -> Vec<f16> {
+        self.pixels.iter().map(|value| f16::from_f32(*value)).collect()
+    }
+
+    /// Function to create a gl texture from the image, converted to half floats on the cpu if options.half_float is set
+    pub unsafe fn upload(&self, options : &TextureOptions) -> GLuint {
+        let mut texture: GLuint = 0;
+        unsafe {
+            gl::GenTextures(1, &mut texture);
+            gl::BindTexture(gl::TEXTURE_2D, texture);
+            options.apply(gl::TEXTURE_2D);
+
+            self.tex_image(gl::TEXTURE_2D, options.half_float);
+
+            if options.mip_filter.is_some() {
+                gl::GenerateMipmap(gl::TEXTURE_2D);
+            }
+            gl::BindTexture(gl::TEXTURE_2D, 0);
+        }
+        texture
+    }
+
+    /// Function to store the image in level 0 of target, like a cubemap face, of the bound texture
+    pub unsafe fn tex_image(&self, target : GLenum, half_float : bool) {
+        let half_pixels = if half_float { Some(self.to_half()) } else { None };
+        let (data_type, data) = match &half_pixels {
+            Some(pixels) => (gl::HALF_FLOAT, pixels.as_ptr() as *const c_void),
+            None => (gl::FLOAT, self.pixels.as_ptr() as *const c_void),
+        };
+
+        unsafe {
+            // Rows of half float RGB images are 6 bytes per pixel, not always 4 byte aligned
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 2);
+            gl::TexImage2D(target, 0, self.internal_format(half_float) as GLint, self.width as GLsizei, self.height as GLsizei, 0, self.pixel_format(), data_type, data);
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 4);
+        }
+    }
+
+    /// Function to sample the image with bilinear filtering, u wraps around and v is clamped, (0, 0) is the first pixel
+    pub fn sample_bilinear(&self, u : f32, v : f32) -> [f32; 4] {
+        let x = u * self.width as f32 - 0.5;
+        let y = (v * self.height as f32 - 0.5).clamp(0., self.height as f32 - 1.);
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+
+        let pixel = |x : i64, y : i64| -> [f32; 4] {
+            let x = x.rem_euclid(self.width as i64) as usize;
+            let y = y.clamp(0, self.height as i64 - 1) as usize;
+            let channels = self.channels as usize;
+            let offset = (y * self.width as usize + x) * channels;
+            let mut value = [0., 0., 0., 1.];
+            value[..channels].copy_from_slice(&self.pixels[offset..offset + channels]);
+            value
+        };
+        let (x0, y0) = (x0 as i64, y0 as i64);
+        let (a, b, c, d) = (pixel(x0, y0), pixel(x0 + 1, y0), pixel(x0, y0 + 1), pixel(x0 + 1, y0 + 1));
+        std::array::from_fn(|i| {
+            let top = a[i] + (b[i] - a[i]) * fx;
+            let bottom = c[i] + (d[i] - c[i]) * fx;
+            top + (bottom - top) * fy
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON : f32 = 1e-5;
+
+    fn assert_pixel_eq(a : [f32; 4], b : [f32; 4]) {
+        assert!(a.iter().zip(&b).all(|(x, y)| (x - y).abs() < EPSILON), "{:?} != {:?}", a, b);
+    }
+
+    /// 2x2 greyscale image, rows 0 (0, 1) and 1 (2, 3)
+    fn image() -> FloatImage {
+        FloatImage::from_pixels(2, 2, 1, vec![0., 1., 2., 3.])
+    }
+
+    #[test]
+    fn bilinear_interpolates_between_texel_centres() {
+        let image = image();
+        // Texel centres give the texel, halfway between them the average
+        assert_pixel_eq(image.sample_bilinear(0.25, 0.25), [0., 0., 0., 1.]);
+        assert_pixel_eq(image.sample_bilinear(0.75, 0.75), [3., 0., 0., 1.]);
+        assert_pixel_eq(image.sample_bilinear(0.5, 0.25), [0.5, 0., 0., 1.]);
+        assert_pixel_eq(image.sample_bilinear(0.5, 0.5), [1.5, 0., 0., 1.]);
+    }
+
+    #[test]
+    fn u_wraps_and_v_clamps() {
+        let image = image();
+        // The left and right edges are between the last and the first column
+        assert_pixel_eq(image.sample_bilinear(0., 0.25), [0.5, 0., 0., 1.]);
+        assert_pixel_eq(image.sample_bilinear(1., 0.25), [0.5, 0., 0., 1.]);
+        // The top and bottom edges only use their own row
+        assert_pixel_eq(image.sample_bilinear(0.25, 0.), [0., 0., 0., 1.]);
+        assert_pixel_eq(image.sample_bilinear(0.25, 1.), [2., 0., 0., 1.]);
+    }
+
+    #[test]
+    fn missing_channels_are_filled() {
+        let rgb = FloatImage::from_pixels(1, 1, 3, vec![0.1, 0.2, 0.3]);
+        assert_pixel_eq(rgb.sample_bilinear(0.5, 0.5), [0.1, 0.2, 0.3, 1.]);
+        assert_eq!(rgb.pixel_format(), gl::RGB);
+        assert_eq!(rgb.internal_format(true), gl::RGB16F);
+
+        let rgba = FloatImage::from_pixels(1, 1, 4, vec![0.1, 0.2, 0.3, 0.4]);
+        assert_pixel_eq(rgba.sample_bilinear(0.5, 0.5), [0.1, 0.2, 0.3, 0.4]);
+        assert_eq!(rgba.internal_format(false), gl::RGBA32F);
+    }
+
+    #[test]
+    fn half_floats_round_trip() {
+        let image = FloatImage::from_pixels(4, 1, 1, vec![0.5, -3.25, 65504., 1e6]);
+        let half = image.to_half();
+        assert_eq!(half[..3].iter().map(|value| value.to_f32()).collect::<Vec<f32>>(), [0.5, -3.25, 65504.]);
+        assert!(half[3].is_infinite());
+        // About 3 significant digits
+        let value = f16::from_f32(1234.567).to_f32();
+        assert!((value - 1234.567).abs() / 1234.567 < 1e-3);
+    }
+
+    #[test]
+    fn reads_radiance_hdr() {
+        // 2x2 uncompressed RGBE, the colour is mantissa * 2^(exponent - 136)
+        let mut file = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 2 +X 2\n".to_vec();
+        file.extend_from_slice(&[128, 64, 32, 129,  128, 128, 128, 130]);   // (1, 0.5, 0.25) and (2, 2, 2)
+        file.extend_from_slice(&[0, 0, 0, 0,  128, 0, 0, 128]);             // black and (0.5, 0, 0)
+        let path = std::env::temp_dir().join(format!("float_texture_{}.hdr", std::process::id()));
+        std::fs::write(&path, file).unwrap();
+
+        let image = FloatImage::read(&path, false).unwrap();
+        assert_eq!((image.width, image.height, image.channels), (2, 2, 3));
+        assert_eq!(image.pixels, [1., 0.5, 0.25, 2., 2., 2., 0., 0., 0., 0.5, 0., 0.]);
+
+        // Flipped, the bottom row comes first
+        let flipped = FloatImage::read(&path, true).unwrap();
+        assert_eq!(flipped.pixels[..6], image.pixels[6..]);
+        assert!(is_float_path(path.to_str().unwrap()));
+    }
+}
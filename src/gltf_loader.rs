@@ -0,0 +1,258 @@
+// glTF 2.0 loading using the gltf crate
+
+// Notes:
+// Loading is split in two - load_gltf_data parses the file into plain cpu data, and Model::load_gltf uploads it.
+// This keeps the parsing testable without an OpenGL context.
+// The gltf crate resolves buffers and images (glb chunk, data uris and external files) and handles interleaved and
+// sparse accessors, so this file only has to walk the scene and convert the data.
+
+use gl::types::*;
+use nalgebra_glm as glm;
+
+use crate::material::AlphaMode;
+use crate::mesh::Vertex;
+
+/// Mesh data from a single glTF primitive, with the node transform already applied
+pub struct GltfMesh {
+    pub name : String,
+    pub vertices : Vec<Vertex>,
+    pub indices : Vec<GLuint>,
+    pub material : Option<usize>,   // index into GltfData::materials
+}
+
+/// Decoded image, always converted to RGBA8
+pub struct GltfImage {
+    pub width : u32,
+    pub height : u32,
+    pub pixels : Vec<u8>,
+}
+
+/// Metallic-roughness material, textures are indices into GltfData::images
+pub struct GltfMaterial {
+    pub name : String,
+    pub base_color_factor : glm::Vec4,
+    pub base_color_texture : Option<usize>,
+    pub metallic_factor : f32,
+    pub roughness_factor : f32,
+    pub metallic_roughness_texture : Option<usize>,
+    pub normal_texture : Option<usize>,
+    pub emissive_factor : glm::Vec3,
+    pub emissive_texture : Option<usize>,
+    pub alpha_mode : AlphaMode,
+    pub alpha_cutoff : f32,
+    pub double_sided : bool,
+}
+
+/// Everything needed to create a Model from a glTF file
+pub struct GltfData {
+    pub meshes : Vec<GltfMesh>,
+    pub materials : Vec<GltfMaterial>,
+    pub images : Vec<GltfImage>,
+}
+
+/// Function to parse a .gltf or .glb file, including its buffers and images
+pub fn load_gltf_data(path : &str) -> Result<GltfData, gltf::Error> {
+    let (document, buffers, images) = gltf::import(path)?;
+
+    let materials = document.materials().map(|material| convert_material(&material)).collect();
+    let images = images.into_iter().map(convert_image).collect();
+
+    // Walk the default scene (or the first one), applying node transforms down the hierarchy
+    let mut meshes = Vec::new();
+    if let Some(scene) = document.default_scene().or_else(|| document.scenes().next()) {
+        for node in scene.nodes() {
+            load_node(&node, &glm::Mat4::identity(), &buffers, &mut meshes);
+        }
+    }
+
+    Ok(GltfData { meshes, materials, images })
+}
+
+fn load_node(node : &gltf::Node, parent_transform : &glm::Mat4, buffers : &[gltf::buffer::Data], meshes : &mut Vec<GltfMesh>) {
+    let transform = parent_transform * glm::Mat4::from(node.transform().matrix());
+
+    if let Some(mesh) = node.mesh() {
+        let name = mesh.name().or(node.name()).unwrap_or("").to_string();
+        for primitive in mesh.primitives() {
+            if primitive.mode() != gltf::mesh::Mode::Triangles {
+                println!("Warning: skipping primitive of mesh '{}', mode {:?} is not supported", name, primitive.mode());
+                continue;
+            }
+            if let Some(mesh) = load_primitive(&name, &primitive, &transform, buffers) {
+                meshes.push(mesh);
+            }
+        }
+    }
+
+    for child in node.children() {
+        load_node(&child, &transform, buffers, meshes);
+    }
+}
+
+fn load_primitive(name : &str, primitive : &gltf::Primitive, transform : &glm::Mat4, buffers : &[gltf::buffer::Data]) -> Option<GltfMesh> {
+    let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+
+    let positions: Vec<[f32; 3]> = match reader.read_positions() {
+        Some(positions) => positions.collect(),
+        None => {
+            println!("Warning: skipping primitive of mesh '{}' without positions", name);
+            return None;
+        }
+    };
+    // Missing normals are left as zero, mesh validation replaces them with face normals
+    let normals: Vec<[f32; 3]> = reader.read_normals().map(|n| n.collect()).unwrap_or_default();
+    let tex_coords: Vec<[f32; 2]> = reader.read_tex_coords(0).map(|t| t.into_f32().collect()).unwrap_or_default();
+
+    // Normals are transformed using the normal matrix, in case the node has a non-uniform scale
+    let normal_matrix = glm::transpose(&glm::inverse(&glm::mat4_to_mat3(transform)));
+
+    let vertices = positions.iter().enumerate().map(|(i, position)| {
+        let position = transform * glm::vec4(position[0], position[1], position[2], 1.);
+        let normal = normals.get(i).map(|n| normal_matrix * glm::vec3(n[0], n[1], n[2])).unwrap_or_else(glm::Vec3::zeros);
+        let tex_coords = tex_coords.get(i).map(|t| glm::vec2(t[0], t[1])).unwrap_or_else(glm::Vec2::zeros);
+        Vertex {
+            position : position.xyz(),
+            normal : if glm::length2(&normal) > 0. { glm::normalize(&normal) } else { normal },
+            tex_coords,
+        }
+    }).collect();
+
+    // 8, 16 and 32 bit indices are all widened to u32, non-indexed primitives use the vertices in order
+    let mut indices: Vec<GLuint> = match reader.read_indices() {
+        Some(indices) => indices.into_u32().collect(),
+        None => (0..positions.len() as GLuint).collect(),
+    };
+
+    // A mirroring transform flips the winding order
+    if glm::determinant(transform) < 0. {
+        for face in indices.chunks_exact_mut(3) {
+            face.swap(1, 2);
+        }
+    }
+
+    Some(GltfMesh {
+        name : name.to_string(),
+        vertices,
+        indices,
+        material : primitive.material().index(),
+    })
+}
+
+fn convert_material(material : &gltf::Material) -> GltfMaterial {
+    let pbr = material.pbr_metallic_roughness();
+    GltfMaterial {
+        name : material.name().unwrap_or("").to_string(),
+        base_color_factor : glm::Vec4::from(pbr.base_color_factor()),
+        base_color_texture : pbr.base_color_texture().map(|info| info.texture().source().index()),
+        metallic_factor : pbr.metallic_factor(),
+        roughness_factor : pbr.roughness_factor(),
+        metallic_roughness_texture : pbr.metallic_roughness_texture().map(|info| info.texture().source().index()),
+        normal_texture : material.normal_texture().map(|info| info.texture().source().index()),
+        emissive_factor : glm::Vec3::from(material.emissive_factor()),
+        emissive_texture : material.emissive_texture().map(|info| info.texture().source().index()),
+        alpha_mode : match material.alpha_mode() {
+            gltf::material::AlphaMode::Opaque => AlphaMode::Opaque,
+            gltf::material::AlphaMode::Mask => AlphaMode::Mask,
+            gltf::material::AlphaMode::Blend => AlphaMode::Blend,
+        },
+        alpha_cutoff : material.alpha_cutoff().unwrap_or(0.5),
+        double_sided : material.double_sided(),
+    }
+}
+
+/// Function to convert any 8 bit image format to RGBA8, higher bit depths are truncated
+fn convert_image(image : gltf::image::Data) -> GltfImage {
+    use gltf::image::Format;
+
+    let (channels, bytes_per_channel) = match image.format {
+        Format::R8 => (1, 1),
+        Format::R8G8 => (2, 1),
+        Format::R8G8B8 => (3, 1),
+        Format::R8G8B8A8 => (4, 1),
+        Format::R16 => (1, 2),
+        Format::R16G16 => (2, 2),
+        Format::R16G16B16 => (3, 2),
+        Format::R16G16B16A16 => (4, 2),
+        Format::R32G32B32FLOAT => (3, 4),
+        Format::R32G32B32A32FLOAT => (4, 4),
+    };
+
+    let mut pixels = Vec::with_capacity((image.width * image.height * 4) as usize);
+    for pixel in image.pixels.chunks_exact(channels * bytes_per_channel) {
+        let channel = |c : usize| -> u8 {
+            let bytes = &pixel[c * bytes_per_channel..(c + 1) * bytes_per_channel];
+            match bytes_per_channel {
+                1 => bytes[0],
+                2 => (u16::from_le_bytes([bytes[0], bytes[1]]) >> 8) as u8,
+                _ => (f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]).clamp(0., 1.) * 255.) as u8,
+            }
+        };
+        match channels {
+            1 => pixels.extend_from_slice(&[channel(0), channel(0), channel(0), 255]),
+            2 => pixels.extend_from_slice(&[channel(0), channel(0), channel(0), channel(1)]),
+            3 => pixels.extend_from_slice(&[channel(0), channel(1), channel(2), 255]),
+            _ => pixels.extend_from_slice(&[channel(0), channel(1), channel(2), channel(3)]),
+        }
+    }
+
+    GltfImage { width : image.width, height : image.height, pixels }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loads_embedded_interleaved_triangle() {
+        let data = load_gltf_data("./resources/models/gltf/triangle.gltf").unwrap();
+
+        assert_eq!(data.meshes.len(), 1);
+        let mesh = &data.meshes[0];
+        assert_eq!(mesh.name, "triangle");
+        assert_eq!(mesh.indices, vec![0, 1, 2]);    // stored as 16 bit indices
+
+        // Node translation is applied to the positions, normals and uvs are read from the interleaved buffer
+        assert_eq!(mesh.vertices.len(), 3);
+        assert_eq!(mesh.vertices[1].position, glm::vec3(1., 0., -1.));
+        assert_eq!(mesh.vertices[1].normal, glm::vec3(0., 0., 1.));
+        assert_eq!(mesh.vertices[2].tex_coords, glm::vec2(0., 1.));
+
+        // Material and embedded image
+        let material = &data.materials[mesh.material.unwrap()];
+        assert_eq!(material.base_color_factor, glm::vec4(1., 0.5, 0.5, 1.));
+        assert_eq!(material.base_color_texture, Some(0));
+        assert_eq!(material.alpha_mode, AlphaMode::Opaque);
+        let image = &data.images[0];
+        assert_eq!((image.width, image.height), (2, 2));
+        assert_eq!(&image.pixels[0..8], &[255, 0, 0, 255, 0, 255, 0, 255]);
+    }
+
+    #[test]
+    fn loads_binary_sparse_quad_with_node_hierarchy() {
+        let data = load_gltf_data("./resources/models/gltf/quad_sparse.glb").unwrap();
+
+        assert_eq!(data.meshes.len(), 1);
+        let mesh = &data.meshes[0];
+        assert_eq!(mesh.indices, vec![0, 1, 2, 0, 2, 3]);
+
+        // Parent scale of 2 and child translation of (1,0,0), vertex 2 is replaced by the sparse accessor
+        assert_eq!(mesh.vertices[0].position, glm::vec3(2., 0., 0.));
+        assert_eq!(mesh.vertices[2].position, glm::vec3(4., 2., 0.));
+        assert_eq!(mesh.vertices[3].position, glm::vec3(2., 2., 0.));
+        assert_eq!(mesh.vertices[2].normal, glm::vec3(0., 0., 1.));
+
+        let material = &data.materials[mesh.material.unwrap()];
+        assert_eq!(material.metallic_factor, 1.);
+        assert_eq!(material.roughness_factor, 0.5);
+        assert_eq!(material.metallic_roughness_texture, Some(0));
+        assert_eq!(material.emissive_factor, glm::vec3(0.25, 0., 0.));
+        assert_eq!(material.alpha_mode, AlphaMode::Mask);
+        assert_eq!(material.alpha_cutoff, 0.3);
+        assert!(material.double_sided);
+
+        // External image next to the glb
+        let image = &data.images[0];
+        assert_eq!((image.width, image.height), (2, 2));
+        assert_eq!(&image.pixels[0..8], &[0, 0, 0, 255, 255, 255, 255, 255]);
+    }
+}
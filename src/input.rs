@@ -0,0 +1,190 @@
+// Input snapshot for the cameras - keys, mouse buttons, cursor movement, scroll and resizes
+
+// Notes:
+// Cameras read their input from an InputState instead of the glfw window, so they can be unit tested, driven by
+// scripts or used with another windowing library. GlfwInput fills it from glfw : events (key presses, scroll,
+// resizes) are collected as they arrive, held keys, buttons and the cursor are polled when the snapshot is taken.
+// Tutorials update their cameras at a fixed rate while events are handled every frame, so everything that happens
+// between two snapshots is added up.
+// Cameras that rotate while a button is held ask for the cursor to be captured (Camera::captures_cursor), glfw then
+// hides it and reports unlimited movement, which replaces warping the cursor back to the center of the window.
+
+use std::collections::HashSet;
+use std::mem;
+
+use nalgebra_glm as glm;
+
+/// Keys used by the cameras
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Key {
+    W,
+    A,
+    S,
+    D,
+    Q,
+    E,
+    R,
+    F,
+    L,
+    O,
+    P,
+    Space,
+    LeftShift,
+    LeftControl,
+}
+
+impl Key {
+    pub const ALL : [Key; 14] = [
+        Key::W, Key::A, Key::S, Key::D, Key::Q, Key::E, Key::R, Key::F, Key::L, Key::O, Key::P,
+        Key::Space, Key::LeftShift, Key::LeftControl,
+    ];
+
+    pub fn glfw_key(&self) -> glfw::Key {
+        match self {
+            Key::W => glfw::Key::W,
+            Key::A => glfw::Key::A,
+            Key::S => glfw::Key::S,
+            Key::D => glfw::Key::D,
+            Key::Q => glfw::Key::Q,
+            Key::E => glfw::Key::E,
+            Key::R => glfw::Key::R,
+            Key::F => glfw::Key::F,
+            Key::L => glfw::Key::L,
+            Key::O => glfw::Key::O,
+            Key::P => glfw::Key::P,
+            Key::Space => glfw::Key::Space,
+            Key::LeftShift => glfw::Key::LeftShift,
+            Key::LeftControl => glfw::Key::LeftControl,
+        }
+    }
+
+    /// Function to get the key for a glfw key, None for keys the cameras do not use
+    pub fn from_glfw(key : glfw::Key) -> Option<Key> {
+        Key::ALL.into_iter().find(|k| k.glfw_key() == key)
+    }
+}
+
+/// Mouse buttons used by the cameras
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle,
+}
+
+impl MouseButton {
+    pub const ALL : [MouseButton; 3] = [MouseButton::Left, MouseButton::Right, MouseButton::Middle];
+
+    pub fn glfw_button(&self) -> glfw::MouseButton {
+        match self {
+            MouseButton::Left => glfw::MouseButtonLeft,
+            MouseButton::Right => glfw::MouseButtonRight,
+            MouseButton::Middle => glfw::MouseButtonMiddle,
+        }
+    }
+}
+
+/// Input since the previous snapshot, consumed by Camera::update
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct InputState {
+    pub keys_down : HashSet<Key>,               // held when the snapshot was taken
+    pub keys_pressed : HashSet<Key>,            // pressed since the previous snapshot, for toggles
+    pub buttons_down : HashSet<MouseButton>,    // held when the snapshot was taken
+    pub cursor_delta : glm::Vec2,               // pixels the cursor moved, y points down
+    pub scroll : glm::Vec2,                     // scroll wheel steps, y > 0 scrolls up
+    pub resized : Option<(u32, u32)>,           // new framebuffer size, if it changed
+}
+
+impl InputState {
+    /// Function to create a snapshot with keys held, for tests and scripts
+    #[allow(dead_code)]
+    pub fn with_keys(keys : &[Key]) -> InputState {
+        InputState { keys_down : keys.iter().copied().collect(), ..Default::default() }
+    }
+
+    pub fn key_down(&self, key : Key) -> bool {
+        self.keys_down.contains(&key)
+    }
+
+    pub fn key_pressed(&self, key : Key) -> bool {
+        self.keys_pressed.contains(&key)
+    }
+
+    pub fn button_down(&self, button : MouseButton) -> bool {
+        self.buttons_down.contains(&button)
+    }
+}
+
+/// Adapter filling InputState snapshots from a glfw window
+pub struct GlfwInput {
+    pending : InputState,               // events since the previous snapshot
+    last_cursor : Option<(f64, f64)>,   // None until the next snapshot after the cursor mode changed
+    last_keys : HashSet<Key>,
+    cursor_captured : bool,
+}
+
+impl GlfwInput {
+    pub fn new() -> GlfwInput {
+        GlfwInput {
+            pending : InputState::default(),
+            last_cursor : None,
+            last_keys : HashSet::new(),
+            cursor_captured : false,
+        }
+    }
+
+    /// Function to collect an event, call it for every event of the window
+    /// Note : framebuffer and scroll polling has to be enabled, key polling catches presses shorter than an update
+    pub fn handle_event(&mut self, event : &glfw::WindowEvent) {
+        match event {
+            glfw::WindowEvent::Key(key, _, glfw::Action::Press, _) => {
+                if let Some(key) = Key::from_glfw(*key) {
+                    self.pending.keys_pressed.insert(key);
+                }
+            },
+            glfw::WindowEvent::Scroll(x_offset, y_offset) => {
+                self.pending.scroll += glm::vec2(*x_offset as f32, *y_offset as f32);
+            },
+            glfw::WindowEvent::FramebufferSize(width, height) => {
+                self.pending.resized = Some((*width as u32, *height as u32));
+            },
+            _ => {}
+        }
+    }
+
+    /// Function to take a snapshot of the input since the previous one
+    ///
+    /// capture_cursor hides the cursor and keeps it in the window, pass Camera::captures_cursor
+    pub fn snapshot(&mut self, window : &mut glfw::PWindow, capture_cursor : bool) -> InputState {
+        if capture_cursor != self.cursor_captured {
+            window.set_cursor_mode(if capture_cursor { glfw::CursorMode::Disabled } else { glfw::CursorMode::Normal });
+            self.cursor_captured = capture_cursor;
+            // The position jumps when the mode changes, skip that movement
+            self.last_cursor = None;
+        }
+
+        let mut state = mem::take(&mut self.pending);
+
+        let cursor = window.get_cursor_pos();
+        if let Some(last) = self.last_cursor {
+            state.cursor_delta = glm::vec2((cursor.0 - last.0) as f32, (cursor.1 - last.1) as f32);
+        }
+        self.last_cursor = Some(cursor);
+
+        state.keys_down = Key::ALL.into_iter().filter(|key| window.get_key(key.glfw_key()) == glfw::Action::Press).collect();
+        state.buttons_down = MouseButton::ALL.into_iter()
+            .filter(|button| window.get_mouse_button(button.glfw_button()) == glfw::Action::Press)
+            .collect();
+        // Keys that went down since the last snapshot count as pressed, in case key polling is not enabled
+        state.keys_pressed.extend(state.keys_down.difference(&self.last_keys).copied());
+        self.last_keys = state.keys_down.clone();
+
+        state
+    }
+}
+
+impl Default for GlfwInput {
+    fn default() -> GlfwInput {
+        GlfwInput::new()
+    }
+}
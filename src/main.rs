@@ -8,8 +8,28 @@
 mod shader;
 mod macros;
 mod camera;
+mod camera_path;
+mod input;
+mod projection;
 mod model;
+mod material;
+mod texture;
+mod gltf_loader;
 mod mesh;
+mod stream_mesh;
+mod scene;
+mod animation;
+mod morph;
+mod asset_loader;
+mod compressed_texture;
+mod float_texture;
+mod cubemap;
+mod skybox;
+mod texture_atlas;
+mod mesh_cache;
+mod ply_loader;
+mod stl_loader;
+mod resources;
 
 #[cfg(feature = "chapter-1")]
 mod _1_getting_started;
@@ -48,11 +68,15 @@ use _7_in_practice::*;
 
 fn main() {
     let args: Vec<String> = std::env::args().collect();
+    // --assets <dir> adds a search path for tutorial assets, see resources.rs
+    let (args, asset_paths) = resources::parse_asset_flags(&args);
     if args.len() != 2 {
         println!("Call with the number of the tutorial, e.g. `1_2_1` for _1_getting_started/_2_1_hello_triangle_ex1.rs");
+        println!("Optionally add asset search paths with `--assets <dir>`");
         std::process::exit(1);
     }
     let tutorial_id = &args[1];
+    resources::ResourceResolver::set_global(resources::ResourceResolver::from_sources(&asset_paths));
 
     match tutorial_id.as_str() {
         #[cfg(feature = "chapter-1")] "1_1"     => main_1_1(),
@@ -101,6 +125,7 @@ fn main() {
         #[cfg(feature = "chapter-2")] "2_6_1"   => main_2_6_1(),
 
         #[cfg(feature = "chapter-3")] "3_1"   => main_3_1(),
+        #[cfg(feature = "chapter-3")] "3_2"   => main_3_2(),
 
         #[cfg(feature = "chapter-4")] "4_1"   => main_4_1(),
         #[cfg(feature = "chapter-4")] "4_1_1"   => main_4_1_1(),
@@ -111,6 +136,8 @@ fn main() {
         #[cfg(feature = "chapter-4")] "4_4"   => main_4_4(),
         #[cfg(feature = "chapter-4")] "4_4_e"   => main_4_4_e(),
         #[cfg(feature = "chapter-4")] "4_5"   => main_4_5(),
+        #[cfg(feature = "chapter-4")] "4_6"   => main_4_6(),
+        #[cfg(feature = "chapter-4")] "4_7"   => main_4_7(),
         // #[cfg(feature = "chapter-4")] "4_3_1" => main_4_3_1(),
         // #[cfg(feature = "chapter-4")] "4_3_2" => main_4_3_2(),
         // #[cfg(feature = "chapter-4")] "4_5_1" => main_4_5_1(),
@@ -0,0 +1,291 @@
+// Material used by meshes - named texture slots, colour factors and render state
+
+use std::ffi::CStr;
+
+use gl::{self, types::*};
+use nalgebra_glm as glm;
+
+use crate::cubemap::ENVIRONMENT_TEXTURE_UNIT;
+use crate::shader::Shader;
+use crate::texture::Texture;
+
+/// How the alpha channel of the diffuse colour is used
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum AlphaMode {
+    #[default]
+    Opaque,     // alpha is ignored
+    Mask,       // fragments with alpha below alpha_cutoff are discarded
+    Blend,      // alpha blended, should be drawn after opaque objects
+}
+
+/// Fixed function state a material needs, applied by Model::draw_with
+///
+/// Only the state materials change is tracked, the cull mode and blend equation are left to the caller
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RenderState {
+    pub cull_face : bool,
+    pub blend : bool,
+    pub depth_mask : bool,
+    pub blend_src : GLenum,
+    pub blend_dst : GLenum,
+}
+
+impl RenderState {
+    /// Function to read the current state from OpenGL, to restore it after drawing
+    pub unsafe fn current() -> RenderState {
+        unsafe {
+            let mut depth_mask = gl::TRUE;
+            let mut blend_src = 0;
+            let mut blend_dst = 0;
+            gl::GetBooleanv(gl::DEPTH_WRITEMASK, &mut depth_mask);
+            gl::GetIntegerv(gl::BLEND_SRC_RGB, &mut blend_src);
+            gl::GetIntegerv(gl::BLEND_DST_RGB, &mut blend_dst);
+            RenderState {
+                cull_face : gl::IsEnabled(gl::CULL_FACE) == gl::TRUE,
+                blend : gl::IsEnabled(gl::BLEND) == gl::TRUE,
+                depth_mask : depth_mask == gl::TRUE,
+                blend_src : blend_src as GLenum,
+                blend_dst : blend_dst as GLenum,
+            }
+        }
+    }
+
+    /// Function to set the state in OpenGL
+    pub unsafe fn apply(&self) {
+        unsafe {
+            if self.cull_face { gl::Enable(gl::CULL_FACE); } else { gl::Disable(gl::CULL_FACE); }
+            if self.blend { gl::Enable(gl::BLEND); } else { gl::Disable(gl::BLEND); }
+            gl::DepthMask(if self.depth_mask { gl::TRUE } else { gl::FALSE });
+            gl::BlendFunc(self.blend_src, self.blend_dst);
+        }
+    }
+}
+
+/// Texture slots of a material, each slot is bound to its own texture unit
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextureSlot {
+    Diffuse,
+    Specular,
+    Emissive,
+    Normal,
+    Opacity,
+    Roughness,  // read from the green channel, matching glTF's combined metallic-roughness texture
+    Metallic,   // read from the blue channel
+}
+
+impl TextureSlot {
+    pub const ALL : [TextureSlot; 7] = [
+        TextureSlot::Diffuse,
+        TextureSlot::Specular,
+        TextureSlot::Emissive,
+        TextureSlot::Normal,
+        TextureSlot::Opacity,
+        TextureSlot::Roughness,
+        TextureSlot::Metallic,
+    ];
+
+    /// Texture unit used by the slot, texture unit 0 is not used
+    pub fn texture_unit(&self) -> u32 {
+        1 + *self as u32
+    }
+}
+
+/// Material shared by one or more meshes
+///
+/// Textures are shared handles, a texture is deleted once no material uses it
+#[derive(Clone, Debug, PartialEq)]
+pub struct Material {
+    pub name : String,
+
+    // Texture slots
+    pub diffuse : Option<Texture>,
+    pub specular : Option<Texture>,
+    pub emissive : Option<Texture>,
+    pub normal : Option<Texture>,
+    pub opacity : Option<Texture>,
+    pub roughness_map : Option<Texture>,
+    pub metallic_map : Option<Texture>,
+
+    // Factors, used in place of the texture when a slot is empty
+    pub diffuse_color : glm::Vec4,
+    pub specular_color : glm::Vec3,
+    pub emissive_color : glm::Vec3,
+    pub shininess : f32,
+    pub roughness : f32,
+    pub metallic : f32,
+    pub bump_scale : Option<f32>,   // Some if the normal slot holds a height map (MTL map_Bump), scales its slopes
+
+    // Render state
+    pub alpha_mode : AlphaMode,
+    pub alpha_cutoff : f32,
+    pub double_sided : bool,
+}
+
+impl Default for Material {
+    fn default() -> Material {
+        Material {
+            name : String::new(),
+            diffuse : None,
+            specular : None,
+            emissive : None,
+            normal : None,
+            opacity : None,
+            roughness_map : None,
+            metallic_map : None,
+            diffuse_color : glm::vec4(1., 1., 1., 1.),
+            specular_color : glm::vec3(1., 1., 1.),
+            emissive_color : glm::vec3(0., 0., 0.),
+            shininess : 32.,
+            roughness : 1.,
+            metallic : 0.,
+            bump_scale : None,
+            alpha_mode : AlphaMode::Opaque,
+            alpha_cutoff : 0.5,
+            double_sided : false,
+        }
+    }
+}
+
+impl Material {
+    /// Function to approximate a Blinn-Phong shininess from a roughness value, used for metallic-roughness materials
+    pub fn roughness_to_shininess(roughness : f32) -> f32 {
+        let alpha = (roughness * roughness).max(0.01);
+        (2. / (alpha * alpha) - 2.).clamp(1., 1024.)
+    }
+
+    /// Function to get the render state for drawing with this material, starting from the caller's state
+    ///
+    /// Double sided materials are not culled. Blended materials use straight alpha blending and do not write depth,
+    /// so they do not hide each other, opaque and masked materials are drawn without blending
+    pub fn render_state(&self, base : &RenderState) -> RenderState {
+        let blend = self.alpha_mode == AlphaMode::Blend;
+        RenderState {
+            cull_face : base.cull_face && !self.double_sided,
+            blend,
+            depth_mask : base.depth_mask && !blend,
+            blend_src : if blend { gl::SRC_ALPHA } else { base.blend_src },
+            blend_dst : if blend { gl::ONE_MINUS_SRC_ALPHA } else { base.blend_dst },
+        }
+    }
+
+    /// Function to get the texture in a slot
+    pub fn texture(&self, slot : TextureSlot) -> Option<&Texture> {
+        match slot {
+            TextureSlot::Diffuse => self.diffuse.as_ref(),
+            TextureSlot::Specular => self.specular.as_ref(),
+            TextureSlot::Emissive => self.emissive.as_ref(),
+            TextureSlot::Normal => self.normal.as_ref(),
+            TextureSlot::Opacity => self.opacity.as_ref(),
+            TextureSlot::Roughness => self.roughness_map.as_ref(),
+            TextureSlot::Metallic => self.metallic_map.as_ref(),
+        }
+    }
+
+    /// Function to get a mutable reference to a slot, used while loading
+    pub fn texture_mut(&mut self, slot : TextureSlot) -> &mut Option<Texture> {
+        match slot {
+            TextureSlot::Diffuse => &mut self.diffuse,
+            TextureSlot::Specular => &mut self.specular,
+            TextureSlot::Emissive => &mut self.emissive,
+            TextureSlot::Normal => &mut self.normal,
+            TextureSlot::Opacity => &mut self.opacity,
+            TextureSlot::Roughness => &mut self.roughness_map,
+            TextureSlot::Metallic => &mut self.metallic_map,
+        }
+    }
+
+    /// Function to bind the material's textures and set all material uniforms
+    ///
+    /// Every uniform is set on each call, so nothing is left over from the previous material.
+    /// Empty slots are bound to texture unit 0 with their use_texture_* flag set to 0
+    ///
+    /// **Assumes that the shader program is activated**
+    pub unsafe fn bind(&self, shader : &Shader) {
+        unsafe {
+            for slot in TextureSlot::ALL {
+                let (sampler, flag) = match slot {
+                    TextureSlot::Diffuse => (c_str!("material.texture_diffuse0"), c_str!("material.use_texture_diff")),
+                    TextureSlot::Specular => (c_str!("material.texture_specular0"), c_str!("material.use_texture_spec")),
+                    TextureSlot::Emissive => (c_str!("material.texture_emissive0"), c_str!("material.use_texture_emis")),
+                    TextureSlot::Normal => (c_str!("material.texture_normal0"), c_str!("material.use_texture_norm")),
+                    TextureSlot::Opacity => (c_str!("material.texture_opacity0"), c_str!("material.use_texture_opac")),
+                    TextureSlot::Roughness => (c_str!("material.texture_roughness0"), c_str!("material.use_texture_rough")),
+                    TextureSlot::Metallic => (c_str!("material.texture_metallic0"), c_str!("material.use_texture_metal")),
+                };
+
+                match self.texture(slot) {
+                    Some(texture) => {
+                        gl::ActiveTexture(gl::TEXTURE0 + slot.texture_unit());
+                        gl::BindTexture(gl::TEXTURE_2D, texture.id());
+                        shader.set_int(sampler, slot.texture_unit() as GLint);
+                        shader.set_int(flag, 1);
+                    },
+                    None => {
+                        shader.set_int(sampler, 0);
+                        shader.set_int(flag, 0);
+                    }
+                }
+            }
+
+            // Samplers of different types cannot share a texture unit, keep the environment cubemap on its own unit
+            // even if the shader does not use it
+            shader.set_int(c_str!("environmentMap"), ENVIRONMENT_TEXTURE_UNIT as GLint);
+
+            // Fallback color for diffuse and specular lighting, only used when there is no diffuse texture
+            let fallback_color = if self.diffuse.is_some() { glm::Vec4::zeros() } else { self.diffuse_color };
+            shader.set_vec4_values(c_str!("material.fallback_color"), fallback_color.x, fallback_color.y, fallback_color.z, fallback_color.w);
+
+            // Factors and render state
+            shader.set_float(c_str!("material.shininess"), self.shininess);
+            shader.set_float(c_str!("material.roughness"), self.roughness);
+            shader.set_float(c_str!("material.metallic"), self.metallic);
+            shader.set_float(c_str!("material.bump_scale"), self.bump_scale.unwrap_or(0.));
+            shader.set_vec4_values(c_str!("material.diffuse_color"), self.diffuse_color.x, self.diffuse_color.y, self.diffuse_color.z, self.diffuse_color.w);
+            shader.set_vec3(c_str!("material.specular_color"), self.specular_color);
+            shader.set_vec3(c_str!("material.emissive_color"), self.emissive_color);
+            shader.set_int(c_str!("material.alpha_mode"), self.alpha_mode as GLint);
+            shader.set_float(c_str!("material.alpha_cutoff"), self.alpha_cutoff);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE : RenderState = RenderState {
+        cull_face : true,
+        blend : false,
+        depth_mask : true,
+        blend_src : gl::ONE,
+        blend_dst : gl::ZERO,
+    };
+
+    #[test]
+    fn opaque_material_keeps_the_base_state() {
+        assert_eq!(Material::default().render_state(&BASE), BASE);
+    }
+
+    #[test]
+    fn double_sided_material_disables_culling() {
+        let material = Material { double_sided : true, ..Default::default() };
+        assert_eq!(material.render_state(&BASE), RenderState { cull_face : false, ..BASE });
+        // Culling that is already off stays off
+        let base = RenderState { cull_face : false, ..BASE };
+        assert!(!Material::default().render_state(&base).cull_face);
+    }
+
+    #[test]
+    fn blended_material_disables_depth_writes() {
+        let blended = Material { alpha_mode : AlphaMode::Blend, ..Default::default() };
+        let state = blended.render_state(&BASE);
+        assert!(state.blend);
+        assert!(!state.depth_mask);
+        assert_eq!((state.blend_src, state.blend_dst), (gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA));
+        assert!(state.cull_face);
+
+        // The next opaque material goes back to the base blend function and depth writes
+        let masked = Material { alpha_mode : AlphaMode::Mask, ..Default::default() };
+        assert_eq!(masked.render_state(&BASE), BASE);
+    }
+}
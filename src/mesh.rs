@@ -1,48 +1,116 @@
 use std::collections::HashMap;
-use std::ffi::CString;
+use std::fmt;
 use std::ptr;
 use std::mem;
 use std::os::raw::c_void;
+use std::rc::Rc;
 use std::ffi::CStr;
 
 use nalgebra_glm as glm;
 use gl::{self, types::*};
 
+use crate::material::{Material, TextureSlot};
+use crate::morph::{self, MorphMode, MorphTarget, MAX_MORPH_TARGETS};
 use crate::shader::Shader;
 
+// Texture unit for the morph target deltas, after the units used by the material
+const MORPH_TEXTURE_UNIT : u32 = TextureSlot::ALL.len() as u32 + 1;
+
 /// Struct to store vertex data
 #[repr(C)] // align struct like C/C++
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Vertex {
     pub position : glm::Vec3,
     pub normal : glm::Vec3,
     pub tex_coords : glm::Vec2,
 }
 
-/// Enum to store all material data
-#[derive(Clone)]
-pub enum Material {
-    Texture{ id : u32, path : String, type_ : MaterialType},
-    Property{ value : f32, type_ : MaterialType}
+/// Joints and weights of a vertex for skinning, stored in a separate buffer so that unskinned meshes do not pay for it
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct VertexSkin {
+    pub joints : [u16; 4],      // indices into the skeleton's joints
+    pub weights : [f32; 4],     // should add up to 1
 }
 
-// Types for shader textures and properties
-#[derive(Clone)]
-pub enum MaterialType {
-    DiffuseTex,
-    SpecularTex,
-    EmissiveTex,
-    Shininess
+/// What the mesh's indices describe
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Primitive {
+    #[default]
+    Triangles,
+    Points,     // e.g. point clouds from scans, every index is drawn as a point
 }
 
-impl Material {
-    /// Function to compare the texture's stored path with other
-    /// 
-    /// Returns false if used on anything other than Material::Texture
-    pub fn is_path_eq(&self, other : &str) -> bool {
+impl Primitive {
+    pub fn gl_mode(&self) -> GLenum {
         match self {
-            Material::Texture { id: _, path, type_ : _ } => path==other,
-            _ => false,
+            Primitive::Triangles => gl::TRIANGLES,
+            Primitive::Points => gl::POINTS,
+        }
+    }
+}
+
+/// Axis aligned bounding box
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bounds {
+    pub min : glm::Vec3,
+    pub max : glm::Vec3,
+}
+
+impl Bounds {
+    /// Function to get the bounds of a set of vertices, None if there are no vertices
+    pub fn from_vertices(vertices : &[Vertex]) -> Option<Bounds> {
+        let first = vertices.first()?.position;
+        Some(vertices.iter().fold(Bounds { min : first, max : first }, |bounds, vertex| Bounds {
+            min : glm::min2(&bounds.min, &vertex.position),
+            max : glm::max2(&bounds.max, &vertex.position),
+        }))
+    }
+
+    pub fn center(&self) -> glm::Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn size(&self) -> glm::Vec3 {
+        self.max - self.min
+    }
+
+    /// Function to get the box containing both boxes
+    pub fn union(&self, other : &Bounds) -> Bounds {
+        Bounds { min : glm::min2(&self.min, &other.min), max : glm::max2(&self.max, &other.max) }
+    }
+
+    /// Function to get the box containing this box after a transformation, from its 8 transformed corners
+    pub fn transformed(&self, transform : &glm::Mat4) -> Bounds {
+        let corner = |i : usize| {
+            let x = if i & 1 == 0 { self.min.x } else { self.max.x };
+            let y = if i & 2 == 0 { self.min.y } else { self.max.y };
+            let z = if i & 4 == 0 { self.min.z } else { self.max.z };
+            (transform * glm::vec4(x, y, z, 1.)).xyz()
+        };
+        let first = corner(0);
+        (1..8).map(corner).fold(Bounds { min : first, max : first }, |bounds, point| Bounds {
+            min : glm::min2(&bounds.min, &point),
+            max : glm::max2(&bounds.max, &point),
+        })
+    }
+}
+
+/// How often the mesh's vertex and index data is expected to change, used as the buffer usage hint
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BufferUsage {
+    #[default]
+    Static,     // uploaded once, e.g. loaded models
+    Dynamic,    // updated occasionally, e.g. procedural deformation, cloth
+    Stream,     // replaced every frame, see StreamMesh for a ring buffered alternative
+}
+
+impl BufferUsage {
+    pub fn gl_usage(&self) -> GLenum {
+        match self {
+            BufferUsage::Static => gl::STATIC_DRAW,
+            BufferUsage::Dynamic => gl::DYNAMIC_DRAW,
+            BufferUsage::Stream => gl::STREAM_DRAW,
         }
     }
 }
@@ -50,22 +118,62 @@ impl Material {
 pub struct Mesh {
     pub vertices : Vec<Vertex>,
     pub indices : Vec<GLuint>,
-    pub materials : Vec<Material>,
+    pub material : Rc<Material>,    // can be shared with other meshes
+    pub skin : Vec<VertexSkin>,     // empty unless set_skin was called
+    pub colors : Vec<glm::Vec4>,    // empty unless set_colors was called
+    pub primitive : Primitive,
+    pub has_tex_coords : bool,      // false if the source had no texture coordinates, tex_coords are all zero then
+    bounds : Option<Bounds>,        // of the vertices, updated whenever they are set
+    morph_targets : Vec<MorphTarget>,
+    morph_weights : Vec<f32>,
+    morph_mode : MorphMode,
+    morph_base : Vec<Vertex>,       // vertices before blending, only used by MorphMode::Cpu
+    morph_buffer : GLuint,          // buffer texture with the deltas, only used by MorphMode::Gpu
+    morph_texture : GLuint,
+    usage : BufferUsage,
+    vertex_capacity : usize,    // number of vertices the vbo can hold
+    index_capacity : usize,     // number of indices the ebo can hold
     vao : GLuint,
     vbo : GLuint,
-    ebo : GLuint
+    ebo : GLuint,
+    skin_vbo : GLuint,
+    color_vbo : GLuint
 }
 
 impl Mesh {
-    pub fn new(v: Vec<Vertex>, i : Vec<GLuint>, t : Vec<Material>) -> Mesh {
+    pub fn new(v: Vec<Vertex>, i : Vec<GLuint>, m : Rc<Material>) -> Mesh {
+        Self::with_usage(v, i, m, BufferUsage::Static)
+    }
+
+    /// Function to create a mesh whose buffers use the provided usage hint
+    /// 
+    /// Use BufferUsage::Dynamic for meshes that are updated with update_vertices/set_vertices
+    pub fn with_usage(v: Vec<Vertex>, i : Vec<GLuint>, m : Rc<Material>, usage : BufferUsage) -> Mesh {
         let mut mesh = Mesh {
             vertices : v,
             indices : i,
-            materials : t,
+            material : m,
+            skin : Vec::new(),
+            colors : Vec::new(),
+            primitive : Primitive::default(),
+            has_tex_coords : true,
+            bounds : None,
+            morph_targets : Vec::new(),
+            morph_weights : Vec::new(),
+            morph_mode : MorphMode::default(),
+            morph_base : Vec::new(),
+            morph_buffer : 0,
+            morph_texture : 0,
+            usage,
+            vertex_capacity : 0,
+            index_capacity : 0,
             vao : 0,
             vbo : 0,
-            ebo : 0
+            ebo : 0,
+            skin_vbo : 0,
+            color_vbo : 0
         };
+        mesh.bounds = Bounds::from_vertices(&mesh.vertices);
         mesh.setup_mesh();
         mesh
     }
@@ -83,19 +191,15 @@ impl Mesh {
 
             // Bind VBO and store vertex data
             gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
-            gl::BufferData(gl::ARRAY_BUFFER, (self.vertices.len() * mem::size_of::<Vertex>()) as GLsizeiptr, &self.vertices[0] as *const Vertex as *const c_void, gl::STATIC_DRAW);
+            // as_ptr instead of &self.vertices[0], so that an empty mesh creates empty buffers instead of panicking
+            gl::BufferData(gl::ARRAY_BUFFER, (self.vertices.len() * mem::size_of::<Vertex>()) as GLsizeiptr, self.vertices.as_ptr() as *const c_void, self.usage.gl_usage());
 
             // Bind EBO and store index data
             gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, self.ebo);
-            gl::BufferData(gl::ELEMENT_ARRAY_BUFFER, (self.indices.len() * mem::size_of::<GLuint>()) as GLsizeiptr, &self.indices[0] as *const GLuint as *const c_void, gl::STATIC_DRAW);
+            gl::BufferData(gl::ELEMENT_ARRAY_BUFFER, (self.indices.len() * mem::size_of::<GLuint>()) as GLsizeiptr, self.indices.as_ptr() as *const c_void, self.usage.gl_usage());
 
             // Link vertex attributes 
-            gl::VertexAttribPointer(0, 3, gl::FLOAT, gl::FALSE, mem::size_of::<Vertex>() as GLsizei, ptr::null());
-            gl::EnableVertexAttribArray(0);
-            gl::VertexAttribPointer(1, 3, gl::FLOAT, gl::FALSE, mem::size_of::<Vertex>() as GLsizei, mem::offset_of!(Vertex, normal) as *const c_void);
-            gl::EnableVertexAttribArray(1);
-            gl::VertexAttribPointer(2, 2, gl::FLOAT, gl::FALSE, mem::size_of::<Vertex>() as GLsizei, mem::offset_of!(Vertex, tex_coords) as *const c_void);
-            gl::EnableVertexAttribArray(2);
+            link_vertex_attributes();
 
             // Note: we can safely unbind VBO since it is bound to the VAO's vertex attribute from VertedAttribPointer
             // gl::BindBuffer(gl::ARRAY_BUFFER, 0);
@@ -103,90 +207,328 @@ impl Mesh {
             gl::BindVertexArray(0);
             // Unbinding VAO/VBO is usually not required, since we will always use BindVertexArray
         }
+        self.vertex_capacity = self.vertices.len();
+        self.index_capacity = self.indices.len();
+    }
+
+    /// Function to set the joints and weights of every vertex, used with the skinned vertex shader
+    /// 
+    /// The data is stored in its own buffer, linked to attributes 3 (joints) and 4 (weights)
+    pub fn set_skin(&mut self, skin : Vec<VertexSkin>) {
+        assert_eq!(skin.len(), self.vertices.len(), "Skin data must have one entry per vertex");
+        self.skin = skin;
+        unsafe {
+            gl::BindVertexArray(self.vao);
+            if self.skin_vbo == 0 {
+                gl::GenBuffers(1, &mut self.skin_vbo);
+            }
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.skin_vbo);
+            gl::BufferData(gl::ARRAY_BUFFER, (self.skin.len() * mem::size_of::<VertexSkin>()) as GLsizeiptr, self.skin.as_ptr() as *const c_void, self.usage.gl_usage());
+
+            // Joints are integers, IPointer keeps them from being converted to floats
+            gl::VertexAttribIPointer(3, 4, gl::UNSIGNED_SHORT, mem::size_of::<VertexSkin>() as GLsizei, ptr::null());
+            gl::EnableVertexAttribArray(3);
+            gl::VertexAttribPointer(4, 4, gl::FLOAT, gl::FALSE, mem::size_of::<VertexSkin>() as GLsizei, mem::offset_of!(VertexSkin, weights) as *const c_void);
+            gl::EnableVertexAttribArray(4);
+
+            gl::BindVertexArray(0);
+        }
+    }
+
+    /// Function to set a colour for every vertex, e.g. from a PLY scan
+    /// 
+    /// The data is stored in its own buffer, linked to attribute 5. The default shader tints the diffuse texture with it, or uses it instead if there is none
+    pub fn set_colors(&mut self, colors : Vec<glm::Vec4>) {
+        assert_eq!(colors.len(), self.vertices.len(), "Colour data must have one entry per vertex");
+        self.colors = colors;
+        unsafe {
+            gl::BindVertexArray(self.vao);
+            if self.color_vbo == 0 {
+                gl::GenBuffers(1, &mut self.color_vbo);
+            }
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.color_vbo);
+            gl::BufferData(gl::ARRAY_BUFFER, (self.colors.len() * mem::size_of::<glm::Vec4>()) as GLsizeiptr, self.colors.as_ptr() as *const c_void, self.usage.gl_usage());
+
+            gl::VertexAttribPointer(5, 4, gl::FLOAT, gl::FALSE, mem::size_of::<glm::Vec4>() as GLsizei, ptr::null());
+            gl::EnableVertexAttribArray(5);
+
+            gl::BindVertexArray(0);
+        }
+    }
+
+    /// Function to set the morph targets of the mesh, all weights start at 0
+    /// 
+    /// With MorphMode::Gpu the deltas are uploaded to a buffer texture and blended by the morph vertex shader.
+    /// With MorphMode::Cpu the blended vertices are uploaded whenever the weights change, the mesh should be
+    /// created with BufferUsage::Dynamic in that case
+    pub fn set_morph_targets(&mut self, mut targets : Vec<MorphTarget>, mode : MorphMode) {
+        for target in &targets {
+            assert_eq!(target.position_deltas.len(), self.vertices.len(), "Morph target '{}' must have one delta per vertex", target.name);
+            assert!(target.normal_deltas.is_empty() || target.normal_deltas.len() == self.vertices.len(), "Morph target '{}' must have one normal delta per vertex", target.name);
+        }
+        if targets.len() > MAX_MORPH_TARGETS {
+            println!("Warning: mesh has {} morph targets, only the first {} are used", targets.len(), MAX_MORPH_TARGETS);
+            targets.truncate(MAX_MORPH_TARGETS);
+        }
+
+        // Restore the unblended vertices if cpu blending was used before, the vertex buffer still holds the blended ones
+        if self.morph_mode == MorphMode::Cpu && !self.morph_base.is_empty() {
+            let base = mem::take(&mut self.morph_base);
+            self.upload_vertices(base);
+        }
+
+        self.morph_weights = vec![0.; targets.len()];
+        self.morph_targets = targets;
+        self.morph_mode = mode;
+
+        match mode {
+            MorphMode::Cpu => self.morph_base = self.vertices.clone(),
+            MorphMode::Gpu => unsafe { self.upload_morph_targets() },
+        }
+    }
+
+    /// Function to set the weight of each morph target, missing weights are set to 0
+    pub fn set_morph_weights(&mut self, weights : &[f32]) {
+        for (i, weight) in self.morph_weights.iter_mut().enumerate() {
+            *weight = weights.get(i).copied().unwrap_or(0.);
+        }
+
+        if self.is_cpu_morphed() {
+            let vertices = morph::blend_morph_targets(&self.morph_base, &self.morph_targets, &self.morph_weights);
+            self.upload_vertices(vertices);
+        }
+    }
+
+    fn is_cpu_morphed(&self) -> bool {
+        self.morph_mode == MorphMode::Cpu && !self.morph_targets.is_empty()
+    }
+
+    pub fn morph_weights(&self) -> &[f32] {
+        &self.morph_weights
+    }
+
+    /// Function to store the deltas in a buffer texture - for each target, all position deltas then all normal deltas
+    /// 
+    /// RGBA32F is used since RGB32F buffer textures require OpenGL 4.0
+    unsafe fn upload_morph_targets(&mut self) {
+        let mut data: Vec<f32> = Vec::with_capacity(self.morph_targets.len() * self.vertices.len() * 8);
+        for target in &self.morph_targets {
+            for delta in &target.position_deltas {
+                data.extend_from_slice(&[delta.x, delta.y, delta.z, 0.]);
+            }
+            for i in 0..self.vertices.len() {
+                let delta = target.normal_deltas.get(i).copied().unwrap_or_else(glm::Vec3::zeros);
+                data.extend_from_slice(&[delta.x, delta.y, delta.z, 0.]);
+            }
+        }
+
+        unsafe {
+            if self.morph_buffer == 0 {
+                gl::GenBuffers(1, &mut self.morph_buffer);
+                gl::GenTextures(1, &mut self.morph_texture);
+            }
+            gl::BindBuffer(gl::TEXTURE_BUFFER, self.morph_buffer);
+            gl::BufferData(gl::TEXTURE_BUFFER, mem::size_of_val(data.as_slice()) as GLsizeiptr, data.as_ptr() as *const c_void, gl::STATIC_DRAW);
+            gl::BindTexture(gl::TEXTURE_BUFFER, self.morph_texture);
+            gl::TexBuffer(gl::TEXTURE_BUFFER, gl::RGBA32F, self.morph_buffer);
+            gl::BindTexture(gl::TEXTURE_BUFFER, 0);
+            gl::BindBuffer(gl::TEXTURE_BUFFER, 0);
+        }
+    }
+
+    /// Function to overwrite part of the vertex data, starting at vertex `offset`
+    /// 
+    /// Only the updated range is uploaded using glBufferSubData. Writing past the end grows the mesh,
+    /// which reallocates the vbo if it is too small, the vao is kept. Growing the mesh drops its skin, colours and
+    /// morph targets, see set_vertices
+    pub fn update_vertices(&mut self, offset : usize, vertices : &[Vertex]) {
+        let end = offset + vertices.len();
+        assert!(offset <= self.vertices.len(), "Vertex offset {} is past the end of the mesh ({})", offset, self.vertices.len());
+        if self.is_cpu_morphed() {
+            // The update goes to the unblended vertices, which are blended and uploaded again as a whole
+            let mut base = self.morph_base.clone();
+            if end > base.len() {
+                base.resize(end, vertices[0].clone());
+            }
+            base[offset..end].clone_from_slice(vertices);
+            self.set_vertices(base);
+            return;
+        }
+
+        if end > self.vertices.len() {
+            self.vertices.resize(end, vertices[0].clone());
+            self.drop_stale_vertex_data();
+        }
+        self.vertices[offset..end].clone_from_slice(vertices);
+        self.bounds = Bounds::from_vertices(&self.vertices);
+
+        unsafe {
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+            if end > self.vertex_capacity {
+                // Buffer is too small, reupload everything - the vao still points at the same vbo
+                self.vertex_capacity = self.vertices.len();
+                gl::BufferData(gl::ARRAY_BUFFER, (self.vertex_capacity * mem::size_of::<Vertex>()) as GLsizeiptr, self.vertices.as_ptr() as *const c_void, self.usage.gl_usage());
+            }
+            else {
+                gl::BufferSubData(gl::ARRAY_BUFFER, (offset * mem::size_of::<Vertex>()) as GLintptr, mem::size_of_val(vertices) as GLsizeiptr, vertices.as_ptr() as *const c_void);
+            }
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+        }
+    }
+
+    /// Function to replace all vertex data
+    /// 
+    /// The old storage is orphaned first, so the driver does not have to wait for draws that still use it.
+    /// With cpu morph targets the vertices become the new unblended shape and the current weights are applied to them.
+    /// If the vertex count changes, the skin, colours and morph targets no longer match the vertices and are dropped
+    pub fn set_vertices(&mut self, vertices : Vec<Vertex>) {
+        if self.is_cpu_morphed() && vertices.len() == self.morph_base.len() {
+            let blended = morph::blend_morph_targets(&vertices, &self.morph_targets, &self.morph_weights);
+            self.morph_base = vertices;
+            self.upload_vertices(blended);
+        }
+        else {
+            self.upload_vertices(vertices);
+        }
+    }
+
+    fn upload_vertices(&mut self, vertices : Vec<Vertex>) {
+        let count_changed = vertices.len() != self.vertices.len();
+        self.vertices = vertices;
+        if count_changed {
+            self.drop_stale_vertex_data();
+        }
+        self.bounds = Bounds::from_vertices(&self.vertices);
+        self.vertex_capacity = self.vertex_capacity.max(self.vertices.len());
+        unsafe {
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+            Self::orphan(gl::ARRAY_BUFFER, self.vertex_capacity * mem::size_of::<Vertex>(), self.usage);
+            gl::BufferSubData(gl::ARRAY_BUFFER, 0, (self.vertices.len() * mem::size_of::<Vertex>()) as GLsizeiptr, self.vertices.as_ptr() as *const c_void);
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+        }
+    }
+
+    /// Function to drop the per vertex data that no longer has one entry per vertex, and unlink its attributes so the
+    /// shaders do not read past the end of its buffers
+    fn drop_stale_vertex_data(&mut self) {
+        let stale = stale_vertex_data(self.vertices.len(), &self.skin, &self.colors, &self.morph_targets);
+        if stale == StaleVertexData::default() {
+            return;
+        }
+        println!("Warning: vertex count of the mesh changed to {}, dropping its {}", self.vertices.len(), stale);
+        unsafe {
+            gl::BindVertexArray(self.vao);
+            if stale.skin {
+                self.skin.clear();
+                gl::DisableVertexAttribArray(3);
+                gl::DisableVertexAttribArray(4);
+            }
+            if stale.colors {
+                self.colors.clear();
+                gl::DisableVertexAttribArray(5);
+            }
+            gl::BindVertexArray(0);
+        }
+        if stale.morph_targets {
+            self.morph_targets.clear();
+            self.morph_weights.clear();
+            self.morph_base.clear();
+        }
+    }
+
+    /// Function to overwrite part of the index data, starting at index `offset`
+    /// 
+    /// Same as update_vertices, indices are not checked against the vertex count
+    #[allow(dead_code)]
+    pub fn update_indices(&mut self, offset : usize, indices : &[GLuint]) {
+        let end = offset + indices.len();
+        assert!(offset <= self.indices.len(), "Index offset {} is past the end of the mesh ({})", offset, self.indices.len());
+        if end > self.indices.len() {
+            self.indices.resize(end, 0);
+        }
+        self.indices[offset..end].copy_from_slice(indices);
+
+        unsafe {
+            // The ebo is part of the vao's state, bind the vao so that the binding is not changed for another vao
+            gl::BindVertexArray(self.vao);
+            if end > self.index_capacity {
+                self.index_capacity = self.indices.len();
+                gl::BufferData(gl::ELEMENT_ARRAY_BUFFER, (self.index_capacity * mem::size_of::<GLuint>()) as GLsizeiptr, self.indices.as_ptr() as *const c_void, self.usage.gl_usage());
+            }
+            else {
+                gl::BufferSubData(gl::ELEMENT_ARRAY_BUFFER, (offset * mem::size_of::<GLuint>()) as GLintptr, mem::size_of_val(indices) as GLsizeiptr, indices.as_ptr() as *const c_void);
+            }
+            gl::BindVertexArray(0);
+        }
+    }
+
+    /// Function to replace all index data, orphaning the old storage
+    pub fn set_indices(&mut self, indices : Vec<GLuint>) {
+        self.indices = indices;
+        self.index_capacity = self.index_capacity.max(self.indices.len());
+        unsafe {
+            gl::BindVertexArray(self.vao);
+            Self::orphan(gl::ELEMENT_ARRAY_BUFFER, self.index_capacity * mem::size_of::<GLuint>(), self.usage);
+            gl::BufferSubData(gl::ELEMENT_ARRAY_BUFFER, 0, (self.indices.len() * mem::size_of::<GLuint>()) as GLsizeiptr, self.indices.as_ptr() as *const c_void);
+            gl::BindVertexArray(0);
+        }
+    }
+
+    /// Function to orphan the buffer bound to target - the driver hands out new storage and frees the old one
+    /// once pending draws are done, instead of stalling until the gpu stops reading it
+    unsafe fn orphan(target : GLenum, size : usize, usage : BufferUsage) {
+        unsafe {
+            gl::BufferData(target, size as GLsizeiptr, ptr::null(), usage.gl_usage());
+        }
     }
 
     /// Function to draw the current mesh using the provided shader program
     /// 
     /// **Assumes that the shader program is activated**
     pub fn draw(&self,shader : &Shader) {
-        // Bind textures and set shader uniforms for all textures
-        unsafe {
-            // Reset uniforms to 0
-            // texture unit 0 is not used
-            // models can have varying textures e.g., no emissive etc
-            // not unsetting this results in other meshes using the textures from a previous call
-            shader.set_int(c_str!("material.texture_diffuse0"), 0);
-            shader.set_int(c_str!("material.texture_specular0"),0);
-            shader.set_int(c_str!("material.texture_emissive0"), 0);
-            shader.set_float(c_str!("material.shininess"), 32.);
-
-            // Set shader uniforms - textures and other material properties
-            let mut diff_num = 0;
-            let mut spec_num = 0;
-            let mut emis_num = 0;
-            let mut texture_unit = 1;
-            for material in &self.materials {
-                match material {
-                    Material::Texture { id, path: _, type_ } => {
-
-                        // Get the current texture count based on the type, and update the mutable count
-                        let (tex_type , tex_num) = match type_ {
-                            MaterialType::DiffuseTex => { 
-                                let num = diff_num;
-                                diff_num += 1;
-                                ("texture_diffuse",num)
-                            },
-                            MaterialType::SpecularTex => {
-                                let num = spec_num;
-                                spec_num += 1;
-                                ("texture_specular",num)
-                            },
-                            MaterialType::EmissiveTex => {
-                                let num = emis_num;
-                                emis_num += 1;
-                                ("texture_emissive",num)
-                            },
-                            _ => {("",0)} // This should not happen, maybe add panic!
-                        };
-
-                        // Activate the current texture unit
-                        gl::ActiveTexture(gl::TEXTURE0 + texture_unit as u32);
-
-                        // Bind the current texture
-                        gl::BindTexture(gl::TEXTURE_2D, *id);
-                        
-                        // Update the texture uniform
-                        shader.set_int( &CString::new(format!("material.{}{}",tex_type, tex_num)).unwrap(), texture_unit as i32);
-
-                        // Incrementing texture unit count
-                        texture_unit += 1;
-                    },
-                    Material::Property { value, type_ } => {
-                        shader.set_float( &CString::new(format!("material.{}",match type_ {
-                                MaterialType::Shininess => "shininess",
-                                _ => ""
-                            })).unwrap(), *value);
-                    },
-                }
-            }
+        // Nothing to draw, avoids binding textures for an empty mesh
+        if self.indices.is_empty() {
+            return;
+        }
 
-            // Texture counts - to avoid branching in the shader
-            shader.set_int(c_str!("material.use_texture_diff"), (diff_num>0) as i32);
-            shader.set_int(c_str!("material.use_texture_spec"), (spec_num>0) as i32);
-            shader.set_int(c_str!("material.use_texture_emis"), (emis_num>0) as i32);
+        unsafe {
+            self.material.bind(shader);
 
-            // Fallback color for diffuse and specular lighting
-            if diff_num == 0 {
-                shader.set_vec4_values(c_str!("material.fallback_color"), 1., 1., 1., 1.);
+            // Morph targets - always set the count, so a mesh without targets is not blended with the previous mesh's
+            if self.morph_mode == MorphMode::Gpu && !self.morph_targets.is_empty() {
+                gl::ActiveTexture(gl::TEXTURE0 + MORPH_TEXTURE_UNIT);
+                gl::BindTexture(gl::TEXTURE_BUFFER, self.morph_texture);
+                shader.set_int(c_str!("morphTargets"), MORPH_TEXTURE_UNIT as GLint);
+                shader.set_int(c_str!("morphTargetCount"), self.morph_targets.len() as GLint);
+                shader.set_int(c_str!("morphVertexCount"), self.vertices.len() as GLint);
+                shader.set_float_array(c_str!("morphWeights"), &self.morph_weights);
+            }
+            else {
+                shader.set_int(c_str!("morphTargetCount"), 0);
             }
+            shader.set_int(c_str!("useVertexColor"), !self.colors.is_empty() as GLint);
 
             // Draw the mesh
             gl::BindVertexArray(self.vao);
-            gl::DrawElements(gl::TRIANGLES, self.indices.len() as i32, gl::UNSIGNED_INT, ptr::null());
+            gl::DrawElements(self.primitive.gl_mode(), self.indices.len() as i32, gl::UNSIGNED_INT, ptr::null());
             gl::BindVertexArray(0);
         }
     }
 
+    /// Function to get the bounds of the mesh's vertices in model space, None for an empty mesh
+    ///
+    /// Kept up to date by the functions that set vertices, so writes to the vertices field are not seen. Cpu morph
+    /// targets are included, gpu morph targets and skinning are not
+    pub fn bounds(&self) -> Option<Bounds> {
+        self.bounds
+    }
+
+    /// Function to check the mesh data for problems, see `validate_mesh_data`
+    #[allow(dead_code)]
+    pub fn validate(&self) -> MeshReport {
+        validate_mesh_data(&self.vertices, &self.indices)
+    }
+
     /// Subdivides the mesh, used in 4_4 to reuse the same model
     pub fn subdivide(&mut self, n : u32) {
         // Vecs to store intermediate results
@@ -242,23 +584,11 @@ impl Mesh {
 
         }
         
-        // Delete old buffers - probably better to subdivide before creating the buffers
-        unsafe {
-            gl::DeleteVertexArrays(1, &self.vao);
-            gl::DeleteBuffers(1, &self.vbo);
-            gl::DeleteBuffers(1, &self.ebo);
-        }
-        
-        self.vertices = final_vertices;
-        self.indices = final_indices;
-        //self.materials = t;
-        self.vao = 0;
-        self.vbo = 0;
-        self.ebo = 0;
+        // Upload the new data into the existing buffers - probably better to subdivide before creating the buffers
+        self.set_vertices(final_vertices);
+        self.set_indices(final_indices);
 
         println!("Mesh after subdivision - Vertices {} , Triangles {}", self.vertices.len(), self.indices.len()/3);
-
-        self.setup_mesh();
     }
 
     fn get_center_vertex(vertices : &mut Vec<Vertex>, vertex_hash : &mut HashMap<PositionHash, GLuint>, v1 : &Vertex, v2 : &Vertex) -> GLuint {
@@ -303,8 +633,260 @@ impl Drop for Mesh {
             gl::DeleteVertexArrays(1, &self.vao);
             gl::DeleteBuffers(1, &self.vbo);
             gl::DeleteBuffers(1, &self.ebo);
+            // Deleting buffer 0 is ignored, so this is safe for meshes without skin, colour or morph data
+            gl::DeleteBuffers(1, &self.skin_vbo);
+            gl::DeleteBuffers(1, &self.color_vbo);
+            gl::DeleteBuffers(1, &self.morph_buffer);
+            gl::DeleteTextures(1, &self.morph_texture);
+        }
+    }
+}
+
+/// Function to link the attributes of Vertex to the bound vao and vbo
+pub(crate) unsafe fn link_vertex_attributes() {
+    unsafe {
+        gl::VertexAttribPointer(0, 3, gl::FLOAT, gl::FALSE, mem::size_of::<Vertex>() as GLsizei, ptr::null());
+        gl::EnableVertexAttribArray(0);
+        gl::VertexAttribPointer(1, 3, gl::FLOAT, gl::FALSE, mem::size_of::<Vertex>() as GLsizei, mem::offset_of!(Vertex, normal) as *const c_void);
+        gl::EnableVertexAttribArray(1);
+        gl::VertexAttribPointer(2, 2, gl::FLOAT, gl::FALSE, mem::size_of::<Vertex>() as GLsizei, mem::offset_of!(Vertex, tex_coords) as *const c_void);
+        gl::EnableVertexAttribArray(2);
+    }
+}
+
+/// Per vertex data of a mesh that no longer has one entry per vertex
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct StaleVertexData {
+    skin : bool,
+    colors : bool,
+    morph_targets : bool,
+}
+
+impl fmt::Display for StaleVertexData {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let names = [(self.skin, "skin"), (self.colors, "colours"), (self.morph_targets, "morph targets")];
+        let stale: Vec<&str> = names.iter().filter(|(stale, _)| *stale).map(|(_, name)| *name).collect();
+        write!(f, "{}", stale.join(", "))
+    }
+}
+
+/// Function to find the per vertex data that does not match vertex_count, empty data always matches
+fn stale_vertex_data(vertex_count : usize, skin : &[VertexSkin], colors : &[glm::Vec4], morph_targets : &[MorphTarget]) -> StaleVertexData {
+    StaleVertexData {
+        skin : !skin.is_empty() && skin.len() != vertex_count,
+        colors : !colors.is_empty() && colors.len() != vertex_count,
+        morph_targets : morph_targets.iter().any(|target| target.position_deltas.len() != vertex_count),
+    }
+}
+
+/// Problems that can be found in mesh data
+#[derive(Debug, Clone, PartialEq)]
+pub enum MeshIssue {
+    EmptyVertices,
+    EmptyIndices,
+    IndexCountNotMultipleOfThree { count : usize },
+    IndexOutOfRange { position : usize, index : GLuint },
+    DegenerateTriangle { triangle : usize },
+    InvalidNormal { vertex : usize },       // NaN, infinite or zero-length
+    NonFinitePosition { vertex : usize },
+}
+
+impl fmt::Display for MeshIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MeshIssue::EmptyVertices => write!(f, "mesh has no vertices"),
+            MeshIssue::EmptyIndices => write!(f, "mesh has no indices"),
+            MeshIssue::IndexCountNotMultipleOfThree { count } => write!(f, "index count {} is not a multiple of 3", count),
+            MeshIssue::IndexOutOfRange { position, index } => write!(f, "index {} at position {} is out of range", index, position),
+            MeshIssue::DegenerateTriangle { triangle } => write!(f, "triangle {} is degenerate", triangle),
+            MeshIssue::InvalidNormal { vertex } => write!(f, "vertex {} has a NaN or zero-length normal", vertex),
+            MeshIssue::NonFinitePosition { vertex } => write!(f, "vertex {} has a non-finite position", vertex),
+        }
+    }
+}
+
+/// Result of validating a mesh, lists every issue found
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MeshReport {
+    pub issues : Vec<MeshIssue>,
+}
+
+impl MeshReport {
+    /// True if no issues were found
+    pub fn is_valid(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+impl fmt::Display for MeshReport {
+    /// Prints the first issue of each kind and a count, a broken model can have an issue for every vertex
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.issues.is_empty() {
+            return write!(f, "no issues");
+        }
+        let mut first = true;
+        for (i, issue) in self.issues.iter().enumerate() {
+            let kind = mem::discriminant(issue);
+            if self.issues[..i].iter().any(|other| mem::discriminant(other) == kind) {
+                continue;
+            }
+            let count = self.issues.iter().filter(|other| mem::discriminant(*other) == kind).count();
+            if !first {
+                write!(f, ", ")?;
+            }
+            first = false;
+            write!(f, "{}", issue)?;
+            if count > 1 {
+                write!(f, " (and {} more)", count - 1)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What to do with a mesh that fails validation while loading a model
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[allow(dead_code)]
+pub enum InvalidMeshPolicy {
+    Keep,       // upload the data as is
+    #[default]
+    Repair,     // fix what can be fixed, drop the mesh if nothing is left
+    Drop,       // skip the mesh
+}
+
+// Squared length of the cross product below which a triangle is treated as having no area
+const DEGENERATE_AREA_EPSILON : f32 = 1e-12;
+
+/// Function to check vertex and index data for problems that would crash or render incorrectly
+/// 
+/// Checks for empty buffers, out of range indices, degenerate triangles, invalid normals and non-finite positions
+pub fn validate_mesh_data(vertices : &[Vertex], indices : &[GLuint]) -> MeshReport {
+    let mut report = MeshReport::default();
+
+    if vertices.is_empty() {
+        report.issues.push(MeshIssue::EmptyVertices);
+    }
+    if indices.is_empty() {
+        report.issues.push(MeshIssue::EmptyIndices);
+    }
+    if !indices.len().is_multiple_of(3) {
+        report.issues.push(MeshIssue::IndexCountNotMultipleOfThree { count: indices.len() });
+    }
+
+    for (vertex, v) in vertices.iter().enumerate() {
+        if !is_finite_vec3(&v.position) {
+            report.issues.push(MeshIssue::NonFinitePosition { vertex });
+        }
+        if !is_valid_normal(&v.normal) {
+            report.issues.push(MeshIssue::InvalidNormal { vertex });
+        }
+    }
+
+    for (position, index) in indices.iter().enumerate() {
+        if *index as usize >= vertices.len() {
+            report.issues.push(MeshIssue::IndexOutOfRange { position, index: *index });
+        }
+    }
+
+    for (triangle, face) in indices.chunks_exact(3).enumerate() {
+        if face.iter().all(|i| (*i as usize) < vertices.len()) && is_degenerate(vertices, face) {
+            report.issues.push(MeshIssue::DegenerateTriangle { triangle });
         }
     }
+
+    report
+}
+
+/// Function to repair vertex and index data in place, returns the report from before the repair
+/// 
+/// Triangles with out of range indices, non-finite positions or no area are removed, trailing indices that do not
+/// form a triangle are dropped, and invalid normals are replaced with the normal of a face using the vertex
+pub fn repair_mesh_data(vertices : &mut [Vertex], indices : &mut Vec<GLuint>) -> MeshReport {
+    let report = validate_mesh_data(vertices, indices);
+    if report.is_valid() {
+        return report;
+    }
+
+    // Drop incomplete triangles and triangles that cannot be drawn
+    indices.truncate(indices.len() - indices.len() % 3);
+    let mut kept = Vec::with_capacity(indices.len());
+    for face in indices.chunks_exact(3) {
+        let in_range = face.iter().all(|i| (*i as usize) < vertices.len());
+        if in_range
+            && face.iter().all(|i| is_finite_vec3(&vertices[*i as usize].position))
+            && !is_degenerate(vertices, face) {
+            kept.extend_from_slice(face);
+        }
+    }
+    *indices = kept;
+
+    // Replace invalid normals with the face normal of any triangle using the vertex
+    let mut face_normals: Vec<Option<glm::Vec3>> = vec![None; vertices.len()];
+    for face in indices.chunks_exact(3) {
+        let normal = face_normal(vertices, face);
+        for i in face {
+            face_normals[*i as usize].get_or_insert(normal);
+        }
+    }
+    for (vertex, normal) in vertices.iter_mut().zip(face_normals) {
+        if !is_finite_vec3(&vertex.position) {
+            vertex.position = glm::Vec3::zeros();
+        }
+        if !is_valid_normal(&vertex.normal) {
+            vertex.normal = normal.unwrap_or_else(glm::Vec3::y);
+        }
+    }
+
+    report
+}
+
+/// Function to calculate smooth normals for meshes without them
+/// 
+/// Each vertex gets the average of the normals of the triangles using it, weighted by triangle area.
+/// Vertices not used by any valid triangle are left with a zero normal, which mesh validation reports
+pub fn generate_normals(vertices : &mut [Vertex], indices : &[GLuint]) {
+    for vertex in vertices.iter_mut() {
+        vertex.normal = glm::Vec3::zeros();
+    }
+    for face in indices.chunks_exact(3) {
+        if face.iter().any(|i| *i as usize >= vertices.len()) {
+            continue;
+        }
+        let (p1, p2, p3) = (vertices[face[0] as usize].position, vertices[face[1] as usize].position, vertices[face[2] as usize].position);
+        // Not normalized, the length is twice the area of the triangle
+        let normal = glm::cross(&(p2 - p1), &(p3 - p1));
+        for i in face {
+            vertices[*i as usize].normal += normal;
+        }
+    }
+    for vertex in vertices.iter_mut() {
+        if is_valid_normal(&vertex.normal) {
+            vertex.normal = glm::normalize(&vertex.normal);
+        }
+    }
+}
+
+fn is_finite_vec3(v : &glm::Vec3) -> bool {
+    v.iter().all(|c| c.is_finite())
+}
+
+fn is_valid_normal(n : &glm::Vec3) -> bool {
+    is_finite_vec3(n) && glm::length2(n) > 0.
+}
+
+fn face_normal(vertices : &[Vertex], face : &[GLuint]) -> glm::Vec3 {
+    let (p1, p2, p3) = (&vertices[face[0] as usize].position, &vertices[face[1] as usize].position, &vertices[face[2] as usize].position);
+    glm::normalize(&glm::cross(&(p2 - p1), &(p3 - p1)))
+}
+
+fn is_degenerate(vertices : &[Vertex], face : &[GLuint]) -> bool {
+    if face[0] == face[1] || face[1] == face[2] || face[2] == face[0] {
+        return true;
+    }
+    let (p1, p2, p3) = (&vertices[face[0] as usize].position, &vertices[face[1] as usize].position, &vertices[face[2] as usize].position);
+    let area = glm::length2(&glm::cross(&(p2 - p1), &(p3 - p1)));
+    // NaN positions are reported separately, only treat finite zero-area triangles as degenerate
+    area.is_finite() && area <= DEGENERATE_AREA_EPSILON
 }
 
 // Struct to hold position hash for position (x,y,z)
@@ -330,4 +912,151 @@ fn integer_decode(val: f64) -> (u64, i16, i8) {
 
     exponent -= 1023 + 52;
     (mantissa, exponent, sign)
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON : f32 = 1e-5;
+
+    fn vertex(x : f32, y : f32, z : f32) -> Vertex {
+        Vertex { position : glm::vec3(x, y, z), normal : glm::Vec3::z(), tex_coords : glm::Vec2::zeros() }
+    }
+
+    /// Square in the xy plane, facing +z
+    fn square() -> (Vec<Vertex>, Vec<GLuint>) {
+        (vec![vertex(0., 0., 0.), vertex(1., 0., 0.), vertex(1., 1., 0.), vertex(0., 1., 0.)], vec![0, 1, 2, 0, 2, 3])
+    }
+
+    #[test]
+    fn valid_mesh_has_no_issues() {
+        let (vertices, indices) = square();
+        let report = validate_mesh_data(&vertices, &indices);
+        assert!(report.is_valid());
+        assert_eq!(report.to_string(), "no issues");
+
+        let report = validate_mesh_data(&[], &[]);
+        assert_eq!(report.issues, [MeshIssue::EmptyVertices, MeshIssue::EmptyIndices]);
+    }
+
+    #[test]
+    fn out_of_range_indices_are_dropped() {
+        let (mut vertices, mut indices) = square();
+        indices.extend_from_slice(&[0, 2, 4, 9, 1, 2]);
+        let report = validate_mesh_data(&vertices, &indices);
+        assert_eq!(report.issues, [
+            MeshIssue::IndexOutOfRange { position : 8, index : 4 },
+            MeshIssue::IndexOutOfRange { position : 9, index : 9 },
+        ]);
+        assert_eq!(report.to_string(), "index 4 at position 8 is out of range (and 1 more)");
+
+        assert_eq!(repair_mesh_data(&mut vertices, &mut indices), report);
+        assert_eq!(indices, [0, 1, 2, 0, 2, 3]);
+        assert!(validate_mesh_data(&vertices, &indices).is_valid());
+    }
+
+    #[test]
+    fn nan_positions_drop_their_triangles() {
+        let (mut vertices, mut indices) = square();
+        vertices[3].position.y = f32::NAN;
+        let report = validate_mesh_data(&vertices, &indices);
+        // A NaN triangle is not also reported as degenerate
+        assert_eq!(report.issues, [MeshIssue::NonFinitePosition { vertex : 3 }]);
+
+        repair_mesh_data(&mut vertices, &mut indices);
+        assert_eq!(indices, [0, 1, 2]);
+        assert_eq!(vertices[3].position, glm::Vec3::zeros());
+    }
+
+    #[test]
+    fn degenerate_triangles_are_dropped() {
+        let (mut vertices, mut indices) = square();
+        vertices.push(vertex(2., 0., 0.));
+        // Repeated index, and three points on a line
+        indices.extend_from_slice(&[0, 0, 1, 0, 1, 4]);
+        let report = validate_mesh_data(&vertices, &indices);
+        assert_eq!(report.issues, [MeshIssue::DegenerateTriangle { triangle : 2 }, MeshIssue::DegenerateTriangle { triangle : 3 }]);
+
+        repair_mesh_data(&mut vertices, &mut indices);
+        assert_eq!(indices, [0, 1, 2, 0, 2, 3]);
+    }
+
+    #[test]
+    fn trailing_partial_triangle_is_dropped() {
+        let (mut vertices, mut indices) = square();
+        indices.extend_from_slice(&[1, 2]);
+        let report = validate_mesh_data(&vertices, &indices);
+        assert_eq!(report.issues, [MeshIssue::IndexCountNotMultipleOfThree { count : 8 }]);
+
+        repair_mesh_data(&mut vertices, &mut indices);
+        assert_eq!(indices, [0, 1, 2, 0, 2, 3]);
+    }
+
+    #[test]
+    fn invalid_normals_use_the_face_normal() {
+        let (mut vertices, mut indices) = square();
+        vertices.push(vertex(5., 5., 5.));
+        vertices[1].normal = glm::Vec3::zeros();
+        vertices[2].normal.x = f32::INFINITY;
+        // Not used by any triangle, there is no face to take a normal from
+        vertices[4].normal = glm::vec3(f32::NAN, 0., 0.);
+        let report = validate_mesh_data(&vertices, &indices);
+        assert_eq!(report.issues, [
+            MeshIssue::InvalidNormal { vertex : 1 },
+            MeshIssue::InvalidNormal { vertex : 2 },
+            MeshIssue::InvalidNormal { vertex : 4 },
+        ]);
+
+        repair_mesh_data(&mut vertices, &mut indices);
+        assert_eq!(vertices[1].normal, glm::Vec3::z());
+        assert_eq!(vertices[2].normal, glm::Vec3::z());
+        assert_eq!(vertices[4].normal, glm::Vec3::y());
+        assert_eq!(indices.len(), 6);
+        assert!(validate_mesh_data(&vertices, &indices).is_valid());
+    }
+
+    #[test]
+    fn rotated_box_bounds_contain_its_corners() {
+        let bounds = Bounds { min : glm::vec3(-1., -2., -3.), max : glm::vec3(1., 2., 3.) };
+        // 90 degrees around z swaps the x and y extents, then the box is moved
+        let transform = glm::translate(&glm::Mat4::identity(), &glm::vec3(10., 0., 0.))
+            * glm::rotate_z(&glm::Mat4::identity(), std::f32::consts::FRAC_PI_2);
+        let rotated = bounds.transformed(&transform);
+        assert!((rotated.min - glm::vec3(8., -1., -3.)).norm() < EPSILON);
+        assert!((rotated.max - glm::vec3(12., 1., 3.)).norm() < EPSILON);
+
+        // 45 degrees puts the corners on the axes, the box grows to contain them
+        let diagonal = Bounds { min : glm::vec3(-1., -1., 0.), max : glm::vec3(1., 1., 0.) }
+            .transformed(&glm::rotate_z(&glm::Mat4::identity(), std::f32::consts::FRAC_PI_4));
+        let corner = std::f32::consts::SQRT_2;
+        assert!((diagonal.min - glm::vec3(-corner, -corner, 0.)).norm() < EPSILON);
+        assert!((diagonal.max - glm::vec3(corner, corner, 0.)).norm() < EPSILON);
+    }
+
+    #[test]
+    fn union_contains_both_boxes() {
+        let a = Bounds { min : glm::vec3(0., 0., 0.), max : glm::vec3(1., 1., 1.) };
+        let b = Bounds { min : glm::vec3(-1., 0.5, 2.), max : glm::vec3(0.5, 3., 4.) };
+        let union = Bounds { min : glm::vec3(-1., 0., 0.), max : glm::vec3(1., 3., 4.) };
+        assert_eq!(a.union(&b), union);
+        assert_eq!(b.union(&a), union);
+        assert_eq!(Bounds::from_vertices(&[vertex(0., 0., 0.), vertex(1., 1., 1.)]), Some(a));
+        assert_eq!(Bounds::from_vertices(&[]), None);
+    }
+
+    #[test]
+    fn per_vertex_data_of_another_length_is_stale() {
+        let skin = vec![VertexSkin { joints : [0; 4], weights : [1., 0., 0., 0.] }; 3];
+        let colors = vec![glm::vec4(1., 1., 1., 1.); 3];
+        let targets = vec![MorphTarget { name : "smile".into(), position_deltas : vec![glm::Vec3::zeros(); 3], normal_deltas : Vec::new() }];
+
+        assert_eq!(stale_vertex_data(3, &skin, &colors, &targets), StaleVertexData::default());
+        // Subdividing or growing the mesh changes the count, every kind of data is stale then
+        let stale = stale_vertex_data(12, &skin, &colors, &targets);
+        assert_eq!(stale, StaleVertexData { skin : true, colors : true, morph_targets : true });
+        assert_eq!(stale.to_string(), "skin, colours, morph targets");
+        // A mesh without the data has nothing to drop
+        assert_eq!(stale_vertex_data(12, &[], &[], &[]), StaleVertexData::default());
+        assert_eq!(stale_vertex_data(2, &[], &colors, &[]).to_string(), "colours");
+    }
+}
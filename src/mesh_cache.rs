@@ -0,0 +1,425 @@
+// Binary mesh cache - parsed OBJ data stored next to the source file, so later loads skip parsing
+
+// Notes:
+// Parsing a large OBJ like Sponza with tobj takes seconds, reading the same vertices back from a binary file takes
+// milliseconds. After the first parse, load_obj_cached writes <file>.obj.meshcache with the vertex and index buffers,
+// the MTL materials and a hash of the OBJ and its MTL files. Later loads memory-map the cache and use it if the hash,
+// format version and invalid mesh policy all match, otherwise the OBJ is parsed again and the cache rewritten.
+// Textures are not cached, materials only store their paths.
+// All values are little endian. Bump CACHE_VERSION whenever the layout or the parsing changes.
+
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use gl::types::*;
+use memmap2::Mmap;
+use nalgebra_glm as glm;
+
+use crate::mesh::{InvalidMeshPolicy, Vertex};
+use crate::model::{Model, ModelError, ObjData, ObjMesh};
+
+const MAGIC : &[u8; 8] = b"LORMESH\0";
+pub const CACHE_VERSION : u32 = 1;
+const CACHE_EXTENSION : &str = "meshcache";
+
+/// Function to read an OBJ file, from its mesh cache if the cache is up to date
+///
+/// The cache is written after parsing, failing to read or write it only prints a warning
+pub fn load_obj_cached(path : &str, policy : InvalidMeshPolicy) -> Result<ObjData, ModelError> {
+    let cache = cache_path(path);
+    let hash = match source_hash(path) {
+        Ok(hash) => hash,
+        // Let the parser report the missing file
+        Err(_) => return Model::parse_obj(path, policy),
+    };
+
+    if let Some(data) = read_cache(&cache, policy, hash) {
+        println!("Loaded {} meshes from cache {}", data.meshes.len(), cache.display());
+        return Ok(data);
+    }
+
+    let data = Model::parse_obj(path, policy)?;
+    if let Err(error) = write_cache(&cache, &data, policy, hash) {
+        println!("Warning: failed to write mesh cache {} ({})", cache.display(), error);
+    }
+    Ok(data)
+}
+
+/// Function to get the cache file of a source file, e.g. sponza.obj.meshcache
+pub fn cache_path(path : &str) -> PathBuf {
+    PathBuf::from(format!("{}.{}", path, CACHE_EXTENSION))
+}
+
+/// Function to hash an OBJ file and the MTL files it references (FNV-1a), missing MTL files are part of the hash
+pub fn source_hash(path : &str) -> io::Result<u64> {
+    let source = fs::read(path)?;
+    let directory = Path::new(path).parent().unwrap_or_else(|| Path::new(""));
+
+    let mut hash = fnv1a(FNV_OFFSET, &source);
+    for line in String::from_utf8_lossy(&source).lines() {
+        let Some(mtl) = line.trim_start().strip_prefix("mtllib") else {
+            continue;
+        };
+        let mtl = mtl.trim();
+        hash = fnv1a(hash, mtl.as_bytes());
+        hash = match fs::read(directory.join(mtl)) {
+            Ok(bytes) => fnv1a(hash, &bytes),
+            Err(_) => fnv1a(hash, b"#missing"),
+        };
+    }
+    Ok(hash)
+}
+
+const FNV_OFFSET : u64 = 0xcbf29ce484222325;
+const FNV_PRIME : u64 = 0x100000001b3;
+
+fn fnv1a(mut hash : u64, bytes : &[u8]) -> u64 {
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+    hash
+}
+
+/// Function to read a cache file, None if it is missing, outdated or broken
+pub fn read_cache(cache : &Path, policy : InvalidMeshPolicy, hash : u64) -> Option<ObjData> {
+    let file = File::open(cache).ok()?;
+    // The file may be rewritten by another process while mapped, decode only reads it once and checks every length
+    let bytes = unsafe { Mmap::map(&file) }.ok()?;
+    let directory = cache.parent().unwrap_or_else(|| Path::new("")).to_str()?.into();
+    decode(&bytes, policy, hash, directory)
+}
+
+/// Function to write a cache file, through a temporary file so a failed write never leaves a broken cache
+pub fn write_cache(cache : &Path, data : &ObjData, policy : InvalidMeshPolicy, hash : u64) -> io::Result<()> {
+    let temporary = cache.with_extension(format!("{}.tmp", CACHE_EXTENSION));
+    fs::write(&temporary, encode(data, policy, hash))?;
+    fs::rename(&temporary, cache)
+}
+
+// --Encoding-------------------------------------------------------------------------------------------------------------------- //
+
+/// Function to encode OBJ data into the cache format
+pub fn encode(data : &ObjData, policy : InvalidMeshPolicy, hash : u64) -> Vec<u8> {
+    let vertex_count: usize = data.meshes.iter().map(|mesh| mesh.vertices.len()).sum();
+    let index_count: usize = data.meshes.iter().map(|mesh| mesh.indices.len()).sum();
+    let mut writer = Writer { bytes : Vec::with_capacity(64 + vertex_count * 32 + index_count * 4) };
+
+    writer.bytes.extend_from_slice(MAGIC);
+    writer.u32(CACHE_VERSION);
+    writer.u8(policy as u8);
+    writer.u64(hash);
+
+    writer.u32(data.materials.len() as u32);
+    for material in &data.materials {
+        writer.string(&material.name);
+        for color in [material.ambient, material.diffuse, material.specular] {
+            writer.option(color, |writer, color| color.iter().for_each(|c| writer.f32(*c)));
+        }
+        for value in [material.shininess, material.dissolve, material.optical_density] {
+            writer.option(value, Writer::f32);
+        }
+        for texture in [&material.ambient_texture, &material.diffuse_texture, &material.specular_texture,
+                        &material.normal_texture, &material.shininess_texture, &material.dissolve_texture] {
+            writer.option(texture.as_deref(), Writer::string);
+        }
+        writer.option(material.illumination_model, Writer::u8);
+
+        // Sorted so the same material always gives the same bytes
+        let mut params: Vec<(&String, &String)> = material.unknown_param.iter().collect();
+        params.sort();
+        writer.u32(params.len() as u32);
+        for (key, value) in params {
+            writer.string(key);
+            writer.string(value);
+        }
+    }
+
+    writer.u32(data.meshes.len() as u32);
+    for mesh in &data.meshes {
+        writer.string(&mesh.name);
+        writer.option(mesh.material.map(|id| id as u32), Writer::u32);
+        writer.u8(mesh.has_tex_coords as u8);
+        writer.u32(mesh.vertices.len() as u32);
+        for vertex in &mesh.vertices {
+            for value in vertex.position.iter().chain(vertex.normal.iter()).chain(vertex.tex_coords.iter()) {
+                writer.f32(*value);
+            }
+        }
+        writer.u32(mesh.indices.len() as u32);
+        for index in &mesh.indices {
+            writer.u32(*index);
+        }
+    }
+    writer.bytes
+}
+
+/// Function to decode the cache format, None if the header does not match or the data is truncated
+pub fn decode(bytes : &[u8], policy : InvalidMeshPolicy, hash : u64, directory : String) -> Option<ObjData> {
+    let mut reader = Reader { bytes, position : 0 };
+    if reader.take(MAGIC.len())? != MAGIC || reader.u32()? != CACHE_VERSION || reader.u8()? != policy as u8 || reader.u64()? != hash {
+        return None;
+    }
+
+    let material_count = reader.u32()?;
+    let mut materials = Vec::new();
+    for _ in 0..material_count {
+        let name = reader.string()?;
+        let mut colors = [None; 3];
+        for color in &mut colors {
+            *color = reader.option(|reader| Some([reader.f32()?, reader.f32()?, reader.f32()?]))?;
+        }
+        let mut values = [None; 3];
+        for value in &mut values {
+            *value = reader.option(Reader::f32)?;
+        }
+        let mut textures: [Option<String>; 6] = Default::default();
+        for texture in &mut textures {
+            *texture = reader.option(Reader::string)?;
+        }
+        let illumination_model = reader.option(Reader::u8)?;
+
+        let [ambient, diffuse, specular] = colors;
+        let [shininess, dissolve, optical_density] = values;
+        let [ambient_texture, diffuse_texture, specular_texture, normal_texture, shininess_texture, dissolve_texture] = textures;
+        let mut material = tobj::Material {
+            name, ambient, diffuse, specular, shininess, dissolve, optical_density,
+            ambient_texture, diffuse_texture, specular_texture, normal_texture, shininess_texture, dissolve_texture,
+            illumination_model, ..Default::default()
+        };
+        for _ in 0..reader.u32()? {
+            material.unknown_param.insert(reader.string()?, reader.string()?);
+        }
+        materials.push(material);
+    }
+
+    let mesh_count = reader.u32()?;
+    let mut meshes = Vec::new();
+    for _ in 0..mesh_count {
+        let name = reader.string()?;
+        let material = reader.option(Reader::u32)?.map(|id| id as usize);
+        let has_tex_coords = reader.u8()? != 0;
+
+        let vertex_count = reader.u32()? as usize;
+        let vertex_bytes = reader.take(vertex_count.checked_mul(32)?)?;
+        let vertices = vertex_bytes.chunks_exact(32).map(|chunk| {
+            let value = |i : usize| f32::from_le_bytes(chunk[i * 4..i * 4 + 4].try_into().unwrap());
+            Vertex {
+                position : glm::vec3(value(0), value(1), value(2)),
+                normal : glm::vec3(value(3), value(4), value(5)),
+                tex_coords : glm::vec2(value(6), value(7)),
+            }
+        }).collect();
+
+        let index_count = reader.u32()? as usize;
+        let index_bytes = reader.take(index_count.checked_mul(4)?)?;
+        let indices: Vec<GLuint> = index_bytes.chunks_exact(4).map(|chunk| u32::from_le_bytes(chunk.try_into().unwrap())).collect();
+
+        meshes.push(ObjMesh { name, vertices, indices, material, has_tex_coords });
+    }
+
+    // Anything left over means the file is not what this version wrote
+    if reader.position != bytes.len() {
+        return None;
+    }
+    Some(ObjData { directory, meshes, materials })
+}
+
+struct Writer {
+    bytes : Vec<u8>,
+}
+
+impl Writer {
+    fn u8(&mut self, value : u8) {
+        self.bytes.push(value);
+    }
+
+    fn u32(&mut self, value : u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value : u64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn f32(&mut self, value : f32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn string(&mut self, value : &str) {
+        self.u32(value.len() as u32);
+        self.bytes.extend_from_slice(value.as_bytes());
+    }
+
+    /// A presence byte, followed by the value if there is one
+    fn option<T>(&mut self, value : Option<T>, write : impl FnOnce(&mut Writer, T)) {
+        self.u8(value.is_some() as u8);
+        if let Some(value) = value {
+            write(self, value);
+        }
+    }
+}
+
+struct Reader<'a> {
+    bytes : &'a [u8],
+    position : usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count : usize) -> Option<&'a [u8]> {
+        let bytes = self.bytes.get(self.position..self.position.checked_add(count)?)?;
+        self.position += count;
+        Some(bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn f32(&mut self) -> Option<f32> {
+        Some(f32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let length = self.u32()? as usize;
+        String::from_utf8(self.take(length)?.to_vec()).ok()
+    }
+
+    /// Outer None if the data is broken, inner None if the value was not stored
+    fn option<T>(&mut self, read : impl FnOnce(&mut Reader<'a>) -> Option<T>) -> Option<Option<T>> {
+        match self.u8()? {
+            0 => Some(None),
+            1 => Some(Some(read(self)?)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OBJ : &str = "mtllib cube.mtl
+o Quad
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vt 0 0
+vt 1 0
+vt 1 1
+vt 0 1
+vn 0 0 1
+usemtl Painted
+f 1/1/1 2/2/1 3/3/1
+f 1/1/1 3/3/1 4/4/1
+o Triangle
+v 0 0 1
+v 1 0 1
+v 0 1 1
+f 5 6 7
+";
+
+    const MTL : &str = "newmtl Painted
+Ka 0.1 0.1 0.1
+Kd 0.8 0.5 0.2
+Ks 1 1 1
+Ns 64
+d 0.5
+illum 2
+map_Kd painted.png
+Pr 0.25
+";
+
+    /// Function to write the test OBJ and MTL into a new directory, returns the OBJ path
+    fn write_source(name : &str) -> String {
+        let directory = std::env::temp_dir().join(format!("mesh_cache_{}_{}", name, std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(directory.join("cube.obj"), OBJ).unwrap();
+        fs::write(directory.join("cube.mtl"), MTL).unwrap();
+        directory.join("cube.obj").to_str().unwrap().to_string()
+    }
+
+    fn assert_same_data(a : &ObjData, b : &ObjData) {
+        assert_eq!(a.directory, b.directory);
+        assert_eq!(a.meshes.len(), b.meshes.len());
+        for (a, b) in a.meshes.iter().zip(&b.meshes) {
+            assert_eq!(a.name, b.name);
+            assert_eq!(a.material, b.material);
+            assert_eq!(a.has_tex_coords, b.has_tex_coords);
+            assert_eq!(a.indices, b.indices);
+            assert_eq!(a.vertices, b.vertices);
+        }
+        assert_eq!(a.materials.len(), b.materials.len());
+        for (a, b) in a.materials.iter().zip(&b.materials) {
+            // tobj::Material has no PartialEq, its Debug output covers every field
+            let (mut a, mut b) = (a.clone(), b.clone());
+            let (a_params, b_params) = (std::mem::take(&mut a.unknown_param), std::mem::take(&mut b.unknown_param));
+            assert_eq!(format!("{:?}", a), format!("{:?}", b));
+            assert_eq!(a_params, b_params);
+        }
+    }
+
+    #[test]
+    fn cache_matches_fresh_parse() {
+        let path = write_source("matches");
+        let cache = cache_path(&path);
+        let _ = fs::remove_file(&cache);
+
+        // First load parses and writes the cache, the second one reads it
+        let parsed = load_obj_cached(&path, InvalidMeshPolicy::Repair).unwrap();
+        assert!(cache.exists());
+        let hash = source_hash(&path).unwrap();
+        let cached = read_cache(&cache, InvalidMeshPolicy::Repair, hash).unwrap();
+        assert_same_data(&parsed, &cached);
+        assert_same_data(&Model::parse_obj(&path, InvalidMeshPolicy::Repair).unwrap(), &load_obj_cached(&path, InvalidMeshPolicy::Repair).unwrap());
+
+        assert_eq!(cached.meshes.len(), 2);
+        assert_eq!(cached.materials[0].diffuse_texture.as_deref(), Some("painted.png"));
+        assert_eq!(cached.materials[0].unknown_param.get("Pr").map(String::as_str), Some("0.25"));
+        assert!(!cached.meshes[1].has_tex_coords);
+
+        fs::remove_dir_all(Path::new(&path).parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn outdated_cache_is_ignored() {
+        let path = write_source("outdated");
+        let data = Model::parse_obj(&path, InvalidMeshPolicy::Repair).unwrap();
+        let hash = source_hash(&path).unwrap();
+        let bytes = encode(&data, InvalidMeshPolicy::Repair, hash);
+        let directory = data.directory.clone();
+
+        assert!(decode(&bytes, InvalidMeshPolicy::Repair, hash, directory.clone()).is_some());
+        assert!(decode(&bytes, InvalidMeshPolicy::Repair, hash ^ 1, directory.clone()).is_none());
+        assert!(decode(&bytes, InvalidMeshPolicy::Drop, hash, directory.clone()).is_none());
+
+        // Other format versions
+        let mut other_version = bytes.clone();
+        other_version[8..12].copy_from_slice(&(CACHE_VERSION + 1).to_le_bytes());
+        assert!(decode(&other_version, InvalidMeshPolicy::Repair, hash, directory.clone()).is_none());
+
+        // Truncated or extended files
+        for length in [0, 10, bytes.len() / 2, bytes.len() - 1] {
+            assert!(decode(&bytes[..length], InvalidMeshPolicy::Repair, hash, directory.clone()).is_none());
+        }
+        let mut extended = bytes.clone();
+        extended.push(0);
+        assert!(decode(&extended, InvalidMeshPolicy::Repair, hash, directory).is_none());
+
+        // Editing the MTL changes the hash
+        fs::write(Path::new(&path).with_extension("mtl"), MTL.replace("Ns 64", "Ns 32")).unwrap();
+        assert_ne!(source_hash(&path).unwrap(), hash);
+
+        fs::remove_dir_all(Path::new(&path).parent().unwrap()).unwrap();
+    }
+}
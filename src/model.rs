@@ -1,29 +1,110 @@
+use std::error::Error;
+use std::ffi::CStr;
+use std::fmt;
 use std::path::Path;
-use std::os::raw::c_void;
+use std::rc::Rc;
 
 use tobj;
 use gl::{self,types::*};
 use nalgebra_glm as glm;
 
-use crate::mesh::Mesh;
-use crate::mesh::Material;
-use crate::mesh::MaterialType;
+use crate::gltf_loader;
+use crate::mesh_cache;
+use crate::material::{AlphaMode, Material, RenderState, TextureSlot};
+use crate::mesh::{self, Bounds, Mesh, InvalidMeshPolicy, Primitive};
 use crate::mesh::Vertex;
+use crate::ply_loader::{self, PlyError};
 use crate::shader::Shader;
+use crate::stl_loader::{self, StlError};
+use crate::texture::{self, Texture, TextureCache, TextureData, TextureOptions, FALLBACK_PIXELS};
+
+/// Errors that stop a model from loading
+/// 
+/// Problems that can be worked around, like a missing MTL file or texture, only print a warning
+#[derive(Debug)]
+pub enum ModelError {
+    Obj { path : String, error : tobj::LoadError },
+    Gltf { path : String, error : gltf::Error },
+    Ply { path : String, error : PlyError },
+    Stl { path : String, error : StlError },
+    UnsupportedFormat { path : String },    // extension not handled by Model::load
+    WorkerStopped { path : String },        // a background loading thread exited before sending everything
+}
+
+impl fmt::Display for ModelError {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ModelError::Obj { path, error } => write!(f, "Failed to load OBJ file {} : {}", path, error),
+            ModelError::Gltf { path, error } => write!(f, "Failed to load glTF file {} : {}", path, error),
+            ModelError::Ply { path, error } => write!(f, "Failed to load PLY file {} : {}", path, error),
+            ModelError::Stl { path, error } => write!(f, "Failed to load STL file {} : {}", path, error),
+            ModelError::UnsupportedFormat { path } => write!(f, "Failed to load model {} : unsupported file extension", path),
+            ModelError::WorkerStopped { path } => write!(f, "Failed to load model {} : loading thread stopped unexpectedly", path),
+        }
+    }
+}
+
+impl Error for ModelError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ModelError::Obj { error, .. } => Some(error),
+            ModelError::Gltf { error, .. } => Some(error),
+            ModelError::Ply { error, .. } => Some(error),
+            ModelError::Stl { error, .. } => Some(error),
+            ModelError::UnsupportedFormat { .. } | ModelError::WorkerStopped { .. } => None,
+        }
+    }
+}
+
+/// Mesh data read from an OBJ file, not uploaded yet
+pub struct ObjMesh {
+    pub name : String,
+    pub vertices : Vec<Vertex>,
+    pub indices : Vec<GLuint>,
+    pub material : Option<usize>,   // index into ObjData::materials
+    pub has_tex_coords : bool,      // false if the OBJ had no texture coordinates for this mesh
+}
+
+/// Everything needed to create a Model from an OBJ file, see Model::parse_obj
+pub struct ObjData {
+    pub directory : String,         // textures paths are relative to this
+    pub meshes : Vec<ObjMesh>,
+    pub materials : Vec<tobj::Material>,
+}
+
+/// Parameters for Model::draw_with
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DrawParams {
+    pub transform : glm::Mat4,                  // model matrix
+    pub view_position : Option<glm::Vec3>,      // camera position, blended meshes are sorted back to front if set
+    pub apply_render_state : bool,              // apply each material's render state, see Model::draw_with
+}
+
+impl Default for DrawParams {
+    fn default() -> DrawParams {
+        DrawParams {
+            transform : glm::Mat4::identity(),
+            view_position : None,
+            apply_render_state : true,
+        }
+    }
+}
 
 /// Struct that represents a model with multiple meshes
 pub struct Model {
     pub meshes : Vec<Mesh>, // custom mesh obj
-    textures_loaded : Vec<Material>,
-    directory : String
+    directory : String,
+    pub invalid_mesh_policy : InvalidMeshPolicy, // what load_model does with meshes that fail validation
+    pub stl_weld_angle : Option<f32>,   // crease angle in degrees for welding STL vertices, None keeps faceted normals
 }
 
 impl Model {
     pub fn new() -> Self {
         Model {
             meshes : Vec::new(),
-            textures_loaded : Vec::new(),
-            directory : String::new()
+            directory : String::new(),
+            invalid_mesh_policy : InvalidMeshPolicy::default(),
+            stl_weld_angle : None,
         }
     }
 
@@ -40,14 +121,14 @@ impl Model {
             vertex.position *= scale;
             vertex.tex_coords *= tex_scale;
         }
+        let material = Material {
+            diffuse: Some(self.load_texture_if_required(texture_path, TextureOptions::color())),
+            ..Default::default()
+        };
         self.meshes.push(Mesh::new(
             vertices,
             Vec::from([0,1,3, 1,2,3]),
-            Vec::from([Material::Texture {
-                id: unsafe { Self::load_texture(texture_path, "") },
-                type_: MaterialType::DiffuseTex,
-                path: texture_path.into()}
-            ])
+            Rc::new(material)
         ));
     }
 
@@ -66,7 +147,7 @@ impl Model {
         self.meshes.push(Mesh::new(
             vertices,
             Vec::from([0,1,3, 1,2,3]),
-            Vec::from([])
+            Rc::new(Material::default())
         ));
     }
 
@@ -82,153 +163,614 @@ impl Model {
         }
     }
 
-    /// Function to load a 3D model from path using tobj
-    pub fn load_model(&mut self, path : &str) {
+    /// Function to load a model with the loader matching its extension - .obj, .gltf, .glb, .ply or .stl
+    pub fn load(&mut self, path : &str) -> Result<(), ModelError> {
+        let extension = Path::new(path).extension().and_then(|extension| extension.to_str()).unwrap_or("").to_ascii_lowercase();
+        match extension.as_str() {
+            "obj" => self.load_model(path),
+            "gltf" | "glb" => self.load_gltf(path),
+            "ply" => self.load_ply(path),
+            "stl" => self.load_stl(path),
+            _ => Err(ModelError::UnsupportedFormat { path : path.into() }),
+        }
+    }
+
+    /// Function to load a 3D model from path using tobj, or from its mesh cache if the OBJ did not change (see mesh_cache.rs)
+    /// 
+    /// A missing or broken MTL file gives default materials, missing normals are generated and textures that fail
+    /// to load are replaced by a fallback texture, these only print a warning
+    pub fn load_model(&mut self, path : &str) -> Result<(), ModelError> {
         println!("Loading model from {}", path);
+        let data = mesh_cache::load_obj_cached(path, self.invalid_mesh_policy)?;
+        self.directory = data.directory.clone();
+
+        // Materials are created once and shared by all meshes using them
+        let materials: Vec<Rc<Material>> = data.materials.iter().map(|material| {
+            Rc::new(Self::create_material(material, |path, options| self.load_texture_if_required(path, options)))
+        }).collect();
+        let default_material = Rc::new(Material::default());
+
+        for mesh in data.meshes {
+            let material = mesh.material.and_then(|material_id| materials.get(material_id)).unwrap_or(&default_material);
+            let mut gpu_mesh = Mesh::new(mesh.vertices, mesh.indices, material.clone());
+            gpu_mesh.has_tex_coords = mesh.has_tex_coords;
+            self.meshes.push(gpu_mesh);
+        }
+        Ok(())
+    }
+
+    /// Function to read an OBJ file and its MTL into cpu data, without any OpenGL calls
+    /// 
+    /// Meshes are already validated using policy, so this can run on a worker thread (see asset_loader.rs)
+    pub fn parse_obj(path : &str, policy : InvalidMeshPolicy) -> Result<ObjData, ModelError> {
         let p = Path::new(path);
-        self.directory = p.parent().unwrap_or_else(|| Path::new("")).to_str().unwrap().into();
+        let directory = p.parent().unwrap_or_else(|| Path::new("")).to_str().unwrap().into();
 
-        let object = tobj::load_obj(path, &tobj::GPU_LOAD_OPTIONS);
-        let (models, materials) = object.expect("Failed to load OBJ file");
-        let materials = materials.expect("Failed to load MTL file");
-        //self.materials = materials;
+        let (models, materials) = tobj::load_obj(path, &tobj::GPU_LOAD_OPTIONS).map_err(|error| ModelError::Obj { path : path.into(), error })?;
+        let materials = materials.unwrap_or_else(|error| {
+            println!("Warning: failed to load MTL file for {} ({}), using default materials", path, error);
+            Vec::new()
+        });
 
         println!("# of models: {}", models.len());
         println!("# of materials: {}", materials.len());
 
-        
-
-        for j in 0..models.len() {
-            let model = &models[j];
+        let mut meshes = Vec::with_capacity(models.len());
+        for model in &models {
             let num_vertices = model.mesh.positions.len() / 3;
+            let has_normals = model.mesh.normals.len() >= num_vertices * 3;
+            let has_tex_coords = model.mesh.texcoords.len() >= num_vertices * 2;
+
             let mut vertices = Vec::with_capacity(num_vertices);
+            for i in 0..num_vertices { 
+                vertices.push(Vertex { 
+                    position: glm::vec3(model.mesh.positions[i*3],model.mesh.positions[i*3+1],model.mesh.positions[i*3+2]), 
+                    normal: if has_normals {
+                        glm::vec3(model.mesh.normals[i*3],model.mesh.normals[i*3+1],model.mesh.normals[i*3+2])
+                    } else {
+                        glm::Vec3::zeros()
+                    },
+                    tex_coords: if has_tex_coords {
+                        glm::vec2(model.mesh.texcoords[i*2],model.mesh.texcoords[i*2+1])
+                    } else {
+                        glm::Vec2::zeros()
+                    },
+                });
+            }
 
-            if model.mesh.texcoords.len() <= ((num_vertices - 1) * 2)  { // model without texture, using if statement outside loop to avoid branching
-                println!("{}", model.mesh.texcoords.len());
-                for i in 0..num_vertices { 
-                    vertices.push(Vertex { 
-                        position: glm::vec3(model.mesh.positions[i*3],model.mesh.positions[i*3+1],model.mesh.positions[i*3+2]), 
-                        normal: glm::vec3(model.mesh.normals[i*3],model.mesh.normals[i*3+1],model.mesh.normals[i*3+2]), 
-                        tex_coords: glm::vec2(i as f32,i as f32)
-                    });
-                }
+            if !has_normals {
+                println!("Warning: mesh '{}' has no normals, generating them", model.name);
+                mesh::generate_normals(&mut vertices, &model.mesh.indices);
+            }
+            if !has_tex_coords {
+                println!("Warning: mesh '{}' has no texture coordinates", model.name);
+            }
+
+            let mut indices = model.mesh.indices.clone();
+
+            // Validate before creating any buffers, empty or broken meshes would otherwise panic or render garbage
+            if !Self::check_mesh(policy, &model.name, &mut vertices, &mut indices) {
+                continue;
+            }
+
+            meshes.push(ObjMesh {
+                name : model.name.clone(),
+                vertices,
+                indices,
+                material : model.mesh.material_id,
+                has_tex_coords,
+            });
+        }
+
+        Ok(ObjData { directory, meshes, materials })
+    }
+
+    /// Function to load a glTF 2.0 model (.gltf or .glb) from path
+    /// 
+    /// Node transforms are applied to the vertices, so the model is drawn with a single model matrix
+    pub fn load_gltf(&mut self, path : &str) -> Result<(), ModelError> {
+        println!("Loading glTF model from {}", path);
+        let data = gltf_loader::load_gltf_data(path).map_err(|error| ModelError::Gltf { path : path.into(), error })?;
+
+        println!("# of meshes: {}", data.meshes.len());
+        println!("# of materials: {}", data.materials.len());
+
+        // Upload images when a material uses them, an image used as both colour and data map is uploaded twice
+        // glTF uvs start at the top left so images are not flipped
+        let key = TextureCache::canonical_key(path, "");
+        let materials: Vec<Rc<Material>> = TextureCache::with_global(|cache| {
+            let mut texture = |i : usize, slot : TextureSlot| {
+                let key = format!("{}#image{}", key, i);
+                let options = TextureOptions { flip : false, ..TextureOptions::for_slot(slot) };
+                let image = &data.images[i];
+                cache.get(&key, options).unwrap_or_else(|| {
+                    cache.insert_pixels(&key, options, image.width, image.height, &image.pixels)
+                })
+            };
+
+            data.materials.iter().map(|material| {
+                let base_color = material.base_color_factor;
+                Rc::new(Material {
+                    name: material.name.clone(),
+                    diffuse: material.base_color_texture.map(|i| texture(i, TextureSlot::Diffuse)),
+                    normal: material.normal_texture.map(|i| texture(i, TextureSlot::Normal)),
+                    emissive: material.emissive_texture.map(|i| texture(i, TextureSlot::Emissive)),
+                    // Roughness and metallic are the green and blue channels of the same texture
+                    roughness_map: material.metallic_roughness_texture.map(|i| texture(i, TextureSlot::Roughness)),
+                    metallic_map: material.metallic_roughness_texture.map(|i| texture(i, TextureSlot::Metallic)),
+                    diffuse_color: base_color,
+                    // Approximation for the phong shaders - metals reflect their base color, dielectrics ~4% white
+                    specular_color: glm::lerp(&glm::vec3(0.04, 0.04, 0.04), &base_color.xyz(), material.metallic_factor),
+                    emissive_color: material.emissive_factor,
+                    shininess: Material::roughness_to_shininess(material.roughness_factor),
+                    roughness: material.roughness_factor,
+                    metallic: material.metallic_factor,
+                    alpha_mode: material.alpha_mode,
+                    alpha_cutoff: material.alpha_cutoff,
+                    double_sided: material.double_sided,
+                    ..Default::default()
+                })
+            }).collect()
+        });
+        let default_material = Rc::new(Material::default());
+
+        for mesh in data.meshes {
+            let (mut vertices, mut indices) = (mesh.vertices, mesh.indices);
+            if !Self::check_mesh(self.invalid_mesh_policy, &mesh.name, &mut vertices, &mut indices) {
+                continue;
             }
-            else {
-                for i in 0..num_vertices { 
-                    vertices.push(Vertex { 
-                        position: glm::vec3(model.mesh.positions[i*3],model.mesh.positions[i*3+1],model.mesh.positions[i*3+2]), 
-                        normal: glm::vec3(model.mesh.normals[i*3],model.mesh.normals[i*3+1],model.mesh.normals[i*3+2]), 
-                        //tex_coords: glm::vec2(model.mesh.positions[i*2],model.mesh.positions[i*2+1])
-                        tex_coords: glm::vec2(model.mesh.texcoords[i*2],model.mesh.texcoords[i*2+1])
-                    });
+            let material = match mesh.material {
+                Some(material_id) => materials[material_id].clone(),
+                None => default_material.clone(),
+            };
+            self.meshes.push(Mesh::new(vertices, indices, material));
+        }
+        Ok(())
+    }
+
+    /// Function to load a PLY mesh or point cloud (ASCII or binary) from path
+    /// 
+    /// Vertex colours are stored with Mesh::set_colors, files without faces are drawn as points
+    pub fn load_ply(&mut self, path : &str) -> Result<(), ModelError> {
+        println!("Loading PLY model from {}", path);
+        let data = ply_loader::load_ply_data(path).map_err(|error| ModelError::Ply { path : path.into(), error })?;
+        let (mut vertices, mut indices) = (data.vertices, data.indices);
+        println!("# of vertices: {}", vertices.len());
+
+        // Point clouds have no triangles to validate
+        if !data.is_point_cloud && !Self::check_mesh(self.invalid_mesh_policy, path, &mut vertices, &mut indices) {
+            return Ok(());
+        }
+        if !data.has_normals && !data.is_point_cloud {
+            println!("Warning: mesh '{}' has no normals, generating them", path);
+        }
+
+        let mut mesh = Mesh::new(vertices, indices, Rc::new(Material::default()));
+        mesh.has_tex_coords = data.has_tex_coords;
+        if data.is_point_cloud {
+            mesh.primitive = Primitive::Points;
+        }
+        if !data.colors.is_empty() {
+            mesh.set_colors(data.colors);
+        }
+        self.meshes.push(mesh);
+        Ok(())
+    }
+
+    /// Function to load an STL mesh (ASCII or binary) from path
+    /// 
+    /// Normals are per face unless stl_weld_angle is set, STL files have no texture coordinates or materials
+    pub fn load_stl(&mut self, path : &str) -> Result<(), ModelError> {
+        println!("Loading STL model from {}", path);
+        let data = stl_loader::load_stl_data(path, self.stl_weld_angle).map_err(|error| ModelError::Stl { path : path.into(), error })?;
+        let (mut vertices, mut indices) = (data.vertices, data.indices);
+        println!("# of triangles: {}", indices.len() / 3);
+
+        let name = if data.name.is_empty() { path } else { &data.name };
+        if !Self::check_mesh(self.invalid_mesh_policy, name, &mut vertices, &mut indices) {
+            return Ok(());
+        }
+        let mut mesh = Mesh::new(vertices, indices, Rc::new(Material::default()));
+        mesh.has_tex_coords = false;
+        self.meshes.push(mesh);
+        Ok(())
+    }
+
+    /// Function to create a material from a tobj material, load_texture is called for each texture path
+    /// 
+    /// Colour maps are loaded as sRGB and data maps as linear, see TextureOptions::for_slot.
+    /// Colours multiply their texture in the default shaders, so a texture without a colour (Kd, Ks, Ke) keeps its
+    /// own colours. map_d makes the material alpha tested, d below 1 alpha blended. illum 0 and 1 turn off specular
+    /// highlights. Pr and Pm are the roughness and metallic factors of the PBR extension
+    pub(crate) fn create_material(material : &tobj::Material, mut load_texture : impl FnMut(&str, TextureOptions) -> Texture) -> Material {
+        let mut result = Material {
+            name: material.name.clone(),
+            ..Default::default()
+        };
+
+        let textures = Self::material_textures(material);
+        for (slot, path) in &textures {
+            *result.texture_mut(*slot) = Some(load_texture(path, TextureOptions::for_slot(*slot)));
+        }
+        let has_texture = |slot : TextureSlot| textures.iter().any(|(texture_slot, _)| *texture_slot == slot);
+        let texture_default = |slot : TextureSlot| if has_texture(slot) { glm::vec3(1., 1., 1.) } else { glm::Vec3::zeros() };
+
+        // Opacity - d, or its inverse Tr
+        let opacity = material.dissolve
+            .or_else(|| Self::parse_mtl_floats(material, "Tr").map(|tr| 1. - tr[0]))
+            .unwrap_or(1.);
+        if has_texture(TextureSlot::Opacity) {
+            result.alpha_mode = AlphaMode::Mask;
+        }
+        else if opacity < 1. {
+            result.alpha_mode = AlphaMode::Blend;
+        }
+
+        let diffuse = material.diffuse.map(glm::Vec3::from).unwrap_or(glm::vec3(1., 1., 1.));
+        result.diffuse_color = glm::vec4(diffuse.x, diffuse.y, diffuse.z, opacity);
+        result.specular_color = material.specular.map(glm::Vec3::from).unwrap_or_else(|| texture_default(TextureSlot::Specular));
+        result.emissive_color = Self::parse_mtl_floats(material, "Ke")
+            .map(|ke| if ke.len() >= 3 { glm::vec3(ke[0], ke[1], ke[2]) } else { glm::vec3(ke[0], ke[0], ke[0]) })
+            .unwrap_or_else(|| texture_default(TextureSlot::Emissive));
+
+        // PBR extension, a map without a factor uses the map as is
+        let roughness = Self::parse_mtl_floats(material, "Pr").map(|pr| pr[0]);
+        let metallic = Self::parse_mtl_floats(material, "Pm").map(|pm| pm[0]);
+        result.roughness = roughness.unwrap_or(1.);
+        result.metallic = metallic.unwrap_or(if has_texture(TextureSlot::Metallic) { 1. } else { 0. });
+        if let (Some(metallic), None) = (metallic, material.specular) {
+            // Same approximation as glTF materials - metals reflect their diffuse colour, dielectrics ~4% white
+            result.specular_color = glm::lerp(&glm::vec3(0.04, 0.04, 0.04), &diffuse, metallic);
+        }
+
+        if let Some(shininess) = material.shininess {
+            result.shininess = shininess*128./1000.;
+        }
+        else if let Some(roughness) = roughness {
+            result.shininess = Material::roughness_to_shininess(roughness);
+        }
+        if let Some(0 | 1) = material.illumination_model {
+            result.specular_color = glm::Vec3::zeros();
+        }
+
+        // map_Bump is a height map, norm a tangent space normal map
+        if let Some(statement) = &material.normal_texture {
+            result.bump_scale = Some(Self::parse_texture_statement(statement).1.unwrap_or(1.));
+        }
+
+        result
+    }
+
+    /// Function to get the texture paths used by a tobj material, and the slot each one goes in
+    /// 
+    /// Texture options like -bm are removed from the paths, and backslashes are replaced with slashes
+    pub(crate) fn material_textures(material : &tobj::Material) -> Vec<(TextureSlot, String)> {
+        let statements = [
+            (TextureSlot::Diffuse, material.diffuse_texture.as_ref()),
+            (TextureSlot::Specular, material.specular_texture.as_ref()),
+            (TextureSlot::Emissive, material.unknown_param.get("map_Ke")),
+            (TextureSlot::Normal, material.normal_texture.as_ref().or(material.unknown_param.get("norm"))),
+            (TextureSlot::Opacity, material.dissolve_texture.as_ref()),
+            (TextureSlot::Roughness, material.unknown_param.get("map_Pr")),
+            (TextureSlot::Metallic, material.unknown_param.get("map_Pm")),
+        ];
+        statements.into_iter()
+            .filter_map(|(slot, statement)| Some((slot, Self::parse_texture_statement(statement?).0)))
+            .filter(|(_, path)| !path.is_empty())
+            .collect()
+    }
+
+    /// Function to split an MTL texture statement like `-bm 0.5 textures\wall_bump.png` into the path and the bump
+    /// multiplier, other options are skipped
+    fn parse_texture_statement(statement : &str) -> (String, Option<f32>) {
+        let mut rest = statement.trim();
+        let mut bump_multiplier = None;
+        while let Some(option) = rest.strip_prefix('-') {
+            let (name, after) = option.split_once(char::is_whitespace).unwrap_or((option, ""));
+            rest = after.trim_start();
+            // Options with a fixed number of values, -o -s and -t take up to 3 numbers
+            let values = match name {
+                "mm" => 2,
+                "o" | "s" | "t" => 3,
+                "blendu" | "blendv" | "boost" | "bm" | "cc" | "clamp" | "imfchan" | "texres" | "type" => 1,
+                _ => 0,
+            };
+            for i in 0..values {
+                let (value, after) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+                let number = value.parse::<f32>();
+                if number.is_err() && i > 0 {
+                    break;
+                }
+                if name == "bm" {
+                    bump_multiplier = number.ok();
                 }
+                rest = after.trim_start();
             }
+        }
+        (rest.replace('\\', "/"), bump_multiplier)
+    }
 
-            let indices = model.mesh.indices.clone();
-            
-            // materials - textures and properties
-            let mut textures: Vec<Material> = Vec::new();
-            if let Some(material_id) = model.mesh.material_id {
-                let material = &materials[material_id].clone();
-                
-                if let Some(path) = &material.diffuse_texture {
-                    textures.push(self.load_texture_if_required(path.as_str(), MaterialType::DiffuseTex));
+    /// Function to read the numbers of an MTL statement tobj does not parse, like Ke or Pr
+    fn parse_mtl_floats(material : &tobj::Material, key : &str) -> Option<Vec<f32>> {
+        let values: Vec<f32> = material.unknown_param.get(key)?.split_whitespace().map_while(|value| value.parse().ok()).collect();
+        if values.is_empty() { None } else { Some(values) }
+    }
+
+    /// Function to apply an InvalidMeshPolicy to mesh data before it is uploaded
+    /// 
+    /// Returns false if the mesh should be skipped
+    fn check_mesh(policy : InvalidMeshPolicy, name : &str, vertices : &mut [Vertex], indices : &mut Vec<GLuint>) -> bool {
+        match policy {
+            InvalidMeshPolicy::Keep => {
+                let report = mesh::validate_mesh_data(vertices, indices);
+                if !report.is_valid() {
+                    println!("Warning: mesh '{}' has issues, keeping as is: {}", name, report);
                 }
-                if let Some(path) = &material.specular_texture {
-                    textures.push(self.load_texture_if_required(path.as_str(), MaterialType::SpecularTex));
+                true
+            },
+            InvalidMeshPolicy::Repair => {
+                let report = mesh::repair_mesh_data(vertices, indices);
+                if report.is_valid() {
+                    return true;
                 }
-                if let Some(shininess) = material.shininess {
-                    textures.push(Material::Property { value: (shininess*128./1000.), type_: MaterialType::Shininess })
+                if indices.is_empty() {
+                    println!("Warning: mesh '{}' has nothing left after repair, dropping: {}", name, report);
+                    return false;
                 }
-
-                for (k, v) in &material.unknown_param {
-                    if k.to_string() == "map_Ke" {
-                           textures.push(self.load_texture_if_required(v.as_str(), MaterialType::EmissiveTex));
-                    }
+                println!("Warning: mesh '{}' was repaired: {}", name, report);
+                true
+            },
+            InvalidMeshPolicy::Drop => {
+                let report = mesh::validate_mesh_data(vertices, indices);
+                if !report.is_valid() {
+                    println!("Warning: mesh '{}' has issues, dropping: {}", name, report);
+                    return false;
                 }
-            }
-
-            self.meshes.push(Mesh::new(vertices, indices, textures));
+                true
+            },
         }
     }
 
+    /// Function to draw all meshes, the model matrix is expected to be set by the caller
+    ///
+    /// The shader computes the normal matrix itself, and the current render state is used for every material
     pub fn draw(&self, shader : &Shader) {
+        unsafe {
+            shader.set_int(c_str!("hasNormalMatrix"), 0);
+        }
         for mesh in &self.meshes {
             mesh.draw(shader);
         }
     }
 
-    fn load_texture_if_required(&mut self,path: &str, type_name: MaterialType) -> Material {
-        {
-            let texture = self.textures_loaded.iter().find(|t| t.is_path_eq(path));
-            if let Some(texture) = texture {
-                return texture.clone();
+    /// Function to draw all meshes with a transform, setting the model and normal matrices
+    ///
+    /// With params.apply_render_state, each material's render state is applied (see Material::render_state) : opaque
+    /// and masked meshes are drawn first, then blended meshes, back to front if params.view_position is set. The render
+    /// state is restored afterwards
+    ///
+    /// Without it, the meshes are drawn in order with whatever blending, culling and depth state the caller has set,
+    /// like Model::draw. Alpha modes, double sided materials and params.view_position are ignored
+    ///
+    /// **Assumes that the shader program is activated**
+    pub fn draw_with(&self, shader : &Shader, params : &DrawParams) {
+        unsafe {
+            // Inverse transpose of the upper 3x3, computed once here instead of for every vertex
+            let normal_matrix = glm::mat4_to_mat3(&glm::inverse_transpose(params.transform));
+            shader.set_mat4(c_str!("model"), params.transform);
+            shader.set_mat3(c_str!("normalMatrix"), normal_matrix);
+            shader.set_int(c_str!("hasNormalMatrix"), 1);
+
+            if !params.apply_render_state {
+                for mesh in &self.meshes {
+                    mesh.draw(shader);
+                }
+                return;
+            }
+
+            let (blended, opaque): (Vec<&Mesh>, Vec<&Mesh>) = self.meshes.iter()
+                .partition(|mesh| mesh.material.alpha_mode == AlphaMode::Blend);
+            // Distances are computed once per mesh instead of on every comparison, the sort is stable so
+            // without a view position the meshes keep their order
+            let mut blended: Vec<(f32, &Mesh)> = blended.into_iter().map(|mesh| {
+                let distance = params.view_position.zip(mesh.bounds())
+                    .map(|(view_position, bounds)| glm::distance2(&bounds.transformed(&params.transform).center(), &view_position));
+                (distance.unwrap_or(0.), mesh)
+            }).collect();
+            blended.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+            let saved_state = RenderState::current();
+            let mut current_state = saved_state;
+            for mesh in opaque.into_iter().chain(blended.into_iter().map(|(_, mesh)| mesh)) {
+                let state = mesh.material.render_state(&saved_state);
+                if state != current_state {
+                    state.apply();
+                    current_state = state;
+                }
+                mesh.draw(shader);
+            }
+            if current_state != saved_state {
+                saved_state.apply();
             }
         }
-        
-        //println!("Loading texture from path {}", path);
+    }
 
-        let texture = Material::Texture {
-            id: unsafe { Self::load_texture(path, &self.directory) },
-            type_: type_name,
-            path: path.into()
-        };
-        self.textures_loaded.push(texture.clone());
-        texture
+    /// Function to get the bounds of all meshes in model space, None if the model has no vertices
+    pub fn bounds(&self) -> Option<Bounds> {
+        self.meshes.iter()
+            .filter_map(|mesh| mesh.bounds())
+            .reduce(|a, b| a.union(&b))
     }
 
-    pub unsafe fn load_texture(path: &str, directory: &str) -> GLuint {
-        let absolute_path = Path::new(directory).join(path);
-        let mut texture: GLuint = 0;
-        unsafe {
-            // Generate Texture
-            gl::GenTextures(1, &mut texture);
-
-            // Bind texture to target and texture unit
-            gl::ActiveTexture(gl::TEXTURE1);
-            gl::BindTexture(gl::TEXTURE_2D, texture);
-
-            // Interpolation
-            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
-            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
-
-            // Border wrap
-            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S,gl::REPEAT as i32);
-            gl::TexParameteri(gl::TEXTURE_2D,gl::TEXTURE_WRAP_T,gl::REPEAT as i32);
-            // Only required for CLAMP_TO_BORDER
-            //gl::TexParameterfv(gl::TEXTURE_2D,gl::TEXTURE_BORDER_COLOR,[1.0, 1.0, 1.0, 1.0].as_ptr());
-            
-            //println!("Loading image from path {}", absolute_path.to_str().unwrap());
-            // Loading image from file
-            let img = image::open(&absolute_path).expect("Failed to load texture").flipv().into_rgba8();
-
-            //println!("Storing texture data");
-            // Store data into texture
-            gl::TexImage2D(gl::TEXTURE_2D,0,gl::RGBA as i32,img.width() as i32,img.height() as i32,0,gl::RGBA as u32,gl::UNSIGNED_BYTE,img.as_ptr() as *const u8 as *const c_void);
-
-            //println!("Generating mip maps");
-            // Generate mip maps for the texture
-            gl::GenerateMipmap(gl::TEXTURE_2D);
-
-            // Unbind texture
-            gl::BindTexture(gl::TEXTURE_2D, 0);
+    /// Function to load a texture relative to the model's directory, shared with other models through the texture cache
+    /// 
+    /// A texture that fails to load is replaced by the fallback texture, with a warning
+    fn load_texture_if_required(&mut self, path: &str, options : TextureOptions) -> Texture {
+        //println!("Loading texture from path {}", path);
+        TextureCache::with_global(|cache| {
+            cache.load(path, &self.directory, options).unwrap_or_else(|error| {
+                println!("Warning: failed to load texture {} ({}), using a fallback texture", path, error);
+                cache.fallback()
+            })
+        })
+    }
 
-        };
-        texture
+    /// Function to load a texture file into a new gl texture, not shared through the texture cache
+    /// 
+    /// A texture that fails to load is replaced by the fallback checker, with a warning
+    #[allow(dead_code)]
+    pub unsafe fn load_texture(path: &str, directory: &str, options : &TextureOptions) -> GLuint {
+        //println!("Loading image from path {}", absolute_path.to_str().unwrap());
+        // Loading image from file
+        match TextureData::read(path, directory, options.flip) {
+            Ok(data) => unsafe { data.upload(options) },
+            Err(error) => {
+                println!("Warning: failed to load texture {} ({}), using a fallback texture", path, error);
+                unsafe { texture::load_texture_from_pixels(2, 2, &FALLBACK_PIXELS, &TextureOptions::nearest()) }
+            }
+        }
     }
 }
 
-impl Drop for Model {
-    fn drop(&mut self) {
-        for texture in &self.textures_loaded {
-            match texture {
-                Material::Texture { id, path: _, type_: _ } => {
-                    //println!("Deleting texture {} : {}",id, path);
-                    unsafe {
-                        gl::DeleteTextures(1, id as *const u32);
-                    }},
-                _ => {},
-            }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    /// Function to write an OBJ, and optionally its MTL, into a new directory, returns the OBJ path
+    fn write_obj(name : &str, obj : &str, mtl : Option<&str>) -> String {
+        let directory = std::env::temp_dir().join(format!("model_{}_{}", name, std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(directory.join("model.obj"), obj).unwrap();
+        if let Some(mtl) = mtl {
+            fs::write(directory.join("model.mtl"), mtl).unwrap();
+        }
+        directory.join("model.obj").to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn missing_mtl_gives_default_materials() {
+        let path = write_obj("missing_mtl", "mtllib model.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nvn 0 0 1\nusemtl Painted\nf 1//1 2//1 3//1\n", None);
+        let data = Model::parse_obj(&path, InvalidMeshPolicy::Repair).unwrap();
+        assert!(data.materials.is_empty());
+        assert_eq!(data.meshes.len(), 1);
+        // No material to point to, the loaders use the default material
+        assert_eq!(data.meshes[0].material, None);
+    }
+
+    #[test]
+    fn missing_normals_are_generated() {
+        // Quad in the xy plane, counter-clockwise seen from +z, without normals or texture coordinates
+        let path = write_obj("no_normals", "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nf 1 2 3 4\n", None);
+        let data = Model::parse_obj(&path, InvalidMeshPolicy::Drop).unwrap();
+        let mesh = &data.meshes[0];
+        assert_eq!(mesh.indices.len(), 6);
+        for vertex in &mesh.vertices {
+            assert!((vertex.normal - glm::Vec3::z()).norm() < 1e-6, "{}", vertex.normal);
         }
+        assert!(!mesh.has_tex_coords);
+        assert!(mesh.vertices.iter().all(|vertex| vertex.tex_coords == glm::Vec2::zeros()));
+    }
+
+    #[test]
+    fn texture_coordinates_are_flagged() {
+        let path = write_obj("tex_coords", "v 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0 0\nvt 1 0\nvt 0 1\nf 1/1 2/2 3/3\n", None);
+        let data = Model::parse_obj(&path, InvalidMeshPolicy::Repair).unwrap();
+        assert!(data.meshes[0].has_tex_coords);
+        assert_eq!(data.meshes[0].vertices[1].tex_coords, glm::vec2(1., 0.));
+    }
+
+    #[test]
+    fn missing_obj_is_an_error() {
+        let path = std::env::temp_dir().join("model_does_not_exist.obj");
+        let result = Model::parse_obj(path.to_str().unwrap(), InvalidMeshPolicy::Repair);
+        assert!(matches!(result, Err(ModelError::Obj { .. })));
+    }
+
+    fn mtl_material(params : &[(&str, &str)]) -> tobj::Material {
+        tobj::Material {
+            name : "test".into(),
+            diffuse : Some([0.8, 0.2, 0.2]),
+            unknown_param : params.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect(),
+            ..Default::default()
+        }
+    }
+
+    /// Function to create a material that has no textures, so nothing is loaded or deleted on the GPU
+    fn untextured(material : &tobj::Material) -> Material {
+        Model::create_material(material, |path, _| panic!("unexpected texture {}", path))
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn texture_statement_options_are_skipped() {
+        assert_eq!(Model::parse_texture_statement("-bm 0.5 textures\\wall_bump.png"), ("textures/wall_bump.png".into(), Some(0.5)));
+        assert_eq!(Model::parse_texture_statement("-o 0.5 0.25 0 -s 2 2 1 brick.png"), ("brick.png".into(), None));
+        // -o with fewer than 3 values stops at the path
+        assert_eq!(Model::parse_texture_statement("-o 0.5 brick.png"), ("brick.png".into(), None));
+        assert_eq!(Model::parse_texture_statement("-clamp on textures\\old brick wall.png"), ("textures/old brick wall.png".into(), None));
+        assert_eq!(Model::parse_texture_statement("  my textures/wall.png  "), ("my textures/wall.png".into(), None));
+    }
+
+    #[test]
+    fn mtl_floats_stop_at_the_first_word() {
+        let material = mtl_material(&[("Ke", "0.1 0.2 0.3"), ("Pr", "0.5 extra 1"), ("Pm", "metal")]);
+        assert_eq!(Model::parse_mtl_floats(&material, "Ke"), Some(vec![0.1, 0.2, 0.3]));
+        assert_eq!(Model::parse_mtl_floats(&material, "Pr"), Some(vec![0.5]));
+        assert_eq!(Model::parse_mtl_floats(&material, "Pm"), None);
+        assert_eq!(Model::parse_mtl_floats(&material, "Tr"), None);
+    }
+
+    #[test]
+    fn opacity_sets_the_alpha_mode() {
+        assert_eq!(untextured(&mtl_material(&[])).alpha_mode, AlphaMode::Opaque);
+
+        let dissolved = untextured(&tobj::Material { dissolve : Some(0.5), ..mtl_material(&[]) });
+        assert_eq!(dissolved.alpha_mode, AlphaMode::Blend);
+        assert_eq!(dissolved.diffuse_color.w, 0.5);
+
+        let transparent = untextured(&mtl_material(&[("Tr", "0.25")]));
+        assert_eq!(transparent.alpha_mode, AlphaMode::Blend);
+        assert_eq!(transparent.diffuse_color.w, 0.75);
+
+        // A map_d mask is alpha tested, even with d below 1
+        let mut loaded = Vec::new();
+        let masked = Model::create_material(&tobj::Material {
+            dissolve : Some(0.5),
+            dissolve_texture : Some("textures\\leaf_mask.png".into()),
+            ..mtl_material(&[])
+        }, |path, _| {
+            loaded.push(path.to_string());
+            Texture::new(0, path)
+        });
+        assert_eq!(masked.alpha_mode, AlphaMode::Mask);
+        assert!(masked.opacity.is_some());
+        assert_eq!(loaded, ["textures/leaf_mask.png"]);
+        // Dropping the texture would delete it on the GPU
+        std::mem::forget(masked);
+    }
+
+    #[test]
+    fn metallic_factor_sets_the_specular_colour() {
+        let metal = untextured(&mtl_material(&[("Pm", "1")]));
+        assert_eq!(metal.metallic, 1.);
+        assert!((metal.specular_color - glm::vec3(0.8, 0.2, 0.2)).norm() < 1e-6);
+
+        let dielectric = untextured(&mtl_material(&[("Pm", "0")]));
+        assert!((dielectric.specular_color - glm::vec3(0.04, 0.04, 0.04)).norm() < 1e-6);
+
+        // An explicit Ks wins over the approximation
+        let specular = untextured(&tobj::Material { specular : Some([0.5, 0.5, 0.5]), ..mtl_material(&[("Pm", "1")]) });
+        assert_eq!(specular.specular_color, glm::vec3(0.5, 0.5, 0.5));
+    }
+
+    #[test]
+    fn illumination_models_without_highlights_have_no_specular() {
+        for illum in [0, 1] {
+            let material = untextured(&tobj::Material { specular : Some([1., 1., 1.]), illumination_model : Some(illum), ..mtl_material(&[]) });
+            assert_eq!(material.specular_color, glm::Vec3::zeros());
+        }
+        let highlights = untextured(&tobj::Material { specular : Some([1., 1., 1.]), illumination_model : Some(2), ..mtl_material(&[]) });
+        assert_eq!(highlights.specular_color, glm::vec3(1., 1., 1.));
+    }
+}
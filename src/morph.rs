@@ -0,0 +1,152 @@
+// Morph target (blend shape) animation
+
+// Notes:
+// A morph target stores a position delta (and optionally a normal delta) for every vertex of a mesh. The final vertex is
+// base + sum(weight_i * delta_i). On the gpu the deltas are stored in a buffer texture read with texelFetch in the
+// vertex shader (see Mesh::set_morph_targets), blend_morph_targets does the same on the cpu as a fallback.
+
+use nalgebra_glm as glm;
+
+use crate::mesh::Vertex;
+
+/// Maximum number of targets blended at once, keep in sync with MAX_MORPH_TARGETS in the morph vertex shader
+pub const MAX_MORPH_TARGETS : usize = 8;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MorphTarget {
+    pub name : String,
+    pub position_deltas : Vec<glm::Vec3>,   // one per vertex
+    pub normal_deltas : Vec<glm::Vec3>,     // one per vertex, or empty if the target does not change normals
+}
+
+/// Where morph targets are blended
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum MorphMode {
+    #[default]
+    Gpu,    // in the vertex shader, the mesh's vertices are not changed
+    Cpu,    // blended into the mesh's vertex buffer when the weights change
+}
+
+/// Keyframed weights for all targets of a mesh
+#[derive(Clone, Debug)]
+pub struct MorphAnimation {
+    pub times : Vec<f32>,           // in seconds, increasing
+    pub weights : Vec<Vec<f32>>,    // one weight per target, for each keyframe
+}
+
+/// Function to blend morph targets into the base vertices on the cpu
+///
+/// Targets without a weight are ignored, normals are renormalized after blending
+pub fn blend_morph_targets(base : &[Vertex], targets : &[MorphTarget], weights : &[f32]) -> Vec<Vertex> {
+    let mut vertices = base.to_vec();
+    for (target, weight) in targets.iter().zip(weights) {
+        if *weight == 0. {
+            continue;
+        }
+        for (vertex, delta) in vertices.iter_mut().zip(&target.position_deltas) {
+            vertex.position += delta * *weight;
+        }
+        for (vertex, delta) in vertices.iter_mut().zip(&target.normal_deltas) {
+            vertex.normal += delta * *weight;
+        }
+    }
+
+    for vertex in &mut vertices {
+        if glm::length2(&vertex.normal) > 0. {
+            vertex.normal = glm::normalize(&vertex.normal);
+        }
+    }
+    vertices
+}
+
+impl MorphAnimation {
+    /// Function to get the weights at a time, linearly interpolated between keyframes
+    ///
+    /// If looping, time wraps around the last keyframe, otherwise it is clamped
+    pub fn sample(&self, time : f32, looping : bool) -> Vec<f32> {
+        let (Some(first), Some(last)) = (self.times.first(), self.times.last()) else {
+            return Vec::new();
+        };
+        let duration = last - first;
+        let time = if looping && duration > 0. { first + (time - first).rem_euclid(duration) } else { time.clamp(*first, *last) };
+
+        // Index of the first keyframe after time
+        let next = self.times.partition_point(|t| *t <= time);
+        if next == 0 {
+            return self.weights[0].clone();
+        }
+        if next == self.times.len() {
+            return self.weights[next - 1].clone();
+        }
+
+        let t = (time - self.times[next - 1]) / (self.times[next] - self.times[next - 1]);
+        self.weights[next - 1].iter().zip(&self.weights[next]).map(|(a, b)| a + (b - a) * t).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON : f32 = 1e-6;
+
+    fn base() -> Vec<Vertex> {
+        vec![
+            Vertex { position : glm::vec3(0., 0., 0.), normal : glm::vec3(0., 0., 1.), tex_coords : glm::vec2(0., 0.) },
+            Vertex { position : glm::vec3(1., 0., 0.), normal : glm::vec3(0., 0., 1.), tex_coords : glm::vec2(1., 0.) },
+        ]
+    }
+
+    fn targets() -> Vec<MorphTarget> {
+        vec![
+            MorphTarget {
+                name : "raise".into(),
+                position_deltas : vec![glm::vec3(0., 1., 0.), glm::vec3(0., 2., 0.)],
+                normal_deltas : vec![],
+            },
+            MorphTarget {
+                name : "tilt".into(),
+                position_deltas : vec![glm::vec3(1., 0., 0.), glm::vec3(0., 0., 0.)],
+                normal_deltas : vec![glm::vec3(1., 0., -1.), glm::vec3(0., 0., 0.)],
+            },
+        ]
+    }
+
+    #[test]
+    fn zero_weights_return_the_base_mesh() {
+        let vertices = blend_morph_targets(&base(), &targets(), &[0., 0.]);
+        assert_eq!(vertices[1].position, base()[1].position);
+        assert_eq!(vertices[1].normal, base()[1].normal);
+    }
+
+    #[test]
+    fn weighted_deltas_are_added() {
+        let vertices = blend_morph_targets(&base(), &targets(), &[0.5, 1.]);
+        assert!((vertices[0].position - glm::vec3(1., 0.5, 0.)).norm() < EPSILON);
+        assert!((vertices[1].position - glm::vec3(1., 1., 0.)).norm() < EPSILON);
+
+        // (0,0,1) + (1,0,-1) = (1,0,0), renormalized
+        assert!((vertices[0].normal - glm::vec3(1., 0., 0.)).norm() < EPSILON);
+        assert_eq!(vertices[1].normal, glm::vec3(0., 0., 1.));
+        // Other attributes are not touched
+        assert_eq!(vertices[1].tex_coords, glm::vec2(1., 0.));
+    }
+
+    #[test]
+    fn missing_weights_are_ignored() {
+        let vertices = blend_morph_targets(&base(), &targets(), &[1.]);
+        assert!((vertices[1].position - glm::vec3(1., 2., 0.)).norm() < EPSILON);
+    }
+
+    #[test]
+    fn animation_interpolates_weights() {
+        let animation = MorphAnimation {
+            times : vec![0., 1., 2.],
+            weights : vec![vec![0., 1.], vec![1., 0.], vec![0., 0.]],
+        };
+        assert_eq!(animation.sample(0.25, false), vec![0.25, 0.75]);
+        assert_eq!(animation.sample(1.5, false), vec![0.5, 0.]);
+        assert_eq!(animation.sample(3., false), vec![0., 0.]);
+        assert_eq!(animation.sample(2.25, true), vec![0.25, 0.75]);
+    }
+}
@@ -0,0 +1,479 @@
+// PLY (Stanford polygon format) loading - ASCII and binary, used by scanning pipelines
+
+// Notes:
+// A PLY file is a text header declaring elements (vertex, face, anything else) and their properties, followed by the
+// data as text or as little/big endian binary. The header is parsed first, then every element is read in order, so
+// elements this loader does not use are still read past correctly.
+// Vertices can have normals (nx ny nz), colours (red green blue alpha, integers are scaled to 0-1) and texture
+// coordinates (u v, s t or texture_u texture_v). Faces are polygons, split into triangle fans.
+// A file without faces is a point cloud, loaded with Primitive::Points.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+
+use gl::types::*;
+use nalgebra_glm as glm;
+
+use crate::mesh::{self, Vertex};
+
+/// Errors that stop a PLY file from loading
+#[derive(Debug)]
+pub enum PlyError {
+    Io(io::Error),
+    Header(String),     // missing or malformed header line
+    Data(String),       // data does not match the header
+}
+
+impl fmt::Display for PlyError {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PlyError::Io(error) => write!(f, "{}", error),
+            PlyError::Header(message) => write!(f, "invalid header, {}", message),
+            PlyError::Data(message) => write!(f, "invalid data, {}", message),
+        }
+    }
+}
+
+impl Error for PlyError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PlyError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// Mesh data read from a PLY file, not uploaded yet
+pub struct PlyData {
+    pub vertices : Vec<Vertex>,
+    pub indices : Vec<GLuint>,      // triangles, or every vertex once for a point cloud
+    pub colors : Vec<glm::Vec4>,    // one per vertex, empty if the file has no colours
+    pub has_normals : bool,         // false if the file has none, generated for meshes and left at zero for point clouds
+    pub has_tex_coords : bool,
+    pub is_point_cloud : bool,      // true if the file has no faces
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ScalarType {
+    Int8,
+    UInt8,
+    Int16,
+    UInt16,
+    Int32,
+    UInt32,
+    Float32,
+    Float64,
+}
+
+impl ScalarType {
+    fn parse(name : &str) -> Option<ScalarType> {
+        Some(match name {
+            "char" | "int8" => ScalarType::Int8,
+            "uchar" | "uint8" => ScalarType::UInt8,
+            "short" | "int16" => ScalarType::Int16,
+            "ushort" | "uint16" => ScalarType::UInt16,
+            "int" | "int32" => ScalarType::Int32,
+            "uint" | "uint32" => ScalarType::UInt32,
+            "float" | "float32" => ScalarType::Float32,
+            "double" | "float64" => ScalarType::Float64,
+            _ => return None,
+        })
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            ScalarType::Int8 | ScalarType::UInt8 => 1,
+            ScalarType::Int16 | ScalarType::UInt16 => 2,
+            ScalarType::Int32 | ScalarType::UInt32 | ScalarType::Float32 => 4,
+            ScalarType::Float64 => 8,
+        }
+    }
+
+    /// Largest value of an unsigned integer type, colours stored as integers are divided by it
+    fn color_scale(&self) -> f32 {
+        match self {
+            ScalarType::UInt8 => 255.,
+            ScalarType::UInt16 => 65535.,
+            _ => 1.,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Property {
+    Scalar { name : String, value_type : ScalarType },
+    List { name : String, count_type : ScalarType, item_type : ScalarType },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Element {
+    name : String,
+    count : usize,
+    properties : Vec<Property>,
+}
+
+/// Function to read a PLY file, normals are generated for meshes that have none
+pub fn load_ply_data(path : &str) -> Result<PlyData, PlyError> {
+    parse_ply(&fs::read(path).map_err(PlyError::Io)?)
+}
+
+/// Function to parse the contents of a PLY file
+pub fn parse_ply(bytes : &[u8]) -> Result<PlyData, PlyError> {
+    let (format, elements, body_start) = parse_header(bytes)?;
+    let body = &bytes[body_start..];
+    let mut reader = match format {
+        Format::Ascii => {
+            let text = std::str::from_utf8(body).map_err(|_| PlyError::Data("ASCII data is not valid text".into()))?;
+            Reader::Ascii(text.split_ascii_whitespace())
+        },
+        Format::BinaryLittleEndian => Reader::Binary { bytes : body, position : 0, big_endian : false },
+        Format::BinaryBigEndian => Reader::Binary { bytes : body, position : 0, big_endian : true },
+    };
+
+    let mut vertices = Vec::new();
+    let mut colors = Vec::new();
+    let mut indices = Vec::new();
+    let mut has_normals = false;
+    let mut has_tex_coords = false;
+    let mut has_faces = false;
+
+    for element in &elements {
+        // Every value takes at least a byte, so a count the data cannot hold comes from a broken header, checked
+        // before anything is reserved for it
+        let min_size = element.count.checked_mul(element.properties.len().max(1));
+        if min_size.is_none_or(|size| size > body.len()) {
+            return Err(PlyError::Data(format!("{} {} elements do not fit in {} bytes of data", element.count, element.name, body.len())));
+        }
+        match element.name.as_str() {
+            "vertex" => {
+                let columns = VertexColumns::new(element);
+                has_normals = columns.normal.is_some();
+                has_tex_coords = columns.tex_coords.is_some();
+                let mut row = vec![0.; element.properties.len()];
+                vertices.reserve(element.count);
+                for _ in 0..element.count {
+                    read_row(&mut reader, element, &mut row, |_, _| Ok(()))?;
+                    vertices.push(columns.vertex(&row));
+                    if let Some(color) = columns.color(&row) {
+                        colors.push(color);
+                    }
+                }
+            },
+            "face" => {
+                has_faces = true;
+                let list = element.properties.iter().position(|property| {
+                    matches!(property, Property::List { name, .. } if name == "vertex_indices" || name == "vertex_index")
+                }).ok_or_else(|| PlyError::Header("face element has no vertex_indices list".into()))?;
+
+                let mut row = vec![0.; element.properties.len()];
+                let mut polygon = Vec::new();
+                for _ in 0..element.count {
+                    polygon.clear();
+                    read_row(&mut reader, element, &mut row, |property, value| {
+                        if property == list {
+                            if value < 0. {
+                                return Err(PlyError::Data(format!("negative vertex index {}", value)));
+                            }
+                            polygon.push(value as GLuint);
+                        }
+                        Ok(())
+                    })?;
+                    // Triangle fan, polygons are expected to be convex
+                    for i in 2..polygon.len() {
+                        indices.extend_from_slice(&[polygon[0], polygon[i - 1], polygon[i]]);
+                    }
+                }
+            },
+            // Edges, materials and other elements are skipped
+            _ => {
+                let mut row = vec![0.; element.properties.len()];
+                for _ in 0..element.count {
+                    read_row(&mut reader, element, &mut row, |_, _| Ok(()))?;
+                }
+            },
+        }
+    }
+
+    if let Some(index) = indices.iter().find(|index| **index as usize >= vertices.len()) {
+        return Err(PlyError::Data(format!("vertex index {} is out of range, there are {} vertices", index, vertices.len())));
+    }
+
+    let is_point_cloud = !has_faces;
+    if is_point_cloud {
+        indices = (0..vertices.len() as GLuint).collect();
+    }
+    else if !has_normals {
+        mesh::generate_normals(&mut vertices, &indices);
+    }
+
+    Ok(PlyData { vertices, indices, colors, has_normals, has_tex_coords, is_point_cloud })
+}
+
+/// Function to parse the header, returns the data format, the elements and where the data starts
+fn parse_header(bytes : &[u8]) -> Result<(Format, Vec<Element>, usize), PlyError> {
+    const END : &[u8] = b"end_header";
+    let end = bytes.windows(END.len()).position(|window| window == END)
+        .ok_or_else(|| PlyError::Header("no end_header line".into()))?;
+    // Data starts after the end of the end_header line, which may end with \r\n
+    let body_start = match bytes[end..].iter().position(|byte| *byte == b'\n') {
+        Some(newline) => end + newline + 1,
+        None => bytes.len(),
+    };
+    let header = std::str::from_utf8(&bytes[..end]).map_err(|_| PlyError::Header("header is not valid text".into()))?;
+
+    let mut lines = header.lines().map(str::trim).filter(|line| !line.is_empty());
+    if lines.next() != Some("ply") {
+        return Err(PlyError::Header("file does not start with ply".into()));
+    }
+
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    for line in lines {
+        let words: Vec<&str> = line.split_ascii_whitespace().collect();
+        match words.as_slice() {
+            ["format", name, _version] => {
+                format = Some(match *name {
+                    "ascii" => Format::Ascii,
+                    "binary_little_endian" => Format::BinaryLittleEndian,
+                    "binary_big_endian" => Format::BinaryBigEndian,
+                    _ => return Err(PlyError::Header(format!("unknown format {}", name))),
+                });
+            },
+            ["comment", ..] | ["obj_info", ..] => {},
+            ["element", name, count] => {
+                let count = count.parse().map_err(|_| PlyError::Header(format!("invalid element count in '{}'", line)))?;
+                elements.push(Element { name : name.to_string(), count, properties : Vec::new() });
+            },
+            ["property", "list", count_type, item_type, name] => {
+                let property = Property::List {
+                    name : name.to_string(),
+                    count_type : parse_type(count_type)?,
+                    item_type : parse_type(item_type)?,
+                };
+                elements.last_mut().ok_or_else(|| PlyError::Header("property before any element".into()))?.properties.push(property);
+            },
+            ["property", value_type, name] => {
+                let property = Property::Scalar { name : name.to_string(), value_type : parse_type(value_type)? };
+                elements.last_mut().ok_or_else(|| PlyError::Header("property before any element".into()))?.properties.push(property);
+            },
+            _ => return Err(PlyError::Header(format!("unknown line '{}'", line))),
+        }
+    }
+
+    let format = format.ok_or_else(|| PlyError::Header("no format line".into()))?;
+    Ok((format, elements, body_start))
+}
+
+fn parse_type(name : &str) -> Result<ScalarType, PlyError> {
+    ScalarType::parse(name).ok_or_else(|| PlyError::Header(format!("unknown property type {}", name)))
+}
+
+/// Function to read one row of an element, scalar values are stored in row and list items are passed to list_item
+fn read_row(reader : &mut Reader, element : &Element, row : &mut [f64], mut list_item : impl FnMut(usize, f64) -> Result<(), PlyError>) -> Result<(), PlyError> {
+    for (i, property) in element.properties.iter().enumerate() {
+        match property {
+            Property::Scalar { value_type, .. } => row[i] = reader.read(*value_type)?,
+            Property::List { count_type, item_type, .. } => {
+                let count = reader.read(*count_type)?;
+                if count < 0. {
+                    return Err(PlyError::Data(format!("negative list length in element {}", element.name)));
+                }
+                for _ in 0..count as usize {
+                    list_item(i, reader.read(*item_type)?)?;
+                }
+            },
+        }
+    }
+    Ok(())
+}
+
+/// Source of values, the same element walking code works for both text and binary data
+enum Reader<'a> {
+    Ascii(std::str::SplitAsciiWhitespace<'a>),
+    Binary { bytes : &'a [u8], position : usize, big_endian : bool },
+}
+
+impl Reader<'_> {
+    fn read(&mut self, value_type : ScalarType) -> Result<f64, PlyError> {
+        match self {
+            Reader::Ascii(words) => {
+                let word = words.next().ok_or_else(|| PlyError::Data("file ends before all elements were read".into()))?;
+                word.parse().map_err(|_| PlyError::Data(format!("'{}' is not a number", word)))
+            },
+            Reader::Binary { bytes, position, big_endian } => {
+                let size = value_type.size();
+                let data = bytes.get(*position..*position + size).ok_or_else(|| PlyError::Data("file ends before all elements were read".into()))?;
+                *position += size;
+
+                let mut buffer = [0u8; 8];
+                buffer[..size].copy_from_slice(data);
+                if *big_endian {
+                    buffer[..size].reverse();
+                }
+                Ok(match value_type {
+                    ScalarType::Int8 => buffer[0] as i8 as f64,
+                    ScalarType::UInt8 => buffer[0] as f64,
+                    ScalarType::Int16 => i16::from_le_bytes([buffer[0], buffer[1]]) as f64,
+                    ScalarType::UInt16 => u16::from_le_bytes([buffer[0], buffer[1]]) as f64,
+                    ScalarType::Int32 => i32::from_le_bytes(buffer[..4].try_into().unwrap()) as f64,
+                    ScalarType::UInt32 => u32::from_le_bytes(buffer[..4].try_into().unwrap()) as f64,
+                    ScalarType::Float32 => f32::from_le_bytes(buffer[..4].try_into().unwrap()) as f64,
+                    ScalarType::Float64 => f64::from_le_bytes(buffer),
+                })
+            },
+        }
+    }
+}
+
+/// Which property of the vertex element holds each vertex attribute
+struct VertexColumns {
+    position : [Option<usize>; 3],
+    normal : Option<[usize; 3]>,
+    tex_coords : Option<[usize; 2]>,
+    color : Option<([usize; 3], Option<usize>, f32)>,   // rgb, alpha, scale
+}
+
+impl VertexColumns {
+    fn new(element : &Element) -> VertexColumns {
+        let mut columns: HashMap<&str, (usize, ScalarType)> = HashMap::new();
+        for (i, property) in element.properties.iter().enumerate() {
+            if let Property::Scalar { name, value_type } = property {
+                columns.insert(name.as_str(), (i, *value_type));
+            }
+        }
+        let find = |names : &[&str]| names.iter().find_map(|name| columns.get(name).map(|(i, _)| *i));
+        let find_all = |names : &[&str]| -> Option<Vec<usize>> { names.iter().map(|name| columns.get(name).map(|(i, _)| *i)).collect() };
+
+        let normal = find_all(&["nx", "ny", "nz"]).map(|n| [n[0], n[1], n[2]]);
+        let tex_coords = [["u", "v"], ["s", "t"], ["texture_u", "texture_v"]].iter()
+            .find_map(|names| find_all(names)).map(|t| [t[0], t[1]]);
+        let color = [["red", "green", "blue", "alpha"], ["diffuse_red", "diffuse_green", "diffuse_blue", "diffuse_alpha"]].iter()
+            .find_map(|names| {
+                let rgb = find_all(&names[..3])?;
+                let scale = columns[names[0]].1.color_scale();
+                Some(([rgb[0], rgb[1], rgb[2]], find(&names[3..]), scale))
+            });
+
+        VertexColumns {
+            position : [find(&["x"]), find(&["y"]), find(&["z"])],
+            normal,
+            tex_coords,
+            color,
+        }
+    }
+
+    fn vertex(&self, row : &[f64]) -> Vertex {
+        let get = |column : Option<usize>| column.map(|i| row[i] as f32).unwrap_or(0.);
+        Vertex {
+            position : glm::vec3(get(self.position[0]), get(self.position[1]), get(self.position[2])),
+            normal : self.normal.map(|[x, y, z]| glm::vec3(row[x] as f32, row[y] as f32, row[z] as f32)).unwrap_or_else(glm::Vec3::zeros),
+            tex_coords : self.tex_coords.map(|[u, v]| glm::vec2(row[u] as f32, row[v] as f32)).unwrap_or_else(glm::Vec2::zeros),
+        }
+    }
+
+    fn color(&self, row : &[f64]) -> Option<glm::Vec4> {
+        let ([r, g, b], alpha, scale) = self.color?;
+        let alpha = alpha.map(|a| row[a] as f32 / scale).unwrap_or(1.);
+        Some(glm::vec4(row[r] as f32 / scale, row[g] as f32 / scale, row[b] as f32 / scale, alpha))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON : f32 = 1e-6;
+
+    const SQUARE_HEADER : &str = "element vertex 4\nproperty float x\nproperty float y\nproperty float z\nelement face 1\nproperty list uchar int vertex_indices\nend_header\n";
+
+    /// Function to write the unit square with one quad face in a binary format
+    fn binary_square(format : &str, to_bytes : fn(f32) -> [u8; 4], index_bytes : fn(i32) -> [u8; 4]) -> Vec<u8> {
+        let mut bytes = format!("ply\nformat {} 1.0\n{}", format, SQUARE_HEADER).into_bytes();
+        for [x, y] in [[0., 0.], [1., 0.], [1., 1.], [0., 1.]] {
+            for value in [x, y, 0.] {
+                bytes.extend_from_slice(&to_bytes(value));
+            }
+        }
+        bytes.push(4);
+        for index in 0..4 {
+            bytes.extend_from_slice(&index_bytes(index));
+        }
+        bytes
+    }
+
+    fn ascii(body : &str) -> Vec<u8> {
+        format!("ply\nformat ascii 1.0\n{}", body).into_bytes()
+    }
+
+    fn assert_square(data : &PlyData) {
+        let positions: Vec<[f32; 3]> = data.vertices.iter().map(|vertex| [vertex.position.x, vertex.position.y, vertex.position.z]).collect();
+        assert_eq!(positions, [[0., 0., 0.], [1., 0., 0.], [1., 1., 0.], [0., 1., 0.]]);
+        assert_eq!(data.indices, [0, 1, 2, 0, 2, 3]);
+        assert!(!data.is_point_cloud);
+    }
+
+    #[test]
+    fn ascii_and_binary_files_read_the_same() {
+        let ascii_square = ascii(&format!("{}0 0 0\n1 0 0\n1 1 0\n0 1 0\n4 0 1 2 3\n", SQUARE_HEADER));
+        assert_square(&parse_ply(&ascii_square).unwrap());
+        assert_square(&parse_ply(&binary_square("binary_little_endian", f32::to_le_bytes, i32::to_le_bytes)).unwrap());
+        assert_square(&parse_ply(&binary_square("binary_big_endian", f32::to_be_bytes, i32::to_be_bytes)).unwrap());
+    }
+
+    #[test]
+    fn quads_are_split_into_fans_with_generated_normals() {
+        let data = parse_ply(&ascii(&format!("{}0 0 0\n1 0 0\n1 1 0\n0 1 0\n4 0 1 2 3\n", SQUARE_HEADER))).unwrap();
+        assert_eq!(data.indices, [0, 1, 2, 0, 2, 3]);
+        assert!(!data.has_normals);
+        for vertex in &data.vertices {
+            assert!((vertex.normal - glm::vec3(0., 0., 1.)).norm() < EPSILON);
+        }
+    }
+
+    #[test]
+    fn file_without_faces_is_a_point_cloud() {
+        let data = parse_ply(&ascii("element vertex 3\nproperty float x\nproperty float y\nproperty float z\nend_header\n0 0 0\n1 0 0\n0 1 0\n")).unwrap();
+        assert!(data.is_point_cloud);
+        assert_eq!(data.indices, [0, 1, 2]);
+        assert!(data.vertices.iter().all(|vertex| vertex.normal == glm::Vec3::zeros()));
+    }
+
+    #[test]
+    fn integer_colours_are_scaled() {
+        let header = "element vertex 2\nproperty float x\nproperty float y\nproperty float z\n";
+        let uchar = parse_ply(&ascii(&format!("{}property uchar red\nproperty uchar green\nproperty uchar blue\nend_header\n0 0 0 255 0 51\n1 0 0 0 255 0\n", header))).unwrap();
+        assert_eq!(uchar.colors.len(), 2);
+        assert!((uchar.colors[0] - glm::vec4(1., 0., 0.2, 1.)).norm() < EPSILON);
+        assert!((uchar.colors[1] - glm::vec4(0., 1., 0., 1.)).norm() < EPSILON);
+
+        let float = parse_ply(&ascii(&format!("{}property float red\nproperty float green\nproperty float blue\nproperty float alpha\nend_header\n0 0 0 0.5 0.25 1 0.5\n1 0 0 0 0 0 1\n", header))).unwrap();
+        assert!((float.colors[0] - glm::vec4(0.5, 0.25, 1., 0.5)).norm() < EPSILON);
+        assert!((float.colors[1] - glm::vec4(0., 0., 0., 1.)).norm() < EPSILON);
+    }
+
+    #[test]
+    fn bad_indices_are_errors() {
+        let out_of_range = parse_ply(&ascii(&format!("{}0 0 0\n1 0 0\n1 1 0\n0 1 0\n3 0 1 4\n", SQUARE_HEADER)));
+        assert!(matches!(out_of_range, Err(PlyError::Data(message)) if message.contains("out of range")));
+        let negative = parse_ply(&ascii(&format!("{}0 0 0\n1 0 0\n1 1 0\n0 1 0\n3 0 -1 2\n", SQUARE_HEADER)));
+        assert!(matches!(negative, Err(PlyError::Data(message)) if message.contains("negative vertex index")));
+    }
+
+    #[test]
+    fn huge_element_count_is_an_error() {
+        let huge = ascii(&format!("element vertex {}\nproperty float x\nend_header\n0\n", usize::MAX));
+        assert!(matches!(parse_ply(&huge), Err(PlyError::Data(_))));
+        let empty = ascii("element vertex 1000000000\nend_header\n");
+        assert!(matches!(parse_ply(&empty), Err(PlyError::Data(_))));
+    }
+}
@@ -0,0 +1,224 @@
+// Projection used by the cameras - perspective, orthographic and off-axis frustum
+
+// Notes:
+// Cameras keep their near and far planes and the window size, the projection only describes the shape of the view.
+// Every mode is given in a way that does not depend on the window's aspect ratio : the vertical extent is fixed and
+// the horizontal extent follows the aspect, so resizing the window never stretches the image.
+// Orthographic views are used for top/side views, 2D overlays and light cameras for shadow maps. The off-axis
+// frustum is for views whose center is not in the middle of the window, like stereo pairs or tiled rendering.
+
+use nalgebra_glm as glm;
+
+/// Shape of a camera's view volume
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[allow(dead_code)]
+pub enum Projection {
+    // Vertical field of view in degrees
+    Perspective { fov : f32 },
+    // Half of the view's height in world units at zoom 1, the visible half height is size / zoom
+    Orthographic { size : f32, zoom : f32 },
+    // Perspective with the center of the view shifted, like a shifted camera lens. shift is a fraction of the
+    // view's width and height, (0.5, 0) puts the view direction on the right edge of the window. A zero shift is the
+    // same as Perspective { fov }
+    Frustum { fov : f32, shift : glm::Vec2 },
+}
+
+impl Default for Projection {
+    fn default() -> Projection {
+        Projection::Perspective { fov : 45. }
+    }
+}
+
+impl Projection {
+    /// Function to create an orthographic projection showing height world units vertically
+    pub fn orthographic(height : f32) -> Projection {
+        Projection::Orthographic { size : height / 2., zoom : 1. }
+    }
+
+    /// Function to get the projection matrix, aspect is width / height of the window
+    pub fn matrix(&self, aspect : f32, near : f32, far : f32) -> glm::Mat4 {
+        match *self {
+            Projection::Perspective { fov } => glm::perspective(aspect, fov.to_radians(), near, far),
+            Projection::Orthographic { size, zoom } => {
+                let half_height = size / zoom;
+                let half_width = half_height * aspect;
+                glm::ortho(-half_width, half_width, -half_height, half_height, near, far)
+            },
+            Projection::Frustum { .. } => {
+                let (left, right, bottom, top) = self.extents(aspect);
+                frustum(left * near, right * near, bottom * near, top * near, near, far)
+            },
+        }
+    }
+
+    pub fn is_orthographic(&self) -> bool {
+        matches!(self, Projection::Orthographic { .. })
+    }
+
+    /// Function to get the height of the view in world units at a distance from the camera
+    pub fn view_height(&self, distance : f32) -> f32 {
+        match *self {
+            Projection::Perspective { fov } => 2. * distance * (fov.to_radians() / 2.).tan(),
+            Projection::Orthographic { size, zoom } => 2. * size / zoom,
+            Projection::Frustum { fov, .. } => 2. * distance * (fov.to_radians() / 2.).tan(),
+        }
+    }
+
+    /// Function to zoom in, amount > 0 zooms in and < 0 zooms out
+    ///
+    /// Perspective changes the fov by amount degrees, limited to 1..90. The other modes are scaled by about the same
+    /// factor as that change would at 45 degrees
+    pub fn zoom(&mut self, amount : f32) {
+        match self {
+            Projection::Perspective { fov } => *fov = (*fov - amount).clamp(1., 90.),
+            _ => self.scale((amount / 40.).exp()),
+        }
+    }
+
+    /// Function to magnify the view by a factor, > 1 zooms in
+    pub fn scale(&mut self, factor : f32) {
+        match self {
+            Projection::Perspective { fov } | Projection::Frustum { fov, .. } => {
+                *fov = (2. * ((fov.to_radians() / 2.).tan() / factor).atan()).to_degrees().clamp(1., 90.);
+            },
+            Projection::Orthographic { zoom, .. } => *zoom = (*zoom * factor).clamp(1e-4, 1e4),
+        }
+    }
+
+    /// Function to get an orthographic projection that shows things at distance with the same size as this one
+    ///
+    /// Used to switch between perspective and orthographic without jumping, an orthographic projection is returned as it is
+    pub fn to_orthographic(self, distance : f32) -> Projection {
+        match self {
+            Projection::Orthographic { .. } => self,
+            _ => Projection::orthographic(self.view_height(distance)),
+        }
+    }
+
+    /// Function to get a perspective projection with fov that shows things at distance with the same size as this one
+    ///
+    /// Returns the distance the camera should move to, so the view does not jump. A perspective projection is returned as
+    /// it is with the same distance
+    pub fn to_perspective(self, fov : f32, distance : f32) -> (Projection, f32) {
+        match self {
+            Projection::Perspective { .. } => (self, distance),
+            _ => {
+                let perspective = Projection::Perspective { fov };
+                (perspective, self.view_height(distance) / perspective.view_height(1.))
+            },
+        }
+    }
+
+    /// Function to fit a sphere of radius in the view, returns the distance to place the camera at
+    ///
+    /// Orthographic projections are zoomed to fit, the distance then only keeps the camera outside the sphere
+    pub fn fit(&mut self, radius : f32, aspect : f32) -> f32 {
+        // The smaller of the vertical and horizontal extents limits the fit, narrow windows fit the sphere as well
+        let narrow = aspect.min(1.);
+        match self {
+            Projection::Perspective { fov } => {
+                let half_fov = fov.to_radians() / 2.;
+                let half_fov = half_fov.min((half_fov.tan() * aspect).atan());
+                radius / half_fov.sin()
+            },
+            Projection::Orthographic { size, zoom } => {
+                *zoom = *size * narrow / radius;
+                radius * 2.
+            },
+            Projection::Frustum { .. } => {
+                // Off-axis, the closest edge to the view direction limits it, there is no fit if it is outside the view
+                let (left, right, bottom, top) = self.extents(aspect);
+                let tangent = (-left).min(right).min(-bottom).min(top).max(1e-3);
+                radius / tangent.atan().sin()
+            },
+        }
+    }
+
+    /// Function to get the left, right, bottom and top of the view at distance 1 in front of the camera
+    fn extents(&self, aspect : f32) -> (f32, f32, f32, f32) {
+        let (fov, shift) = match *self {
+            Projection::Perspective { fov } => (fov, glm::Vec2::zeros()),
+            Projection::Frustum { fov, shift } => (fov, shift),
+            Projection::Orthographic { .. } => return (-aspect, aspect, -1., 1.),
+        };
+        let half_height = (fov.to_radians() / 2.).tan();
+        let half_width = half_height * aspect;
+        let center = glm::vec2(shift.x * 2. * half_width, shift.y * 2. * half_height);
+        (center.x - half_width, center.x + half_width, center.y - half_height, center.y + half_height)
+    }
+}
+
+/// Function to get an off-axis perspective matrix like glFrustum, the extents are at the near plane
+///
+/// Right handed, with depth mapped to -1..1 like glm::perspective
+pub fn frustum(left : f32, right : f32, bottom : f32, top : f32, near : f32, far : f32) -> glm::Mat4 {
+    glm::mat4(
+        2. * near / (right - left), 0., (right + left) / (right - left), 0.,
+        0., 2. * near / (top - bottom), (top + bottom) / (top - bottom), 0.,
+        0., 0., -(far + near) / (far - near), -2. * far * near / (far - near),
+        0., 0., -1., 0.,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON : f32 = 1e-4;
+
+    fn assert_mat4_eq(a : &glm::Mat4, b : &glm::Mat4) {
+        assert!(a.iter().zip(b.iter()).all(|(x, y)| (x - y).abs() < EPSILON), "{} != {}", a, b);
+    }
+
+    /// Projects a point in view space to normalized device coordinates
+    fn project(matrix : &glm::Mat4, point : glm::Vec3) -> glm::Vec3 {
+        let clip = matrix * glm::vec4(point.x, point.y, point.z, 1.);
+        clip.xyz() / clip.w
+    }
+
+    #[test]
+    fn symmetric_frustum_matches_perspective() {
+        let frustum = Projection::Frustum { fov : 45., shift : glm::Vec2::zeros() };
+        let perspective = Projection::Perspective { fov : 45. };
+        for aspect in [0.5, 1., 16. / 9.] {
+            assert_mat4_eq(&frustum.matrix(aspect, 0.1, 100.), &perspective.matrix(aspect, 0.1, 100.));
+        }
+    }
+
+    #[test]
+    fn resizing_keeps_the_aspect() {
+        // A square in front of the camera stays square on screen in every mode
+        for projection in [
+            Projection::Perspective { fov : 60. },
+            Projection::orthographic(4.),
+            Projection::Frustum { fov : 60., shift : glm::vec2(0.3, -0.2) },
+        ] {
+            for (width, height) in [(800., 600.), (300., 900.)] {
+                let matrix = projection.matrix(width / height, 0.1, 100.);
+                let a = project(&matrix, glm::vec3(0., 0., -5.));
+                let b = project(&matrix, glm::vec3(0.5, 0.5, -5.));
+                let pixels = glm::vec2((b.x - a.x) * width, (b.y - a.y) * height);
+                assert!((pixels.x - pixels.y).abs() < EPSILON * width, "{:?} {} {}", projection, width, pixels);
+            }
+        }
+    }
+
+    #[test]
+    fn fit_shows_the_whole_sphere() {
+        for mut projection in [
+            Projection::Perspective { fov : 45. },
+            Projection::orthographic(1.),
+            Projection::Frustum { fov : 50., shift : glm::vec2(-0.2, 0.1) },
+        ] {
+            for aspect in [0.5, 2.] {
+                let distance = projection.fit(3., aspect);
+                let matrix = projection.matrix(aspect, 0.01, 100.);
+                // Points on the sphere, around its center in front of the camera
+                for direction in [glm::Vec3::x(), -glm::Vec3::x(), glm::Vec3::y(), -glm::Vec3::y(), glm::Vec3::z()] {
+                    let ndc = project(&matrix, glm::vec3(0., 0., -distance) + direction * 3.);
+                    assert!(ndc.x.abs() <= 1. + EPSILON && ndc.y.abs() <= 1. + EPSILON, "{:?} {} {}", projection, aspect, ndc);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,240 @@
+// Resource resolver - finds assets by logical name in a list of search paths
+
+// Notes:
+// Tutorials refer to assets by logical names relative to an asset root, like models/sponza/sponza.obj, instead of
+// paths compiled into the source. Search paths are tried in order:
+//   1. --assets <dir> command line flags, in the order given
+//   2. the LEARN_OPENGL_ASSETS environment variable, a list of directories like PATH (; on windows, : elsewhere)
+//   3. assets.cfg in the working directory, one directory per line, # starts a comment, relative paths are
+//      relative to the file
+//   4. ./resources, the assets that come with the repo
+// So a model that is too large for the repo (Sponza) can live anywhere, e.g. run with
+// `cargo run --release 4_1_1 --assets C:/Downloads/assets` with the model in C:/Downloads/assets/models/sponza.
+// main.rs sets the global resolver once at startup, see ResourceResolver::set_global.
+
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Environment variable with extra search paths
+pub const ASSETS_VARIABLE : &str = "LEARN_OPENGL_ASSETS";
+/// Config file with extra search paths, read from the working directory
+pub const CONFIG_FILE : &str = "assets.cfg";
+/// Command line flag adding a search path
+pub const ASSETS_FLAG : &str = "--assets";
+/// Assets that come with the repo, always searched last
+pub const DEFAULT_ROOT : &str = "./resources";
+
+static GLOBAL : OnceLock<ResourceResolver> = OnceLock::new();
+
+/// Error returned when an asset is not found in any search path
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResourceError {
+    pub name : String,
+    pub tried : Vec<PathBuf>,   // every location that was checked, in order
+}
+
+impl fmt::Display for ResourceError {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Could not find asset '{}', tried:", self.name)?;
+        for path in &self.tried {
+            write!(f, "\n  {}", path.display())?;
+        }
+        write!(f, "\nAdd a search path with {} <dir>, the {} environment variable or {}", ASSETS_FLAG, ASSETS_VARIABLE, CONFIG_FILE)
+    }
+}
+
+impl Error for ResourceError {}
+
+/// Ordered list of directories assets are looked up in
+#[derive(Debug, Clone, Default)]
+pub struct ResourceResolver {
+    search_paths : Vec<PathBuf>,
+}
+
+impl ResourceResolver {
+    /// Function to create a resolver without any search paths
+    pub fn new() -> ResourceResolver {
+        ResourceResolver::default()
+    }
+
+    /// Function to create a resolver from command line search paths, the environment variable, the config file and
+    /// the default root, in that order
+    pub fn from_sources(cli_paths : &[PathBuf]) -> ResourceResolver {
+        let mut resolver = ResourceResolver::new();
+        for path in cli_paths {
+            resolver.add_search_path(path);
+        }
+        if let Some(paths) = env::var_os(ASSETS_VARIABLE) {
+            for path in env::split_paths(&paths) {
+                resolver.add_search_path(path);
+            }
+        }
+        if let Ok(config) = fs::read_to_string(CONFIG_FILE) {
+            for path in parse_config(&config, Path::new(".")) {
+                resolver.add_search_path(path);
+            }
+        }
+        resolver.add_search_path(DEFAULT_ROOT);
+        resolver
+    }
+
+    /// Function to add a search path after the existing ones, empty paths and duplicates are ignored
+    pub fn add_search_path(&mut self, path : impl Into<PathBuf>) {
+        let path = path.into();
+        if !path.as_os_str().is_empty() && !self.search_paths.contains(&path) {
+            self.search_paths.push(path);
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn search_paths(&self) -> &[PathBuf] {
+        &self.search_paths
+    }
+
+    /// Function to find an asset, name is relative to the search paths, e.g. models/sponza/sponza.obj
+    ///
+    /// Absolute names are only checked as they are. The error lists every location that was tried
+    pub fn resolve(&self, name : &str) -> Result<PathBuf, ResourceError> {
+        let candidates: Vec<PathBuf> = if Path::new(name).is_absolute() {
+            vec![PathBuf::from(name)]
+        }
+        else {
+            self.search_paths.iter().map(|root| root.join(name)).collect()
+        };
+
+        match candidates.iter().find(|path| path.exists()) {
+            Some(path) => Ok(path.clone()),
+            None => Err(ResourceError { name : name.into(), tried : candidates }),
+        }
+    }
+
+    /// Function to set the resolver used by resolve and require, can only be set once
+    ///
+    /// Returns false if the global resolver was already set or used
+    pub fn set_global(resolver : ResourceResolver) -> bool {
+        GLOBAL.set(resolver).is_ok()
+    }
+
+    /// Function to get the global resolver, created from the environment without command line paths if it was never set
+    pub fn global() -> &'static ResourceResolver {
+        GLOBAL.get_or_init(|| ResourceResolver::from_sources(&[]))
+    }
+}
+
+/// Function to find an asset with the global resolver, as a string for the loaders
+pub fn resolve(name : &str) -> Result<String, ResourceError> {
+    ResourceResolver::global().resolve(name).map(|path| path.to_string_lossy().into_owned())
+}
+
+/// Function to find an asset with the global resolver, exits with the list of tried locations if it is missing
+///
+/// For tutorials, which cannot do anything useful without their assets
+pub fn require(name : &str) -> String {
+    resolve(name).unwrap_or_else(|error| {
+        println!("{}", error);
+        std::process::exit(1);
+    })
+}
+
+/// Function to split --assets flags from the command line arguments, returns the other arguments and the paths
+///
+/// Both `--assets <dir>` and `--assets=<dir>` are accepted
+pub fn parse_asset_flags(args : &[String]) -> (Vec<String>, Vec<PathBuf>) {
+    let mut rest = Vec::new();
+    let mut paths = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == ASSETS_FLAG {
+            match args.next() {
+                Some(path) => paths.push(PathBuf::from(path)),
+                None => println!("Warning: {} needs a directory", ASSETS_FLAG),
+            }
+        }
+        else if let Some(path) = arg.strip_prefix(ASSETS_FLAG).and_then(|value| value.strip_prefix('=')) {
+            paths.push(PathBuf::from(path));
+        }
+        else {
+            rest.push(arg.clone());
+        }
+    }
+    (rest, paths)
+}
+
+/// Function to read the search paths of a config file, relative paths are joined to directory
+fn parse_config(config : &str, directory : &Path) -> Vec<PathBuf> {
+    config.lines()
+        .map(|line| line.split('#').next().unwrap_or("").trim())
+        .filter(|line| !line.is_empty())
+        .map(|line| directory.join(line))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args : &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn asset_flags_are_split_from_the_arguments() {
+        let (rest, paths) = parse_asset_flags(&args(&["4_1_1", "--assets", "first", "--assets=second", "--other"]));
+        assert_eq!(rest, ["4_1_1", "--other"]);
+        assert_eq!(paths, [PathBuf::from("first"), PathBuf::from("second")]);
+
+        // A trailing flag without a directory is dropped
+        let (rest, paths) = parse_asset_flags(&args(&["4_1_1", "--assets"]));
+        assert_eq!(rest, ["4_1_1"]);
+        assert!(paths.is_empty());
+    }
+
+    #[test]
+    fn config_skips_comments_and_blank_lines() {
+        let config = "# asset directories\n\nlarge_assets\n   \n  other  # downloaded separately\n#old\n";
+        let paths = parse_config(config, Path::new("root"));
+        assert_eq!(paths, [Path::new("root").join("large_assets"), Path::new("root").join("other")]);
+    }
+
+    #[test]
+    fn search_paths_are_tried_in_order() {
+        let directory = env::temp_dir().join(format!("resources_order_{}", std::process::id()));
+        let (first, second) = (directory.join("first"), directory.join("second"));
+        fs::create_dir_all(first.join("textures")).unwrap();
+        fs::create_dir_all(second.join("textures")).unwrap();
+        fs::write(first.join("textures/both.png"), "").unwrap();
+        fs::write(second.join("textures/both.png"), "").unwrap();
+        fs::write(second.join("textures/second.png"), "").unwrap();
+
+        let mut resolver = ResourceResolver::new();
+        resolver.add_search_path(&first);
+        resolver.add_search_path(&second);
+        resolver.add_search_path(&first);
+        assert_eq!(resolver.search_paths(), [first.clone(), second.clone()]);
+
+        let both = resolver.resolve("textures/both.png");
+        let only_second = resolver.resolve("textures/second.png");
+        let missing = resolver.resolve("textures/missing.png");
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(both, Ok(first.join("textures/both.png")));
+        assert_eq!(only_second, Ok(second.join("textures/second.png")));
+        assert_eq!(missing, Err(ResourceError {
+            name : "textures/missing.png".into(),
+            tried : vec![first.join("textures/missing.png"), second.join("textures/missing.png")],
+        }));
+    }
+
+    #[test]
+    fn absolute_names_are_only_tried_as_they_are() {
+        let mut resolver = ResourceResolver::new();
+        resolver.add_search_path("resources");
+        let name = env::temp_dir().join(format!("resources_missing_{}.png", std::process::id()));
+        let error = resolver.resolve(name.to_str().unwrap()).unwrap_err();
+        assert_eq!(error.tried, [name]);
+    }
+}
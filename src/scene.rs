@@ -0,0 +1,272 @@
+// Scene graph - nodes with local transforms, parent/child links and attached models
+
+// Notes:
+// Nodes are stored in a Vec and refer to each other by index, which avoids Rc<RefCell<>> cycles between parents and
+// children. World matrices are cached, changing a node's transform only marks it and its descendants as dirty, and
+// the matrices are recalculated the next time they are needed.
+
+use std::ffi::CStr;
+use std::rc::Rc;
+
+use nalgebra_glm as glm;
+
+use crate::model::Model;
+use crate::shader::Shader;
+
+/// Index of a node in its scene
+pub type NodeId = usize;
+
+/// Local transform of a node - translation, rotation and scale (TRS)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    pub translation : glm::Vec3,
+    pub rotation : glm::Quat,
+    pub scale : glm::Vec3,
+}
+
+impl Default for Transform {
+    fn default() -> Transform {
+        Transform {
+            translation : glm::vec3(0., 0., 0.),
+            rotation : glm::quat_identity(),
+            scale : glm::vec3(1., 1., 1.),
+        }
+    }
+}
+
+impl Transform {
+    pub fn from_translation(translation : glm::Vec3) -> Transform {
+        Transform { translation, ..Default::default() }
+    }
+
+    /// Function to get the transform as a matrix, scale is applied first and translation last
+    pub fn matrix(&self) -> glm::Mat4 {
+        glm::translation(&self.translation) * glm::quat_to_mat4(&self.rotation) * glm::scaling(&self.scale)
+    }
+}
+
+pub struct Node {
+    pub name : String,
+    pub model : Option<Rc<Model>>,  // drawn using the node's world matrix, can be shared between nodes
+    local : Transform,
+    parent : Option<NodeId>,
+    children : Vec<NodeId>,
+    world : glm::Mat4,              // cached world matrix, only valid if dirty is false
+    dirty : bool,
+}
+
+#[allow(dead_code)]
+impl Node {
+    pub fn local(&self) -> &Transform {
+        &self.local
+    }
+
+    pub fn parent(&self) -> Option<NodeId> {
+        self.parent
+    }
+
+    pub fn children(&self) -> &[NodeId] {
+        &self.children
+    }
+}
+
+/// Hierarchy of nodes
+#[derive(Default)]
+pub struct Scene {
+    nodes : Vec<Node>,
+}
+
+#[allow(dead_code)]
+impl Scene {
+    pub fn new() -> Scene {
+        Scene { nodes : Vec::new() }
+    }
+
+    /// Function to add a node, as a child of parent or as a root node
+    pub fn add_node(&mut self, name : &str, parent : Option<NodeId>, local : Transform) -> NodeId {
+        let id = self.nodes.len();
+        self.nodes.push(Node {
+            name : name.into(),
+            model : None,
+            local,
+            parent,
+            children : Vec::new(),
+            world : glm::Mat4::identity(),
+            dirty : true,
+        });
+        if let Some(parent) = parent {
+            self.nodes[parent].children.push(id);
+        }
+        id
+    }
+
+    pub fn node(&self, id : NodeId) -> &Node {
+        &self.nodes[id]
+    }
+
+    /// Function to get a node by name, returns the first match
+    pub fn find(&self, name : &str) -> Option<NodeId> {
+        self.nodes.iter().position(|node| node.name == name)
+    }
+
+    /// Function to attach a model to a node, replacing any model already attached
+    pub fn attach(&mut self, id : NodeId, model : Rc<Model>) {
+        self.nodes[id].model = Some(model);
+    }
+
+    pub fn set_transform(&mut self, id : NodeId, local : Transform) {
+        self.nodes[id].local = local;
+        self.mark_dirty(id);
+    }
+
+    pub fn set_translation(&mut self, id : NodeId, translation : glm::Vec3) {
+        self.nodes[id].local.translation = translation;
+        self.mark_dirty(id);
+    }
+
+    pub fn set_rotation(&mut self, id : NodeId, rotation : glm::Quat) {
+        self.nodes[id].local.rotation = rotation;
+        self.mark_dirty(id);
+    }
+
+    pub fn set_scale(&mut self, id : NodeId, scale : glm::Vec3) {
+        self.nodes[id].local.scale = scale;
+        self.mark_dirty(id);
+    }
+
+    /// Function to move a node under a new parent, or make it a root node
+    ///
+    /// The local transform is kept, so the node moves along with its new parent
+    pub fn set_parent(&mut self, id : NodeId, parent : Option<NodeId>) {
+        if let Some(parent) = parent {
+            assert!(!self.is_ancestor(id, parent), "Node {} cannot be parented to its own descendant {}", id, parent);
+        }
+        if let Some(old_parent) = self.nodes[id].parent {
+            self.nodes[old_parent].children.retain(|child| *child != id);
+        }
+        if let Some(parent) = parent {
+            self.nodes[parent].children.push(id);
+        }
+        self.nodes[id].parent = parent;
+        self.mark_dirty(id);
+    }
+
+    /// Function to get the world matrix of a node, recalculating it and its parents if required
+    pub fn world_matrix(&mut self, id : NodeId) -> glm::Mat4 {
+        if self.nodes[id].dirty {
+            let parent_world = match self.nodes[id].parent {
+                Some(parent) => self.world_matrix(parent),
+                None => glm::Mat4::identity(),
+            };
+            let node = &mut self.nodes[id];
+            node.world = parent_world * node.local.matrix();
+            node.dirty = false;
+        }
+        self.nodes[id].world
+    }
+
+    /// Function to recalculate all dirty world matrices
+    pub fn update(&mut self) {
+        for id in 0..self.nodes.len() {
+            self.world_matrix(id);
+        }
+    }
+
+    /// Function to draw every node with an attached model, setting the shader's model uniform to its world matrix
+    ///
+    /// **Assumes that the shader program is activated**
+    pub fn draw(&mut self, shader : &Shader) {
+        self.update();
+        for node in &self.nodes {
+            if let Some(model) = &node.model {
+                unsafe {
+                    shader.set_mat4(c_str!("model"), node.world);
+                }
+                model.draw(shader);
+            }
+        }
+    }
+
+    fn mark_dirty(&mut self, id : NodeId) {
+        // Descendants depend on this node's world matrix
+        let mut stack = vec![id];
+        while let Some(id) = stack.pop() {
+            self.nodes[id].dirty = true;
+            stack.extend_from_slice(&self.nodes[id].children);
+        }
+    }
+
+    fn is_ancestor(&self, ancestor : NodeId, mut id : NodeId) -> bool {
+        loop {
+            if id == ancestor {
+                return true;
+            }
+            match self.nodes[id].parent {
+                Some(parent) => id = parent,
+                None => return false,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn translation_of(matrix : &glm::Mat4) -> glm::Vec3 {
+        glm::vec3(matrix[(0, 3)], matrix[(1, 3)], matrix[(2, 3)])
+    }
+
+    #[test]
+    fn children_follow_their_parent() {
+        let mut scene = Scene::new();
+        let parent = scene.add_node("parent", None, Transform::from_translation(glm::vec3(1., 0., 0.)));
+        let child = scene.add_node("child", Some(parent), Transform::from_translation(glm::vec3(0., 2., 0.)));
+        assert_eq!(translation_of(&scene.world_matrix(child)), glm::vec3(1., 2., 0.));
+
+        // Moving the parent only marks the child, its matrix is recalculated when asked for
+        scene.set_translation(parent, glm::vec3(5., 0., 0.));
+        assert!(scene.node(child).dirty);
+        assert_eq!(translation_of(&scene.world_matrix(child)), glm::vec3(5., 2., 0.));
+        assert!(!scene.node(child).dirty && !scene.node(parent).dirty);
+
+        // Rotation and scale of the parent apply to the child's offset
+        scene.set_rotation(parent, glm::quat_angle_axis(90_f32.to_radians(), &glm::Vec3::z()));
+        scene.set_scale(parent, glm::vec3(2., 2., 2.));
+        let position = translation_of(&scene.world_matrix(child));
+        assert!((position - glm::vec3(1., 0., 0.)).norm() < 1e-5, "{}", position);
+    }
+
+    #[test]
+    fn reparenting_marks_the_subtree_dirty() {
+        let mut scene = Scene::new();
+        let a = scene.add_node("a", None, Transform::from_translation(glm::vec3(1., 0., 0.)));
+        let b = scene.add_node("b", None, Transform::from_translation(glm::vec3(0., 1., 0.)));
+        let child = scene.add_node("child", Some(a), Transform::default());
+        let grandchild = scene.add_node("grandchild", Some(child), Transform::from_translation(glm::vec3(0., 0., 1.)));
+        scene.update();
+        assert!(!scene.node(grandchild).dirty);
+
+        scene.set_parent(child, Some(b));
+        assert!(scene.node(child).dirty && scene.node(grandchild).dirty);
+        assert!(!scene.node(a).dirty && !scene.node(b).dirty);
+        assert!(scene.node(a).children().is_empty());
+        assert_eq!(scene.node(b).children(), [child]);
+        assert_eq!(translation_of(&scene.world_matrix(grandchild)), glm::vec3(0., 1., 1.));
+
+        // Back to a root node, only its own transform is left
+        scene.set_parent(child, None);
+        assert!(scene.node(b).children().is_empty());
+        assert_eq!(translation_of(&scene.world_matrix(grandchild)), glm::vec3(0., 0., 1.));
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot be parented to its own descendant")]
+    fn parenting_to_a_descendant_is_rejected() {
+        let mut scene = Scene::new();
+        let root = scene.add_node("root", None, Transform::default());
+        let child = scene.add_node("child", Some(root), Transform::default());
+        let grandchild = scene.add_node("grandchild", Some(child), Transform::default());
+        scene.set_parent(root, Some(grandchild));
+    }
+}
@@ -86,6 +86,12 @@ impl Shader {
         }
     }
 
+    pub unsafe fn set_float_array(&self, name: &CStr, floats: &[GLfloat]) {
+        unsafe {
+            gl::Uniform1fv(gl::GetUniformLocation(self.id, name.as_ptr()), floats.len() as GLsizei, floats.as_ptr());
+        }
+    }
+
     pub unsafe fn set_vec2(&self, name: &CStr, vec2: glm::Vec2) {
         unsafe {
             gl::Uniform2fv(gl::GetUniformLocation(self.id, name.as_ptr()), 1, vec2.as_ptr() as *const GLfloat);
@@ -110,12 +116,25 @@ impl Shader {
         }
     }
 
+    pub unsafe fn set_mat3(&self, name: &CStr, mat: glm::Mat3) {
+        unsafe {
+            gl::UniformMatrix3fv(gl::GetUniformLocation(self.id, name.as_ptr()), 1, gl::FALSE, mat.as_slice().as_ptr() as *const GLfloat);
+        }
+    }
+
     pub unsafe fn set_mat4(&self, name: &CStr, mat: glm::Mat4) {
         unsafe {
             gl::UniformMatrix4fv(gl::GetUniformLocation(self.id, name.as_ptr()), 1, gl::FALSE, mat.as_slice().as_ptr() as *const GLfloat);
         }
     }
 
+    pub unsafe fn set_mat4_array(&self, name: &CStr, mats: &[glm::Mat4]) {
+        unsafe {
+            // Matrices are contiguous, column major
+            gl::UniformMatrix4fv(gl::GetUniformLocation(self.id, name.as_ptr()), mats.len() as GLsizei, gl::FALSE, mats.as_ptr() as *const GLfloat);
+        }
+    }
+
     unsafe fn check_compile_errors(&self, shader: u32, shader_type: &str) {
         let mut success = gl::FALSE as GLint;
         let mut log = Vec::with_capacity(1024);
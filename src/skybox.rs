@@ -0,0 +1,112 @@
+// Skybox - a cubemap drawn around the camera, behind everything else
+
+// Notes:
+// The cube is drawn with the camera's rotation only (Camera::rotation_cam_matrix), so it moves with the camera and
+// never gets closer. The vertex shader sets z = w, giving every fragment the maximum depth of 1.0. Drawing it after the
+// opaque geometry with depth func LEQUAL then only fills pixels nothing else was drawn to, which saves shading pixels
+// that would be covered anyway.
+
+use std::ffi::CStr;
+use std::mem;
+use std::os::raw::c_void;
+
+use gl::{self, types::*};
+
+use crate::camera::Camera;
+use crate::shader::Shader;
+use crate::texture::Texture;
+
+const VERTEX_SHADER : &str = "./src/_4_advanced_opengl/shaders/6_skybox.vert";
+const FRAGMENT_SHADER : &str = "./src/_4_advanced_opengl/shaders/6_skybox.frag";
+
+// Unit cube around the camera, face culling is disabled while drawing it
+const CUBE_VERTICES : [f32; 108] = [
+    -1.,  1., -1.,  -1., -1., -1.,   1., -1., -1.,   1., -1., -1.,   1.,  1., -1.,  -1.,  1., -1.,
+    -1., -1.,  1.,  -1., -1., -1.,  -1.,  1., -1.,  -1.,  1., -1.,  -1.,  1.,  1.,  -1., -1.,  1.,
+     1., -1., -1.,   1., -1.,  1.,   1.,  1.,  1.,   1.,  1.,  1.,   1.,  1., -1.,   1., -1., -1.,
+    -1., -1.,  1.,  -1.,  1.,  1.,   1.,  1.,  1.,   1.,  1.,  1.,   1., -1.,  1.,  -1., -1.,  1.,
+    -1.,  1., -1.,   1.,  1., -1.,   1.,  1.,  1.,   1.,  1.,  1.,  -1.,  1.,  1.,  -1.,  1., -1.,
+    -1., -1., -1.,  -1., -1.,  1.,   1., -1., -1.,   1., -1., -1.,  -1., -1.,  1.,   1., -1.,  1.,
+];
+
+pub struct Skybox {
+    pub cubemap : Texture,
+    pub tone_mapping : bool,    // for HDR cubemaps, maps colours above 1 back into range
+    pub exposure : f32,
+    shader : Shader,
+    vao : GLuint,
+    vbo : GLuint,
+}
+
+impl Skybox {
+    /// Function to create a skybox for a cubemap, see cubemap::load_cubemap and cubemap::load_equirectangular
+    ///
+    /// The cubemap should be linear, loaded with TextureOptions::srgb or as floats, the skybox is gamma encoded
+    pub fn new(cubemap : Texture) -> Skybox {
+        let mut skybox = Skybox {
+            cubemap,
+            tone_mapping : false,
+            exposure : 1.,
+            shader : Shader::new(VERTEX_SHADER, FRAGMENT_SHADER),
+            vao : 0,
+            vbo : 0,
+        };
+
+        unsafe {
+            gl::GenVertexArrays(1, &mut skybox.vao);
+            gl::GenBuffers(1, &mut skybox.vbo);
+
+            gl::BindVertexArray(skybox.vao);
+            gl::BindBuffer(gl::ARRAY_BUFFER, skybox.vbo);
+            gl::BufferData(gl::ARRAY_BUFFER, mem::size_of_val(&CUBE_VERTICES) as GLsizeiptr, CUBE_VERTICES.as_ptr() as *const c_void, gl::STATIC_DRAW);
+
+            gl::VertexAttribPointer(0, 3, gl::FLOAT, gl::FALSE, 3 * mem::size_of::<GLfloat>() as GLsizei, std::ptr::null());
+            gl::EnableVertexAttribArray(0);
+
+            gl::BindVertexArray(0);
+        }
+        skybox
+    }
+
+    /// Function to draw the skybox, call after drawing opaque geometry and before transparent geometry
+    ///
+    /// Depth func and face culling are restored afterwards, the skybox's shader is left active
+    pub unsafe fn draw(&self, camera : &impl Camera) {
+        unsafe {
+            self.shader.use_program();
+            self.shader.set_mat4(c_str!("camMatrix"), camera.rotation_cam_matrix());
+            self.shader.set_int(c_str!("skybox"), 0);
+            self.shader.set_int(c_str!("toneMapping"), self.tone_mapping as GLint);
+            self.shader.set_float(c_str!("exposure"), self.exposure);
+
+            let mut depth_func = 0;
+            gl::GetIntegerv(gl::DEPTH_FUNC, &mut depth_func);
+            let cull_face = gl::IsEnabled(gl::CULL_FACE) == gl::TRUE;
+
+            // Depth is 1.0 everywhere, LEQUAL lets it pass where the depth buffer was only cleared
+            gl::DepthFunc(gl::LEQUAL);
+            gl::Disable(gl::CULL_FACE);
+
+            gl::ActiveTexture(gl::TEXTURE0);
+            gl::BindTexture(gl::TEXTURE_CUBE_MAP, self.cubemap.id());
+            gl::BindVertexArray(self.vao);
+            gl::DrawArrays(gl::TRIANGLES, 0, 36);
+            gl::BindVertexArray(0);
+            gl::BindTexture(gl::TEXTURE_CUBE_MAP, 0);
+
+            gl::DepthFunc(depth_func as GLenum);
+            if cull_face {
+                gl::Enable(gl::CULL_FACE);
+            }
+        }
+    }
+}
+
+impl Drop for Skybox {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteVertexArrays(1, &self.vao);
+            gl::DeleteBuffers(1, &self.vbo);
+        }
+    }
+}
@@ -0,0 +1,279 @@
+// STL loading - ASCII and binary, used by CAD pipelines
+
+// Notes:
+// An STL file is a list of triangles, each with its own normal and three vertex positions. There are no shared
+// vertices, so every triangle gets three vertices with the face normal, which gives the faceted look of CAD parts.
+// Welding (weld_vertices) merges vertices at the same position whose face normals are within a crease angle and
+// averages their normals, so curved surfaces are smooth while sharp edges stay sharp.
+// Binary files start with an 80 byte header, which some exporters fill with "solid ..." as well, so a file is only
+// read as ASCII if its size does not match the triangle count of the binary layout.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+
+use gl::types::*;
+use nalgebra_glm as glm;
+
+use crate::mesh::Vertex;
+
+const HEADER_SIZE : usize = 80;
+const TRIANGLE_SIZE : usize = 50;   // normal, three positions and a 2 byte attribute
+
+/// Errors that stop an STL file from loading
+#[derive(Debug)]
+pub enum StlError {
+    Io(io::Error),
+    Data(String),
+}
+
+impl fmt::Display for StlError {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StlError::Io(error) => write!(f, "{}", error),
+            StlError::Data(message) => write!(f, "invalid data, {}", message),
+        }
+    }
+}
+
+impl Error for StlError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            StlError::Io(error) => Some(error),
+            StlError::Data(_) => None,
+        }
+    }
+}
+
+/// Mesh data read from an STL file, not uploaded yet
+pub struct StlData {
+    pub name : String,              // name of the first solid for ASCII files, empty for binary files
+    pub vertices : Vec<Vertex>,
+    pub indices : Vec<GLuint>,
+}
+
+/// Function to read an STL file, weld_angle is the crease angle in degrees used to weld vertices, None keeps facets
+pub fn load_stl_data(path : &str, weld_angle : Option<f32>) -> Result<StlData, StlError> {
+    let mut data = parse_stl(&fs::read(path).map_err(StlError::Io)?)?;
+    if let Some(angle) = weld_angle {
+        (data.vertices, data.indices) = weld_vertices(&data.vertices, angle);
+    }
+    Ok(data)
+}
+
+/// Function to parse the contents of an STL file, every triangle has its own three vertices
+pub fn parse_stl(bytes : &[u8]) -> Result<StlData, StlError> {
+    let binary_count = bytes.get(HEADER_SIZE..HEADER_SIZE + 4).map(|count| u32::from_le_bytes(count.try_into().unwrap()) as usize);
+    let is_binary = binary_count.is_some_and(|count| HEADER_SIZE + 4 + count * TRIANGLE_SIZE == bytes.len());
+
+    let (name, triangles) = if is_binary {
+        (String::new(), parse_binary(&bytes[HEADER_SIZE + 4..]))
+    }
+    else if bytes.trim_ascii_start().starts_with(b"solid") {
+        parse_ascii(bytes)?
+    }
+    else {
+        return Err(StlError::Data("not an ASCII STL file, and the size does not match the binary triangle count".into()));
+    };
+
+    let mut vertices = Vec::with_capacity(triangles.len() * 3);
+    for (normal, positions) in &triangles {
+        // Many exporters write zero normals, the winding order gives the same normal
+        let face_normal = glm::cross(&(positions[1] - positions[0]), &(positions[2] - positions[0]));
+        let normal = if is_valid_normal(normal) {
+            glm::normalize(normal)
+        }
+        else if is_valid_normal(&face_normal) {
+            glm::normalize(&face_normal)
+        }
+        else {
+            glm::Vec3::zeros()
+        };
+        for position in positions {
+            vertices.push(Vertex { position : *position, normal, tex_coords : glm::Vec2::zeros() });
+        }
+    }
+    let indices = (0..vertices.len() as GLuint).collect();
+    Ok(StlData { name, vertices, indices })
+}
+
+type Triangle = (glm::Vec3, [glm::Vec3; 3]);
+
+fn parse_binary(bytes : &[u8]) -> Vec<Triangle> {
+    bytes.chunks_exact(TRIANGLE_SIZE).map(|chunk| {
+        let vector = |offset : usize| {
+            let value = |i : usize| f32::from_le_bytes(chunk[offset + i * 4..offset + i * 4 + 4].try_into().unwrap());
+            glm::vec3(value(0), value(1), value(2))
+        };
+        (vector(0), [vector(12), vector(24), vector(36)])
+    }).collect()
+}
+
+fn parse_ascii(bytes : &[u8]) -> Result<(String, Vec<Triangle>), StlError> {
+    let text = std::str::from_utf8(bytes).map_err(|_| StlError::Data("ASCII file is not valid text".into()))?;
+    let name = text.lines().next().unwrap_or("").trim().strip_prefix("solid").unwrap_or("").trim().to_string();
+
+    // Only normal and vertex lines carry data, solid/facet/outer loop/endloop/endfacet are structure
+    let mut words = text.split_ascii_whitespace();
+    let mut triangles = Vec::new();
+    let mut normal = glm::Vec3::zeros();
+    let mut positions = Vec::with_capacity(3);
+    while let Some(word) = words.next() {
+        match word {
+            "normal" => normal = read_vector(&mut words)?,
+            "vertex" => positions.push(read_vector(&mut words)?),
+            "endfacet" => {
+                if positions.len() != 3 {
+                    return Err(StlError::Data(format!("facet {} has {} vertices instead of 3", triangles.len(), positions.len())));
+                }
+                triangles.push((normal, [positions[0], positions[1], positions[2]]));
+                positions.clear();
+                normal = glm::Vec3::zeros();
+            },
+            _ => {},
+        }
+    }
+    Ok((name, triangles))
+}
+
+fn read_vector(words : &mut std::str::SplitAsciiWhitespace) -> Result<glm::Vec3, StlError> {
+    let mut vector = glm::Vec3::zeros();
+    for i in 0..3 {
+        let word = words.next().ok_or_else(|| StlError::Data("file ends in the middle of a facet".into()))?;
+        vector[i] = word.parse().map_err(|_| StlError::Data(format!("'{}' is not a number", word)))?;
+    }
+    Ok(vector)
+}
+
+/// Function to merge vertices at the same position whose normals are less than max_angle degrees apart
+///
+/// The merged vertex gets the average normal, returns the new vertices and indices into them
+pub fn weld_vertices(vertices : &[Vertex], max_angle : f32) -> (Vec<Vertex>, Vec<GLuint>) {
+    let min_cos = max_angle.to_radians().cos();
+
+    // For each position, the vertices created so far with the normal they were created with
+    let mut welded: Vec<Vertex> = Vec::new();
+    let mut normal_sums: Vec<glm::Vec3> = Vec::new();
+    let mut groups: HashMap<[u32; 3], Vec<(usize, glm::Vec3)>> = HashMap::new();
+    let mut indices = Vec::with_capacity(vertices.len());
+
+    for vertex in vertices {
+        // + 0. turns -0. into 0., so both hash the same
+        let key = [vertex.position.x, vertex.position.y, vertex.position.z].map(|value| (value + 0.).to_bits());
+        let group = groups.entry(key).or_default();
+        let index = match group.iter().find(|(_, normal)| glm::dot(normal, &vertex.normal) >= min_cos) {
+            Some((index, _)) => {
+                normal_sums[*index] += vertex.normal;
+                *index
+            },
+            None => {
+                group.push((welded.len(), vertex.normal));
+                welded.push(vertex.clone());
+                normal_sums.push(vertex.normal);
+                welded.len() - 1
+            },
+        };
+        indices.push(index as GLuint);
+    }
+
+    for (vertex, sum) in welded.iter_mut().zip(normal_sums) {
+        if is_valid_normal(&sum) {
+            vertex.normal = glm::normalize(&sum);
+        }
+    }
+    (welded, indices)
+}
+
+fn is_valid_normal(normal : &glm::Vec3) -> bool {
+    normal.iter().all(|value| value.is_finite()) && glm::length2(normal) > 1e-12
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON : f32 = 1e-6;
+
+    /// Function to write triangles in the binary layout, with a header that starts like an ASCII file
+    fn binary_stl(triangles : &[[[f32; 3]; 4]]) -> Vec<u8> {
+        let mut bytes = b"solid exported by a CAD tool".to_vec();
+        bytes.resize(HEADER_SIZE, b' ');
+        bytes.extend_from_slice(&(triangles.len() as u32).to_le_bytes());
+        for triangle in triangles {
+            for value in triangle.as_flattened() {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+            bytes.extend_from_slice(&[0, 0]);
+        }
+        bytes
+    }
+
+    fn vertex(position : [f32; 3], normal : [f32; 3]) -> Vertex {
+        Vertex { position : glm::make_vec3(&position), normal : glm::make_vec3(&normal), tex_coords : glm::Vec2::zeros() }
+    }
+
+    /// Two triangles meeting at a right angle along the edge from (0,0,0) to (0,1,0)
+    fn folded_edge() -> Vec<Vertex> {
+        vec![
+            vertex([0., 0., 0.], [0., 0., 1.]), vertex([1., 0., 0.], [0., 0., 1.]), vertex([0., 1., 0.], [0., 0., 1.]),
+            vertex([0., 0., 0.], [-1., 0., 0.]), vertex([0., 1., 0.], [-1., 0., 0.]), vertex([0., 0., 1.], [-1., 0., 0.]),
+        ]
+    }
+
+    #[test]
+    fn binary_file_with_solid_header_is_binary() {
+        let bytes = binary_stl(&[[[0., 0., 1.], [0., 0., 0.], [1., 0., 0.], [0., 1., 0.]]]);
+        let data = parse_stl(&bytes).unwrap();
+        assert_eq!(data.name, "");
+        assert_eq!(data.vertices.len(), 3);
+        assert_eq!(data.vertices[1].position, glm::vec3(1., 0., 0.));
+        assert_eq!(data.indices, [0, 1, 2]);
+    }
+
+    #[test]
+    fn ascii_file_is_ascii() {
+        let text = "solid part\n  facet normal 0 0 0\n    outer loop\n      vertex 0 0 0\n      vertex 1 0 0\n      vertex 0 1 0\n    endloop\n  endfacet\nendsolid part\n";
+        let data = parse_stl(text.as_bytes()).unwrap();
+        assert_eq!(data.name, "part");
+        assert_eq!(data.vertices.len(), 3);
+        // The zero normal is replaced by the face normal
+        assert!((data.vertices[0].normal - glm::vec3(0., 0., 1.)).norm() < EPSILON);
+
+        assert!(matches!(parse_stl(b"not an stl file"), Err(StlError::Data(_))));
+    }
+
+    #[test]
+    fn welding_keeps_edges_sharper_than_the_crease_angle() {
+        let (vertices, indices) = weld_vertices(&folded_edge(), 30.);
+        assert_eq!(vertices.len(), 6);
+        assert_eq!(indices, [0, 1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn welding_merges_and_averages_normals_within_the_crease_angle() {
+        let (vertices, indices) = weld_vertices(&folded_edge(), 100.);
+        assert_eq!(vertices.len(), 4);
+        assert_eq!(indices, [0, 1, 2, 0, 2, 3]);
+        let average = glm::normalize(&glm::vec3(-1., 0., 1.));
+        assert!((vertices[0].normal - average).norm() < EPSILON);
+        assert!((vertices[2].normal - average).norm() < EPSILON);
+        assert!((vertices[1].normal - glm::vec3(0., 0., 1.)).norm() < EPSILON);
+    }
+
+    #[test]
+    fn load_welds_only_with_a_crease_angle() {
+        let triangles = [[[0., 0., 1.], [0., 0., 0.], [1., 0., 0.], [0., 1., 0.]], [[0., 0., 1.], [1., 0., 0.], [1., 1., 0.], [0., 1., 0.]]];
+        let path = std::env::temp_dir().join(format!("stl_loader_square_{}.stl", std::process::id()));
+        fs::write(&path, binary_stl(&triangles)).unwrap();
+        let facets = load_stl_data(path.to_str().unwrap(), None);
+        let welded = load_stl_data(path.to_str().unwrap(), Some(30.));
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(facets.unwrap().vertices.len(), 6);
+        let welded = welded.unwrap();
+        assert_eq!(welded.vertices.len(), 4);
+        assert_eq!(welded.indices, [0, 1, 2, 1, 3, 2]);
+    }
+}
@@ -0,0 +1,257 @@
+// Mesh for geometry that is replaced every frame, e.g. debug lines, particles, cpu deformed meshes
+
+// Notes:
+// Writing into a buffer the gpu is still reading from stalls until the draw is done. Instead the buffers are split
+// into SEGMENTS regions used in turn (ring buffer), and each region is guarded by a fence placed after its draw.
+// By the time a region is reused, the gpu has usually finished with it, so writing does not have to wait.
+
+use std::mem;
+use std::os::raw::c_void;
+use std::ptr;
+use std::rc::Rc;
+
+use gl::{self, types::*};
+
+use crate::material::Material;
+use crate::mesh::{self, Vertex};
+use crate::shader::Shader;
+
+// Number of regions in the ring buffer, 3 allows the cpu to be two frames ahead of the gpu
+const SEGMENTS : usize = 3;
+
+// Timeout for each wait on a fence, in nanoseconds
+const FENCE_TIMEOUT : u64 = 1_000_000;
+
+pub struct StreamMesh {
+    pub material : Rc<Material>,
+    ring : StreamRing,
+    fences : [GLsync; SEGMENTS],
+    vao : GLuint,
+    vbo : GLuint,
+    ebo : GLuint
+}
+
+impl StreamMesh {
+    /// Function to create a stream mesh that can hold up to max_vertices and max_indices per frame
+    pub fn new(max_vertices : usize, max_indices : usize, material : Rc<Material>) -> StreamMesh {
+        let mut mesh = StreamMesh {
+            material,
+            ring : StreamRing::new(max_vertices, max_indices),
+            fences : [ptr::null(); SEGMENTS],
+            vao : 0,
+            vbo : 0,
+            ebo : 0
+        };
+
+        unsafe {
+            gl::GenVertexArrays(1, &mut mesh.vao);
+            gl::GenBuffers(1, &mut mesh.vbo);
+            gl::GenBuffers(1, &mut mesh.ebo);
+
+            gl::BindVertexArray(mesh.vao);
+
+            // Allocate storage for all regions, data is written later using write()
+            gl::BindBuffer(gl::ARRAY_BUFFER, mesh.vbo);
+            gl::BufferData(gl::ARRAY_BUFFER, (SEGMENTS * max_vertices * mem::size_of::<Vertex>()) as GLsizeiptr, ptr::null(), gl::STREAM_DRAW);
+            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, mesh.ebo);
+            gl::BufferData(gl::ELEMENT_ARRAY_BUFFER, (SEGMENTS * max_indices * mem::size_of::<GLuint>()) as GLsizeiptr, ptr::null(), gl::STREAM_DRAW);
+
+            mesh::link_vertex_attributes();
+
+            gl::BindVertexArray(0);
+        }
+        mesh
+    }
+
+    /// Function to write this frame's geometry into the next region of the ring buffer
+    ///
+    /// Indices are relative to the vertices passed in the same call
+    pub fn write(&mut self, vertices : &[Vertex], indices : &[GLuint]) {
+        let range = self.ring.next(vertices.len(), indices.len());
+
+        unsafe {
+            // Wait until the gpu is done with the last draw from this region
+            self.wait_for_segment(range.segment);
+
+            gl::BindVertexArray(self.vao);
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+            Self::write_range(gl::ARRAY_BUFFER, range.first_vertex, vertices);
+            Self::write_range(gl::ELEMENT_ARRAY_BUFFER, range.first_index, indices);
+            gl::BindVertexArray(0);
+        }
+    }
+
+    /// Function to draw the geometry from the last call to write
+    ///
+    /// **Assumes that the shader program is activated**
+    pub fn draw(&mut self, shader : &Shader) {
+        let Some(range) = self.ring.current() else {
+            return;
+        };
+
+        unsafe {
+            self.material.bind(shader);
+
+            // Indices start at 0 for each region, offset them to the region's vertices using base vertex
+            gl::BindVertexArray(self.vao);
+            gl::DrawElementsBaseVertex(
+                gl::TRIANGLES,
+                range.index_count as GLsizei,
+                gl::UNSIGNED_INT,
+                (range.first_index * mem::size_of::<GLuint>()) as *const c_void,
+                range.first_vertex as GLint);
+            gl::BindVertexArray(0);
+
+            // Fence the region, replacing the fence from an earlier draw in the same frame
+            if !self.fences[range.segment].is_null() {
+                gl::DeleteSync(self.fences[range.segment]);
+            }
+            self.fences[range.segment] = gl::FenceSync(gl::SYNC_GPU_COMMANDS_COMPLETE, 0);
+        }
+    }
+
+    /// Function to block until the fence of a region is signaled
+    unsafe fn wait_for_segment(&mut self, segment : usize) {
+        let fence = self.fences[segment];
+        if fence.is_null() {
+            return;
+        }
+        unsafe {
+            loop {
+                let result = gl::ClientWaitSync(fence, gl::SYNC_FLUSH_COMMANDS_BIT, FENCE_TIMEOUT);
+                if result == gl::ALREADY_SIGNALED || result == gl::CONDITION_SATISFIED || result == gl::WAIT_FAILED {
+                    break;
+                }
+            }
+            gl::DeleteSync(fence);
+        }
+        self.fences[segment] = ptr::null();
+    }
+
+    /// Function to copy data into the buffer bound to target, starting at element `offset`
+    ///
+    /// The range is mapped unsynchronized, the fences make sure that the gpu is no longer using it
+    unsafe fn write_range<T>(target : GLenum, offset : usize, data : &[T]) {
+        if data.is_empty() {
+            return;
+        }
+        unsafe {
+            let dst = gl::MapBufferRange(
+                target,
+                (offset * mem::size_of::<T>()) as GLintptr,
+                mem::size_of_val(data) as GLsizeiptr,
+                gl::MAP_WRITE_BIT | gl::MAP_INVALIDATE_RANGE_BIT | gl::MAP_UNSYNCHRONIZED_BIT);
+            if dst.is_null() {
+                println!("Warning: failed to map stream buffer");
+                return;
+            }
+            ptr::copy_nonoverlapping(data.as_ptr(), dst as *mut T, data.len());
+            gl::UnmapBuffer(target);
+        }
+    }
+}
+
+/// Where a region's data starts in the vertex and index buffers, in elements
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct RingRange {
+    segment : usize,
+    first_vertex : usize,   // base vertex for the draw, the indices of each region start at 0
+    first_index : usize,
+    index_count : usize,
+}
+
+/// Which region of the ring buffer each write goes to, kept apart from the OpenGL calls
+#[derive(Debug, Clone, PartialEq)]
+struct StreamRing {
+    max_vertices : usize,   // capacity of a single region
+    max_indices : usize,
+    segment : usize,        // region that was written last
+    index_count : usize,    // number of indices written to the current region
+}
+
+impl StreamRing {
+    fn new(max_vertices : usize, max_indices : usize) -> StreamRing {
+        StreamRing { max_vertices, max_indices, segment : SEGMENTS - 1, index_count : 0 }
+    }
+
+    /// Function to move to the next region for a write of vertex_count vertices and index_count indices
+    fn next(&mut self, vertex_count : usize, index_count : usize) -> RingRange {
+        assert!(vertex_count <= self.max_vertices, "StreamMesh can hold {} vertices, got {}", self.max_vertices, vertex_count);
+        assert!(index_count <= self.max_indices, "StreamMesh can hold {} indices, got {}", self.max_indices, index_count);
+
+        self.segment = (self.segment + 1) % SEGMENTS;
+        self.index_count = index_count;
+        self.range()
+    }
+
+    /// Function to get the region written last, None if there is nothing to draw
+    fn current(&self) -> Option<RingRange> {
+        (self.index_count > 0).then(|| self.range())
+    }
+
+    fn range(&self) -> RingRange {
+        RingRange {
+            segment : self.segment,
+            first_vertex : self.segment * self.max_vertices,
+            first_index : self.segment * self.max_indices,
+            index_count : self.index_count,
+        }
+    }
+}
+
+impl Drop for StreamMesh {
+    fn drop(&mut self) {
+        unsafe {
+            for fence in self.fences {
+                if !fence.is_null() {
+                    gl::DeleteSync(fence);
+                }
+            }
+            gl::DeleteVertexArrays(1, &self.vao);
+            gl::DeleteBuffers(1, &self.vbo);
+            gl::DeleteBuffers(1, &self.ebo);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_rotate_through_the_regions() {
+        let mut ring = StreamRing::new(100, 150);
+        let segments: Vec<usize> = (0..SEGMENTS * 2).map(|_| ring.next(4, 6).segment).collect();
+        assert_eq!(segments, [0, 1, 2, 0, 1, 2]);
+    }
+
+    #[test]
+    fn regions_are_offset_by_their_capacity() {
+        let mut ring = StreamRing::new(100, 150);
+        ring.next(4, 6);
+        let range = ring.next(100, 150);
+        assert_eq!(range, RingRange { segment : 1, first_vertex : 100, first_index : 150, index_count : 150 });
+        assert_eq!(ring.current(), Some(range));
+    }
+
+    #[test]
+    fn nothing_to_draw_before_or_after_an_empty_write() {
+        let mut ring = StreamRing::new(100, 150);
+        assert_eq!(ring.current(), None);
+        ring.next(4, 6);
+        ring.next(0, 0);
+        assert_eq!(ring.current(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "StreamMesh can hold 100 vertices, got 101")]
+    fn too_many_vertices_panics() {
+        StreamRing::new(100, 150).next(101, 6);
+    }
+
+    #[test]
+    #[should_panic(expected = "StreamMesh can hold 150 indices, got 151")]
+    fn too_many_indices_panics() {
+        StreamRing::new(100, 150).next(4, 151);
+    }
+}
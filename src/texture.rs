@@ -0,0 +1,583 @@
+// Textures shared between models - reference counted handles, texture options, reading and uploading texture files and a cache keyed by path and options
+
+// Notes:
+// A Texture is a cheap handle (an Rc), materials clone it instead of copying the gl id. The gl texture is deleted when
+// the last handle is dropped, so a model can be dropped while another model still uses the same texture.
+// The cache only keeps Weak references, it never keeps a texture alive by itself. It is stored per thread since
+// textures can only be created on the thread owning the OpenGL context, see TextureCache::with_global.
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::error::Error;
+use std::ffi::CStr;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::os::raw::{c_char, c_void};
+use std::path::Path;
+use std::rc::{Rc, Weak};
+
+use gl::{self, types::*};
+
+use crate::compressed_texture::{self, CompressedImage, CompressedTextureError};
+use crate::float_texture::{self, FloatImage};
+use crate::material::TextureSlot;
+
+#[derive(Debug)]
+struct TextureHandle {
+    id : GLuint,
+    path : String,
+}
+
+impl Drop for TextureHandle {
+    fn drop(&mut self) {
+        //println!("Deleting texture {} : {}", self.id, self.path);
+        // 0 is never a gl texture, so there is nothing to delete and no context is needed
+        if self.id != 0 {
+            unsafe {
+                gl::DeleteTextures(1, &self.id);
+            }
+        }
+    }
+}
+
+/// Texture loaded to the gpu, along with the path it was loaded from
+///
+/// Clones share the same gl texture, which is deleted when the last clone is dropped
+#[derive(Clone, Debug)]
+pub struct Texture {
+    handle : Rc<TextureHandle>,
+}
+
+impl Texture {
+    /// Function to take ownership of a gl texture, it is deleted when the last clone is dropped
+    pub fn new(id : GLuint, path : &str) -> Texture {
+        Texture { handle : Rc::new(TextureHandle { id, path : path.into() }) }
+    }
+
+    pub fn id(&self) -> GLuint {
+        self.handle.id
+    }
+
+    pub fn path(&self) -> &str {
+        &self.handle.path
+    }
+}
+
+impl PartialEq for Texture {
+    fn eq(&self, other : &Texture) -> bool {
+        Rc::ptr_eq(&self.handle, &other.handle)
+    }
+}
+
+// Anisotropic filtering is an extension before OpenGL 4.6, the gl crate does not include its enums
+const TEXTURE_MAX_ANISOTROPY : GLenum = 0x84FE;
+const MAX_TEXTURE_MAX_ANISOTROPY : GLenum = 0x84FF;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Filter {
+    Nearest,
+    Linear,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[allow(dead_code)]
+pub enum Wrap {
+    Repeat,
+    MirroredRepeat,
+    ClampToEdge,
+    ClampToBorder,  // uses TextureOptions::border_color
+}
+
+impl Wrap {
+    pub(crate) fn gl_wrap(self) -> GLenum {
+        match self {
+            Wrap::Repeat => gl::REPEAT,
+            Wrap::MirroredRepeat => gl::MIRRORED_REPEAT,
+            Wrap::ClampToEdge => gl::CLAMP_TO_EDGE,
+            Wrap::ClampToBorder => gl::CLAMP_TO_BORDER,
+        }
+    }
+}
+
+/// How a texture is sampled and stored, part of the cache key since the same image can be loaded with different options
+///
+/// Colour maps (diffuse, emissive) should use color(), so they are converted from sRGB to linear when sampled.
+/// Data maps (specular, normal, roughness...) should use data(), their values are already linear
+#[derive(Clone, Copy, Debug)]
+pub struct TextureOptions {
+    pub min_filter : Filter,
+    pub mag_filter : Filter,
+    pub mip_filter : Option<Filter>,    // None for no mip maps
+    pub wrap_s : Wrap,
+    pub wrap_t : Wrap,
+    pub border_color : [f32; 4],
+    pub anisotropy : f32,               // 1 to disable, clamped to what the driver supports, ignored if unsupported
+    pub srgb : bool,                    // SRGB8_ALPHA8 instead of RGBA8
+    pub half_float : bool,              // RGB16F instead of RGB32F for float images, ignored for 8 bit images
+    pub flip : bool,                    // flip vertically when loading a file, since OpenGL expects the bottom row first
+}
+
+impl Default for TextureOptions {
+    /// Trilinear filtering, repeat wrap, linear format
+    fn default() -> TextureOptions {
+        TextureOptions {
+            min_filter : Filter::Linear,
+            mag_filter : Filter::Linear,
+            mip_filter : Some(Filter::Linear),
+            wrap_s : Wrap::Repeat,
+            wrap_t : Wrap::Repeat,
+            border_color : [0., 0., 0., 0.],
+            anisotropy : 1.,
+            srgb : false,
+            half_float : false,
+            flip : true,
+        }
+    }
+}
+
+// Options are the key of TextureCache, so Eq and Hash both use float_bits : -0 equals 0 and NaN equals NaN
+impl PartialEq for TextureOptions {
+    fn eq(&self, other : &TextureOptions) -> bool {
+        self.min_filter == other.min_filter
+            && self.mag_filter == other.mag_filter
+            && self.mip_filter == other.mip_filter
+            && self.wrap_s == other.wrap_s
+            && self.wrap_t == other.wrap_t
+            && self.float_bits() == other.float_bits()
+            && self.srgb == other.srgb
+            && self.half_float == other.half_float
+            && self.flip == other.flip
+    }
+}
+
+impl Eq for TextureOptions {}
+
+impl Hash for TextureOptions {
+    fn hash<H : Hasher>(&self, state : &mut H) {
+        self.min_filter.hash(state);
+        self.mag_filter.hash(state);
+        self.mip_filter.hash(state);
+        self.wrap_s.hash(state);
+        self.wrap_t.hash(state);
+        self.float_bits().hash(state);
+        self.srgb.hash(state);
+        self.half_float.hash(state);
+        self.flip.hash(state);
+    }
+}
+
+impl TextureOptions {
+    /// Options for colour maps - sRGB, trilinear and anisotropic filtering
+    pub fn color() -> TextureOptions {
+        TextureOptions {
+            srgb : true,
+            anisotropy : 16.,
+            ..Default::default()
+        }
+    }
+
+    /// Options for data maps - linear, trilinear filtering
+    pub fn data() -> TextureOptions {
+        TextureOptions::default()
+    }
+
+    /// Options with nearest filtering and no mip maps, for pixel art and lookup textures
+    pub fn nearest() -> TextureOptions {
+        TextureOptions {
+            min_filter : Filter::Nearest,
+            mag_filter : Filter::Nearest,
+            mip_filter : None,
+            ..Default::default()
+        }
+    }
+
+    /// Options for HDR environment maps and float lookup tables - clamped, bilinear, no mip maps
+    #[allow(dead_code)]
+    pub fn hdr() -> TextureOptions {
+        TextureOptions {
+            mip_filter : None,
+            wrap_s : Wrap::ClampToEdge,
+            wrap_t : Wrap::ClampToEdge,
+            ..Default::default()
+        }
+    }
+
+    /// Function to get the options for a material slot, colour or data
+    pub fn for_slot(slot : TextureSlot) -> TextureOptions {
+        match slot {
+            TextureSlot::Diffuse | TextureSlot::Emissive => TextureOptions::color(),
+            _ => TextureOptions::data(),
+        }
+    }
+
+    /// Function to get the bits of the border colour and anisotropy, with -0 turned into 0 and every NaN into the same NaN
+    fn float_bits(&self) -> [u32; 5] {
+        let [r, g, b, a] = self.border_color;
+        [r, g, b, a, self.anisotropy].map(|value| if value.is_nan() { f32::NAN.to_bits() } else { (value + 0.).to_bits() })
+    }
+
+    pub fn internal_format(&self) -> GLenum {
+        if self.srgb { gl::SRGB8_ALPHA8 } else { gl::RGBA8 }
+    }
+
+    fn gl_min_filter(&self) -> GLenum {
+        match (self.min_filter, self.mip_filter) {
+            (Filter::Nearest, None) => gl::NEAREST,
+            (Filter::Linear, None) => gl::LINEAR,
+            (Filter::Nearest, Some(Filter::Nearest)) => gl::NEAREST_MIPMAP_NEAREST,
+            (Filter::Linear, Some(Filter::Nearest)) => gl::LINEAR_MIPMAP_NEAREST,
+            (Filter::Nearest, Some(Filter::Linear)) => gl::NEAREST_MIPMAP_LINEAR,
+            (Filter::Linear, Some(Filter::Linear)) => gl::LINEAR_MIPMAP_LINEAR,
+        }
+    }
+
+    /// Function to set the sampler parameters of the texture bound to target
+    pub unsafe fn apply(&self, target : GLenum) {
+        unsafe {
+            gl::TexParameteri(target, gl::TEXTURE_MIN_FILTER, self.gl_min_filter() as GLint);
+            let mag_filter = match self.mag_filter { Filter::Nearest => gl::NEAREST, Filter::Linear => gl::LINEAR };
+            gl::TexParameteri(target, gl::TEXTURE_MAG_FILTER, mag_filter as GLint);
+            gl::TexParameteri(target, gl::TEXTURE_WRAP_S, self.wrap_s.gl_wrap() as GLint);
+            gl::TexParameteri(target, gl::TEXTURE_WRAP_T, self.wrap_t.gl_wrap() as GLint);
+            gl::TexParameterfv(target, gl::TEXTURE_BORDER_COLOR, self.border_color.as_ptr());
+
+            let max_anisotropy = max_anisotropy();
+            if self.anisotropy > 1. && max_anisotropy > 1. {
+                gl::TexParameterf(target, TEXTURE_MAX_ANISOTROPY, self.anisotropy.min(max_anisotropy));
+            }
+        }
+    }
+}
+
+thread_local! {
+    static MAX_ANISOTROPY : Cell<Option<f32>> = const { Cell::new(None) };
+}
+
+/// Function to get the maximum anisotropy supported by the driver, 1 if anisotropic filtering is not available
+///
+/// Checked once per thread, needs a current OpenGL context
+pub fn max_anisotropy() -> f32 {
+    MAX_ANISOTROPY.with(|max| {
+        if let Some(value) = max.get() {
+            return value;
+        }
+        let mut value = 1.;
+        if has_extension("GL_EXT_texture_filter_anisotropic") || has_extension("GL_ARB_texture_filter_anisotropic") {
+            unsafe {
+                gl::GetFloatv(MAX_TEXTURE_MAX_ANISOTROPY, &mut value);
+            }
+        }
+        max.set(Some(value));
+        value
+    })
+}
+
+/// Function to check if the current OpenGL context supports an extension
+pub fn has_extension(name : &str) -> bool {
+    unsafe {
+        let mut count = 0;
+        gl::GetIntegerv(gl::NUM_EXTENSIONS, &mut count);
+        (0..count as GLuint).any(|i| {
+            let extension = gl::GetStringi(gl::EXTENSIONS, i);
+            !extension.is_null() && CStr::from_ptr(extension as *const c_char).to_bytes() == name.as_bytes()
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum TextureError {
+    Image(image::ImageError),
+    Compressed(CompressedTextureError),
+    Cubemap(String),    // faces that are not square or not the same size
+    Atlas(String),      // images that do not fit in an atlas, or an empty texture array
+}
+
+impl fmt::Display for TextureError {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TextureError::Image(error) => write!(f, "{}", error),
+            TextureError::Compressed(error) => write!(f, "{}", error),
+            TextureError::Cubemap(error) | TextureError::Atlas(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl Error for TextureError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TextureError::Image(error) => Some(error),
+            TextureError::Compressed(error) => Some(error),
+            TextureError::Cubemap(_) | TextureError::Atlas(_) => None,
+        }
+    }
+}
+
+/// Texture file read into memory, not uploaded yet
+pub enum TextureData {
+    Rgba8(image::RgbaImage),
+    Compressed(CompressedImage),    // DDS and KTX2 files, uploaded without decoding
+    Float(FloatImage),              // HDR and EXR files
+}
+
+impl TextureData {
+    /// Function to read a texture file relative to directory, if flip is set the first row is the bottom of the image
+    ///
+    /// No OpenGL calls, so this can run on a worker thread
+    pub fn read(path : &str, directory : &str, flip : bool) -> Result<TextureData, TextureError> {
+        if float_texture::is_float_path(path) {
+            return FloatImage::read(&Path::new(directory).join(path), flip).map(TextureData::Float).map_err(TextureError::Image);
+        }
+        if !compressed_texture::is_compressed_path(path) {
+            return decode_texture(path, directory, flip).map(TextureData::Rgba8).map_err(TextureError::Image);
+        }
+
+        let mut image = compressed_texture::read_compressed(&Path::new(directory).join(path)).map_err(TextureError::Compressed)?;
+        if !flip || image.flip_vertical() {
+            return Ok(TextureData::Compressed(image));
+        }
+
+        // Blocks that cannot be flipped are decoded, only the first level is kept and mip maps are generated on upload
+        let pixels = image.decode_level(0);
+        let decoded = image::RgbaImage::from_raw(image.width, image.height, pixels).unwrap();
+        Ok(TextureData::Rgba8(image::imageops::flip_vertical(&decoded)))
+    }
+
+    /// Function to upload the texture into a new gl texture
+    pub unsafe fn upload(&self, options : &TextureOptions) -> GLuint {
+        match self {
+            TextureData::Rgba8(image) => unsafe { load_texture_from_pixels(image.width(), image.height(), image, options) },
+            TextureData::Compressed(image) => unsafe { image.upload(options) },
+            TextureData::Float(image) => unsafe { image.upload(options) },
+        }
+    }
+}
+
+/// Function to read a texture file as RGBA8, if flip is set the first row is the bottom of the image
+/// 
+/// No OpenGL calls, so this can run on a worker thread
+pub fn decode_texture(path: &str, directory: &str, flip : bool) -> image::ImageResult<image::RgbaImage> {
+    let absolute_path = Path::new(directory).join(path);
+    let image = image::open(&absolute_path)?;
+    Ok(if flip { image.flipv() } else { image }.into_rgba8())
+}
+
+/// Function to create a texture from RGBA8 pixel data, rows are uploaded in the order given
+/// 
+/// The texture is bound to the active texture unit while uploading, and unbound afterwards
+pub unsafe fn load_texture_from_pixels(width : u32, height : u32, pixels : &[u8], options : &TextureOptions) -> GLuint {
+    let mut texture: GLuint = 0;
+    unsafe {
+        // Generate Texture
+        gl::GenTextures(1, &mut texture);
+        gl::BindTexture(gl::TEXTURE_2D, texture);
+
+        // Interpolation, wrap and anisotropy
+        options.apply(gl::TEXTURE_2D);
+
+        //println!("Storing texture data");
+        // Store data into texture
+        // Rows of RGB images are not always 4 byte aligned, RGBA always is
+        gl::TexImage2D(gl::TEXTURE_2D,0,options.internal_format() as i32,width as i32,height as i32,0,gl::RGBA,gl::UNSIGNED_BYTE,pixels.as_ptr() as *const c_void);
+
+        //println!("Generating mip maps");
+        // Generate mip maps for the texture
+        if options.mip_filter.is_some() {
+            gl::GenerateMipmap(gl::TEXTURE_2D);
+        }
+
+        // Unbind texture
+        gl::BindTexture(gl::TEXTURE_2D, 0);
+    }
+    texture
+}
+
+// 2x2 magenta and black checker, used when a texture fails to load
+pub(crate) const FALLBACK_PIXELS : [u8; 16] = [255, 0, 255, 255, 0, 0, 0, 255, 0, 0, 0, 255, 255, 0, 255, 255];
+const FALLBACK_KEY : &str = "#fallback";
+
+thread_local! {
+    static GLOBAL_CACHE : RefCell<TextureCache> = RefCell::new(TextureCache::new());
+}
+
+/// Textures loaded so far, keyed by canonical path and options
+#[derive(Default)]
+pub struct TextureCache {
+    entries : HashMap<(String, TextureOptions), Weak<TextureHandle>>,
+}
+
+impl TextureCache {
+    pub fn new() -> TextureCache {
+        TextureCache { entries : HashMap::new() }
+    }
+
+    /// Function to use the cache shared by every model on this thread
+    pub fn with_global<R>(f : impl FnOnce(&mut TextureCache) -> R) -> R {
+        GLOBAL_CACHE.with(|cache| f(&mut cache.borrow_mut()))
+    }
+
+    /// Function to get the key of a texture file, so the same file reached through different paths is only loaded once
+    ///
+    /// Falls back to the joined path if the file does not exist
+    pub fn canonical_key(path : &str, directory : &str) -> String {
+        let path = Path::new(directory).join(path);
+        let path = path.canonicalize().unwrap_or(path);
+        path.to_string_lossy().into_owned()
+    }
+
+    /// Function to get a texture that is still in use
+    pub fn get(&self, key : &str, options : TextureOptions) -> Option<Texture> {
+        let handle = self.entries.get(&(key.to_string(), options))?.upgrade()?;
+        Some(Texture { handle })
+    }
+
+    /// Function to load a texture file relative to directory, or share it if it is already loaded
+    pub fn load(&mut self, path : &str, directory : &str, options : TextureOptions) -> Result<Texture, TextureError> {
+        let key = Self::canonical_key(path, directory);
+        if let Some(texture) = self.get(&key, options) {
+            return Ok(texture);
+        }
+
+        let data = TextureData::read(path, directory, options.flip)?;
+        Ok(self.insert_data(&key, options, &data))
+    }
+
+    /// Function to upload a texture read with TextureData::read under key, replacing any entry already using it
+    pub fn insert_data(&mut self, key : &str, options : TextureOptions, data : &TextureData) -> Texture {
+        match data {
+            TextureData::Rgba8(image) => self.insert_pixels(key, options, image.width(), image.height(), image),
+            TextureData::Compressed(_) | TextureData::Float(_) => {
+                let texture = unsafe { Texture::new(data.upload(&options), key) };
+                self.insert(key, options, texture)
+            },
+        }
+    }
+
+    /// Function to upload RGBA8 pixels under key, replacing any entry already using it
+    ///
+    /// Used for images that are already decoded, like embedded glTF images or textures decoded on another thread.
+    /// The pixels are not flipped, options.flip only matters when loading files
+    pub fn insert_pixels(&mut self, key : &str, options : TextureOptions, width : u32, height : u32, pixels : &[u8]) -> Texture {
+        let texture = unsafe {
+            Texture::new(load_texture_from_pixels(width, height, pixels, &options), key)
+        };
+        self.insert(key, options, texture)
+    }
+
+    /// Function to add an uploaded texture under key, the cache only keeps a Weak reference to it
+    fn insert(&mut self, key : &str, options : TextureOptions, texture : Texture) -> Texture {
+        self.purge();
+        self.entries.insert((key.to_string(), options), Rc::downgrade(&texture.handle));
+        texture
+    }
+
+    /// Function to get the texture used in place of textures that fail to load, a magenta and black checker
+    pub fn fallback(&mut self) -> Texture {
+        let options = TextureOptions::nearest();
+        match self.get(FALLBACK_KEY, options) {
+            Some(texture) => texture,
+            None => self.insert_pixels(FALLBACK_KEY, options, 2, 2, &FALLBACK_PIXELS),
+        }
+    }
+
+    /// Function to get the number of textures still in use
+    pub fn len(&self) -> usize {
+        self.entries.values().filter(|handle| handle.strong_count() > 0).count()
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Function to remove entries of textures that were dropped
+    pub fn purge(&mut self) {
+        self.entries.retain(|_, handle| handle.strong_count() > 0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::hash_map::DefaultHasher;
+    use std::fs;
+
+    fn hash(options : &TextureOptions) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        options.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn equal_options_hash_the_same() {
+        let zero = TextureOptions::hdr();
+        let negative_zero = TextureOptions { border_color : [-0., 0., -0., 0.], ..zero };
+        assert_eq!(zero, negative_zero);
+        assert_eq!(hash(&zero), hash(&negative_zero));
+
+        // NaN would otherwise never be equal to itself, and every lookup would miss the cache
+        let nan = TextureOptions { anisotropy : f32::NAN, ..zero };
+        let other_nan = TextureOptions { anisotropy : -f32::NAN, ..zero };
+        assert_eq!(nan, other_nan);
+        assert_eq!(hash(&nan), hash(&other_nan));
+        assert_ne!(nan, zero);
+
+        assert_ne!(TextureOptions::color(), TextureOptions::data());
+        assert_eq!(TextureOptions::for_slot(TextureSlot::Emissive), TextureOptions::color());
+    }
+    /// Function to create a texture without a gl texture behind it, so the cache can be tested without a context
+    fn placeholder(key : &str) -> Texture {
+        Texture::new(0, key)
+    }
+
+    #[test]
+    fn loads_of_the_same_file_share_one_texture() {
+        let directory = std::env::temp_dir().join(format!("texture_cache_{}", std::process::id()));
+        fs::create_dir_all(directory.join("textures")).unwrap();
+        fs::write(directory.join("textures").join("wall.png"), []).unwrap();
+
+        let mut cache = TextureCache::new();
+        let key = TextureCache::canonical_key("textures/wall.png", directory.to_str().unwrap());
+        let first = cache.insert(&key, TextureOptions::color(), placeholder(&key));
+
+        // Same file through another path, found in the cache so nothing is read or uploaded
+        let other_directory = directory.join("textures").join("..").join("textures");
+        let second = cache.load("wall.png", other_directory.to_str().unwrap(), TextureOptions::color()).unwrap();
+        assert_eq!(first, second);
+        assert_eq!(Rc::strong_count(&first.handle), 2);
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn texture_is_freed_after_its_last_handle_drops() {
+        let mut cache = TextureCache::new();
+        let first = cache.insert("wall.png", TextureOptions::color(), placeholder("wall.png"));
+        let second = cache.get("wall.png", TextureOptions::color()).unwrap();
+        let handle = Rc::downgrade(&first.handle);
+
+        // Still used by the second owner
+        drop(first);
+        assert_eq!(cache.get("wall.png", TextureOptions::color()).as_ref(), Some(&second));
+        assert_eq!(cache.len(), 1);
+
+        drop(second);
+        assert!(handle.upgrade().is_none());
+        assert_eq!(cache.get("wall.png", TextureOptions::color()), None);
+        assert!(cache.is_empty());
+
+        // The dead entry stays until the next purge
+        assert_eq!(cache.entries.len(), 1);
+        cache.purge();
+        assert!(cache.entries.is_empty());
+    }
+
+    #[test]
+    fn different_options_are_separate_entries() {
+        let mut cache = TextureCache::new();
+        let color = cache.insert("wall.png", TextureOptions::color(), placeholder("wall.png"));
+        let data = cache.insert("wall.png", TextureOptions::data(), placeholder("wall.png"));
+        assert_ne!(color, data);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get("wall.png", TextureOptions::color()), Some(color));
+        assert_eq!(cache.get("wall.png", TextureOptions::data()), Some(data));
+    }
+}
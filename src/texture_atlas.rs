@@ -0,0 +1,407 @@
+// Texture atlases and texture arrays - fewer textures, so meshes can share a material and be drawn together
+
+// Notes:
+// Each texture is normally its own GL_TEXTURE_2D, so every mesh with a different texture needs its own draw call.
+// An atlas packs several images into one texture, and the uvs of each mesh are remapped to the image's region. This
+// only works for uvs in [0, 1], a repeating texture would sample its neighbours in the atlas. Regions are padded by
+// repeating their edge pixels, which stops bilinear filtering and the first few mip levels from bleeding.
+// A texture array (GL_TEXTURE_2D_ARRAY) keeps every image in its own layer instead, so uvs can repeat, but every layer
+// has the same size and shaders have to sample it with a layer index (sampler2DArray).
+// Packing uses shelves: images are sorted by height and placed left to right in rows as tall as their first image.
+
+use std::collections::HashMap;
+use std::os::raw::c_void;
+use std::path::Path;
+use std::ptr;
+use std::rc::Rc;
+
+use gl::{self, types::*};
+use nalgebra_glm as glm;
+
+use crate::material::Material;
+use crate::mesh::{Mesh, Vertex};
+use crate::model::Model;
+use crate::texture::{self, Texture, TextureError, TextureOptions};
+
+/// Region of an atlas in pixels, rows counted from the first row of the atlas
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AtlasRect {
+    pub x : u32,
+    pub y : u32,
+    pub width : u32,
+    pub height : u32,
+}
+
+impl AtlasRect {
+    /// Function to map uvs of the original image to uvs of an atlas of atlas_width x atlas_height
+    pub fn remap_uv(&self, uv : glm::Vec2, atlas_width : u32, atlas_height : u32) -> glm::Vec2 {
+        glm::vec2(
+            (self.x as f32 + uv.x * self.width as f32) / atlas_width as f32,
+            (self.y as f32 + uv.y * self.height as f32) / atlas_height as f32,
+        )
+    }
+}
+
+/// Function to pack sizes into an area of width x height, with padding pixels around each region
+///
+/// Returns the regions in the order of sizes, without their padding, or None if they do not fit
+pub fn pack(sizes : &[(u32, u32)], width : u32, height : u32, padding : u32) -> Option<Vec<AtlasRect>> {
+    // Tallest first, so each shelf wastes as little height as possible
+    let mut order: Vec<usize> = (0..sizes.len()).collect();
+    order.sort_by_key(|i| (std::cmp::Reverse(sizes[*i].1), std::cmp::Reverse(sizes[*i].0)));
+
+    let mut rects = vec![AtlasRect { x : 0, y : 0, width : 0, height : 0 }; sizes.len()];
+    let (mut x, mut y, mut shelf_height) = (0, 0, 0);
+    for i in order {
+        let (image_width, image_height) = sizes[i];
+        let (padded_width, padded_height) = (image_width + 2 * padding, image_height + 2 * padding);
+        if padded_width > width {
+            return None;
+        }
+        // Start a new shelf when the current one is full
+        if x + padded_width > width {
+            y += shelf_height;
+            x = 0;
+            shelf_height = 0;
+        }
+        if y + padded_height > height {
+            return None;
+        }
+        rects[i] = AtlasRect { x : x + padding, y : y + padding, width : image_width, height : image_height };
+        x += padded_width;
+        shelf_height = shelf_height.max(padded_height);
+    }
+    Some(rects)
+}
+
+/// Function to pack sizes into the smallest power of two atlas up to max_size x max_size
+///
+/// Sizes are tried in increasing area, square before wide. Returns the atlas size and the regions
+pub fn pack_power_of_two(sizes : &[(u32, u32)], max_size : u32, padding : u32) -> Option<(u32, u32, Vec<AtlasRect>)> {
+    let area: u64 = sizes.iter().map(|(w, h)| (*w + 2 * padding) as u64 * (*h + 2 * padding) as u64).sum();
+    let mut width = 1;
+    let mut height = 1;
+    while (width as u64 * height as u64) < area {
+        if width == height { width *= 2 } else { height *= 2 }
+    }
+
+    while width <= max_size && height <= max_size {
+        if let Some(rects) = pack(sizes, width, height, padding) {
+            return Some((width, height, rects));
+        }
+        if width == height { width *= 2 } else { height *= 2 }
+    }
+    None
+}
+
+/// Function to copy image into atlas at rect, repeating its edge pixels into the padding around it
+pub fn blit_padded(atlas : &mut image::RgbaImage, image : &image::RgbaImage, rect : &AtlasRect, padding : u32) {
+    let padding = padding as i64;
+    for y in -padding..rect.height as i64 + padding {
+        for x in -padding..rect.width as i64 + padding {
+            let source_x = x.clamp(0, rect.width as i64 - 1) as u32;
+            let source_y = y.clamp(0, rect.height as i64 - 1) as u32;
+            let (atlas_x, atlas_y) = (rect.x as i64 + x, rect.y as i64 + y);
+            if atlas_x >= 0 && atlas_y >= 0 && atlas_x < atlas.width() as i64 && atlas_y < atlas.height() as i64 {
+                atlas.put_pixel(atlas_x as u32, atlas_y as u32, *image.get_pixel(source_x, source_y));
+            }
+        }
+    }
+}
+
+/// Function to check if every uv is inside [0, 1], so the mesh can use an atlas region
+pub fn tex_coords_in_unit_range(vertices : &[Vertex]) -> bool {
+    const EPSILON : f32 = 1e-4;
+    vertices.iter().all(|vertex| {
+        vertex.tex_coords.x >= -EPSILON && vertex.tex_coords.x <= 1. + EPSILON
+            && vertex.tex_coords.y >= -EPSILON && vertex.tex_coords.y <= 1. + EPSILON
+    })
+}
+
+/// Function to check if a texture was loaded from an image file that can be read again
+///
+/// Textures without a file, like the fallback texture or glTF embedded images ("<file>#imageN"), are not
+pub fn is_texture_file(texture : &Texture) -> bool {
+    Path::new(texture.path()).is_file()
+}
+
+/// Function to append a mesh to a batch, offsetting its indices by the vertices already in the batch
+pub fn append_mesh(batch_vertices : &mut Vec<Vertex>, batch_indices : &mut Vec<GLuint>, vertices : &[Vertex], indices : &[GLuint]) {
+    let offset = batch_vertices.len() as GLuint;
+    batch_vertices.extend_from_slice(vertices);
+    batch_indices.extend(indices.iter().map(|index| index + offset));
+}
+
+/// Images packed into a single texture
+pub struct TextureAtlas {
+    pub texture : Texture,
+    pub width : u32,
+    pub height : u32,
+    pub regions : HashMap<String, AtlasRect>,   // by name, e.g. the path the image was loaded from
+}
+
+impl TextureAtlas {
+    /// Function to pack images into an atlas of at most max_size x max_size and upload it
+    ///
+    /// Rows are copied as they are, images loaded with the same flip as the atlas keep their orientation
+    pub fn new(images : &[(String, image::RgbaImage)], max_size : u32, padding : u32, options : &TextureOptions) -> Result<TextureAtlas, TextureError> {
+        let sizes: Vec<(u32, u32)> = images.iter().map(|(_, image)| image.dimensions()).collect();
+        let Some((width, height, rects)) = pack_power_of_two(&sizes, max_size, padding) else {
+            return Err(TextureError::Atlas(format!("{} images do not fit in a {}x{} atlas", images.len(), max_size, max_size)));
+        };
+
+        let mut atlas = image::RgbaImage::new(width, height);
+        for ((_, image), rect) in images.iter().zip(&rects) {
+            blit_padded(&mut atlas, image, rect, padding);
+        }
+
+        let id = unsafe { texture::load_texture_from_pixels(width, height, &atlas, options) };
+        Ok(TextureAtlas {
+            texture : Texture::new(id, &format!("#atlas({})", images.len())),
+            width,
+            height,
+            regions : images.iter().map(|(name, _)| name.clone()).zip(rects).collect(),
+        })
+    }
+
+    /// Function to remap the uvs of vertices into the region named name
+    pub fn remap_tex_coords(&self, name : &str, vertices : &mut [Vertex]) {
+        let rect = self.regions[name];
+        for vertex in vertices {
+            vertex.tex_coords = rect.remap_uv(vertex.tex_coords, self.width, self.height);
+        }
+    }
+}
+
+/// Function to merge meshes of a model that only differ by their diffuse texture into one mesh per material layout
+///
+/// Meshes with uvs outside [0, 1], skinned or morphed meshes, and meshes without a diffuse texture file are moved to
+/// the new model as they are. The diffuse textures of the batched meshes are read again from their paths and packed into
+/// atlases of at most max_size. Returns the atlases and the new model
+#[allow(dead_code)]
+pub fn batch_model(model : Model, max_size : u32, options : &TextureOptions) -> Result<(Vec<TextureAtlas>, Model), TextureError> {
+    let mut batched = Model::new();
+    batched.invalid_mesh_policy = model.invalid_mesh_policy;
+
+    // Group by material layout - every property apart from the name and diffuse texture
+    let mut groups: Vec<(Material, Vec<Mesh>)> = Vec::new();
+    for mesh in model.meshes {
+        let batchable = mesh.material.diffuse.as_ref().is_some_and(is_texture_file) && mesh.has_tex_coords && mesh.skin.is_empty() && mesh.colors.is_empty()
+            && mesh.morph_weights().is_empty() && tex_coords_in_unit_range(&mesh.vertices);
+        if !batchable {
+            batched.meshes.push(mesh);
+            continue;
+        }
+        let layout = Material { name : String::new(), diffuse : None, ..(*mesh.material).clone() };
+        match groups.iter_mut().find(|(group_layout, _)| *group_layout == layout) {
+            Some((_, meshes)) => meshes.push(mesh),
+            None => groups.push((layout, vec![mesh])),
+        }
+    }
+
+    let mut atlases = Vec::new();
+    for (layout, meshes) in groups {
+        // Each texture is packed once, even if several meshes use it
+        let mut images: Vec<(String, image::RgbaImage)> = Vec::new();
+        for mesh in &meshes {
+            let path = mesh.material.diffuse.as_ref().unwrap().path();
+            if !images.iter().any(|(name, _)| name == path) {
+                images.push((path.to_string(), texture::decode_texture(path, "", options.flip).map_err(TextureError::Image)?));
+            }
+        }
+        let atlas = TextureAtlas::new(&images, max_size, 2, options)?;
+
+        let (mut vertices, mut indices) = (Vec::new(), Vec::new());
+        for mesh in &meshes {
+            let mut mesh_vertices = mesh.vertices.clone();
+            atlas.remap_tex_coords(mesh.material.diffuse.as_ref().unwrap().path(), &mut mesh_vertices);
+            append_mesh(&mut vertices, &mut indices, &mesh_vertices, &mesh.indices);
+        }
+        let material = Material { name : format!("atlas {}", atlases.len()), diffuse : Some(atlas.texture.clone()), ..layout };
+        batched.meshes.push(Mesh::new(vertices, indices, Rc::new(material)));
+        atlases.push(atlas);
+    }
+    Ok((atlases, batched))
+}
+
+/// Images stored as the layers of a GL_TEXTURE_2D_ARRAY
+#[allow(dead_code)]
+pub struct TextureArray {
+    pub texture : Texture,
+    pub width : u32,
+    pub height : u32,
+    pub layers : HashMap<String, u32>,  // layer of each image by name
+}
+
+#[allow(dead_code)]
+impl TextureArray {
+    /// Function to upload images as the layers of a texture array, in order
+    ///
+    /// Every layer has the size of the largest image, smaller images are scaled up with a warning
+    pub fn new(images : &[(String, image::RgbaImage)], options : &TextureOptions) -> Result<TextureArray, TextureError> {
+        if images.is_empty() {
+            return Err(TextureError::Atlas("a texture array needs at least one image".into()));
+        }
+        let width = images.iter().map(|(_, image)| image.width()).max().unwrap();
+        let height = images.iter().map(|(_, image)| image.height()).max().unwrap();
+
+        let mut texture: GLuint = 0;
+        unsafe {
+            gl::GenTextures(1, &mut texture);
+            gl::BindTexture(gl::TEXTURE_2D_ARRAY, texture);
+            options.apply(gl::TEXTURE_2D_ARRAY);
+
+            // Allocate every layer, then fill them one by one
+            gl::TexImage3D(gl::TEXTURE_2D_ARRAY, 0, options.internal_format() as GLint, width as GLsizei, height as GLsizei, images.len() as GLsizei, 0, gl::RGBA, gl::UNSIGNED_BYTE, ptr::null());
+            for (layer, (name, image)) in images.iter().enumerate() {
+                let resized;
+                let pixels = if image.dimensions() == (width, height) {
+                    image
+                } else {
+                    println!("Warning: texture array layer {} is {}x{}, scaling to {}x{}", name, image.width(), image.height(), width, height);
+                    resized = image::imageops::resize(image, width, height, image::imageops::FilterType::Triangle);
+                    &resized
+                };
+                gl::TexSubImage3D(gl::TEXTURE_2D_ARRAY, 0, 0, 0, layer as GLint, width as GLsizei, height as GLsizei, 1, gl::RGBA, gl::UNSIGNED_BYTE, pixels.as_ptr() as *const c_void);
+            }
+
+            if options.mip_filter.is_some() {
+                gl::GenerateMipmap(gl::TEXTURE_2D_ARRAY);
+            }
+            gl::BindTexture(gl::TEXTURE_2D_ARRAY, 0);
+        }
+
+        Ok(TextureArray {
+            texture : Texture::new(texture, &format!("#array({})", images.len())),
+            width,
+            height,
+            layers : images.iter().enumerate().map(|(layer, (name, _))| (name.clone(), layer as u32)).collect(),
+        })
+    }
+
+    /// Function to read image files relative to directory into a texture array, layers are in the order of paths
+    pub fn from_files(paths : &[&str], directory : &str, options : &TextureOptions) -> Result<TextureArray, TextureError> {
+        let mut images = Vec::with_capacity(paths.len());
+        for path in paths {
+            images.push((path.to_string(), texture::decode_texture(path, directory, options.flip).map_err(TextureError::Image)?));
+        }
+        TextureArray::new(&images, options)
+    }
+
+    pub fn layer(&self, name : &str) -> Option<u32> {
+        self.layers.get(name).copied()
+    }
+}
+
+#[cfg(test)]
+impl AtlasRect {
+    /// Function to check if two regions share any pixels
+    pub fn overlaps(&self, other : &AtlasRect) -> bool {
+        self.x < other.x + other.width && other.x < self.x + self.width
+            && self.y < other.y + other.height && other.y < self.y + self.height
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn assert_valid_packing(sizes : &[(u32, u32)], width : u32, height : u32, padding : u32, rects : &[AtlasRect]) {
+        assert_eq!(rects.len(), sizes.len());
+        for (rect, (w, h)) in rects.iter().zip(sizes) {
+            assert_eq!((rect.width, rect.height), (*w, *h));
+            assert!(rect.x >= padding && rect.y >= padding);
+            assert!(rect.x + rect.width + padding <= width && rect.y + rect.height + padding <= height);
+        }
+        // Regions grown by their padding must not overlap either
+        let padded = |r : &AtlasRect| AtlasRect { x : r.x - padding, y : r.y - padding, width : r.width + 2 * padding, height : r.height + 2 * padding };
+        for i in 0..rects.len() {
+            for j in i + 1..rects.len() {
+                assert!(!padded(&rects[i]).overlaps(&padded(&rects[j])), "{:?} overlaps {:?}", rects[i], rects[j]);
+            }
+        }
+    }
+
+    #[test]
+    fn packs_without_overlap() {
+        let sizes = [(64, 64), (32, 128), (100, 20), (16, 16), (16, 16), (50, 70), (128, 32)];
+        let rects = pack(&sizes, 256, 256, 2).unwrap();
+        assert_valid_packing(&sizes, 256, 256, 2, &rects);
+    }
+
+    #[test]
+    fn rejects_sizes_that_do_not_fit() {
+        assert!(pack(&[(300, 10)], 256, 256, 0).is_none());
+        assert!(pack(&[(200, 200), (200, 200)], 256, 256, 0).is_none());
+        assert!(pack(&[(256, 256)], 256, 256, 1).is_none());
+        assert!(pack(&[(256, 256)], 256, 256, 0).is_some());
+    }
+
+    #[test]
+    fn power_of_two_atlas_grows_to_fit() {
+        // 4 images of 64x64 fill a 128x128 atlas exactly
+        let (width, height, rects) = pack_power_of_two(&[(64, 64); 4], 1024, 0).unwrap();
+        assert_eq!((width, height), (128, 128));
+        assert_valid_packing(&[(64, 64); 4], width, height, 0, &rects);
+
+        // With padding they no longer fit in 128x128 or in two rows of 256x128
+        let (width, height, rects) = pack_power_of_two(&[(64, 64); 4], 1024, 1).unwrap();
+        assert_eq!((width, height), (256, 256));
+        assert_valid_packing(&[(64, 64); 4], width, height, 1, &rects);
+
+        assert!(pack_power_of_two(&[(64, 64); 4], 64, 0).is_none());
+    }
+
+    #[test]
+    fn remaps_uvs_into_regions() {
+        let rect = AtlasRect { x : 64, y : 32, width : 64, height : 32 };
+        assert_eq!(rect.remap_uv(glm::vec2(0., 0.), 256, 128), glm::vec2(0.25, 0.25));
+        assert_eq!(rect.remap_uv(glm::vec2(1., 1.), 256, 128), glm::vec2(0.5, 0.5));
+        assert_eq!(rect.remap_uv(glm::vec2(0.5, 0.5), 256, 128), glm::vec2(0.375, 0.375));
+    }
+
+    #[test]
+    fn padding_repeats_edge_pixels() {
+        let mut image = image::RgbaImage::new(2, 2);
+        image.put_pixel(0, 0, image::Rgba([1, 0, 0, 255]));
+        image.put_pixel(1, 0, image::Rgba([2, 0, 0, 255]));
+        image.put_pixel(0, 1, image::Rgba([3, 0, 0, 255]));
+        image.put_pixel(1, 1, image::Rgba([4, 0, 0, 255]));
+
+        let mut atlas = image::RgbaImage::new(6, 6);
+        blit_padded(&mut atlas, &image, &AtlasRect { x : 2, y : 2, width : 2, height : 2 }, 2);
+        assert_eq!(atlas.get_pixel(2, 2)[0], 1);
+        assert_eq!(atlas.get_pixel(0, 0)[0], 1);
+        assert_eq!(atlas.get_pixel(5, 0)[0], 2);
+        assert_eq!(atlas.get_pixel(0, 5)[0], 3);
+        assert_eq!(atlas.get_pixel(5, 5)[0], 4);
+    }
+
+    #[test]
+    fn appended_meshes_keep_their_triangles() {
+        let vertex = |x : f32| Vertex { position : glm::vec3(x, 0., 0.), normal : glm::vec3(0., 0., 1.), tex_coords : glm::vec2(0., 0.) };
+        let (mut vertices, mut indices) = (Vec::new(), Vec::new());
+        append_mesh(&mut vertices, &mut indices, &[vertex(0.), vertex(1.), vertex(2.)], &[0, 1, 2]);
+        append_mesh(&mut vertices, &mut indices, &[vertex(3.), vertex(4.), vertex(5.)], &[2, 1, 0]);
+        assert_eq!(indices, vec![0, 1, 2, 5, 4, 3]);
+        assert_eq!(vertices[indices[3] as usize].position.x, 5.);
+
+        assert!(tex_coords_in_unit_range(&vertices));
+        vertices[0].tex_coords = glm::vec2(2., 0.);
+        assert!(!tex_coords_in_unit_range(&vertices));
+    }
+    #[test]
+    fn only_textures_read_from_files_are_batched() {
+        let directory = std::env::temp_dir().join(format!("texture_atlas_{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("wall.png");
+        fs::write(&path, []).unwrap();
+        let path = path.to_str().unwrap();
+
+        // Texture 0 has no gl texture, so the handles can be dropped without a context
+        assert!(is_texture_file(&Texture::new(0, path)));
+        assert!(!is_texture_file(&Texture::new(0, "#fallback")));
+        assert!(!is_texture_file(&Texture::new(0, &format!("{}#image0", path))));
+    }
+}